@@ -7,6 +7,7 @@
         atomic::{self, AtomicU64},
         Arc,
     },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use lapce_xi_rope::{
@@ -18,6 +19,7 @@
     Cursor, Delta, DeltaBuilder, DeltaElement, Interval, Rope, RopeDelta, RopeInfo,
 };
 use lsp_types::Position;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     char_buffer::CharBuffer,
@@ -35,7 +37,7 @@
 
 use rope_text::*;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 enum Contents {
     Edit {
         /// Groups related edits together so that they are undone and re-done
@@ -59,7 +61,7 @@ enum Contents {
     },
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Revision {
     num: u64,
     max_undo_so_far: usize,
@@ -68,6 +70,58 @@ struct Revision {
     cursor_after: Option<CursorMode>,
 }
 
+/// A group of edits in the undo tree of a buffer. Its index in the tree is
+/// the id of the group, and the root is the initial content.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UndoNode {
+    /// The group the edits were made on top of
+    pub parent: usize,
+    /// When the last edit of the group was made, in milliseconds since the
+    /// unix epoch
+    pub time: u64,
+    /// The number of characters inserted by the group
+    pub inserted: usize,
+    /// The number of characters deleted by the group
+    pub deleted: usize,
+}
+
+impl UndoNode {
+    fn new(parent: usize) -> Self {
+        Self {
+            parent,
+            time: now_millis(),
+            inserted: 0,
+            deleted: 0,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// The undo history of a buffer, which can be stored and restored on a
+/// buffer with the same text.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UndoHistory {
+    revs: Vec<Revision>,
+    cur_undo: usize,
+    undos: BTreeSet<usize>,
+    undo_group_id: usize,
+    live_undos: Vec<usize>,
+    deletes_from_union: Subset,
+    undone_groups: BTreeSet<usize>,
+    tombstones: Rope,
+    undo_tree: Vec<UndoNode>,
+    /// The index in `revs` of the revision that was pristine, which is the
+    /// last one when it's not known
+    #[serde(default)]
+    pristine: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct InvalLines {
     pub start_line: usize,
@@ -90,6 +144,7 @@ pub struct Buffer {
     deletes_from_union: Subset,
     undone_groups: BTreeSet<usize>,
     tombstones: Rope,
+    undo_tree: Vec<UndoNode>,
     this_edit_type: EditType,
     last_edit_type: EditType,
 
@@ -131,6 +186,7 @@ pub fn new(text: &str) -> Self {
             deletes_from_union: Subset::new(text.len()),
             undone_groups: BTreeSet::new(),
             tombstones: Rope::default(),
+            undo_tree: vec![UndoNode::new(0)],
 
             this_edit_type: EditType::Other,
             last_edit_type: EditType::Other,
@@ -280,6 +336,68 @@ pub fn reload(
         (delta, inval_lines, edits)
     }
 
+    pub fn undo_history(&self) -> UndoHistory {
+        UndoHistory {
+            revs: self.revs.clone(),
+            cur_undo: self.cur_undo,
+            undos: self.undos.clone(),
+            undo_group_id: self.undo_group_id,
+            live_undos: self.live_undos.clone(),
+            deletes_from_union: self.deletes_from_union.clone(),
+            undone_groups: self.undone_groups.clone(),
+            tombstones: self.tombstones.clone(),
+            undo_tree: self.undo_tree.clone(),
+            pristine: self.find_rev(self.pristine_rev_id),
+        }
+    }
+
+    /// Replaces the undo history with `history`, which must have been taken
+    /// from a buffer with the same text. The buffer is pristine afterwards
+    /// if it was when the history was taken, and its revision moves past the
+    /// current one. Returns false if the history doesn't match the text.
+    pub fn restore_undo_history(&mut self, mut history: UndoHistory) -> bool {
+        if history.revs.is_empty()
+            || history.deletes_from_union.len_after_delete() != self.text.len()
+            || history.deletes_from_union.count(CountMatcher::NonZero)
+                != history.tombstones.len()
+            || history.live_undos.is_empty()
+            || history.cur_undo > history.live_undos.len()
+            || history.undo_tree.len() != history.undo_group_id
+            || history.pristine.map_or(false, |i| i >= history.revs.len())
+        {
+            return false;
+        }
+
+        // the revisions are renumbered to carry on from the current one, so
+        // that the numbers never go backwards
+        let base = self.rev();
+        for (i, rev) in history.revs.iter_mut().enumerate() {
+            rev.num = base + 1 + i as u64;
+        }
+        let last_rev = history.revs.last().unwrap().num;
+        let pristine_rev = history
+            .pristine
+            .map(|i| history.revs[i].num)
+            .unwrap_or(last_rev);
+
+        self.revs = history.revs;
+        self.cur_undo = history.cur_undo;
+        self.undos = history.undos;
+        self.undo_group_id = history.undo_group_id;
+        self.live_undos = history.live_undos;
+        self.deletes_from_union = history.deletes_from_union;
+        self.undone_groups = history.undone_groups;
+        self.tombstones = history.tombstones;
+        self.undo_tree = history.undo_tree;
+        self.this_edit_type = EditType::Other;
+        self.last_edit_type = EditType::Other;
+
+        self.rev_counter = last_rev + 1;
+        self.atomic_rev.store(last_rev, atomic::Ordering::Release);
+        self.pristine_rev_id = pristine_rev;
+        true
+    }
+
     pub fn detect_indent(&mut self, syntax: Option<&Syntax>) {
         self.indent_style =
             auto_detect_indent_style(&self.text).unwrap_or_else(|| {
@@ -345,6 +463,20 @@ fn add_delta(
             new_deletes_from_union,
         );
 
+        if let Some(Revision {
+            edit: Contents::Edit {
+                inserts, deletes, ..
+            },
+            ..
+        }) = self.revs.last()
+        {
+            if let Some(node) = self.undo_tree.get_mut(undo_group) {
+                node.inserted += inserts.count(CountMatcher::NonZero);
+                node.deleted += deletes.count(CountMatcher::NonZero);
+                node.time = now_millis();
+            }
+        }
+
         (delta, inval_lines, edits)
     }
 
@@ -390,6 +522,10 @@ fn calculate_undo_group(&mut self) -> usize {
             *self.live_undos.last().unwrap()
         } else {
             let undo_group = self.undo_group_id;
+            // the edits are made on top of the current group, which is where
+            // a new branch of the undo tree starts after an undo
+            let parent = self.live_undos[self.cur_undo - 1];
+            self.undo_tree.push(UndoNode::new(parent));
             self.live_undos.truncate(self.cur_undo);
             self.live_undos.push(undo_group);
             self.cur_undo += 1;
@@ -569,13 +705,13 @@ fn find_first_undo_candidate_index(
         }
     }
 
-    fn compute_undo(&self, groups: &BTreeSet<usize>) -> (Revision, Subset) {
-        let toggled_groups = self
-            .undone_groups
-            .symmetric_difference(groups)
-            .cloned()
-            .collect();
-        let first_candidate = self.find_first_undo_candidate_index(&toggled_groups);
+    /// The deletes from union with `groups` undone, where the revisions
+    /// before `first_candidate` don't toggle any group
+    fn deletes_from_union_for_undos(
+        &self,
+        groups: &BTreeSet<usize>,
+        first_candidate: usize,
+    ) -> Subset {
         // the `false` below: don't invert undos since our first_candidate is based on the current undo set, not past
         let mut deletes_from_union = self
             .deletes_from_union_before_index(first_candidate, false)
@@ -606,6 +742,19 @@ fn compute_undo(&self, groups: &BTreeSet<usize>) -> (Revision, Subset) {
             }
         }
 
+        deletes_from_union
+    }
+
+    fn compute_undo(&self, groups: &BTreeSet<usize>) -> (Revision, Subset) {
+        let toggled_groups = self
+            .undone_groups
+            .symmetric_difference(groups)
+            .cloned()
+            .collect();
+        let first_candidate = self.find_first_undo_candidate_index(&toggled_groups);
+        let deletes_from_union =
+            self.deletes_from_union_for_undos(groups, first_candidate);
+
         let cursor_before = self
             .revs
             .get(first_candidate)
@@ -726,6 +875,119 @@ pub fn do_redo(
         Some((delta, inval_lines, edits, cursor_after))
     }
 
+    pub fn undo_tree(&self) -> &[UndoNode] {
+        &self.undo_tree
+    }
+
+    /// The group of the undo tree the buffer is at
+    pub fn current_undo_node(&self) -> usize {
+        self.live_undos[self.cur_undo - 1]
+    }
+
+    /// The groups from the root of the undo tree to `node`, and the groups
+    /// which are undone at `node`
+    fn undo_path(&self, node: usize) -> (Vec<usize>, BTreeSet<usize>) {
+        let mut path = vec![node];
+        let mut group = node;
+        while group != 0 {
+            group = self.undo_tree[group].parent;
+            path.push(group);
+        }
+        path.reverse();
+
+        let on_path = path.iter().copied().collect::<BTreeSet<_>>();
+        let undos = (1..self.undo_tree.len())
+            .filter(|group| !on_path.contains(group))
+            .collect();
+        (path, undos)
+    }
+
+    /// Goes to the state after the group `node` of the undo tree, undoing the
+    /// groups that aren't on its path and redoing the ones that are.
+    /// Redo carries on along the most recent branch below `node`.
+    pub fn undo_to(
+        &mut self,
+        node: usize,
+    ) -> Option<(RopeDelta, InvalLines, SyntaxEdit, Option<CursorMode>)> {
+        if node >= self.undo_tree.len() || node == self.current_undo_node() {
+            return None;
+        }
+
+        let (mut path, undos) = self.undo_path(node);
+        // the cursor is restored from the first group that changes
+        let undoing = self
+            .undos
+            .symmetric_difference(&undos)
+            .next()
+            .map(|group| undos.contains(group))
+            .unwrap_or(false);
+
+        self.cur_undo = path.len();
+        let mut last = node;
+        while let Some(child) = (last + 1..self.undo_tree.len())
+            .rev()
+            .find(|group| self.undo_tree[*group].parent == last)
+        {
+            path.push(child);
+            last = child;
+        }
+        self.live_undos = path;
+        self.undos = undos;
+        self.last_edit_type = EditType::Undo;
+        let (delta, inval_lines, edits, cursor_before, cursor_after) =
+            self.undo(self.undos.clone());
+
+        let cursor = if undoing { cursor_before } else { cursor_after };
+        Some((delta, inval_lines, edits, cursor))
+    }
+
+    /// The group `steps` groups after the current one in the order they were
+    /// made, going across the branches of the undo tree
+    pub fn undo_node_by_steps(&self, steps: isize) -> usize {
+        let node = self.current_undo_node() as isize + steps;
+        node.clamp(0, self.undo_tree.len() as isize - 1) as usize
+    }
+
+    /// The last group made before the time of the current group shifted by
+    /// `millis`, going across the branches of the undo tree
+    pub fn undo_node_by_time(&self, millis: i64) -> usize {
+        let current = self.current_undo_node();
+        let time = self.undo_tree[current].time as i64 + millis;
+        let node = self
+            .undo_tree
+            .iter()
+            .rposition(|node| node.time as i64 <= time)
+            .unwrap_or(0);
+        if millis < 0 {
+            node.min(current)
+        } else {
+            node.max(current)
+        }
+    }
+
+    /// The text at the state after the group `node` of the undo tree
+    pub fn undo_node_text(&self, node: usize) -> Rope {
+        if node >= self.undo_tree.len() || node == self.current_undo_node() {
+            return self.text.clone();
+        }
+
+        let (_, undos) = self.undo_path(node);
+        let toggled_groups = self
+            .undone_groups
+            .symmetric_difference(&undos)
+            .cloned()
+            .collect();
+        let first_candidate = self.find_first_undo_candidate_index(&toggled_groups);
+        let deletes_from_union =
+            self.deletes_from_union_for_undos(&undos, first_candidate);
+        Delta::synthesize(
+            &self.tombstones,
+            &self.deletes_from_union,
+            &deletes_from_union,
+        )
+        .apply(&self.text)
+    }
+
     pub fn rope_text(&self) -> RopeText {
         RopeText::new(&self.text)
     }
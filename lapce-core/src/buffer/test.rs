@@ -16,6 +16,111 @@ fn is_pristine() {
         buffer.do_undo();
         assert!(buffer.is_pristine());
     }
+
+    #[test]
+    fn restore_undo_history() {
+        let mut buffer = Buffer::new("");
+        buffer.init_content(Rope::from("abc"));
+        buffer.edit(&[(Selection::caret(0), "d")], EditType::InsertChars);
+        buffer.edit(&[(Selection::region(1, 3), "")], EditType::Delete);
+        assert_eq!(buffer.to_string(), "dc");
+
+        buffer.set_pristine();
+
+        let mut restored = Buffer::new("");
+        restored.init_content(buffer.text().clone());
+        let rev = restored.rev();
+        assert!(restored.restore_undo_history(buffer.undo_history()));
+        assert!(restored.is_pristine());
+        assert!(restored.rev() > rev);
+
+        restored.do_undo();
+        assert_eq!(restored.to_string(), "dabc");
+        restored.do_undo();
+        assert_eq!(restored.to_string(), "abc");
+        assert!(restored.do_undo().is_none());
+        restored.do_redo();
+        assert_eq!(restored.to_string(), "dabc");
+    }
+
+    #[test]
+    fn restore_undo_history_keeps_pristine_revision() {
+        let mut buffer = Buffer::new("");
+        buffer.init_content(Rope::from("abc"));
+        buffer.edit(&[(Selection::caret(0), "d")], EditType::InsertChars);
+        assert!(!buffer.is_pristine());
+
+        let mut restored = Buffer::new("");
+        restored.init_content(buffer.text().clone());
+        assert!(restored.restore_undo_history(buffer.undo_history()));
+        assert!(!restored.is_pristine());
+        restored.do_undo();
+        assert_eq!(restored.to_string(), "abc");
+        assert!(restored.is_pristine());
+    }
+
+    #[test]
+    fn restore_undo_history_of_other_text() {
+        let mut buffer = Buffer::new("");
+        buffer.init_content(Rope::from("abc"));
+        buffer.edit(&[(Selection::caret(0), "d")], EditType::InsertChars);
+
+        let mut other = Buffer::new("");
+        other.init_content(Rope::from("abc"));
+        assert!(!other.restore_undo_history(buffer.undo_history()));
+        assert_eq!(other.to_string(), "abc");
+    }
+    #[test]
+    fn undo_tree_keeps_branches() {
+        let mut buffer = Buffer::new("");
+        buffer.init_content(Rope::from("abc"));
+        buffer.edit(&[(Selection::caret(3), "d")], EditType::InsertChars);
+        buffer.do_undo();
+        buffer.edit(&[(Selection::caret(3), "e")], EditType::InsertChars);
+        assert_eq!(buffer.to_string(), "abce");
+        assert_eq!(buffer.undo_tree()[2].parent, 0);
+        assert_eq!(buffer.undo_tree()[2].inserted, 1);
+        assert_eq!(buffer.current_undo_node(), 2);
+
+        buffer.undo_to(1);
+        assert_eq!(buffer.to_string(), "abcd");
+        assert_eq!(buffer.current_undo_node(), 1);
+        assert_eq!(buffer.undo_node_text(2).to_string(), "abce");
+        assert_eq!(buffer.undo_node_text(0).to_string(), "abc");
+
+        buffer.do_undo();
+        assert_eq!(buffer.to_string(), "abc");
+        buffer.do_redo();
+        assert_eq!(buffer.to_string(), "abcd");
+
+        buffer.undo_to(2);
+        assert_eq!(buffer.to_string(), "abce");
+    }
+
+    #[test]
+    fn undo_node_by_steps_and_time() {
+        let mut buffer = Buffer::new("");
+        buffer.init_content(Rope::from("abc"));
+        for (i, s) in ["d", "e", "f"].iter().enumerate() {
+            buffer.edit(&[(Selection::caret(3 + i), *s)], EditType::Other);
+        }
+        for (i, node) in buffer.undo_tree.iter_mut().enumerate() {
+            node.time = i as u64 * 1000;
+        }
+        assert_eq!(buffer.current_undo_node(), 3);
+
+        assert_eq!(buffer.undo_node_by_steps(-1), 2);
+        assert_eq!(buffer.undo_node_by_steps(5), 3);
+        assert_eq!(buffer.undo_node_by_steps(-10), 0);
+
+        assert_eq!(buffer.undo_node_by_time(-1500), 1);
+        assert_eq!(buffer.undo_node_by_time(-5000), 0);
+        assert_eq!(buffer.undo_node_by_time(1000), 3);
+
+        buffer.undo_to(1);
+        assert_eq!(buffer.to_string(), "abcd");
+        assert_eq!(buffer.undo_node_by_time(1500), 2);
+    }
 }
 
 mod motion {
@@ -96,6 +96,30 @@ pub enum EditCommand {
     DuplicateLineUp,
     #[strum(serialize = "duplicate_line_down")]
     DuplicateLineDown,
+    #[strum(message = "Go to Earlier State")]
+    #[strum(serialize = "undo_earlier")]
+    UndoEarlier,
+    #[strum(message = "Go to Later State")]
+    #[strum(serialize = "undo_later")]
+    UndoLater,
+    #[strum(message = "Align Cursors")]
+    #[strum(serialize = "align_cursors")]
+    AlignCursors,
+    #[strum(message = "Insert Incrementing Numbers")]
+    #[strum(serialize = "insert_number_sequence")]
+    InsertNumberSequence,
+    #[strum(message = "Sort Lines")]
+    #[strum(serialize = "sort_lines")]
+    SortLines,
+    #[strum(message = "Reverse Lines")]
+    #[strum(serialize = "reverse_lines")]
+    ReverseLines,
+    #[strum(message = "Remove Duplicate Lines")]
+    #[strum(serialize = "unique_lines")]
+    UniqueLines,
+    #[strum(message = "Rotate Selection Contents")]
+    #[strum(serialize = "rotate_selections")]
+    RotateSelections,
 }
 
 #[derive(
@@ -281,6 +305,15 @@ pub enum FocusCommand {
     #[strum(message = "Go to Type Definition")]
     #[strum(serialize = "goto_type_definition")]
     GotoTypeDefinition,
+    #[strum(message = "Go to Implementation")]
+    #[strum(serialize = "goto_implementation")]
+    GotoImplementation,
+    #[strum(message = "Go to Declaration")]
+    #[strum(serialize = "goto_declaration")]
+    GotoDeclaration,
+    #[strum(message = "Show Type Hierarchy")]
+    #[strum(serialize = "show_type_hierarchy")]
+    ShowTypeHierarchy,
     #[strum(message = "Show Hover")]
     #[strum(serialize = "show_hover")]
     ShowHover,
@@ -305,6 +338,9 @@ pub enum FocusCommand {
     #[strum(serialize = "format_document")]
     #[strum(message = "Format Document")]
     FormatDocument,
+    #[strum(serialize = "format_selection")]
+    #[strum(message = "Format Selection")]
+    FormatSelection,
     #[strum(serialize = "search")]
     Search,
     #[strum(serialize = "inline_find_right")]
@@ -313,6 +349,15 @@ pub enum FocusCommand {
     InlineFindLeft,
     #[strum(serialize = "repeat_last_inline_find")]
     RepeatLastInlineFind,
+    #[strum(message = "Surround Selection With…")]
+    #[strum(serialize = "surround_add")]
+    SurroundAdd,
+    #[strum(message = "Delete Surrounding Pair")]
+    #[strum(serialize = "surround_delete")]
+    SurroundDelete,
+    #[strum(message = "Change Surrounding Pair")]
+    #[strum(serialize = "surround_change")]
+    SurroundChange,
     #[strum(message = "Save")]
     #[strum(serialize = "save")]
     Save,
@@ -332,6 +377,18 @@ pub enum FocusCommand {
     SelectNextSyntaxItem,
     #[strum(serialize = "select_previous_syntax_item")]
     SelectPreviousSyntaxItem,
+    #[strum(serialize = "terminal_previous_command")]
+    #[strum(message = "Terminal: Jump to Previous Command")]
+    TerminalPreviousCommand,
+    #[strum(serialize = "terminal_next_command")]
+    #[strum(message = "Terminal: Jump to Next Command")]
+    TerminalNextCommand,
+    #[strum(serialize = "terminal_copy_last_command_output")]
+    #[strum(message = "Terminal: Copy Last Command Output")]
+    TerminalCopyLastCommandOutput,
+    #[strum(serialize = "run_selection_in_terminal")]
+    #[strum(message = "Run Selection in Terminal")]
+    RunSelectionInTerminal,
 }
 
 #[derive(
@@ -376,6 +433,20 @@ pub enum MultiSelectionCommand {
     InsertCursorBelow,
     #[strum(serialize = "insert_cursor_end_of_line")]
     InsertCursorEndOfLine,
+    #[strum(serialize = "column_select_up")]
+    ColumnSelectUp,
+    #[strum(serialize = "column_select_down")]
+    ColumnSelectDown,
+    #[strum(serialize = "column_select_left")]
+    ColumnSelectLeft,
+    #[strum(serialize = "column_select_right")]
+    ColumnSelectRight,
+    #[strum(message = "Split Selection into Lines")]
+    #[strum(serialize = "split_selection_into_lines")]
+    SplitSelectionIntoLines,
+    #[strum(message = "Select Search Matches in Selection")]
+    #[strum(serialize = "select_search_matches_in_selection")]
+    SelectSearchMatchesInSelection,
     #[strum(serialize = "select_current_line")]
     SelectCurrentLine,
     #[strum(serialize = "select_all_current")]
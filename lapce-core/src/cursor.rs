@@ -16,6 +16,38 @@ pub enum ColPosition {
     Col(f64),
 }
 
+/// Where the columns of the lines are displayed, to line text up by its
+/// position on screen rather than by its column in the text
+pub trait LineLayout {
+    /// The horizontal position of the column `col` of `line`
+    fn col_x(&mut self, buffer: &Buffer, line: usize, col: usize) -> f64;
+
+    /// The column of `line` displayed at the horizontal position `x`, at
+    /// most the end of the line
+    fn x_col(&mut self, buffer: &Buffer, line: usize, x: f64) -> usize;
+
+    /// The width of a space
+    fn space_width(&mut self) -> f64;
+}
+
+/// The layout where every column is as wide as a space, for text that
+/// isn't displayed
+pub struct TextColumns;
+
+impl LineLayout for TextColumns {
+    fn col_x(&mut self, _buffer: &Buffer, _line: usize, col: usize) -> f64 {
+        col as f64
+    }
+
+    fn x_col(&mut self, buffer: &Buffer, line: usize, x: f64) -> usize {
+        (x.round() as usize).min(buffer.line_end_col(line, true))
+    }
+
+    fn space_width(&mut self) -> f64 {
+        1.0
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Cursor {
     pub mode: CursorMode,
@@ -187,6 +219,16 @@ pub fn apply_delta(&mut self, delta: &RopeDelta) {
 
     pub fn yank(&self, buffer: &Buffer) -> RegisterData {
         let (content, mode) = match &self.mode {
+            CursorMode::Insert(selection)
+                if is_block_selection(buffer, selection) =>
+            {
+                let lines = selection
+                    .regions()
+                    .iter()
+                    .map(|region| buffer.slice_to_cow(region.min()..region.max()))
+                    .collect::<Vec<_>>();
+                (lines.join("\n") + "\n", VisualMode::Blockwise)
+            }
             CursorMode::Insert(selection) => {
                 let mut mode = VisualMode::Normal;
                 let mut content = "".to_string();
@@ -514,3 +556,25 @@ pub fn get_first_selection_after(
             Cursor::new(cursor_mode, None, None)
         })
 }
+
+/// Whether the selection is a column (block) selection: one region on each
+/// of consecutive lines, where not every region is empty
+fn is_block_selection(buffer: &Buffer, selection: &Selection) -> bool {
+    let regions = selection.regions();
+    if regions.len() < 2 || regions.iter().all(|region| region.is_caret()) {
+        return false;
+    }
+
+    let mut last_line = None;
+    for region in regions {
+        let line = buffer.line_of_offset(region.min());
+        if buffer.line_of_offset(region.max()) != line {
+            return false;
+        }
+        if last_line.map(|last_line| last_line + 1 != line) == Some(true) {
+            return false;
+        }
+        last_line = Some(line);
+    }
+    true
+}
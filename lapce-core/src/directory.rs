@@ -110,6 +110,48 @@ pub fn config_directory() -> Option<PathBuf> {
         }
     }
 
+    /// Get the path to the user snippets folder within the config directory
+    /// Snippets are stored within as a file per language
+    pub fn snippets_directory() -> Option<PathBuf> {
+        if let Some(dir) = Self::config_directory() {
+            let dir = dir.join("snippets");
+            if !dir.exists() {
+                let _ = std::fs::create_dir(&dir);
+            }
+            Some(dir)
+        } else {
+            None
+        }
+    }
+
+    /// Get the path to the undo directory
+    /// The undo history of each file is stored within as an individual file
+    pub fn undo_directory() -> Option<PathBuf> {
+        if let Some(dir) = Self::data_local_directory() {
+            let dir = dir.join("undo");
+            if !dir.exists() {
+                let _ = std::fs::create_dir(&dir);
+            }
+            Some(dir)
+        } else {
+            None
+        }
+    }
+
+    /// Get the path to the local history directory
+    /// The versions of each saved file are stored within in a directory of their own
+    pub fn local_history_directory() -> Option<PathBuf> {
+        if let Some(dir) = Self::data_local_directory() {
+            let dir = dir.join("local-history");
+            if !dir.exists() {
+                let _ = std::fs::create_dir(&dir);
+            }
+            Some(dir)
+        } else {
+            None
+        }
+    }
+
     pub fn local_socket() -> Option<PathBuf> {
         Self::data_local_directory().map(|dir| dir.join("local.sock"))
     }
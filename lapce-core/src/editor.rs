@@ -1,15 +1,16 @@
 use std::collections::HashSet;
 
 use itertools::Itertools;
-use lapce_xi_rope::RopeDelta;
+use lapce_xi_rope::{RopeDelta, Transformer};
 
 use crate::{
     buffer::{Buffer, InvalLines},
     command::EditCommand,
-    cursor::{get_first_selection_after, Cursor, CursorMode},
+    cursor::{get_first_selection_after, Cursor, CursorMode, LineLayout},
     mode::{Mode, MotionMode, VisualMode},
     register::{Clipboard, Register, RegisterData, RegisterKind},
     selection::{InsertDrift, SelRegion, Selection},
+    surround::Surround,
     syntax::{
         edit::SyntaxEdit,
         util::{
@@ -479,14 +480,144 @@ pub fn execute_motion_mode(
                     Self::do_outdent(buffer, selection);
                 deltas.push((delta, inval_lines, edits));
             }
+            MotionMode::Surround => {
+                // select the text of the motion, the pair to surround it with
+                // is typed afterwards
+                let (start, end) =
+                    format_start_end(buffer, start, end, is_vertical, true);
+                cursor.mode = CursorMode::Visual {
+                    start,
+                    end: buffer.prev_grapheme_offset(end, 1, start),
+                    mode: VisualMode::Normal,
+                };
+            }
         }
         deltas
     }
 
+    /// Surrounds every selected region with the pair,
+    /// keeping the line break of a whole line selection outside of it.
+    pub fn surround_add(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        surround: &Surround,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let selection = cursor.edit_selection(buffer);
+        let open = surround.open();
+        let close = surround.close();
+
+        let mut regions = Vec::new();
+        let mut edits = Vec::new();
+        for region in selection.regions() {
+            if region.is_caret() {
+                continue;
+            }
+            let start = region.min();
+            let content = buffer.slice_to_cow(start..region.max());
+            let end = start + content.trim_end_matches(['\r', '\n']).len();
+            regions.push((start, end));
+            edits.push((Selection::caret(start), open.as_str()));
+            edits.push((Selection::caret(end), close.as_str()));
+        }
+        if edits.is_empty() {
+            return Vec::new();
+        }
+
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Other);
+        let mut transformer = Transformer::new(&delta);
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                let offset = transformer.transform(regions[0].0, false);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Insert(_) => {
+                let mut selection = Selection::new();
+                for (start, end) in regions {
+                    selection.add_region(SelRegion::new(
+                        transformer.transform(start, true),
+                        transformer.transform(end, false),
+                        None,
+                    ));
+                }
+                cursor.mode = CursorMode::Insert(selection);
+            }
+        }
+        vec![(delta, inval_lines, edits)]
+    }
+
+    /// Deletes the innermost pair of `target` around every cursor.
+    pub fn surround_delete(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        target: &Surround,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        Self::surround_replace(cursor, buffer, target, "", "")
+    }
+
+    /// Replaces the innermost pair of `target` around every cursor
+    /// with the `surround` pair.
+    pub fn surround_change(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        target: &Surround,
+        surround: &Surround,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let open = surround.open();
+        let close = surround.close();
+        Self::surround_replace(cursor, buffer, target, &open, &close)
+    }
+
+    fn surround_replace(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        target: &Surround,
+        open: &str,
+        close: &str,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let offsets = match &cursor.mode {
+            CursorMode::Insert(selection) => selection
+                .regions()
+                .iter()
+                .map(|region| region.end)
+                .collect(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                vec![cursor.offset()]
+            }
+        };
+
+        let mut ranges = offsets
+            .into_iter()
+            .filter_map(|offset| target.find(buffer.text(), offset))
+            .flat_map(|(open_range, close_range)| {
+                [(open_range, open), (close_range, close)]
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|(range, _)| range.start);
+
+        // cursors inside the same pair find it more than once
+        let mut last_end = 0;
+        let mut edits = Vec::new();
+        for (range, content) in ranges {
+            if range.start < last_end {
+                continue;
+            }
+            last_end = range.end;
+            edits.push((Selection::region(range.start, range.end), content));
+        }
+        if edits.is_empty() {
+            return Vec::new();
+        }
+
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Other);
+        cursor.apply_delta(&delta);
+        vec![(delta, inval_lines, edits)]
+    }
+
     pub fn do_paste(
         cursor: &mut Cursor,
         buffer: &mut Buffer,
         data: &RegisterData,
+        layout: &mut dyn LineLayout,
     ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
         let mut deltas = Vec::new();
         match data.mode {
@@ -525,6 +656,14 @@ pub fn do_paste(
                     }
                 }
             }
+            VisualMode::Blockwise if !cursor.is_visual() => {
+                deltas.push(Self::paste_block(
+                    cursor,
+                    buffer,
+                    &data.content,
+                    layout,
+                ));
+            }
             VisualMode::Linewise | VisualMode::Blockwise => {
                 let (selection, content) = match &cursor.mode {
                     CursorMode::Normal(offset) => {
@@ -582,6 +721,100 @@ pub fn do_paste(
         deltas
     }
 
+    /// Pastes the lines of a block where the cursor is displayed on
+    /// consecutive lines, like a block selection, padding short lines with
+    /// spaces and adding lines past the end.
+    /// With as many cursors as lines, each cursor gets one of the lines.
+    fn paste_block(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        content: &str,
+        layout: &mut dyn LineLayout,
+    ) -> (RopeDelta, InvalLines, SyntaxEdit) {
+        let lines = content.lines().collect::<Vec<_>>();
+
+        if let CursorMode::Insert(selection) = &cursor.mode {
+            if selection.len() == lines.len() {
+                let edits = selection
+                    .regions()
+                    .iter()
+                    .zip(lines.iter())
+                    .map(|(region, line)| {
+                        (Selection::region(region.min(), region.max()), *line)
+                    })
+                    .collect::<Vec<_>>();
+                let (delta, inval_lines, edits) =
+                    buffer.edit(&edits, EditType::Paste);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.mode = CursorMode::Insert(selection);
+                return (delta, inval_lines, edits);
+            }
+        }
+
+        let offset = match &cursor.mode {
+            CursorMode::Normal(offset) => buffer.next_grapheme_offset(
+                *offset,
+                1,
+                buffer.offset_line_end(*offset, true),
+            ),
+            CursorMode::Insert(_) | CursorMode::Visual { .. } => cursor.offset(),
+        };
+        let (start_line, col) = buffer.offset_to_line_col(offset);
+        let x = layout.col_x(buffer, start_line, col);
+        let space_width = layout.space_width();
+        let spaces = |width: f64| " ".repeat((width / space_width).round() as usize);
+        let last_line = buffer.last_line();
+
+        let mut edits = Vec::new();
+        for (i, content) in lines.iter().enumerate() {
+            let line = start_line + i;
+            if line > last_line {
+                edits.push((
+                    Selection::caret(buffer.len()),
+                    format!("\n{}{content}", spaces(x)),
+                ));
+                continue;
+            }
+            if content.is_empty() {
+                continue;
+            }
+            let line_end_col = buffer.line_end_col(line, true);
+            let line_end_x = layout.col_x(buffer, line, line_end_col);
+            if line != start_line && line_end_x < x {
+                edits.push((
+                    Selection::caret(buffer.line_end_offset(line, true)),
+                    format!("{}{content}", spaces(x - line_end_x)),
+                ));
+            } else {
+                let col = if line == start_line {
+                    col
+                } else {
+                    layout.x_col(buffer, line, x)
+                };
+                edits.push((
+                    Selection::caret(buffer.offset_of_line_col(line, col)),
+                    content.to_string(),
+                ));
+            }
+        }
+
+        let edits = edits
+            .iter()
+            .map(|(selection, s)| (selection, s.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Paste);
+        let mut transformer = Transformer::new(&delta);
+        let offset = transformer.transform(offset, cursor.is_insert());
+        cursor.mode = match cursor.mode {
+            CursorMode::Insert(_) => CursorMode::Insert(Selection::caret(offset)),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                CursorMode::Normal(offset)
+            }
+        };
+        (delta, inval_lines, edits)
+    }
+
     fn do_indent(
         buffer: &mut Buffer,
         selection: Selection,
@@ -697,6 +930,7 @@ fn duplicate_line(
         vec![(delta, inval_lines, edits)]
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn do_edit<T: Clipboard>(
         cursor: &mut Cursor,
         buffer: &mut Buffer,
@@ -705,6 +939,7 @@ pub fn do_edit<T: Clipboard>(
         clipboard: &mut T,
         modal: bool,
         register: &mut Register,
+        layout: &mut dyn LineLayout,
     ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
         use crate::command::EditCommand::*;
         match cmd {
@@ -930,23 +1165,13 @@ pub fn do_edit<T: Clipboard>(
                 if let Some((delta, inval_lines, edits, cursor_mode)) =
                     buffer.do_undo()
                 {
-                    if let Some(cursor_mode) = cursor_mode {
-                        cursor.mode = if modal {
-                            CursorMode::Normal(cursor_mode.offset())
-                        } else if cursor.is_insert() {
-                            cursor_mode
-                        } else {
-                            CursorMode::Insert(Selection::caret(
-                                cursor_mode.offset(),
-                            ))
-                        };
-                    } else if let Some(new_cursor) =
-                        get_first_selection_after(cursor, buffer, &delta)
-                    {
-                        *cursor = new_cursor
-                    } else {
-                        cursor.apply_delta(&delta);
-                    }
+                    Self::restore_undo_cursor(
+                        cursor,
+                        buffer,
+                        &delta,
+                        cursor_mode,
+                        modal,
+                    );
                     vec![(delta, inval_lines, edits)]
                 } else {
                     vec![]
@@ -956,23 +1181,13 @@ pub fn do_edit<T: Clipboard>(
                 if let Some((delta, inval_lines, edits, cursor_mode)) =
                     buffer.do_redo()
                 {
-                    if let Some(cursor_mode) = cursor_mode {
-                        cursor.mode = if modal {
-                            CursorMode::Normal(cursor_mode.offset())
-                        } else if cursor.is_insert() {
-                            cursor_mode
-                        } else {
-                            CursorMode::Insert(Selection::caret(
-                                cursor_mode.offset(),
-                            ))
-                        };
-                    } else if let Some(new_cursor) =
-                        get_first_selection_after(cursor, buffer, &delta)
-                    {
-                        *cursor = new_cursor
-                    } else {
-                        cursor.apply_delta(&delta);
-                    }
+                    Self::restore_undo_cursor(
+                        cursor,
+                        buffer,
+                        &delta,
+                        cursor_mode,
+                        modal,
+                    );
                     vec![(delta, inval_lines, edits)]
                 } else {
                     vec![]
@@ -980,7 +1195,8 @@ pub fn do_edit<T: Clipboard>(
             }
             ClipboardCopy => {
                 let data = cursor.yank(buffer);
-                clipboard.put_string(data.content);
+                clipboard.put_string(&data.content);
+                register.add_clipboard(data);
 
                 match &cursor.mode {
                     CursorMode::Visual {
@@ -999,12 +1215,15 @@ pub fn do_edit<T: Clipboard>(
             }
             ClipboardCut => {
                 let data = cursor.yank(buffer);
-                clipboard.put_string(data.content);
+                clipboard.put_string(&data.content);
+                let is_block = data.mode == VisualMode::Blockwise;
+                register.add_clipboard(data);
 
                 let selection =
                     if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
                         for region in selection.regions_mut() {
-                            if region.is_caret() {
+                            // the empty regions of a block are on short lines
+                            if region.is_caret() && !is_block {
                                 let line = buffer.line_of_offset(region.start);
                                 let start = buffer.offset_of_line(line);
                                 let end = buffer.offset_of_line(line + 1);
@@ -1026,13 +1245,9 @@ pub fn do_edit<T: Clipboard>(
             }
             ClipboardPaste => {
                 if let Some(s) = clipboard.get_string() {
-                    let mode = if s.ends_with('\n') {
-                        VisualMode::Linewise
-                    } else {
-                        VisualMode::Normal
-                    };
+                    let mode = register.clipboard_mode(&s);
                     let data = RegisterData { content: s, mode };
-                    Self::do_paste(cursor, buffer, &data)
+                    Self::do_paste(cursor, buffer, &data, layout)
                 } else {
                     vec![]
                 }
@@ -1055,7 +1270,7 @@ pub fn do_edit<T: Clipboard>(
             }
             Paste => {
                 let data = register.unnamed.clone();
-                Self::do_paste(cursor, buffer, &data)
+                Self::do_paste(cursor, buffer, &data, layout)
             }
             PasteBefore => {
                 let offset = cursor.offset();
@@ -1063,7 +1278,7 @@ pub fn do_edit<T: Clipboard>(
                 let mut local_cursor =
                     Cursor::new(CursorMode::Insert(Selection::new()), None, None);
                 local_cursor.set_offset(offset, false, false);
-                Self::do_paste(&mut local_cursor, buffer, &data)
+                Self::do_paste(&mut local_cursor, buffer, &data, layout)
             }
             NewLineAbove => {
                 let offset = cursor.offset();
@@ -1466,6 +1681,240 @@ pub fn do_edit<T: Clipboard>(
             DuplicateLineDown => {
                 Self::duplicate_line(cursor, buffer, DuplicateDirection::Down)
             }
+            UndoEarlier => {
+                let node = buffer.undo_node_by_steps(-1);
+                Self::undo_to(cursor, buffer, node, modal)
+            }
+            UndoLater => {
+                let node = buffer.undo_node_by_steps(1);
+                Self::undo_to(cursor, buffer, node, modal)
+            }
+            AlignCursors => Self::align_cursors(cursor, buffer),
+            InsertNumberSequence => Self::insert_number_sequence(cursor, buffer),
+            SortLines => Self::transform_lines(cursor, buffer, |lines| {
+                lines.sort_unstable();
+            }),
+            ReverseLines => {
+                Self::transform_lines(cursor, buffer, |lines| lines.reverse())
+            }
+            UniqueLines => Self::transform_lines(cursor, buffer, |lines| {
+                let mut seen = HashSet::new();
+                lines.retain(|line| seen.insert(*line));
+            }),
+            RotateSelections => Self::rotate_selections(cursor, buffer),
+        }
+    }
+
+    /// Goes to the state after the group `node` of the undo tree of the buffer
+    pub fn undo_to(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        node: usize,
+        modal: bool,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        if let Some((delta, inval_lines, edits, cursor_mode)) = buffer.undo_to(node)
+        {
+            Self::restore_undo_cursor(cursor, buffer, &delta, cursor_mode, modal);
+            vec![(delta, inval_lines, edits)]
+        } else {
+            vec![]
+        }
+    }
+
+    fn restore_undo_cursor(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        delta: &RopeDelta,
+        cursor_mode: Option<CursorMode>,
+        modal: bool,
+    ) {
+        if let Some(cursor_mode) = cursor_mode {
+            cursor.mode = if modal {
+                CursorMode::Normal(cursor_mode.offset())
+            } else if cursor.is_insert() {
+                cursor_mode
+            } else {
+                CursorMode::Insert(Selection::caret(cursor_mode.offset()))
+            };
+        } else if let Some(new_cursor) =
+            get_first_selection_after(cursor, buffer, delta)
+        {
+            *cursor = new_cursor
+        } else {
+            cursor.apply_delta(delta);
+        }
+    }
+
+    /// Pads the text before the cursors with spaces so they line up.
+    fn align_cursors(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let padding = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.align_padding(buffer.text()),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return vec![],
+        };
+        if padding.is_empty() {
+            return vec![];
+        }
+
+        let edits = padding
+            .into_iter()
+            .map(|(offset, n)| (Selection::caret(offset), " ".repeat(n)))
+            .collect::<Vec<_>>();
+        let edits = edits
+            .iter()
+            .map(|(selection, s)| (selection, s.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Other);
+        cursor.apply_delta(&delta);
+        vec![(delta, inval_lines, edits)]
+    }
+
+    /// Replaces every region with an incrementing number, starting from the
+    /// number in the first region or from 1.
+    fn insert_number_sequence(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.clone(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return vec![],
+        };
+        let start = selection
+            .first()
+            .and_then(|region| {
+                buffer
+                    .slice_to_cow(region.min()..region.max())
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+            })
+            .unwrap_or(1);
+
+        let edits = selection
+            .regions()
+            .iter()
+            .enumerate()
+            .map(|(i, region)| {
+                (
+                    Selection::region(region.min(), region.max()),
+                    (start + i as i64).to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let edits = edits
+            .iter()
+            .map(|(selection, s)| (selection, s.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Other);
+        cursor.apply_delta(&delta);
+        vec![(delta, inval_lines, edits)]
+    }
+
+    /// Rearranges the lines covered by the selection with `f`, every range of
+    /// consecutive lines on its own.
+    fn transform_lines(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        f: impl Fn(&mut Vec<&str>),
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let selection = cursor.edit_selection(buffer);
+        let mut ranges = Vec::new();
+        let mut contents = Vec::new();
+        for lines in selection.line_ranges(buffer.text()) {
+            let start = buffer.offset_of_line(lines.start);
+            let end = buffer.line_end_offset(lines.end - 1, true);
+            let content = buffer.slice_to_cow(start..end);
+            let line_ending = if content.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            };
+            let mut lines = content.lines().collect::<Vec<_>>();
+            f(&mut lines);
+            let new_content = lines.join(line_ending);
+            if new_content != content {
+                ranges.push((start, end));
+                contents.push(new_content);
+            }
+        }
+        if ranges.is_empty() {
+            return vec![];
+        }
+
+        let edits = ranges
+            .iter()
+            .zip(contents.iter())
+            .map(|((start, end), content)| {
+                (Selection::region(*start, *end), content.as_str())
+            })
+            .collect::<Vec<_>>();
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Other);
+        Self::select_replaced(cursor, &delta, &ranges);
+        vec![(delta, inval_lines, edits)]
+    }
+
+    /// Moves the content of every region to the next region, and the content
+    /// of the last region to the first one.
+    fn rotate_selections(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) if selection.len() > 1 => {
+                selection.clone()
+            }
+            _ => return vec![],
+        };
+
+        let ranges = selection
+            .regions()
+            .iter()
+            .map(|region| (region.min(), region.max()))
+            .collect::<Vec<_>>();
+        let mut contents = ranges
+            .iter()
+            .map(|(start, end)| buffer.slice_to_cow(*start..*end).to_string())
+            .collect::<Vec<_>>();
+        contents.rotate_right(1);
+
+        let edits = ranges
+            .iter()
+            .zip(contents.iter())
+            .map(|((start, end), content)| {
+                (Selection::region(*start, *end), content.as_str())
+            })
+            .collect::<Vec<_>>();
+        let (delta, inval_lines, edits) = buffer.edit(&edits, EditType::Other);
+        Self::select_replaced(cursor, &delta, &ranges);
+        vec![(delta, inval_lines, edits)]
+    }
+
+    /// Selects the new text of the replaced ranges in insert mode,
+    /// or puts the cursor at the start of the first one otherwise.
+    fn select_replaced(
+        cursor: &mut Cursor,
+        delta: &RopeDelta,
+        ranges: &[(usize, usize)],
+    ) {
+        let mut transformer = Transformer::new(delta);
+        match cursor.mode {
+            CursorMode::Insert(_) => {
+                let mut selection = Selection::new();
+                for (start, end) in ranges {
+                    selection.add_region(SelRegion::new(
+                        transformer.transform(*start, false),
+                        transformer.transform(*end, true),
+                        None,
+                    ));
+                }
+                cursor.mode = CursorMode::Insert(selection);
+            }
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.mode =
+                    CursorMode::Normal(transformer.transform(ranges[0].0, false));
+            }
         }
     }
 }
@@ -1479,11 +1928,42 @@ enum DuplicateDirection {
 mod test {
     use crate::{
         buffer::Buffer,
-        cursor::{Cursor, CursorMode},
+        command::EditCommand,
+        cursor::{Cursor, CursorMode, LineLayout, TextColumns},
         editor::{DuplicateDirection, Editor},
+        mode::VisualMode,
+        register::{Clipboard, Register, RegisterData},
         selection::{SelRegion, Selection},
+        surround::Surround,
     };
 
+    #[derive(Default)]
+    struct TestClipboard(Option<String>);
+
+    impl Clipboard for TestClipboard {
+        fn get_string(&self) -> Option<String> {
+            self.0.clone()
+        }
+
+        fn put_string(&mut self, s: impl AsRef<str>) {
+            self.0 = Some(s.as_ref().to_string());
+        }
+    }
+
+    /// Runs `cmd` the way the editor does, in non-modal mode
+    fn run_edit(cursor: &mut Cursor, buffer: &mut Buffer, cmd: EditCommand) {
+        Editor::do_edit(
+            cursor,
+            buffer,
+            &cmd,
+            None,
+            &mut TestClipboard::default(),
+            false,
+            &mut Register::default(),
+            &mut TextColumns,
+        );
+    }
+
     #[test]
     fn test_insert_simple() {
         let mut buffer = Buffer::new("abc");
@@ -1716,5 +2196,274 @@ fn check_multiple_cursor_match_insertion() {
         assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
     }
 
+    #[test]
+    fn test_surround_add_multiple_cursor() {
+        let mut buffer = Buffer::new("abc def\nghi\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(8, 11, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::surround_add(
+            &mut cursor,
+            &mut buffer,
+            &Surround::from_char('(').unwrap(),
+        );
+        assert_eq!(
+            "( abc ) def\n( ghi )\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut end_selection = Selection::new();
+        end_selection.add_region(SelRegion::new(2, 5, None));
+        end_selection.add_region(SelRegion::new(14, 17, None));
+        assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
+    }
+
+    #[test]
+    fn test_surround_add_tag() {
+        let mut buffer = Buffer::new("abc\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 3,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+
+        Editor::surround_add(
+            &mut cursor,
+            &mut buffer,
+            &Surround::Tag("p class=\"a\"".to_string()),
+        );
+        assert_eq!(
+            "<p class=\"a\">abc</p>\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(cursor.mode, CursorMode::Normal(0));
+    }
+
+    #[test]
+    fn test_surround_delete() {
+        let mut buffer = Buffer::new("f(( a ), [b])");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+
+        Editor::surround_delete(
+            &mut cursor,
+            &mut buffer,
+            &Surround::target_from_char('(').unwrap(),
+        );
+        assert_eq!("f(a, [b])", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Normal(2));
+    }
+
+    #[test]
+    fn test_surround_change_multiple_cursor() {
+        let mut buffer = Buffer::new("'a' + 'bc'\n'd'");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::caret(8));
+        selection.add_region(SelRegion::caret(12));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::surround_change(
+            &mut cursor,
+            &mut buffer,
+            &Surround::target_from_char('\'').unwrap(),
+            &Surround::from_char('"').unwrap(),
+        );
+        assert_eq!(
+            "\"a\" + \"bc\"\n\"d\"",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_yank_block_selection() {
+        let buffer = Buffer::new("abcd\nef\nghij");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 3, None));
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::new(9, 11, None));
+        let cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        let data = cursor.yank(&buffer);
+        assert_eq!("bc\n\nhi\n", data.content);
+        assert_eq!(VisualMode::Blockwise, data.mode);
+    }
+
+    #[test]
+    fn test_paste_block() {
+        let data = RegisterData {
+            content: "12\n34\n56\n".to_string(),
+            mode: VisualMode::Blockwise,
+        };
+
+        let mut buffer = Buffer::new("abc\nd\nefg");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, &mut TextColumns);
+        assert_eq!("a12bc\nd34\ne56fg", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Normal(1));
+
+        let mut buffer = Buffer::new("abc\nd\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, &mut TextColumns);
+        assert_eq!("abc12\nd  34\n   56", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Insert(Selection::caret(5)));
+
+        let mut buffer = Buffer::new("a");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, &mut TextColumns);
+        assert_eq!("a12\n 34\n 56", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    /// A layout with tabs four spaces wide
+    struct TabLayout;
+
+    impl LineLayout for TabLayout {
+        fn col_x(&mut self, buffer: &Buffer, line: usize, col: usize) -> f64 {
+            let start = buffer.offset_of_line(line);
+            buffer
+                .slice_to_cow(start..start + col)
+                .chars()
+                .fold(0.0, |x, c| if c == '\t' { x + 4.0 } else { x + 1.0 })
+        }
+
+        fn x_col(&mut self, buffer: &Buffer, line: usize, x: f64) -> usize {
+            let end_col = buffer.line_end_col(line, true);
+            (0..=end_col)
+                .find(|col| self.col_x(buffer, line, *col) >= x)
+                .unwrap_or(end_col)
+        }
+
+        fn space_width(&mut self) -> f64 {
+            1.0
+        }
+    }
+
+    #[test]
+    fn test_paste_block_visual_column() {
+        let data = RegisterData {
+            content: "1\n2\n3\n".to_string(),
+            mode: VisualMode::Blockwise,
+        };
+
+        let mut buffer = Buffer::new("\tab\nabcdef\nab");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, &mut TabLayout);
+        assert_eq!(
+            "\ta1b\nabcde2f\nab   3",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_paste_block_multiple_cursor() {
+        let data = RegisterData {
+            content: "1\n2\n".to_string(),
+            mode: VisualMode::Blockwise,
+        };
+
+        let mut buffer = Buffer::new("ab\ncd");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 1, None));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, &mut TextColumns);
+        assert_eq!("1b\ncd2", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_align_cursors() {
+        let mut buffer = Buffer::new("a = 1\nbcd = 2\nef = 3\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(10));
+        selection.add_region(SelRegion::caret(17));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::align_cursors(&mut cursor, &mut buffer);
+        assert_eq!(
+            "a   = 1\nbcd = 2\nef  = 3\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut end_selection = Selection::new();
+        end_selection.add_region(SelRegion::caret(4));
+        end_selection.add_region(SelRegion::caret(12));
+        end_selection.add_region(SelRegion::caret(20));
+        assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
+    }
+
+    #[test]
+    fn test_insert_number_sequence() {
+        let mut buffer = Buffer::new("a\nb\nc\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert_number_sequence(&mut cursor, &mut buffer);
+        assert_eq!("a1\nb2\nc3\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("x9\ny\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 2, None));
+        selection.add_region(SelRegion::caret(4));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert_number_sequence(&mut cursor, &mut buffer);
+        assert_eq!("x9\ny10\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_sort_lines() {
+        let mut buffer = Buffer::new("c\na\nb\nz\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 5)), None, None);
+
+        run_edit(&mut cursor, &mut buffer, EditCommand::SortLines);
+        assert_eq!("a\nb\nc\nz\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Insert(Selection::region(0, 5)));
+    }
+
+    #[test]
+    fn test_unique_lines() {
+        let mut buffer = Buffer::new("a\nb\na\nb\nc");
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::region(0, buffer.len())),
+            None,
+            None,
+        );
+
+        run_edit(&mut cursor, &mut buffer, EditCommand::UniqueLines);
+        assert_eq!("a\nb\nc", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_rotate_selections() {
+        let mut buffer = Buffer::new("one two three");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(4, 7, None));
+        selection.add_region(SelRegion::new(8, 13, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::rotate_selections(&mut cursor, &mut buffer);
+        assert_eq!("three one two", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut end_selection = Selection::new();
+        end_selection.add_region(SelRegion::new(0, 5, None));
+        end_selection.add_region(SelRegion::new(6, 9, None));
+        end_selection.add_region(SelRegion::new(10, 13, None));
+        assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
+    }
+
     // TODO(dbuga): add tests duplicating selections (multiple line blocks)
 }
@@ -18,5 +18,6 @@
 pub mod register;
 pub mod selection;
 pub mod style;
+pub mod surround;
 pub mod syntax;
 pub mod word;
@@ -9,6 +9,7 @@ pub enum MotionMode {
     Yank,
     Indent,
     Outdent,
+    Surround,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Copy, Deserialize, Serialize)]
@@ -15,6 +15,8 @@ pub struct RegisterData {
 pub struct Register {
     pub unnamed: RegisterData,
     last_yank: RegisterData,
+    /// The data last put on the system clipboard, which only holds text
+    last_clipboard: RegisterData,
 }
 
 pub enum RegisterKind {
@@ -38,4 +40,22 @@ pub fn add_yank(&mut self, data: RegisterData) {
         self.unnamed = data.clone();
         self.last_yank = data;
     }
+
+    pub fn add_clipboard(&mut self, data: RegisterData) {
+        self.last_clipboard = data;
+    }
+
+    /// The mode to paste the clipboard content `s` with. A block copied from
+    /// the editor is still pasted as a block.
+    pub fn clipboard_mode(&self, s: &str) -> VisualMode {
+        if self.last_clipboard.mode == VisualMode::Blockwise
+            && self.last_clipboard.content == s
+        {
+            VisualMode::Blockwise
+        } else if s.ends_with('\n') {
+            VisualMode::Linewise
+        } else {
+            VisualMode::Normal
+        }
+    }
 }
@@ -1,9 +1,12 @@
-use std::cmp::{max, min, Ordering};
+use std::{
+    cmp::{max, min, Ordering},
+    ops::Range,
+};
 
-use lapce_xi_rope::{RopeDelta, Transformer};
+use lapce_xi_rope::{Rope, RopeDelta, Transformer};
 use serde::{Deserialize, Serialize};
 
-use crate::cursor::ColPosition;
+use crate::{buffer::rope_text::RopeText, cursor::ColPosition};
 
 /// Indicate whether a delta should be applied inside, outside non-caret selection or
 /// after a caret selection (see [`Selection::apply_delta`].
@@ -491,6 +494,109 @@ pub fn replace_last_inserted_region(&mut self, region: SelRegion) {
         self.add_region(region);
     }
 
+    /// Splits the regions spanning several lines into one region per line,
+    /// leaving out the line endings.
+    pub fn split_lines(&self, text: &Rope) -> Selection {
+        let text = RopeText::new(text);
+        let mut selection = Selection::new();
+        for region in &self.regions {
+            let start_line = text.line_of_offset(region.min());
+            let end_line = text.line_of_offset(region.max());
+            if start_line == end_line {
+                selection.add_region(*region);
+                continue;
+            }
+            for line in start_line..end_line + 1 {
+                let start = if line == start_line {
+                    region.min()
+                } else {
+                    text.offset_of_line(line)
+                };
+                let end = if line == end_line {
+                    region.max()
+                } else {
+                    text.line_end_offset(line, true)
+                };
+                // the region ends at the start of this line
+                if line == end_line && start == end {
+                    continue;
+                }
+                selection.add_region(SelRegion::new(start, end, None));
+            }
+        }
+        selection
+    }
+
+    /// A region for each of the `matches` which is inside one of the regions.
+    pub fn select_matches(
+        &self,
+        matches: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Selection {
+        let mut selection = Selection::new();
+        for (start, end) in matches {
+            if self
+                .regions
+                .iter()
+                .any(|region| region.min() <= start && end <= region.max())
+            {
+                selection.add_region(SelRegion::new(start, end, None));
+            }
+        }
+        selection
+    }
+
+    /// The padding to insert before the cursors so that they line up in the
+    /// same column, as offsets with the number of spaces to insert.
+    /// Only the first cursor on a line is aligned.
+    pub fn align_padding(&self, text: &Rope) -> Vec<(usize, usize)> {
+        let text = RopeText::new(text);
+        let mut cursors = Vec::new();
+        let mut last_line = None;
+        for region in &self.regions {
+            let offset = region.min();
+            let line = text.line_of_offset(offset);
+            if last_line == Some(line) {
+                continue;
+            }
+            last_line = Some(line);
+            let col = text
+                .slice_to_cow(text.offset_of_line(line)..offset)
+                .chars()
+                .count();
+            cursors.push((offset, col));
+        }
+
+        let max_col = cursors.iter().map(|(_, col)| *col).max().unwrap_or(0);
+        cursors
+            .into_iter()
+            .filter(|(_, col)| *col < max_col)
+            .map(|(offset, col)| (offset, max_col - col))
+            .collect()
+    }
+
+    /// The ranges of lines covered by the regions, where ranges next to each
+    /// other are merged. A region ending at the start of a line doesn't
+    /// cover that line.
+    pub fn line_ranges(&self, text: &Rope) -> Vec<Range<usize>> {
+        let text = RopeText::new(text);
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for region in &self.regions {
+            let start_line = text.line_of_offset(region.min());
+            let mut end_line = text.line_of_offset(region.max());
+            if end_line > start_line && text.offset_of_line(end_line) == region.max()
+            {
+                end_line -= 1;
+            }
+            match ranges.last_mut() {
+                Some(last) if start_line <= last.end => {
+                    last.end = last.end.max(end_line + 1);
+                }
+                _ => ranges.push(start_line..end_line + 1),
+            }
+        }
+        ranges
+    }
+
     fn search(&self, offset: usize) -> usize {
         if self.regions.is_empty() || offset > self.regions.last().unwrap().max() {
             return self.regions.len();
@@ -535,6 +641,8 @@ fn remove_n_at<T>(v: &mut Vec<T>, index: usize, n: usize) {
 
 #[cfg(test)]
 mod test {
+    use lapce_xi_rope::Rope;
+
     use crate::{
         buffer::Buffer,
         editor::EditType,
@@ -724,4 +832,55 @@ fn should_apply_delta_after_insertion() {
             Selection::caret(5)
         );
     }
+
+    #[test]
+    fn should_split_selection_into_lines() {
+        let text = Rope::from("abc\ndef\nghi\n");
+        let selection = Selection::region(1, 8);
+
+        assert_eq!(
+            selection.split_lines(&text).regions(),
+            vec![SelRegion::new(1, 3, None), SelRegion::new(4, 7, None)]
+        );
+
+        let selection = Selection::region(0, 4);
+        assert_eq!(
+            selection.split_lines(&text).regions(),
+            vec![SelRegion::new(0, 3, None)]
+        );
+    }
+
+    #[test]
+    fn should_select_matches_inside_regions() {
+        let selection = Selection::region(2, 10);
+
+        assert_eq!(
+            selection
+                .select_matches([(0, 3), (3, 5), (6, 10)])
+                .regions(),
+            vec![SelRegion::new(3, 5, None), SelRegion::new(6, 10, None)]
+        );
+    }
+
+    #[test]
+    fn should_return_align_padding() {
+        let text = Rope::from("a = 1\nbcd = 2\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(10));
+
+        assert_eq!(selection.align_padding(&text), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn should_return_merged_line_ranges() {
+        let text = Rope::from("a\nb\nc\nd\ne\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 2, None));
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::caret(8));
+
+        assert_eq!(selection.line_ranges(&text), vec![0..2, 4..5]);
+    }
 }
@@ -0,0 +1,329 @@
+use std::ops::Range;
+
+use lapce_xi_rope::{Cursor, Rope, RopeInfo};
+
+/// Pairs of brackets which can surround text
+const BRACKETS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+
+/// The delimiters around a piece of text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Surround {
+    /// A pair of brackets, `spaced` pads the text with a space on each side
+    Bracket {
+        open: char,
+        close: char,
+        spaced: bool,
+    },
+    /// The same character on both sides, like quotes
+    Char(char),
+    /// An HTML/XML tag, holding the content of the opening tag (`div class="a"`).
+    /// When looking for a surround, an empty tag matches any tag.
+    Tag(String),
+}
+
+impl Surround {
+    /// The surround typed as `c`, following vim-surround: an opening bracket
+    /// pads the text with spaces, a closing one doesn't, and `b`, `B`, `r`
+    /// and `a` are aliases for `)`, `}`, `]` and `>`.
+    pub fn from_char(c: char) -> Option<Self> {
+        let c = match c {
+            'b' => ')',
+            'B' => '}',
+            'r' => ']',
+            'a' => '>',
+            c => c,
+        };
+        for (open, close) in BRACKETS {
+            if c == open || c == close {
+                return Some(Surround::Bracket {
+                    open,
+                    close,
+                    spaced: c == open && c != '<',
+                });
+            }
+        }
+        if c.is_ascii_punctuation() {
+            Some(Surround::Char(c))
+        } else {
+            None
+        }
+    }
+
+    /// The existing surround to look for when `c` is typed, where `t` means any tag
+    pub fn target_from_char(c: char) -> Option<Self> {
+        if c == 't' {
+            return Some(Surround::Tag(String::new()));
+        }
+        Self::from_char(c)
+    }
+
+    /// Whether typing `c` when adding a surround should ask for a tag
+    pub fn is_tag_char(c: char) -> bool {
+        c == 't' || c == '<'
+    }
+
+    pub fn open(&self) -> String {
+        match self {
+            Surround::Bracket { open, spaced, .. } => {
+                if *spaced {
+                    format!("{open} ")
+                } else {
+                    open.to_string()
+                }
+            }
+            Surround::Char(c) => c.to_string(),
+            Surround::Tag(tag) => format!("<{tag}>"),
+        }
+    }
+
+    pub fn close(&self) -> String {
+        match self {
+            Surround::Bracket { close, spaced, .. } => {
+                if *spaced {
+                    format!(" {close}")
+                } else {
+                    close.to_string()
+                }
+            }
+            Surround::Char(c) => c.to_string(),
+            Surround::Tag(tag) => {
+                format!("</{}>", tag.split_whitespace().next().unwrap_or(""))
+            }
+        }
+    }
+
+    /// Finds the innermost pair of this kind around `offset`, returning the
+    /// range of the opening and of the closing delimiter.
+    /// A spaced bracket also takes the whitespace inside the pair.
+    pub fn find(
+        &self,
+        text: &Rope,
+        offset: usize,
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        match self {
+            Surround::Bracket {
+                open,
+                close,
+                spaced,
+            } => {
+                let (open, close) = find_bracket(text, offset, *open, *close)?;
+                if *spaced {
+                    let inner = text.slice_to_cow(open.end..close.start);
+                    let leading =
+                        inner.len() - inner.trim_start_matches([' ', '\t']).len();
+                    let trailing =
+                        inner.len() - inner.trim_end_matches([' ', '\t']).len();
+                    if leading == inner.len() {
+                        return Some((open.start..close.start, close));
+                    }
+                    Some((
+                        open.start..open.end + leading,
+                        close.start - trailing..close.end,
+                    ))
+                } else {
+                    Some((open, close))
+                }
+            }
+            Surround::Char(c) => find_char(text, offset, *c),
+            Surround::Tag(tag) => find_tag(text, offset, tag),
+        }
+    }
+}
+
+fn find_bracket(
+    text: &Rope,
+    offset: usize,
+    open: char,
+    close: char,
+) -> Option<(Range<usize>, Range<usize>)> {
+    let at_offset = Cursor::new(text, offset).peek_next_codepoint();
+    let open_offset = if at_offset == Some(open) {
+        offset
+    } else {
+        let mut cursor = Cursor::<RopeInfo>::new(text, offset);
+        let mut depth = 0;
+        loop {
+            let c = cursor.prev_codepoint()?;
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                if depth == 0 {
+                    break cursor.pos();
+                }
+                depth -= 1;
+            }
+        }
+    };
+
+    let mut cursor = Cursor::<RopeInfo>::new(text, open_offset + open.len_utf8());
+    let mut depth = 0;
+    let close_offset = loop {
+        let c = cursor.next_codepoint()?;
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                break cursor.pos() - close.len_utf8();
+            }
+            depth -= 1;
+        }
+    };
+
+    Some((
+        open_offset..open_offset + open.len_utf8(),
+        close_offset..close_offset + close.len_utf8(),
+    ))
+}
+
+/// Quotes don't nest, so they are paired up from the start of the line
+fn find_char(
+    text: &Rope,
+    offset: usize,
+    c: char,
+) -> Option<(Range<usize>, Range<usize>)> {
+    let line = text.line_of_offset(offset);
+    let line_start = text.offset_of_line(line);
+    let line_end = text.offset_of_line(line + 1);
+    let positions = text
+        .slice_to_cow(line_start..line_end)
+        .char_indices()
+        .filter(|(_, ch)| *ch == c)
+        .map(|(i, _)| line_start + i)
+        .collect::<Vec<_>>();
+    positions
+        .chunks_exact(2)
+        .find(|pair| pair[0] <= offset && offset <= pair[1])
+        .map(|pair| {
+            (
+                pair[0]..pair[0] + c.len_utf8(),
+                pair[1]..pair[1] + c.len_utf8(),
+            )
+        })
+}
+
+fn find_tag(
+    text: &Rope,
+    offset: usize,
+    name: &str,
+) -> Option<(Range<usize>, Range<usize>)> {
+    let content = text.slice_to_cow(0..text.len());
+    let mut stack: Vec<(&str, Range<usize>)> = Vec::new();
+    let mut best: Option<(Range<usize>, Range<usize>)> = None;
+
+    let mut start = 0;
+    while let Some(i) = content[start..].find('<') {
+        let tag_start = start + i;
+        let tag_end = match content[tag_start..].find('>') {
+            Some(i) => tag_start + i + 1,
+            None => break,
+        };
+        start = tag_end;
+
+        let inner = &content[tag_start + 1..tag_end - 1];
+        if inner.starts_with(['!', '?']) || inner.ends_with('/') {
+            continue;
+        }
+        if let Some(closing) = inner.strip_prefix('/') {
+            let closing = closing.trim();
+            // unwind tags which were never closed
+            if let Some(pos) = stack.iter().rposition(|(n, _)| *n == closing) {
+                let (open_name, open) = stack[pos].clone();
+                stack.truncate(pos);
+                let close = tag_start..tag_end;
+                if open.start <= offset
+                    && offset < close.end
+                    && (name.is_empty() || open_name == name)
+                    && best.as_ref().map(|(o, _)| o.start < open.start)
+                        != Some(false)
+                {
+                    best = Some((open, close));
+                }
+            }
+        } else if let Some(tag_name) = inner.split_whitespace().next() {
+            stack.push((tag_name, tag_start..tag_end));
+        }
+        if tag_start > offset && stack.is_empty() {
+            break;
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_xi_rope::Rope;
+
+    use super::Surround;
+
+    #[test]
+    fn test_from_char() {
+        assert_eq!(
+            Surround::from_char('('),
+            Some(Surround::Bracket {
+                open: '(',
+                close: ')',
+                spaced: true
+            })
+        );
+        assert_eq!(
+            Surround::from_char('b'),
+            Some(Surround::Bracket {
+                open: '(',
+                close: ')',
+                spaced: false
+            })
+        );
+        assert_eq!(Surround::from_char('"'), Some(Surround::Char('"')));
+        assert_eq!(Surround::from_char('x'), None);
+        assert_eq!(
+            Surround::target_from_char('t'),
+            Some(Surround::Tag(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_open_close() {
+        let surround = Surround::from_char('{').unwrap();
+        assert_eq!(surround.open(), "{ ");
+        assert_eq!(surround.close(), " }");
+        let surround = Surround::Tag("div class=\"a\"".to_string());
+        assert_eq!(surround.open(), "<div class=\"a\">");
+        assert_eq!(surround.close(), "</div>");
+    }
+
+    #[test]
+    fn test_find_bracket() {
+        let text = Rope::from("a (b [c] (d) e) f");
+        let surround = Surround::from_char(')').unwrap();
+        assert_eq!(surround.find(&text, 4), Some((2..3, 14..15)));
+        assert_eq!(surround.find(&text, 10), Some((9..10, 11..12)));
+        assert_eq!(surround.find(&text, 2), Some((2..3, 14..15)));
+        assert_eq!(surround.find(&text, 0), None);
+
+        let text = Rope::from("( a )");
+        let surround = Surround::from_char('(').unwrap();
+        assert_eq!(surround.find(&text, 2), Some((0..2, 3..5)));
+    }
+
+    #[test]
+    fn test_find_char() {
+        let text = Rope::from("let a = \"b\" + \"c\";\n\"d\"");
+        let surround = Surround::from_char('"').unwrap();
+        assert_eq!(surround.find(&text, 9), Some((8..9, 10..11)));
+        assert_eq!(surround.find(&text, 15), Some((14..15, 16..17)));
+        assert_eq!(surround.find(&text, 4), None);
+        assert_eq!(surround.find(&text, 20), Some((19..20, 21..22)));
+    }
+
+    #[test]
+    fn test_find_tag() {
+        let text = Rope::from("<div><p class=\"a\">b<br/></p><img></div>");
+        let surround = Surround::target_from_char('t').unwrap();
+        assert_eq!(surround.find(&text, 18), Some((5..18, 24..28)));
+        assert_eq!(surround.find(&text, 2), Some((0..5, 33..39)));
+        assert_eq!(surround.find(&text, 29), Some((0..5, 33..39)));
+        let surround = Surround::Tag("div".to_string());
+        assert_eq!(surround.find(&text, 18), Some((0..5, 33..39)));
+    }
+}
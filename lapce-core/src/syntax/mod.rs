@@ -770,6 +770,98 @@ fn find_tag_in_children(&self, node: Node, tag: &str) -> Option<usize> {
         None
     }
 
+    /// Find the ranges of all the identifiers in the document with the same
+    /// text as the identifier at `offset`
+    pub fn find_identifier_occurrences(
+        &self,
+        offset: usize,
+    ) -> Option<Vec<(usize, usize)>> {
+        let tree = self.layers.try_tree()?;
+        let is_identifier = |node: &Node| {
+            node.child_count() == 0 && node.kind().ends_with("identifier")
+        };
+        // The cursor can be right after the identifier as well
+        let node = [offset, offset.saturating_sub(1)]
+            .into_iter()
+            .find_map(|o| {
+                tree.root_node()
+                    .descendant_for_byte_range(o, o)
+                    .filter(is_identifier)
+            })?;
+        let text = self.text.slice_to_cow(node.start_byte()..node.end_byte());
+
+        let mut occurrences = Vec::new();
+        let mut cursor = tree.walk();
+        loop {
+            let node = cursor.node();
+            if is_identifier(&node)
+                && node.end_byte() - node.start_byte() == text.len()
+                && self.text.slice_to_cow(node.start_byte()..node.end_byte()) == text
+            {
+                occurrences.push((node.start_byte(), node.end_byte()));
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return Some(occurrences);
+                }
+            }
+        }
+    }
+
+    /// All the identifiers of the document, as `(start, end, kind)` where
+    /// `kind` is the kind of the identifier node, or the kind of its parent
+    /// when it names a function, like `function_item`.
+    pub fn identifiers(&self) -> Option<Vec<(usize, usize, &'static str)>> {
+        let tree = self.layers.try_tree()?;
+        let mut identifiers = Vec::new();
+        let mut cursor = tree.walk();
+        loop {
+            let node = cursor.node();
+            if node.child_count() == 0 && node.kind().ends_with("identifier") {
+                let kind = node
+                    .parent()
+                    .map(|parent| parent.kind())
+                    .filter(|kind| {
+                        kind.contains("function") || kind.contains("method")
+                    })
+                    .unwrap_or_else(|| node.kind());
+                identifiers.push((node.start_byte(), node.end_byte(), kind));
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return Some(identifiers);
+                }
+            }
+        }
+    }
+
+    /// Whether `offset` is within a string literal
+    pub fn in_string(&self, offset: usize) -> bool {
+        let tree = match self.layers.try_tree() {
+            Some(tree) => tree,
+            None => return false,
+        };
+        let mut node = tree
+            .root_node()
+            .descendant_for_byte_range(offset.saturating_sub(1), offset);
+        while let Some(n) = node {
+            if n.kind().contains("string")
+                && n.start_byte() < offset
+                && offset < n.end_byte()
+            {
+                return true;
+            }
+            node = n.parent();
+        }
+        false
+    }
+
     pub fn sticky_headers(&self, offset: usize) -> Option<Vec<usize>> {
         let tree = self.layers.try_tree()?;
         let mut node = tree.root_node().descendant_for_byte_range(offset, offset)?;
@@ -11,10 +11,12 @@
         EditCommand, FocusCommand, MotionModeCommand, MoveCommand,
         MultiSelectionCommand,
     },
+    language::LapceLanguage,
     syntax::Syntax,
 };
 use lapce_rpc::{
     buffer::BufferId,
+    core::DiagnosticSource,
     file::FileNodeItem,
     plugin::{PluginId, VoltInfo, VoltMetadata},
     source_control::DiffInfo,
@@ -23,10 +25,10 @@
 };
 use lapce_xi_rope::{spans::Spans, Rope};
 use lsp_types::{
-    CodeActionOrCommand, CodeActionResponse, CompletionItem, CompletionResponse,
-    InlayHint, Location, MessageType, Position, ProgressParams,
-    PublishDiagnosticsParams, SelectionRange, SignatureHelp, TextEdit,
-    WorkspaceEdit,
+    CodeActionOrCommand, CodeActionResponse, CodeLens, CompletionItem,
+    CompletionResponse, DocumentHighlight, InlayHint, Location, MessageType,
+    Position, ProgressParams, PublishDiagnosticsParams, SelectionRange,
+    SignatureHelp, TextEdit, TypeHierarchyItem, WorkspaceEdit,
 };
 use serde_json::Value;
 use strum::{self, EnumMessage, IntoEnumIterator};
@@ -34,12 +36,14 @@
 
 use crate::{
     alert::AlertContentData,
+    completion::ScoredCompletionItem,
     data::{
         EditorTabChild, LapceMainSplitData, LapceTabData, LapceWorkspace,
         SplitContent,
     },
-    document::BufferContent,
+    document::{BufferContent, DocumentCodeLens},
     editor::{EditorLocation, EditorPosition, Line, LineCol},
+    hot_exit::UnsavedBuffer,
     keypress::{KeyMap, KeyPress},
     menu::MenuKind,
     palette::{PaletteItem, PaletteType},
@@ -50,6 +54,8 @@
     selection_range::SelectionRangeDirection,
     settings::LapceSettingsKind,
     split::{SplitDirection, SplitMoveDirection},
+    task::TaskConfig,
+    undo,
     update::ReleaseInfo,
 };
 
@@ -114,8 +120,14 @@ pub fn is_palette_command(&self) -> bool {
                 | LapceWorkbenchCommand::ChangeFileLanguage
                 | LapceWorkbenchCommand::ChangeColorTheme
                 | LapceWorkbenchCommand::ChangeIconTheme
+                | LapceWorkbenchCommand::NewTerminalTabWithProfile
+                | LapceWorkbenchCommand::RunTask
+                | LapceWorkbenchCommand::InsertSnippet
+                | LapceWorkbenchCommand::GoToUndoState
+                | LapceWorkbenchCommand::ShowLocalHistory
                 | LapceWorkbenchCommand::ConnectSshHost
                 | LapceWorkbenchCommand::ConnectWsl
+                | LapceWorkbenchCommand::ConnectCommandRemote
                 | LapceWorkbenchCommand::PaletteWorkspace => return true,
                 _ => {}
             }
@@ -281,6 +293,44 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Create New Terminal Tab")]
     NewTerminalTab,
 
+    #[strum(serialize = "new_terminal_tab_with_profile")]
+    #[strum(message = "Create New Terminal Tab With Profile")]
+    NewTerminalTabWithProfile,
+
+    #[strum(serialize = "new_terminal_editor_tab")]
+    #[strum(message = "Create New Terminal in Editor Area")]
+    NewTerminalEditorTab,
+
+    #[strum(serialize = "move_terminal_to_editor")]
+    #[strum(message = "Move Terminal to Editor Area")]
+    MoveTerminalToEditor,
+
+    #[strum(serialize = "move_terminal_to_panel")]
+    #[strum(message = "Move Terminal to Panel")]
+    MoveTerminalToPanel,
+
+    #[strum(serialize = "run_task")]
+    #[strum(message = "Run Task")]
+    RunTask,
+
+    #[strum(serialize = "insert_snippet")]
+    #[strum(message = "Insert Snippet")]
+    InsertSnippet,
+
+    #[strum(serialize = "go_to_undo_state")]
+    #[strum(message = "Go to Earlier or Later State")]
+    GoToUndoState,
+
+    #[strum(serialize = "show_local_history")]
+    #[strum(message = "Show Local History")]
+    ShowLocalHistory,
+
+    /// Replaces the content of the file compared in the active editor with
+    /// the version it's compared to
+    #[strum(serialize = "restore_compared_version")]
+    #[strum(message = "Restore Compared Version")]
+    RestoreComparedVersion,
+
     #[strum(serialize = "close_terminal_tab")]
     #[strum(message = "Close Terminal Tab")]
     CloseTerminalTab,
@@ -325,6 +375,10 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Connect to WSL")]
     ConnectWsl,
 
+    #[strum(serialize = "connect_command_remote")]
+    #[strum(message = "Connect to Command Remote")]
+    ConnectCommandRemote,
+
     #[strum(serialize = "disconnect_remote")]
     #[strum(message = "Disconnect From Remote")]
     DisconnectRemote,
@@ -399,6 +453,10 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "toggle_problem_focus")]
     ToggleProblemFocus,
 
+    #[strum(message = "Toggle Undo Tree Focus")]
+    #[strum(serialize = "toggle_undo_tree_focus")]
+    ToggleUndoTreeFocus,
+
     #[strum(message = "Toggle Search Focus")]
     #[strum(serialize = "toggle_search_focus")]
     ToggleSearchFocus,
@@ -419,6 +477,9 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "toggle_problem_visual")]
     ToggleProblemVisual,
 
+    #[strum(serialize = "toggle_undo_tree_visual")]
+    ToggleUndoTreeVisual,
+
     #[strum(serialize = "toggle_search_visual")]
     ToggleSearchVisual,
 
@@ -577,6 +638,11 @@ pub enum LapceUICommand {
     CancelCompletion(usize),
     ResolveCompletion(BufferId, u64, usize, Box<CompletionItem>),
     UpdateCompletion(usize, String, CompletionResponse, PluginId),
+    /// The built-in completion items listed in the background
+    UpdateBuiltinCompletion {
+        request_id: usize,
+        items: Vec<ScoredCompletionItem>,
+    },
     UpdateSignature {
         request_id: usize,
         resp: SignatureHelp,
@@ -601,6 +667,24 @@ pub enum LapceUICommand {
         rev: u64,
         offset: usize,
     },
+    /// `None` when no language server could provide the highlights
+    UpdateDocumentHighlights {
+        path: PathBuf,
+        rev: u64,
+        offset: usize,
+        highlights: Option<Vec<DocumentHighlight>>,
+    },
+    UpdateCodeLens {
+        path: PathBuf,
+        rev: u64,
+        code_lens: Vec<DocumentCodeLens>,
+    },
+    UpdateResolvedCodeLens {
+        path: PathBuf,
+        rev: u64,
+        index: usize,
+        code_lens: CodeLens,
+    },
     CancelPalette,
     RunCommand(String, Vec<String>),
     RunCodeAction(CodeActionOrCommand, PluginId),
@@ -620,6 +704,11 @@ pub enum LapceUICommand {
     FocusEditor,
     RunPalette(Option<PaletteType>),
     RunPaletteReferences(Vec<EditorLocation<Position>>),
+    RunPaletteTypeHierarchy {
+        root: TypeHierarchyItem,
+        supertypes: Vec<TypeHierarchyItem>,
+        subtypes: Vec<TypeHierarchyItem>,
+    },
     InitPaletteInput(String),
     UpdatePaletteInput(String),
     UpdatePaletteItems(String, im::Vector<PaletteItem>),
@@ -696,7 +785,14 @@ pub enum LapceUICommand {
     },
     CenterOfWindow,
     UpdateLineChanges(BufferId),
-    PublishDiagnostics(PublishDiagnosticsParams),
+    PublishDiagnostics {
+        source: DiagnosticSource,
+        diagnostics: PublishDiagnosticsParams,
+    },
+    OnTypeFormattingTriggers {
+        plugin_id: PluginId,
+        characters: Vec<String>,
+    },
     WorkDoneProgress(ProgressParams),
     UpdateDiffInfo(DiffInfo),
     EnsureVisible((Rect, (f64, f64), Option<EnsureVisiblePosition>)),
@@ -712,6 +808,9 @@ pub enum LapceUICommand {
     HomeDir(PathBuf),
     WorkspaceFileChange,
     ProxyUpdateStatus(ProxyStatus),
+    /// The connection to the proxy was lost and made again, so it needs to
+    /// be brought up to date.
+    ProxyReconnected,
     CloseTerminal(TermId),
     OpenPluginInfo(VoltInfo),
     SplitTerminal(bool, WidgetId),
@@ -755,6 +854,12 @@ pub enum LapceUICommand {
         placeholder: String,
     },
     PaletteReferences(usize, Vec<Location>),
+    PaletteTypeHierarchy {
+        offset: usize,
+        root: TypeHierarchyItem,
+        supertypes: Vec<TypeHierarchyItem>,
+        subtypes: Vec<TypeHierarchyItem>,
+    },
     GotoLocation(Location),
     ActiveFileChanged {
         path: Option<PathBuf>,
@@ -806,6 +911,27 @@ pub enum LapceUICommand {
     CopyPath(PathBuf),
     CopyRelativePath(PathBuf),
     SetLanguage(String),
+    NewTerminalTabWithProfile(String),
+    RunTask(String),
+    /// Runs a task that isn't in `tasks.toml`, like a runnable of a
+    /// language server.
+    RunTaskConfig {
+        name: String,
+        task: TaskConfig,
+    },
+    /// Inserts the body of a snippet into the editor.
+    InsertSnippet(String),
+    /// Moves the editor to a node of the undo tree of its buffer.
+    UndoTo(usize),
+    /// Replaces the content of the buffer of the editor with one of its
+    /// versions, like those in the local history.
+    RestoreVersion(String),
+    /// Runs text from an editor in a terminal, chosen by the language of
+    /// the editor.
+    RunInTerminal {
+        text: String,
+        language: Option<LapceLanguage>,
+    },
     ApplySelectionRange {
         buffer_id: BufferId,
         rev: u64,
@@ -845,7 +971,7 @@ pub struct InitBufferContent<P: EditorPosition> {
     pub path: PathBuf,
     pub content: Rope,
     pub locations: Vec<(WidgetId, EditorLocation<P>)>,
-    pub edits: Option<Rope>,
+    pub unsaved: Option<UnsavedBuffer>,
     pub cb: Option<InitBufferContentCb>,
 }
 
@@ -854,9 +980,12 @@ pub fn execute(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
         let doc = data.main_split.open_docs.get_mut(&self.path).unwrap();
         let doc = Arc::make_mut(doc);
         doc.init_content(self.content.to_owned());
-
-        if let Some(rope) = &self.edits {
-            doc.reload(rope.clone(), false);
+        if let Some(unsaved) = &self.unsaved {
+            unsaved.restore(doc);
+        } else if data.config.editor.persistent_undo {
+            if let Some(history) = undo::load(&self.path, &self.content) {
+                doc.restore_undo_history(history);
+            }
         }
         if let BufferContent::File(path) = doc.content() {
             if let Some(d) = data.main_split.diagnostics.get(path) {
@@ -872,6 +1001,12 @@ pub fn execute(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
                 location.clone(),
                 &data.config,
             );
+            if let Some(unsaved) = &self.unsaved {
+                let doc = data.main_split.open_docs.get(&self.path).unwrap();
+                if let Some(editor) = data.main_split.editors.get_mut(view_id) {
+                    unsaved.restore_cursor(Arc::make_mut(editor), doc, &data.config);
+                }
+            }
         }
 
         // We've loaded the buffer and added it to the view, so inform the caller about it
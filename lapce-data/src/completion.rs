@@ -1,14 +1,29 @@
-use std::{fmt::Display, path::PathBuf, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Error;
 use core::fmt;
 use druid::{EventCtx, Size, WidgetId};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use indexmap::IndexMap;
+use itertools::Itertools;
 use lapce_core::command::FocusCommand;
-use lapce_rpc::{buffer::BufferId, plugin::PluginId};
-use lsp_types::{CompletionItem, CompletionResponse, Position};
+use lapce_core::syntax::Syntax;
+use lapce_rpc::{buffer::BufferId, file::FileNodeItem, plugin::PluginId};
+use lapce_xi_rope::Rope;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionResponse, CompletionTextEdit,
+    Position, Range, TextEdit,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::{config::LapceConfig, list::ListData, proxy::LapceProxy};
 
@@ -36,6 +51,12 @@ fn extract_elements(
             } else if let Some((ele, end)) = Self::extract_placeholder(s, pos) {
                 elements.push(ele);
                 pos = end;
+            } else if let Some((ele, end)) = Self::extract_choice(s, pos) {
+                elements.push(ele);
+                pos = end;
+            } else if let Some((ele, end)) = Self::extract_variable(s, pos) {
+                elements.push(ele);
+                pos = end;
             } else if let Some((ele, end)) =
                 Self::extract_text(s, pos, escs, loose_escs)
             {
@@ -122,6 +143,79 @@ fn extract_placeholder(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
         Some((SnippetElement::PlaceHolder(tab, els), pos + 1))
     }
 
+    #[inline]
+    fn extract_choice(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
+        // Regex for `${num|one,two|}` pattern, where the choices are separated by
+        // commas and `,`, `|` and `\` can be escaped (for example `${1|one,two|}`)
+        static REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"^\$\{(\d+)\|((?:[^|\\]|\\.)*)\|\}"#).unwrap()
+        });
+
+        let caps = REGEX.captures(&s[pos..])?;
+
+        let tab = caps.get(1)?.as_str().parse::<usize>().ok()?;
+
+        let mut choices = vec![String::new()];
+        let mut chars = caps.get(2)?.as_str().chars();
+        while let Some(char) = chars.next() {
+            let choice = choices.last_mut().unwrap();
+            match char {
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        if !matches!(next, ',' | '|' | '\\') {
+                            choice.push(char);
+                        }
+                        choice.push(next);
+                    }
+                }
+                ',' => choices.push(String::new()),
+                _ => choice.push(char),
+            }
+        }
+
+        Some((
+            SnippetElement::Choice(tab, choices),
+            pos + caps.get(0).unwrap().end(),
+        ))
+    }
+
+    #[inline]
+    fn extract_variable(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
+        // Regex for `$name` and `${name}` patterns (for example `$TM_FILENAME`)
+        static REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(
+                r#"^\$(?:([A-Za-z_][A-Za-z0-9_]*)|\{([A-Za-z_][A-Za-z0-9_]*)\})"#,
+            )
+            .unwrap()
+        });
+        // Regex for the start of `${name:default}` pattern, where default can be
+        // empty (for example `${TM_SELECTED_TEXT:text}`)
+        static REGEX_DEFAULT: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r#"^\$\{([A-Za-z_][A-Za-z0-9_]*):"#).unwrap());
+
+        let str = &s[pos..];
+        if let Some(caps) = REGEX.captures(str) {
+            let name = caps.get(1).or_else(|| caps.get(2))?.as_str();
+            return Some((
+                SnippetElement::Variable(name.to_string(), Vec::new()),
+                pos + caps.get(0).unwrap().end(),
+            ));
+        }
+
+        let caps = REGEX_DEFAULT.captures(str)?;
+        let name = caps.get(1)?.as_str().to_string();
+        let (els, end) = Self::extract_elements(
+            s,
+            pos + caps.get(0).unwrap().end(),
+            &['$', '}', '\\'],
+            &[],
+        );
+        if !s[end..].starts_with('}') {
+            return None;
+        }
+        Some((SnippetElement::Variable(name, els), end + 1))
+    }
+
     #[inline]
     fn extract_text(
         s: &str,
@@ -172,6 +266,65 @@ fn write_text_to<Buffer: fmt::Write>(&self, buf: &mut Buffer) -> fmt::Result {
         fmt::Result::Ok(())
     }
 
+    /// Replaces the variables of the snippet with their values from
+    /// `resolve`, or with their defaults when they have no value. Unknown
+    /// variables without a default are replaced with their names.
+    pub fn resolve_variables(&mut self, resolve: &dyn Fn(&str) -> Option<String>) {
+        let elements = std::mem::take(&mut self.elements);
+        self.elements = Self::resolve_elements(elements, resolve);
+    }
+
+    fn resolve_elements(
+        elements: Vec<SnippetElement>,
+        resolve: &dyn Fn(&str) -> Option<String>,
+    ) -> Vec<SnippetElement> {
+        let mut resolved = Vec::new();
+        for el in elements {
+            match el {
+                SnippetElement::PlaceHolder(tab, els) => {
+                    resolved.push(SnippetElement::PlaceHolder(
+                        tab,
+                        Self::resolve_elements(els, resolve),
+                    ))
+                }
+                SnippetElement::Variable(name, default) => match resolve(&name) {
+                    Some(value) if !value.is_empty() => {
+                        resolved.push(SnippetElement::Text(value))
+                    }
+                    None if default.is_empty() => {
+                        resolved.push(SnippetElement::Text(name))
+                    }
+                    _ => resolved.extend(Self::resolve_elements(default, resolve)),
+                },
+                el => resolved.push(el),
+            }
+        }
+        resolved
+    }
+
+    /// The choices of the placeholders that have them, by tab.
+    pub fn choices(&self) -> Vec<(usize, Vec<String>)> {
+        fn collect(
+            elements: &[SnippetElement],
+            choices: &mut Vec<(usize, Vec<String>)>,
+        ) {
+            for el in elements {
+                match el {
+                    SnippetElement::Choice(tab, options) => {
+                        choices.push((*tab, options.clone()))
+                    }
+                    SnippetElement::PlaceHolder(_, els)
+                    | SnippetElement::Variable(_, els) => collect(els, choices),
+                    SnippetElement::Text(_) | SnippetElement::Tabstop(_) => {}
+                }
+            }
+        }
+
+        let mut choices = Vec::new();
+        collect(&self.elements, &mut choices);
+        choices
+    }
+
     #[inline]
     pub fn tabs(&self, pos: usize) -> Vec<(usize, (usize, usize))> {
         Self::elements_tabs(&self.elements, pos)
@@ -198,6 +351,15 @@ pub fn elements_tabs(
                 SnippetElement::Tabstop(tab) => {
                     tabs.push((*tab, (pos, pos)));
                 }
+                SnippetElement::Choice(tab, _) => {
+                    let end = pos + el.len();
+                    tabs.push((*tab, (pos, end)));
+                    pos = end;
+                }
+                SnippetElement::Variable(_, els) => {
+                    tabs.extend_from_slice(&Self::elements_tabs(els, pos));
+                    pos += el.len();
+                }
             }
         }
         tabs
@@ -228,16 +390,24 @@ pub enum SnippetElement {
     Text(String),
     PlaceHolder(usize, Vec<SnippetElement>),
     Tabstop(usize),
+    /// A placeholder whose text is one of the choices, the first by default
+    Choice(usize, Vec<String>),
+    /// A variable with the elements used when it has no value
+    Variable(String, Vec<SnippetElement>),
 }
 
 impl SnippetElement {
     pub fn len(&self) -> usize {
         match &self {
             SnippetElement::Text(text) => text.len(),
-            SnippetElement::PlaceHolder(_, elements) => {
+            SnippetElement::PlaceHolder(_, elements)
+            | SnippetElement::Variable(_, elements) => {
                 elements.iter().map(|e| e.len()).sum()
             }
             SnippetElement::Tabstop(_) => 0,
+            SnippetElement::Choice(_, choices) => {
+                choices.first().map(|c| c.len()).unwrap_or(0)
+            }
         }
     }
 
@@ -257,7 +427,8 @@ pub fn text(&self) -> String {
     fn write_text_to<Buffer: fmt::Write>(&self, buf: &mut Buffer) -> fmt::Result {
         match self {
             SnippetElement::Text(text) => buf.write_str(text),
-            SnippetElement::PlaceHolder(_, elements) => {
+            SnippetElement::PlaceHolder(_, elements)
+            | SnippetElement::Variable(_, elements) => {
                 for child_snippet_elm in elements {
                     // call ourselves recursively
                     child_snippet_elm.write_text_to(buf)?;
@@ -265,6 +436,9 @@ fn write_text_to<Buffer: fmt::Write>(&self, buf: &mut Buffer) -> fmt::Result {
                 fmt::Result::Ok(())
             }
             SnippetElement::Tabstop(_) => fmt::Result::Ok(()),
+            SnippetElement::Choice(_, choices) => {
+                buf.write_str(choices.first().map(|c| c.as_str()).unwrap_or(""))
+            }
         }
     }
 }
@@ -283,10 +457,40 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 f.write_str("}")
             }
             SnippetElement::Tabstop(tab) => write!(f, "${}", tab),
+            SnippetElement::Choice(tab, choices) => {
+                // Trying to write to the provided buffer in the form "${tab|one,two|}"
+                write!(f, "${{{}|", tab)?;
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    for char in choice.chars() {
+                        if matches!(char, ',' | '|' | '\\') {
+                            f.write_str("\\")?;
+                        }
+                        write!(f, "{}", char)?;
+                    }
+                }
+                f.write_str("|}")
+            }
+            SnippetElement::Variable(name, elements) => {
+                if elements.is_empty() {
+                    return write!(f, "${{{}}}", name);
+                }
+                write!(f, "${{{}:", name)?;
+                for child_snippet_elm in elements {
+                    fmt::Display::fmt(child_snippet_elm, f)?;
+                }
+                f.write_str("}")
+            }
         }
     }
 }
 
+/// The plugin id of completion items that don't come from a plugin, like
+/// user snippets or the words of the open documents.
+pub const BUILTIN_PLUGIN_ID: PluginId = PluginId(0);
+
 #[derive(Clone, PartialEq, Eq)]
 pub enum CompletionStatus {
     Inactive,
@@ -304,19 +508,24 @@ pub struct CompletionData {
     pub buffer_id: BufferId,
     pub input: String,
     pub input_items: im::HashMap<String, im::Vector<ScoredCompletionItem>>,
+    /// The items of the built-in providers and the user snippets, listed
+    /// along with the items of each request.
+    pub builtin_items: im::Vector<ScoredCompletionItem>,
     empty: im::Vector<ScoredCompletionItem>,
     pub completion_list: ListData<ScoredCompletionItem, ()>,
     pub matcher: Arc<SkimMatcherV2>,
+    /// The items accepted in the workspace, which rank higher
+    pub history: Arc<CompletionHistory>,
     /// The size of the documentation view
     pub documentation_size: Size,
 }
 
 impl CompletionData {
-    pub fn new(config: Arc<LapceConfig>) -> Self {
+    pub fn new(config: Arc<LapceConfig>, history: CompletionHistory) -> Self {
         let id = WidgetId::next();
-        let mut completion_list = ListData::new(config, id, ());
-        // TODO: Make this configurable
-        completion_list.max_displayed_items = 15;
+        let mut completion_list = ListData::new(config.clone(), id, ());
+        completion_list.max_displayed_items =
+            config.editor.completion_max_displayed_items.max(1);
         Self {
             id,
             scroll_id: WidgetId::next(),
@@ -327,8 +536,10 @@ pub fn new(config: Arc<LapceConfig>) -> Self {
             buffer_id: BufferId(0),
             input: "".to_string(),
             input_items: im::HashMap::new(),
+            builtin_items: im::Vector::new(),
             completion_list,
             matcher: Arc::new(SkimMatcherV2::default().ignore_case()),
+            history: Arc::new(history),
             // TODO: Make this configurable
             documentation_size: Size::new(400.0, 300.0),
             empty: im::Vector::new(),
@@ -376,7 +587,8 @@ pub fn request(
         input: String,
         position: Position,
     ) {
-        self.input_items.insert(input.clone(), im::Vector::new());
+        self.input_items
+            .insert(input.clone(), self.builtin_items.clone());
         proxy
             .proxy_rpc
             .completion(self.request_id, path, input, position);
@@ -389,9 +601,30 @@ pub fn cancel(&mut self) {
         self.status = CompletionStatus::Inactive;
         self.input = "".to_string();
         self.input_items.clear();
+        self.builtin_items.clear();
         self.completion_list.clear_items();
     }
 
+    /// Lists `items` that don't come from a request to the plugins, like the
+    /// choices of a snippet placeholder, for the input starting at `offset`.
+    pub fn show_items(
+        &mut self,
+        buffer_id: BufferId,
+        offset: usize,
+        items: im::Vector<ScoredCompletionItem>,
+    ) {
+        self.request_id += 1;
+        self.status = CompletionStatus::Started;
+        self.buffer_id = buffer_id;
+        self.offset = offset;
+        self.input = "".to_string();
+        self.input_items.clear();
+        self.input_items.insert("".to_string(), items);
+        self.builtin_items.clear();
+        self.completion_list.selected_index = 0;
+        self.filter_items();
+    }
+
     pub fn update_input(&mut self, input: String) {
         self.input = input;
         self.completion_list.selected_index = 0;
@@ -401,6 +634,37 @@ pub fn update_input(&mut self, input: String) {
         self.filter_items();
     }
 
+    /// Adds the built-in items listed in the background for the request
+    /// `request_id` to the items of every input, leaving out the ones a
+    /// plugin already completes to.
+    pub fn receive_builtin(
+        &mut self,
+        request_id: usize,
+        items: Vec<ScoredCompletionItem>,
+    ) {
+        if self.status == CompletionStatus::Inactive || self.request_id != request_id
+        {
+            return;
+        }
+
+        for input_items in self.input_items.values_mut() {
+            let labels: HashSet<String> =
+                input_items.iter().map(|i| i.item.label.clone()).collect();
+            input_items.extend(
+                items
+                    .iter()
+                    .filter(|i| !labels.contains(&i.item.label))
+                    .cloned(),
+            );
+        }
+        self.builtin_items.extend(items);
+        self.filter_items();
+
+        if self.completion_list.selected_index >= self.len() {
+            self.completion_list.selected_index = 0;
+        }
+    }
+
     pub fn receive(
         &mut self,
         request_id: usize,
@@ -417,15 +681,24 @@ pub fn receive(
             CompletionResponse::Array(items) => items,
             CompletionResponse::List(list) => list.items,
         };
+        // The built-in items that the plugin already completes to are left
+        // out, as the items of the plugin know more about them.
+        let labels: HashSet<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        let builtin_items = self.builtin_items.iter().filter(|i| {
+            i.source == CompletionSource::Snippet
+                || !labels.contains(i.item.label.as_str())
+        });
         let items: im::Vector<ScoredCompletionItem> = items
             .iter()
             .map(|i| ScoredCompletionItem {
                 item: i.to_owned(),
                 plugin_id,
+                source: CompletionSource::Plugin,
                 score: 0,
                 label_score: 0,
                 indices: Vec::new(),
             })
+            .chain(builtin_items.cloned())
             .collect();
 
         self.input_items.insert(input, items);
@@ -437,50 +710,95 @@ pub fn receive(
     }
 
     pub fn filter_items(&mut self) {
-        if self.input.is_empty() {
-            self.completion_list.items = self.all_items().clone();
-            return;
-        }
+        let config = self.completion_list.config.clone();
+        let config = &config.editor;
+        self.completion_list.max_displayed_items =
+            config.completion_max_displayed_items.max(1);
+
+        let now = CompletionHistory::now();
+        // The fuzzy score of every item when there's no input, so that the
+        // weights of the sources still apply.
+        const EMPTY_INPUT_SCORE: i64 = 50;
+        const PRESELECT_SCORE: f64 = 20.0;
+        const FRECENCY_SCORE: f64 = 15.0;
+        let rank = |item: &ScoredCompletionItem, score: i64| -> i64 {
+            let weight = match item.source {
+                CompletionSource::Plugin => config.completion_plugin_weight,
+                CompletionSource::Snippet => config.completion_snippet_weight,
+                CompletionSource::SyntaxSymbol => {
+                    config.completion_syntax_symbol_weight
+                }
+                CompletionSource::BufferWord => config.completion_buffer_word_weight,
+                CompletionSource::Path => config.completion_path_weight,
+            };
+            let mut score = score as f64 * weight;
+            if item.item.preselect == Some(true) {
+                score += PRESELECT_SCORE;
+            }
+            score += FRECENCY_SCORE
+                * config.completion_frecency_weight
+                * self.history.frecency(&item.item.label, now).ln_1p();
+            score.round() as i64
+        };
 
-        let mut items: im::Vector<ScoredCompletionItem> = self
-            .all_items()
-            .iter()
-            .filter_map(|i| {
-                let filter_text =
-                    i.item.filter_text.as_ref().unwrap_or(&i.item.label);
-                let shift = i
-                    .item
-                    .label
-                    .match_indices(filter_text)
-                    .next()
-                    .map(|(shift, _)| shift)
-                    .unwrap_or(0);
-                if let Some((score, mut indices)) =
-                    self.matcher.fuzzy_indices(filter_text, &self.input)
-                {
-                    if shift > 0 {
-                        for idx in indices.iter_mut() {
-                            *idx += shift;
-                        }
-                    }
+        let mut items: im::Vector<ScoredCompletionItem> = if self.input.is_empty() {
+            self.all_items()
+                .iter()
+                .map(|i| {
                     let mut item = i.clone();
-                    item.score = score;
-                    item.label_score = score;
-                    item.indices = indices;
-                    if let Some(score) =
-                        self.matcher.fuzzy_match(&i.item.label, &self.input)
+                    item.score = rank(i, EMPTY_INPUT_SCORE);
+                    item.label_score = 0;
+                    item.indices = Vec::new();
+                    item
+                })
+                .collect()
+        } else {
+            self.all_items()
+                .iter()
+                .filter_map(|i| {
+                    let filter_text =
+                        i.item.filter_text.as_ref().unwrap_or(&i.item.label);
+                    let shift = i
+                        .item
+                        .label
+                        .match_indices(filter_text)
+                        .next()
+                        .map(|(shift, _)| shift)
+                        .unwrap_or(0);
+                    if let Some((score, mut indices)) =
+                        self.matcher.fuzzy_indices(filter_text, &self.input)
                     {
+                        if shift > 0 {
+                            for idx in indices.iter_mut() {
+                                *idx += shift;
+                            }
+                        }
+                        let mut item = i.clone();
+                        item.score = rank(i, score);
                         item.label_score = score;
+                        item.indices = indices;
+                        if let Some(score) =
+                            self.matcher.fuzzy_match(&i.item.label, &self.input)
+                        {
+                            item.label_score = score;
+                        }
+                        Some(item)
+                    } else {
+                        None
                     }
-                    Some(item)
-                } else {
-                    None
-                }
-            })
-            .collect();
+                })
+                .collect()
+        };
         items.sort_by(|a, b| {
+            let sort_text = |i: &ScoredCompletionItem| {
+                i.item
+                    .sort_text
+                    .clone()
+                    .unwrap_or_else(|| i.item.label.clone())
+            };
             b.score
                 .cmp(&a.score)
+                .then_with(|| sort_text(a).cmp(&sort_text(b)))
                 .then_with(|| b.label_score.cmp(&a.label_score))
                 .then_with(|| a.item.label.len().cmp(&b.item.label.len()))
         });
@@ -492,15 +810,249 @@ pub fn run_focus_command(&mut self, ctx: &mut EventCtx, command: &FocusCommand)
     }
 }
 
+/// Where a completion item comes from, which weights its rank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionSource {
+    Plugin,
+    Snippet,
+    SyntaxSymbol,
+    BufferWord,
+    Path,
+}
+
 #[derive(Clone, PartialEq)]
 pub struct ScoredCompletionItem {
     pub item: CompletionItem,
     pub plugin_id: PluginId,
+    pub source: CompletionSource,
+    /// The rank of the item, from its fuzzy score, its source and how often
+    /// it was accepted
     pub score: i64,
     pub label_score: i64,
     pub indices: Vec<usize>,
 }
 
+impl ScoredCompletionItem {
+    /// An item that doesn't come from a plugin.
+    pub fn builtin(source: CompletionSource, item: CompletionItem) -> Self {
+        Self {
+            item,
+            plugin_id: BUILTIN_PLUGIN_ID,
+            source,
+            score: 0,
+            label_score: 0,
+            indices: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct CompletionUse {
+    count: u32,
+    /// When the item was last accepted, in seconds since the Unix epoch
+    last_used: u64,
+}
+
+/// How many times and how recently the completion items were accepted in a
+/// workspace, keyed by their labels.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CompletionHistory {
+    uses: HashMap<String, CompletionUse>,
+}
+
+impl CompletionHistory {
+    const MAX_LEN: usize = 1000;
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    pub fn record(&mut self, label: &str) {
+        self.record_at(label, Self::now());
+    }
+
+    fn record_at(&mut self, label: &str, now: u64) {
+        let entry = self.uses.entry(label.to_string()).or_insert(CompletionUse {
+            count: 0,
+            last_used: now,
+        });
+        entry.count = entry.count.saturating_add(1);
+        entry.last_used = now;
+
+        if self.uses.len() > Self::MAX_LEN {
+            if let Some(oldest) = self
+                .uses
+                .iter()
+                .min_by_key(|(_, u)| u.last_used)
+                .map(|(label, _)| label.clone())
+            {
+                self.uses.remove(&oldest);
+            }
+        }
+    }
+
+    /// The number of times `label` was accepted, weighted by how recently.
+    pub fn frecency(&self, label: &str, now: u64) -> f64 {
+        let entry = match self.uses.get(label) {
+            Some(entry) => entry,
+            None => return 0.0,
+        };
+        let age = now.saturating_sub(entry.last_used);
+        let recency = if age < 60 * 60 {
+            4.0
+        } else if age < 24 * 60 * 60 {
+            2.0
+        } else if age < 7 * 24 * 60 * 60 {
+            1.0
+        } else {
+            0.5
+        };
+        entry.count as f64 * recency
+    }
+}
+
+/// The words of `text` to complete to, which are the identifiers of at
+/// least three characters, other than the word starting at `exclude`.
+pub fn buffer_words(text: &str, exclude: usize) -> impl Iterator<Item = &str> {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    text.char_indices()
+        .filter(move |(i, c)| {
+            is_word_char(*c)
+                && text[..*i]
+                    .chars()
+                    .next_back()
+                    .map_or(true, |c| !is_word_char(c))
+        })
+        .filter_map(move |(start, _)| {
+            let end = text[start..]
+                .find(|c| !is_word_char(c))
+                .map_or(text.len(), |len| start + len);
+            let word = &text[start..end];
+            (start != exclude
+                && word.chars().count() >= 3
+                && !word.starts_with(|c: char| c.is_numeric()))
+            .then_some(word)
+        })
+}
+
+/// The text of a document to take the words to complete to from
+pub struct WordSource {
+    pub text: Rope,
+    /// The offset of the word being typed, which is left out
+    pub exclude: usize,
+    /// The file name shown next to the words of another document
+    pub detail: Option<String>,
+}
+
+/// The words of the documents, each listed once.
+pub fn buffer_word_completion_items(
+    sources: &[WordSource],
+    labels: &mut HashSet<String>,
+) -> Vec<ScoredCompletionItem> {
+    const MAX_WORDS: usize = 10_000;
+
+    let mut items = Vec::new();
+    for source in sources {
+        let text = source.text.slice_to_cow(..);
+        for word in buffer_words(&text, source.exclude) {
+            if items.len() >= MAX_WORDS {
+                return items;
+            }
+            if labels.insert(word.to_string()) {
+                items.push(ScoredCompletionItem::builtin(
+                    CompletionSource::BufferWord,
+                    CompletionItem {
+                        label: word.to_string(),
+                        kind: Some(CompletionItemKind::TEXT),
+                        detail: source.detail.clone(),
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+    }
+    items
+}
+
+/// The identifiers of the syntax tree, other than the one starting at
+/// `offset`, which is being typed.
+pub fn syntax_completion_items(
+    syntax: &Syntax,
+    offset: usize,
+    labels: &mut HashSet<String>,
+) -> Vec<ScoredCompletionItem> {
+    let mut kinds = IndexMap::new();
+    for (start, end, kind) in syntax.identifiers().unwrap_or_default() {
+        if start == offset {
+            continue;
+        }
+        let kind = match kind {
+            "type_identifier" => CompletionItemKind::STRUCT,
+            "field_identifier" | "property_identifier" => CompletionItemKind::FIELD,
+            _ if kind.contains("function") || kind.contains("method") => {
+                CompletionItemKind::FUNCTION
+            }
+            _ => CompletionItemKind::VARIABLE,
+        };
+        let label = syntax.text.slice_to_cow(start..end).to_string();
+        let entry = kinds.entry(label).or_insert(kind);
+        // An identifier is a variable wherever it's used, so the kind
+        // from where it's declared wins.
+        if *entry == CompletionItemKind::VARIABLE {
+            *entry = kind;
+        }
+    }
+
+    kinds
+        .into_iter()
+        .filter(|(label, _)| labels.insert(label.clone()))
+        .map(|(label, kind)| {
+            ScoredCompletionItem::builtin(
+                CompletionSource::SyntaxSymbol,
+                CompletionItem {
+                    label,
+                    kind: Some(kind),
+                    ..Default::default()
+                },
+            )
+        })
+        .collect()
+}
+
+/// The entries of a directory as completion items that replace `range`,
+/// the file name being typed.
+pub fn path_completion_items(
+    entries: impl Iterator<Item = FileNodeItem>,
+    range: Range,
+) -> Vec<ScoredCompletionItem> {
+    entries
+        .filter_map(|entry| {
+            let name = entry.path_buf.file_name()?.to_str()?.to_string();
+            let is_dir = entry.is_dir;
+            let (kind, new_text) = if is_dir {
+                (CompletionItemKind::FOLDER, format!("{name}/"))
+            } else {
+                (CompletionItemKind::FILE, name.clone())
+            };
+            Some(ScoredCompletionItem::builtin(
+                CompletionSource::Path,
+                CompletionItem {
+                    label: name,
+                    kind: Some(kind),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                        range, new_text,
+                    ))),
+                    ..Default::default()
+                },
+            ))
+        })
+        .sorted_by(|a, b| a.item.label.cmp(&b.item.label))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -635,6 +1187,56 @@ fn test_snippet() {
         );
     }
 
+    #[test]
+    fn test_snippet_choices_and_variables() {
+        use SnippetElement::*;
+
+        let s =
+            "${1|one,t\\,wo|} $TM_FILENAME ${CLIPBOARD} ${UNKNOWN:${2:default}}$0";
+        let mut parsed = Snippet::from_str(s).unwrap();
+        assert_eq!(
+            Snippet {
+                elements: vec![
+                    Choice(1, vec!["one".into(), "t,wo".into()]),
+                    Text(" ".into()),
+                    Variable("TM_FILENAME".into(), vec![]),
+                    Text(" ".into()),
+                    Variable("CLIPBOARD".into(), vec![]),
+                    Text(" ".into()),
+                    Variable(
+                        "UNKNOWN".into(),
+                        vec![PlaceHolder(2, vec![Text("default".into())])]
+                    ),
+                    Tabstop(0),
+                ]
+            },
+            parsed
+        );
+        assert_eq!(
+            "${1|one,t\\,wo|} ${TM_FILENAME} ${CLIPBOARD} ${UNKNOWN:${2:default}}$0",
+            parsed.to_string()
+        );
+        assert_eq!(
+            vec![(1, vec!["one".into(), "t,wo".into()])],
+            parsed.choices()
+        );
+
+        parsed.resolve_variables(&|name| match name {
+            "TM_FILENAME" => Some("main.rs".to_string()),
+            "CLIPBOARD" => Some(String::new()),
+            _ => None,
+        });
+        assert_eq!("one main.rs  default", parsed.text());
+        assert_eq!(
+            vec![(1, (0, 3)), (2, (13, 20)), (0, (20, 20))],
+            parsed.tabs(0)
+        );
+
+        let mut parsed = Snippet::from_str("$NAME-${OTHER:other}").unwrap();
+        parsed.resolve_variables(&|_| None);
+        assert_eq!("NAME-other", parsed.text());
+    }
+
     #[test]
     fn test_extract_tabstop() {
         fn vec_of_tab_elms(s: &str) -> Vec<(usize, usize)> {
@@ -1077,4 +1679,35 @@ fn test_extract_text() {
             Snippet::extract_text(s, end + 1, &['$', '{', '}', '\\'], &[])
         );
     }
+
+    #[test]
+    fn test_buffer_words() {
+        let text = "let foo_bar = bar(42, 1st, ünïcode);\nfoo";
+        let words: Vec<&str> = buffer_words(text, 0).collect();
+        assert_eq!(vec!["foo_bar", "bar", "ünïcode", "foo"], words);
+
+        let exclude = text.rfind("foo").unwrap();
+        let words: Vec<&str> = buffer_words(text, exclude).collect();
+        assert_eq!(vec!["foo_bar", "bar", "ünïcode"], words);
+    }
+
+    #[test]
+    fn test_completion_history() {
+        let now = 100 * 24 * 60 * 60;
+        let mut history = CompletionHistory::default();
+        assert_eq!(0.0, history.frecency("foo", now));
+
+        history.record_at("foo", now - 30 * 24 * 60 * 60);
+        history.record_at("foo", now - 30 * 24 * 60 * 60);
+        history.record_at("bar", now - 60);
+        assert_eq!(1.0, history.frecency("foo", now));
+        assert_eq!(4.0, history.frecency("bar", now));
+
+        for i in 0..CompletionHistory::MAX_LEN - 1 {
+            history.record_at(&format!("item{i}"), now);
+        }
+        assert_eq!(CompletionHistory::MAX_LEN, history.uses.len());
+        assert_eq!(0.0, history.frecency("foo", now));
+        assert_eq!(4.0, history.frecency("bar", now));
+    }
 }
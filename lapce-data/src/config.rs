@@ -9,8 +9,9 @@
     Color, ExtEventSink, FontFamily, Size, Target,
 };
 use indexmap::IndexMap;
-use lapce_core::directory::Directory;
+use lapce_core::{directory::Directory, language::LapceLanguage};
 use lapce_proxy::plugin::wasi::find_all_volts;
+use lapce_rpc::terminal::TerminalProfile;
 use lsp_types::{CompletionItemKind, SymbolKind};
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
@@ -21,7 +22,8 @@
 
 use crate::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
-    data::{LapceWorkspace, LapceWorkspaceType},
+    data::{CommandRemoteHost, LapceWorkspace, LapceWorkspaceType},
+    snippet::UserSnippets,
     svg::SvgStore,
 };
 
@@ -87,6 +89,12 @@ impl LapceTheme {
         "editor.sticky_header_background";
     pub const EDITOR_DRAG_DROP_TAB_BACKGROUND: &str =
         "editor.drag_drop_tab_background";
+    pub const EDITOR_DOCUMENT_HIGHLIGHT_TEXT: &str =
+        "editor.document_highlight.text";
+    pub const EDITOR_DOCUMENT_HIGHLIGHT_READ: &str =
+        "editor.document_highlight.read";
+    pub const EDITOR_DOCUMENT_HIGHLIGHT_WRITE: &str =
+        "editor.document_highlight.write";
 
     pub const INLAY_HINT_FOREGROUND: &str = "inlay_hint.foreground";
     pub const INLAY_HINT_BACKGROUND: &str = "inlay_hint.background";
@@ -194,6 +202,7 @@ impl LapceIcons {
     pub const CLOSE: &str = "close";
     pub const REMOTE: &str = "remote";
     pub const PROBLEM: &str = "error";
+    pub const UNDO_TREE: &str = "undo_tree";
     pub const UNSAVED: &str = "unsaved";
     pub const WARNING: &str = "warning";
     pub const TERMINAL: &str = "terminal";
@@ -295,6 +304,9 @@ impl LapceIcons {
     pub const COMPLETION_ITEM_KIND_STRING: &str = "completion_item_kind.string";
     pub const COMPLETION_ITEM_KIND_STRUCT: &str = "completion_item_kind.struct";
     pub const COMPLETION_ITEM_KIND_VARIABLE: &str = "completion_item_kind.variable";
+    pub const COMPLETION_ITEM_KIND_TEXT: &str = "completion_item_kind.text";
+    pub const COMPLETION_ITEM_KIND_FILE: &str = "completion_item_kind.file";
+    pub const COMPLETION_ITEM_KIND_FOLDER: &str = "completion_item_kind.folder";
 }
 
 pub trait GetConfig {
@@ -314,6 +326,21 @@ pub struct CoreConfig {
         desc = "Enable customised titlebar and disable OS native one (Linux, BSD, Windows)"
     )]
     pub custom_titlebar: bool,
+    #[field_names(
+        desc = "Remotes reached by running commands through a wrapper like docker exec, with a command, an upload command and an optional local proxy path"
+    )]
+    #[serde(default)]
+    pub remotes: IndexMap<String, CommandRemoteHost>,
+    #[field_names(
+        desc = "Upload the proxy to remote hosts from this machine instead of downloading it on the remote, for hosts without internet access"
+    )]
+    #[serde(default)]
+    pub remote_proxy_upload: bool,
+    #[field_names(
+        desc = "The local proxy to upload to remote hosts of each platform, such as linux-x86_64. Otherwise the lapce-proxy next to Lapce is used for hosts of the same platform."
+    )]
+    #[serde(default)]
+    pub remote_proxy_paths: IndexMap<String, PathBuf>,
 }
 
 #[derive(FieldNames, Debug, Clone, Deserialize, Serialize, Default)]
@@ -349,6 +376,44 @@ pub struct EditorConfig {
         desc = "If the editor should show the documentation of the current completion item"
     )]
     pub completion_show_documentation: bool,
+    #[field_names(
+        desc = "If the words of the open documents are listed in the completion"
+    )]
+    pub completion_buffer_words: bool,
+    #[field_names(
+        desc = "If the files of a directory are listed in the completion when typing a path in a string"
+    )]
+    pub completion_paths: bool,
+    #[field_names(
+        desc = "If the identifiers of the syntax tree of the document are listed in the completion"
+    )]
+    pub completion_syntax_symbols: bool,
+    #[field_names(desc = "Set the maximum number of visible completion items")]
+    pub completion_max_displayed_items: usize,
+    #[field_names(
+        desc = "How much the matching score of completion items from language servers and plugins is weighted when ranking them"
+    )]
+    pub completion_plugin_weight: f64,
+    #[field_names(
+        desc = "How much the matching score of snippets is weighted when ranking completion items"
+    )]
+    pub completion_snippet_weight: f64,
+    #[field_names(
+        desc = "How much the matching score of identifiers of the syntax tree is weighted when ranking completion items"
+    )]
+    pub completion_syntax_symbol_weight: f64,
+    #[field_names(
+        desc = "How much the matching score of words of the open documents is weighted when ranking completion items"
+    )]
+    pub completion_buffer_word_weight: f64,
+    #[field_names(
+        desc = "How much the matching score of paths is weighted when ranking completion items"
+    )]
+    pub completion_path_weight: f64,
+    #[field_names(
+        desc = "How much the completion items accepted often and recently in the workspace are ranked higher. Set to 0 to ignore them."
+    )]
+    pub completion_frecency_weight: f64,
     #[field_names(
         desc = "If the editor should show the signature of the function as the parameters are being typed"
     )]
@@ -374,12 +439,32 @@ pub struct EditorConfig {
     )]
     pub format_on_save: bool,
 
+    #[field_names(
+        desc = "Whether formatting on save should only format the lines changed since the last git commit, instead of the whole document"
+    )]
+    pub format_modified_lines_on_save: bool,
+
+    #[field_names(
+        desc = "Whether it should format while typing the trigger characters declared by the language server"
+    )]
+    pub format_on_type: bool,
+
     #[field_names(desc = "If matching brackets are highlighted")]
     pub highlight_matching_brackets: bool,
 
     #[field_names(desc = "If scope lines are highlighted")]
     pub highlight_scope_lines: bool,
 
+    #[field_names(
+        desc = "If other occurrences of the symbol under the cursor are highlighted"
+    )]
+    pub highlight_symbol_occurrences: bool,
+
+    #[field_names(
+        desc = "How long (in ms) the cursor should rest on a symbol before its occurrences are highlighted"
+    )]
+    pub highlight_symbol_occurrences_delay: u64,
+
     #[field_names(desc = "If inlay hints should be displayed")]
     pub enable_inlay_hints: bool,
 
@@ -405,6 +490,10 @@ pub struct EditorConfig {
         desc = "Set the error lens font size. If 0 it uses the inlay hint font size."
     )]
     pub error_lens_font_size: usize,
+    #[field_names(
+        desc = "If code lenses from language servers should be displayed on a line above the code they belong to"
+    )]
+    pub enable_lsp_code_lens: bool,
     #[field_names(
         desc = "Set the cursor blink interval (in milliseconds). Set to 0 to completely disable."
     )]
@@ -435,6 +524,14 @@ pub struct EditorConfig {
         desc = "If enabled the cursor treats leading soft tabs as if they are hard tabs."
     )]
     pub atomic_soft_tabs: bool,
+    #[field_names(
+        desc = "Whether the undo history of a file is kept when it's closed, and restored when it's opened again unchanged."
+    )]
+    pub persistent_undo: bool,
+    #[field_names(
+        desc = "Set the maximum size (in MB) of the undo histories kept on disk. The least recently saved ones are removed first."
+    )]
+    pub persistent_undo_size: u64,
 }
 
 impl EditorConfig {
@@ -624,6 +721,57 @@ pub struct TerminalConfig {
     pub line_height: usize,
     #[field_names(desc = "Set the terminal Shell")]
     pub shell: String,
+    #[field_names(
+        desc = "The name of the terminal profile new terminals use. If empty, the terminal Shell is used."
+    )]
+    pub default_profile: String,
+    #[field_names(
+        desc = "Named terminal profiles with a program, arguments, environment variables, working directory, title and icon"
+    )]
+    #[serde(default)]
+    pub profiles: IndexMap<String, TerminalProfile>,
+    #[field_names(
+        desc = "The number of lines of scrollback restored in terminals after a restart. If 0, only the terminals are restored."
+    )]
+    pub restore_scrollback: usize,
+    #[field_names(
+        desc = "The terminal profile to run selections in for each language, such as python = \"ipython\". Other languages run in the active terminal."
+    )]
+    #[serde(default)]
+    pub language_profiles: IndexMap<String, String>,
+}
+
+impl TerminalConfig {
+    /// The profile a new terminal is launched with, which is the named
+    /// profile when given, then the default profile, and otherwise the
+    /// terminal Shell.
+    pub fn profile(&self, name: Option<&str>) -> TerminalProfile {
+        let default_profile =
+            Some(self.default_profile.as_str()).filter(|n| !n.is_empty());
+        if let Some(profile) = name
+            .or(default_profile)
+            .and_then(|name| self.profiles.get(name))
+        {
+            return profile.clone();
+        }
+
+        let mut parts = self.shell.split_whitespace();
+        TerminalProfile {
+            program: parts.next().unwrap_or("").to_string(),
+            arguments: parts.map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The name of the profile to run selections in `language` in, if one
+    /// is set for it.
+    pub fn language_profile(&self, language: LapceLanguage) -> Option<&str> {
+        self.language_profiles
+            .iter()
+            .find(|(name, _)| name.parse::<LapceLanguage>().ok() == Some(language))
+            .map(|(_, profile)| profile.as_str())
+            .filter(|profile| !profile.is_empty())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -868,6 +1016,8 @@ pub struct LapceConfig {
     pub available_icon_themes:
         HashMap<String, (String, config::Config, Option<PathBuf>)>,
     #[serde(skip)]
+    pub snippets: Arc<UserSnippets>,
+    #[serde(skip)]
     tab_layout_info: Arc<RwLock<HashMap<(FontFamily, usize), f64>>>,
     #[serde(skip)]
     svg_store: Arc<RwLock<SvgStore>>,
@@ -924,6 +1074,7 @@ pub fn load(workspace: &LapceWorkspace, disabled_volts: &[String]) -> Self {
         lapce_config.available_color_themes =
             Self::load_color_themes(disabled_volts);
         lapce_config.available_icon_themes = Self::load_icon_themes(disabled_volts);
+        lapce_config.snippets = Arc::new(UserSnippets::load(workspace));
         lapce_config.resolve_theme(workspace);
         lapce_config
     }
@@ -1032,6 +1183,7 @@ fn merge_config(
             }
             LapceWorkspaceType::RemoteSSH(_) => {}
             LapceWorkspaceType::RemoteWSL => {}
+            LapceWorkspaceType::RemoteCommand(_) => {}
         }
 
         config
@@ -1621,6 +1773,13 @@ pub fn ui_svg(&self, icon: &'static str) -> Svg {
         })
     }
 
+    pub fn terminal_profile_svg(&self, profile: &TerminalProfile) -> Option<Svg> {
+        profile
+            .icon
+            .as_ref()
+            .and_then(|path| self.svg_store.write().get_svg_on_disk(path))
+    }
+
     pub fn folder_svg(&self, path: &Path) -> Option<(Svg, Option<&Color>)> {
         self.icon_theme
             .foldername
@@ -1724,6 +1883,9 @@ pub fn completion_svg(
             }
             CompletionItemKind::SNIPPET => LapceIcons::COMPLETION_ITEM_KIND_SNIPPET,
             CompletionItemKind::MODULE => LapceIcons::COMPLETION_ITEM_KIND_MODULE,
+            CompletionItemKind::TEXT => LapceIcons::COMPLETION_ITEM_KIND_TEXT,
+            CompletionItemKind::FILE => LapceIcons::COMPLETION_ITEM_KIND_FILE,
+            CompletionItemKind::FOLDER => LapceIcons::COMPLETION_ITEM_KIND_FOLDER,
             _ => LapceIcons::COMPLETION_ITEM_KIND_STRING,
         };
         let theme_str = match kind {
@@ -9,11 +9,11 @@
     rc::Rc,
     sync::Arc,
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use druid::{
     piet::PietText, theme, Command, Data, Env, EventCtx, ExtEventSink,
     FileDialogOptions, Lens, Point, Rect, Size, Target, Vec2, WidgetId, WindowId,
@@ -25,24 +25,29 @@
     cursor::{Cursor, CursorMode},
     directory::Directory,
     editor::EditType,
+    language::LapceLanguage,
     meta,
     mode::{Mode, MotionMode},
     movement::Movement,
     register::Register,
     selection::Selection,
+    surround::Surround,
 };
 use lapce_rpc::{
     buffer::BufferId,
-    core::{CoreMessage, CoreNotification},
-    plugin::VoltInfo,
+    core::{CoreMessage, CoreNotification, DiagnosticSource},
+    plugin::{PluginId, VoltInfo},
     proxy::ProxyResponse,
     source_control::FileDiff,
-    terminal::TermId,
+    terminal::{TermId, TerminalProfile},
     RpcMessage,
 };
 use lapce_xi_rope::{Rope, RopeDelta};
-use lsp_types::{Diagnostic, DiagnosticSeverity, Position, ProgressToken, TextEdit};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, MessageType, Position, ProgressToken, TextEdit,
+};
 use notify::Watcher;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -61,9 +66,13 @@
         SplitInfo, TabsInfo, WindowInfo, WorkspaceInfo,
     },
     document::{BufferContent, Document, LocalBufferKind},
-    editor::{EditorLocation, EditorPosition, LapceEditorBufferData, Line, TabRect},
+    editor::{
+        EditorLocation, EditorPosition, EditorView, LapceEditorBufferData, Line,
+        TabRect,
+    },
     explorer::FileExplorerData,
     find::Find,
+    hot_exit::UnsavedBuffer,
     hover::HoverData,
     keypress::KeyPressData,
     palette::{PaletteData, PaletteType, PaletteViewData},
@@ -73,15 +82,20 @@
     picker::FilePickerData,
     plugin::PluginData,
     problem::ProblemData,
-    proxy::{LapceProxy, ProxyStatus, TermEvent},
+    proxy::{LapceProxy, ProxyInstall, ProxyStatus, TermEvent},
     rename::RenameData,
     search::SearchData,
     settings::LapceSettingsPanelData,
     signature::SignatureData,
     source_control::SourceControlData,
     split::{SplitDirection, SplitMoveDirection},
-    terminal::TerminalPanelData,
+    task::{TaskConfig, TasksConfig},
+    terminal::{
+        LapceTerminalData, RawTerminal, TerminalPanelData, TerminalSplitData,
+        TerminalTask,
+    },
     title::TitleData,
+    undo::UndoTreeData,
     update::ReleaseInfo,
 };
 
@@ -129,6 +143,7 @@ pub fn load(
         let keypress = Arc::new(KeyPressData::new(&config, event_sink.clone()));
         let panel_orders = db
             .get_panel_orders()
+            .map(Self::add_missing_panels)
             .unwrap_or_else(|_| Self::default_panel_orders());
         let latest_release = Arc::new(None);
 
@@ -289,10 +304,27 @@ pub fn default_panel_orders() -> PanelOrder {
             PanelPosition::BottomLeft,
             im::vector![PanelKind::Terminal, PanelKind::Search, PanelKind::Problem,],
         );
+        order.insert(PanelPosition::RightTop, im::vector![PanelKind::UndoTree]);
 
         order
     }
 
+    /// Panel orders saved by an older version don't know about the panels
+    /// added since, so those are put at their default positions.
+    fn add_missing_panels(mut order: PanelOrder) -> PanelOrder {
+        for (position, kinds) in Self::default_panel_orders() {
+            for kind in kinds {
+                if !order.values().any(|kinds| kinds.iter().any(|k| k == &kind)) {
+                    order
+                        .entry(position)
+                        .or_insert_with(im::Vector::new)
+                        .push_back(kind);
+                }
+            }
+        }
+        order
+    }
+
     pub fn reload_env(&self, env: &mut Env) {
         env.set(theme::SCROLLBAR_WIDTH, 10.0);
         env.set(theme::SCROLLBAR_EDGE_WIDTH, 0.0);
@@ -526,6 +558,9 @@ pub fn new(
         if let Some(path) = Directory::plugins_directory() {
             let _ = watcher.watch(&path, notify::RecursiveMode::Recursive);
         }
+        if let Some(path) = Directory::snippets_directory() {
+            let _ = watcher.watch(&path, notify::RecursiveMode::Recursive);
+        }
 
         Self {
             window_id,
@@ -624,6 +659,7 @@ pub struct LapceTabData {
     pub find: Arc<Find>,
     pub source_control: Arc<SourceControlData>,
     pub problem: Arc<ProblemData>,
+    pub undo_tree: Arc<UndoTreeData>,
     pub search: Arc<SearchData>,
     pub plugin: Arc<PluginData>,
     pub picker: Arc<FilePickerData>,
@@ -685,12 +721,24 @@ pub fn new(
         all_disabled_volts.extend_from_slice(&workspace_disabled_volts);
 
         let config = Arc::new(LapceConfig::load(&workspace, &all_disabled_volts));
+        let unsaved_buffers = match db.take_unsaved_buffers(&workspace, tab_id) {
+            Ok(val) => val,
+            Err(err) => {
+                log::warn!("Error during unsaved buffer fetching : {:}", err);
+                Vec::new()
+            }
+        };
         let workspace_info = if workspace.path.is_some() {
             db.get_workspace_info(&workspace).ok()
         } else {
             let mut info = db.get_workspace_info(&workspace).ok();
             if let Some(info) = info.as_mut() {
-                info.split.children.clear();
+                // The editors are only kept when they have unsaved changes
+                // to be restored in
+                if unsaved_buffers.is_empty() {
+                    info.split.children.clear();
+                }
+                info.terminal = None;
                 if let Some(panels) = current_panels.clone() {
                     info.panel = panels;
                 }
@@ -705,12 +753,20 @@ pub fn new(
             workspace.clone(),
             all_disabled_volts,
             config.plugins.clone(),
+            ProxyInstall {
+                upload: config.core.remote_proxy_upload,
+                paths: config.core.remote_proxy_paths.clone(),
+                remotes: config.core.remotes.clone(),
+            },
             term_sender.clone(),
             event_sink.clone(),
         ));
         let title = Arc::new(TitleData::new(config.clone()));
         let palette = Arc::new(PaletteData::new(config.clone(), proxy.clone()));
-        let completion = Arc::new(CompletionData::new(config.clone()));
+        let completion = Arc::new(CompletionData::new(
+            config.clone(),
+            db.get_completion_history(&workspace).unwrap_or_default(),
+        ));
         let hover = Arc::new(HoverData::new());
         let signature = Arc::new(SignatureData::new());
         let rename = Arc::new(RenameData::new());
@@ -733,14 +789,6 @@ pub fn new(
         let search = Arc::new(SearchData::new());
         let file_picker = Arc::new(FilePickerData::new());
 
-        let unsaved_buffers = match db.get_unsaved_buffers() {
-            Ok(val) => val,
-            Err(err) => {
-                log::warn!("Error during unsaved buffer fetching : {:}", err);
-                im::HashMap::new()
-            }
-        };
-
         let mut main_split = LapceMainSplitData::new(
             tab_id,
             workspace_info.as_ref(),
@@ -810,13 +858,30 @@ pub fn new(
             event_sink.clone(),
         );
 
-        let terminal = Arc::new(TerminalPanelData::new(
-            Arc::new(workspace.clone()),
-            proxy.clone(),
-            &config,
-            event_sink.clone(),
-        ));
+        let terminal = Arc::new(
+            workspace_info
+                .as_ref()
+                .and_then(|i| i.terminal.as_ref())
+                .filter(|i| i.tabs.iter().any(|t| !t.terminals.is_empty()))
+                .map(|i| {
+                    i.to_data(
+                        Arc::new(workspace.clone()),
+                        proxy.clone(),
+                        &config,
+                        event_sink.clone(),
+                    )
+                })
+                .unwrap_or_else(|| {
+                    TerminalPanelData::new(
+                        Arc::new(workspace.clone()),
+                        proxy.clone(),
+                        &config,
+                        event_sink.clone(),
+                    )
+                }),
+        );
         let problem = Arc::new(ProblemData::new());
+        let undo_tree = Arc::new(UndoTreeData::new());
         let panel = workspace_info
             .map(|i| {
                 let mut panel = i.panel;
@@ -843,6 +908,7 @@ pub fn new(
             terminal,
             plugin,
             problem,
+            undo_tree,
             search,
             find: Arc::new(Find::new(0)),
             picker: file_picker,
@@ -882,6 +948,9 @@ pub fn workspace_info(&self) -> WorkspaceInfo {
         WorkspaceInfo {
             split: main_split_data.split_info(self),
             panel: (*self.panel).clone(),
+            terminal: self
+                .terminal
+                .panel_info(self.config.terminal.restore_scrollback),
         }
     }
 
@@ -1590,6 +1659,9 @@ pub fn run_workbench_command(
             LapceWorkbenchCommand::ToggleProblemVisual => {
                 self.toggle_panel_visual(ctx, PanelKind::Problem);
             }
+            LapceWorkbenchCommand::ToggleUndoTreeVisual => {
+                self.toggle_panel_visual(ctx, PanelKind::UndoTree);
+            }
             LapceWorkbenchCommand::ToggleTerminalVisual => {
                 self.toggle_panel_visual(ctx, PanelKind::Terminal);
             }
@@ -1624,6 +1696,9 @@ pub fn run_workbench_command(
             LapceWorkbenchCommand::ToggleProblemFocus => {
                 self.toggle_panel_focus(ctx, PanelKind::Problem);
             }
+            LapceWorkbenchCommand::ToggleUndoTreeFocus => {
+                self.toggle_panel_focus(ctx, PanelKind::UndoTree);
+            }
             LapceWorkbenchCommand::ToggleTerminalFocus => {
                 self.toggle_panel_focus(ctx, PanelKind::Terminal);
             }
@@ -1790,6 +1865,13 @@ pub fn run_workbench_command(
                     Target::Widget(self.palette.widget_id),
                 ));
             }
+            LapceWorkbenchCommand::ConnectCommandRemote => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::CommandRemote)),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
             LapceWorkbenchCommand::ConnectWsl => ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
                 LapceUICommand::SetWorkspace(LapceWorkspace {
@@ -1857,11 +1939,113 @@ pub fn run_workbench_command(
                     self.proxy.clone(),
                     &self.config,
                     ctx.get_external_handle(),
+                    None,
                 );
                 if !self.panel.is_panel_visible(&PanelKind::Terminal) {
                     Arc::make_mut(&mut self.panel).show_panel(&PanelKind::Terminal);
                 }
             }
+            LapceWorkbenchCommand::NewTerminalTabWithProfile => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::TerminalProfile)),
+                    Target::Auto,
+                ))
+            }
+            LapceWorkbenchCommand::NewTerminalEditorTab => {
+                let terminal = LapceTerminalData::new(
+                    self.workspace.clone(),
+                    WidgetId::next(),
+                    ctx.get_external_handle(),
+                    self.proxy.clone(),
+                    &self.config,
+                    None,
+                    None,
+                );
+                self.main_split.open_terminal(ctx, terminal);
+            }
+            LapceWorkbenchCommand::MoveTerminalToEditor => {
+                if let Some(terminal) = self
+                    .focused_terminal()
+                    .filter(|t| t.editor_tab_id.is_none())
+                {
+                    let only_one = self
+                        .terminal
+                        .tabs
+                        .get(&terminal.split_id)
+                        .map_or(false, |split| split.terminals.len() == 1);
+                    if only_one {
+                        // The moved terminal keeps running, so only the tab
+                        // goes away and not the terminals in it.
+                        self.remove_terminal_tab(ctx, terminal.split_id);
+                    } else {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::SplitTerminalClose(
+                                terminal.term_id,
+                                terminal.widget_id,
+                            ),
+                            Target::Widget(terminal.split_id),
+                        ));
+                    }
+                    self.main_split.open_terminal(ctx, (*terminal).clone());
+                }
+            }
+            LapceWorkbenchCommand::MoveTerminalToPanel => {
+                let terminal = self
+                    .focused_terminal()
+                    .and_then(|t| self.main_split.take_terminal(ctx, &t.term_id));
+                if let Some(terminal) = terminal {
+                    let widget_id =
+                        Arc::make_mut(&mut self.terminal).add_terminal_tab(terminal);
+                    if !self.panel.is_panel_visible(&PanelKind::Terminal) {
+                        Arc::make_mut(&mut self.panel)
+                            .show_panel(&PanelKind::Terminal);
+                    }
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::Focus,
+                        Target::Widget(widget_id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::RunTask => ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::RunPalette(Some(PaletteType::Task)),
+                Target::Auto,
+            )),
+            LapceWorkbenchCommand::InsertSnippet => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::Snippet)),
+                    Target::Auto,
+                ))
+            }
+            LapceWorkbenchCommand::GoToUndoState => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::UndoState)),
+                    Target::Auto,
+                ))
+            }
+            LapceWorkbenchCommand::ShowLocalHistory => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::LocalHistory)),
+                    Target::Auto,
+                ))
+            }
+            LapceWorkbenchCommand::RestoreComparedVersion => {
+                if let Some(editor) = self.main_split.active_editor() {
+                    if let EditorView::Diff(version) = &editor.view {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RestoreVersion(version.clone()),
+                            Target::Widget(editor.view_id),
+                        ));
+                    }
+                }
+            }
             LapceWorkbenchCommand::NextTerminalTab => {
                 let terminal_panel = Arc::make_mut(&mut self.terminal);
                 if terminal_panel.active >= terminal_panel.tabs_order.len() - 1 {
@@ -1898,34 +2082,10 @@ pub fn run_workbench_command(
                         self.terminal.active_terminal_split().map(|s| s.split_id)
                     })
                     .unwrap_or_else(WidgetId::next);
-                let terminal_panel = Arc::make_mut(&mut self.terminal);
-                terminal_panel.tabs.remove(&split_id);
-                terminal_panel.tabs_order = Arc::new(
-                    terminal_panel
-                        .tabs_order
-                        .iter()
-                        .filter(|w| *w != &split_id)
-                        .copied()
-                        .collect(),
-                );
-                if terminal_panel.tabs_order.is_empty() {
-                    if self.panel.is_panel_visible(&PanelKind::Terminal) {
-                        Arc::make_mut(&mut self.panel)
-                            .hide_panel(&PanelKind::Terminal);
+                if let Some(split) = self.remove_terminal_tab(ctx, split_id) {
+                    for term_id in split.terminals.keys() {
+                        self.proxy.proxy_rpc.terminal_close(*term_id);
                     }
-                    if let Some(active) = *self.main_split.active_tab {
-                        ctx.submit_command(Command::new(
-                            LAPCE_UI_COMMAND,
-                            LapceUICommand::Focus,
-                            Target::Widget(active),
-                        ));
-                    }
-                } else {
-                    ctx.submit_command(Command::new(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::Focus,
-                        Target::Widget(terminal_panel.widget_id),
-                    ));
                 }
             }
             LapceWorkbenchCommand::ShowAbout => {
@@ -2033,15 +2193,318 @@ pub fn run_command(
         }
     }
 
+    /// Runs the task in a new terminal tab, after the tasks it depends on.
+    pub fn run_task(&mut self, event_sink: ExtEventSink, name: &str) {
+        let tasks = TasksConfig::load(&self.workspace);
+        match tasks.run_order(name) {
+            Ok(order) => self.run_task_queue(event_sink, &tasks, order),
+            Err(e) => log::error!("{e}"),
+        }
+    }
+
+    fn run_task_queue(
+        &mut self,
+        event_sink: ExtEventSink,
+        tasks: &TasksConfig,
+        mut queue: Vec<String>,
+    ) {
+        if queue.is_empty() {
+            return;
+        }
+        let name = queue.remove(0);
+        let profile = match tasks.terminal_profile(&name, &self.workspace) {
+            Some(profile) => profile,
+            None => return,
+        };
+        self.launch_task(event_sink, profile, name, queue);
+    }
+
+    /// Runs a task that isn't in `tasks.toml` in a new terminal tab
+    pub fn run_task_config(
+        &mut self,
+        event_sink: ExtEventSink,
+        name: &str,
+        task: &TaskConfig,
+    ) {
+        let profile = task.terminal_profile(name, &self.workspace);
+        self.launch_task(event_sink, profile, name.to_string(), Vec::new());
+    }
+
+    fn launch_task(
+        &mut self,
+        event_sink: ExtEventSink,
+        profile: TerminalProfile,
+        name: String,
+        queue: Vec<String>,
+    ) {
+        Arc::make_mut(&mut self.terminal).new_task_tab(
+            self.workspace.clone(),
+            self.proxy.clone(),
+            event_sink,
+            profile,
+            TerminalTask {
+                name,
+                queue,
+                finished: None,
+            },
+        );
+        if !self.panel.is_panel_visible(&PanelKind::Terminal) {
+            Arc::make_mut(&mut self.panel).show_panel(&PanelKind::Terminal);
+        }
+    }
+
+    /// Brings a proxy that was reconnected to up to date, as it has lost
+    /// the plugins, open buffers and terminals it had before.
+    pub fn resync_proxy(&self) {
+        self.proxy.proxy_rpc.initialize(
+            self.workspace.path.clone(),
+            self.plugin
+                .disabled
+                .iter()
+                .chain(self.plugin.workspace_disabled.iter())
+                .cloned()
+                .collect(),
+            self.config.plugins.clone(),
+            self.window_id.to_usize(),
+            self.id.to_usize(),
+        );
+
+        for doc in self.main_split.open_docs.values() {
+            if !doc.loaded() {
+                continue;
+            }
+            if let BufferContent::File(path) = doc.content() {
+                self.proxy.proxy_rpc.restore_buffer(
+                    doc.id(),
+                    path.clone(),
+                    doc.buffer().text().to_string(),
+                    doc.rev(),
+                );
+            }
+        }
+
+        for terminal in self
+            .terminal
+            .tabs
+            .values()
+            .chain(self.main_split.terminals.values().map(|s| s.as_ref()))
+            .flat_map(|split| split.terminals.values())
+            .filter(|t| t.task.is_none())
+        {
+            terminal.relaunch();
+        }
+    }
+
+    /// Runs text from an editor in the terminal for its language, which is
+    /// a terminal with the profile set for the language if there is one,
+    /// and otherwise the active terminal. A new terminal is opened if there
+    /// is no such terminal.
+    pub fn run_in_terminal(
+        &mut self,
+        event_sink: ExtEventSink,
+        text: &str,
+        language: Option<LapceLanguage>,
+    ) {
+        let profile = language
+            .and_then(|language| self.config.terminal.language_profile(language))
+            .map(|profile| profile.to_string());
+        let terminal = match profile.as_ref() {
+            Some(profile) => self
+                .terminal
+                .active_terminal()
+                .filter(|t| t.profile_name.as_ref() == Some(profile))
+                .or_else(|| {
+                    self.terminal
+                        .tabs
+                        .values()
+                        .chain(
+                            self.main_split.terminals.values().map(|s| s.as_ref()),
+                        )
+                        .flat_map(|split| split.terminals.values())
+                        .find(|t| {
+                            t.task.is_none()
+                                && t.profile_name.as_ref() == Some(profile)
+                        })
+                        .cloned()
+                }),
+            None => self.terminal.active_terminal(),
+        }
+        .filter(|t| t.task.is_none());
+
+        match terminal {
+            Some(terminal) => {
+                terminal.run_text(text);
+                if terminal.editor_tab_id.is_some() {
+                    return;
+                }
+            }
+            None => {
+                let terminal_panel = Arc::make_mut(&mut self.terminal);
+                terminal_panel.new_tab(
+                    self.workspace.clone(),
+                    self.proxy.clone(),
+                    &self.config,
+                    event_sink,
+                    profile,
+                );
+                if let Some(terminal) = terminal_panel.active_terminal() {
+                    terminal.raw.lock().pending_run = Some(text.to_string());
+                }
+            }
+        }
+        if !self.panel.is_panel_visible(&PanelKind::Terminal) {
+            Arc::make_mut(&mut self.panel).show_panel(&PanelKind::Terminal);
+        }
+    }
+
+    /// Called when the process of a terminal exits. If the terminal was
+    /// running a task, its output is matched for problems and the next task
+    /// in the queue is started. Returns whether the terminal should be kept
+    /// open so the output of the task can still be read.
+    pub fn finish_task(
+        &mut self,
+        event_sink: ExtEventSink,
+        term_id: &TermId,
+    ) -> bool {
+        let terminal = match self.get_terminal_mut(term_id) {
+            Some(terminal) => terminal,
+            None => return false,
+        };
+        let task = match terminal.task.as_mut() {
+            Some(task) if task.finished.is_none() => task,
+            _ => return false,
+        };
+
+        let (lines, exit_code) = {
+            let raw = terminal.raw.lock();
+            (raw.text_lines(), raw.shell_integration.last_exit_code)
+        };
+        task.finished = Some(exit_code);
+        let name = task.name.clone();
+        let queue = std::mem::take(&mut task.queue);
+        terminal.profile.title = Some(match exit_code {
+            Some(code) => format!("Task: {name} (exit code {code})"),
+            None => format!("Task: {name} (finished)"),
+        });
+        let cwd = terminal.profile.workdir.clone();
+
+        let tasks = TasksConfig::load(&self.workspace);
+        let problems =
+            tasks.match_problems(&name, &lines, cwd.as_deref(), &self.workspace);
+        self.main_split.set_task_diagnostics(&name, problems);
+
+        // The tasks depending on this one only run once it's known to have
+        // succeeded
+        if exit_code == Some(0) {
+            self.run_task_queue(event_sink, &tasks, queue);
+        }
+        true
+    }
+
+    /// The terminal split with the id, which is either a tab of the
+    /// terminal panel or a terminal in the editor area.
+    pub fn terminal_split(&self, split_id: &WidgetId) -> Option<&TerminalSplitData> {
+        self.terminal
+            .tabs
+            .get(split_id)
+            .or_else(|| self.main_split.terminals.get(split_id).map(|s| s.as_ref()))
+    }
+
+    pub fn terminal_split_mut(
+        &mut self,
+        split_id: &WidgetId,
+    ) -> Option<&mut TerminalSplitData> {
+        if self.terminal.tabs.contains_key(split_id) {
+            Arc::make_mut(&mut self.terminal).tabs.get_mut(split_id)
+        } else {
+            self.main_split
+                .terminals
+                .get_mut(split_id)
+                .map(Arc::make_mut)
+        }
+    }
+
+    /// Finds a terminal in the terminal panel or the editor area.
+    pub fn get_terminal(&self, term_id: &TermId) -> Option<&Arc<LapceTerminalData>> {
+        self.terminal
+            .get_terminal(term_id)
+            .or_else(|| self.main_split.find_terminal(term_id))
+    }
+
+    pub fn get_terminal_mut(
+        &mut self,
+        term_id: &TermId,
+    ) -> Option<&mut LapceTerminalData> {
+        let split_id = self.get_terminal(term_id)?.split_id;
+        self.terminal_split_mut(&split_id)?
+            .terminals
+            .get_mut(term_id)
+            .map(Arc::make_mut)
+    }
+
+    /// Removes a tab from the terminal panel, hiding the panel when it was
+    /// the last one. The terminals in it are left running.
+    fn remove_terminal_tab(
+        &mut self,
+        ctx: &mut EventCtx,
+        split_id: WidgetId,
+    ) -> Option<TerminalSplitData> {
+        let terminal_panel = Arc::make_mut(&mut self.terminal);
+        let split = terminal_panel.tabs.remove(&split_id);
+        terminal_panel.tabs_order = Arc::new(
+            terminal_panel
+                .tabs_order
+                .iter()
+                .filter(|w| *w != &split_id)
+                .copied()
+                .collect(),
+        );
+        if terminal_panel.tabs_order.is_empty() {
+            if self.panel.is_panel_visible(&PanelKind::Terminal) {
+                Arc::make_mut(&mut self.panel).hide_panel(&PanelKind::Terminal);
+            }
+            if let Some(active) = *self.main_split.active_tab {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(active),
+                ));
+            }
+        } else {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(terminal_panel.widget_id),
+            ));
+        }
+        split
+    }
+
+    /// The terminal with the focus, or the active one of the terminal panel.
+    fn focused_terminal(&self) -> Option<Arc<LapceTerminalData>> {
+        self.terminal
+            .tabs
+            .values()
+            .chain(self.main_split.terminals.values().map(|s| s.as_ref()))
+            .flat_map(|split| split.terminals.values())
+            .find(|t| t.widget_id == *self.focus)
+            .cloned()
+            .or_else(|| self.terminal.active_terminal())
+    }
+
     pub fn terminal_update_process(
         tab_id: WidgetId,
         _palette_widget_id: WidgetId,
         receiver: Receiver<(TermId, TermEvent)>,
         event_sink: ExtEventSink,
         _workspace: Arc<LapceWorkspace>,
-        _proxy: Arc<LapceProxy>,
+        proxy: Arc<LapceProxy>,
     ) {
-        let mut terminals = HashMap::new();
+        // How long the terminals have to be quiet before the text a shell
+        // was launched to run is sent without the shell telling it's ready.
+        const PENDING_RUN_TIMEOUT: Duration = Duration::from_secs(2);
+
+        let mut terminals: HashMap<TermId, Arc<Mutex<RawTerminal>>> = HashMap::new();
         let mut last_redraw = Instant::now();
         let mut last_event = None;
         loop {
@@ -2049,21 +2512,48 @@ pub fn terminal_update_process(
             {
                 (term_id, event)
             } else {
-                match receiver.recv() {
+                match receiver.recv_timeout(PENDING_RUN_TIMEOUT) {
                     Ok((term_id, event)) => (term_id, event),
-                    Err(_) => return,
+                    Err(RecvTimeoutError::Timeout) => {
+                        // Shells without integration which don't use
+                        // bracketed paste never tell they're ready.
+                        for (term_id, raw) in terminals.iter() {
+                            let mut raw = raw.lock();
+                            if let Some(text) = raw.pending_run.take() {
+                                proxy
+                                    .proxy_rpc
+                                    .terminal_write(*term_id, &raw.run_input(&text));
+                            }
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
                 }
             };
             match event {
                 TermEvent::CloseTerminal => {
                     terminals.remove(&term_id);
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::CloseTerminal(term_id),
+                        Target::Widget(tab_id),
+                    );
                 }
                 TermEvent::NewTerminal(raw) => {
                     terminals.insert(term_id, raw);
                 }
                 TermEvent::UpdateContent(content) => {
                     if let Some(raw) = terminals.get_mut(&term_id) {
-                        raw.lock().update_content(&content);
+                        let mut raw = raw.lock();
+                        raw.update_content(&content);
+                        if raw.shell_ready() {
+                            if let Some(text) = raw.pending_run.take() {
+                                proxy
+                                    .proxy_rpc
+                                    .terminal_write(term_id, &raw.run_input(&text));
+                            }
+                        }
+                        drop(raw);
                         last_event = receiver.try_recv().ok();
                         if last_event.is_some() {
                             if last_redraw.elapsed().as_millis() > 10 {
@@ -2115,6 +2605,7 @@ pub fn show_panel(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
             PanelKind::Terminal => self.terminal.widget_id,
             PanelKind::Search => self.search.active,
             PanelKind::Problem => self.problem.widget_id,
+            PanelKind::UndoTree => self.undo_tree.widget_id,
         };
         if let PanelKind::Search = kind {
             ctx.submit_command(Command::new(
@@ -2145,7 +2636,10 @@ fn toggle_panel_visual(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
 
     fn toggle_panel_focus(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
         let should_hide = match kind {
-            PanelKind::FileExplorer | PanelKind::Plugin | PanelKind::Problem => {
+            PanelKind::FileExplorer
+            | PanelKind::Plugin
+            | PanelKind::Problem
+            | PanelKind::UndoTree => {
                 // Some panels don't accept focus (yet). Fall back to visibility check
                 // in those cases.
                 self.panel.is_panel_visible(&kind)
@@ -2426,6 +2920,20 @@ pub struct LapceMainSplitData {
     pub proxy: Arc<LapceProxy>,
     pub palette_preview_editor: Arc<WidgetId>,
     pub diagnostics: im::HashMap<PathBuf, Arc<Vec<EditorDiagnostic>>>,
+    /// The diagnostics from language servers, kept apart per server and per
+    /// whether they were pushed or pulled, which are combined with the
+    /// problems found in task output in `diagnostics`.
+    pub lsp_diagnostics:
+        im::HashMap<PathBuf, im::HashMap<DiagnosticSource, Arc<Vec<Diagnostic>>>>,
+    /// The problems found in the output of the last run of each task.
+    pub task_diagnostics:
+        im::HashMap<String, Arc<HashMap<PathBuf, Vec<Diagnostic>>>>,
+    /// The characters each plugin's language server formats on once
+    /// they're typed
+    pub on_type_formatting_triggers: im::HashMap<PluginId, Vec<String>>,
+    /// The terminals opened as editor tabs, each in a terminal split of its
+    /// own keyed by the split id.
+    pub terminals: im::HashMap<WidgetId, Arc<TerminalSplitData>>,
     pub error_count: usize,
     pub warning_count: usize,
     pub workspace: Arc<LapceWorkspace>,
@@ -2541,6 +3049,104 @@ pub fn document_save(
         );
     }
 
+    /// Replaces the diagnostics of `path` which came from `source`, leaving
+    /// the ones from other servers or the other way of getting them alone.
+    pub fn publish_diagnostics(
+        &mut self,
+        path: PathBuf,
+        source: DiagnosticSource,
+        diagnostics: Vec<Diagnostic>,
+    ) {
+        let sources = self.lsp_diagnostics.entry(path.clone()).or_default();
+        if diagnostics.is_empty() {
+            sources.remove(&source);
+        } else {
+            sources.insert(source, Arc::new(diagnostics));
+        }
+        if sources.is_empty() {
+            self.lsp_diagnostics.remove(&path);
+        }
+        self.update_diagnostics(path);
+        self.update_diagnostic_counts();
+    }
+
+    /// Replaces the problems found by the previous run of the task.
+    pub fn set_task_diagnostics(
+        &mut self,
+        task: &str,
+        diagnostics: HashMap<PathBuf, Vec<Diagnostic>>,
+    ) {
+        let mut paths: HashSet<PathBuf> = diagnostics.keys().cloned().collect();
+        if let Some(previous) = self.task_diagnostics.get(task) {
+            paths.extend(previous.keys().cloned());
+        }
+        self.task_diagnostics
+            .insert(task.to_string(), Arc::new(diagnostics));
+        for path in paths {
+            self.update_diagnostics(path);
+        }
+        self.update_diagnostic_counts();
+    }
+
+    fn update_diagnostics(&mut self, path: PathBuf) {
+        let diagnostics = self
+            .lsp_diagnostics
+            .get(&path)
+            .into_iter()
+            .flat_map(|sources| sources.values())
+            .flat_map(|d| d.iter())
+            .chain(
+                self.task_diagnostics
+                    .values()
+                    .filter_map(|d| d.get(&path))
+                    .flatten(),
+            )
+            .map(|d| EditorDiagnostic {
+                range: (0, 0),
+                diagnostic: d.clone(),
+                lines: d
+                    .related_information
+                    .as_ref()
+                    .map(|r| {
+                        r.iter()
+                            .map(|r| r.message.matches('\n').count() + 1 + 1)
+                            .sum()
+                    })
+                    .unwrap_or(0)
+                    + d.message.matches('\n').count()
+                    + 1,
+            })
+            .sorted_by_key(|d| d.diagnostic.range.start)
+            .collect();
+        let diagnostics: Arc<Vec<EditorDiagnostic>> = Arc::new(diagnostics);
+
+        // inform the document about the diagnostics
+        if let Some(document) = self.open_docs.get_mut(&path) {
+            let document = Arc::make_mut(document);
+            document.set_diagnostics(&diagnostics);
+        }
+
+        self.diagnostics.insert(path, diagnostics);
+    }
+
+    fn update_diagnostic_counts(&mut self) {
+        let mut errors = 0;
+        let mut warnings = 0;
+        for (_, diagnostics) in self.diagnostics.iter() {
+            for diagnostic in diagnostics.iter() {
+                if let Some(severity) = diagnostic.diagnostic.severity {
+                    match severity {
+                        DiagnosticSeverity::ERROR => errors += 1,
+                        DiagnosticSeverity::WARNING => warnings += 1,
+                        _ => (),
+                    }
+                }
+            }
+        }
+        self.error_count = errors;
+        self.warning_count = warnings;
+    }
+
     pub fn diagnostics_items(
         &self,
         severity: DiagnosticSeverity,
@@ -3191,8 +3797,9 @@ pub fn install_theme(&mut self, ctx: &mut EventCtx, _config: &LapceConfig) {
                         }
                     }
                 }
-                EditorTabChild::Settings { .. } => {}
-                EditorTabChild::Plugin { .. } => {}
+                EditorTabChild::Settings { .. }
+                | EditorTabChild::Plugin { .. }
+                | EditorTabChild::Terminal { .. } => {}
             }
         }
     }
@@ -3203,7 +3810,7 @@ pub fn export_theme(&mut self, ctx: &mut EventCtx, config: &LapceConfig) {
         let doc = Arc::make_mut(doc);
 
         #[cfg(feature = "lang-toml")]
-        doc.set_language(lapce_core::language::LapceLanguage::Toml);
+        doc.set_language(LapceLanguage::Toml);
 
         doc.reload(Rope::from(config.export_theme()), true);
     }
@@ -3276,6 +3883,7 @@ pub fn go_to_location_cb<
             )
             .view_id;
         let doc = self.editor_doc(editor_view_id);
+        let old_path = doc.content().path().map(Path::to_path_buf);
         let new_buffer = match doc.content() {
             BufferContent::File(path) => path != &location.path,
             BufferContent::Local(_) => true,
@@ -3389,6 +3997,30 @@ pub fn go_to_location_cb<
                 (cb)(ctx, self);
             }
         }
+
+        if let Some(old_path) = old_path {
+            self.drop_unused_histories(&old_path);
+        }
+    }
+
+    /// Drops the versions of the document at `path` that no editor compares
+    /// it to anymore. The git head is kept, as the gutter diffs against it.
+    pub fn drop_unused_histories(&mut self, path: &Path) {
+        let versions: HashSet<String> = self
+            .editors
+            .values()
+            .filter(|editor| editor.content.path() == Some(path))
+            .filter_map(|editor| match &editor.view {
+                EditorView::Diff(version) => Some(version.clone()),
+                _ => None,
+            })
+            .collect();
+        let keep = |version: &str| version == "head" || versions.contains(version);
+        if let Some(doc) = self.open_docs.get_mut(path) {
+            if doc.history_versions().any(|version| !keep(version)) {
+                Arc::make_mut(doc).retain_histories(keep);
+            }
+        }
     }
 
     pub fn jump_to_line(
@@ -3431,7 +4063,7 @@ pub fn new(
         event_sink: ExtEventSink,
         workspace: Arc<LapceWorkspace>,
         db: Arc<LapceDb>,
-        unsaved_buffers: im::HashMap<String, String>,
+        unsaved_buffers: Vec<UnsavedBuffer>,
     ) -> Self {
         let split_id = Arc::new(WidgetId::next());
 
@@ -3488,6 +4120,10 @@ pub fn new(
             proxy,
             palette_preview_editor: Arc::new(palette_preview_editor),
             diagnostics: im::HashMap::new(),
+            lsp_diagnostics: im::HashMap::new(),
+            task_diagnostics: im::HashMap::new(),
+            on_type_formatting_triggers: im::HashMap::new(),
+            terminals: im::HashMap::new(),
             error_count: 0,
             warning_count: 0,
             workspace,
@@ -3504,16 +4140,57 @@ pub fn new(
                 &mut positions,
                 tab_id,
                 config,
-                event_sink,
+                event_sink.clone(),
             );
             main_split_data.split_id = Arc::new(split_data.widget_id);
+
+            let mut restored = Vec::new();
             for (path, locations) in positions.into_iter() {
+                let content = BufferContent::File(path.clone());
                 let unsaved_buffer = unsaved_buffers
-                    .get(&path.to_str().unwrap().to_string())
-                    .map(Rope::from);
+                    .iter()
+                    .find(|b| b.content == content)
+                    .cloned();
+                if let Some(buffer) = unsaved_buffer.as_ref() {
+                    restored.push(buffer.name());
+                }
                 Arc::make_mut(main_split_data.open_docs.get_mut(&path).unwrap())
                     .retrieve_file(locations.clone(), unsaved_buffer, None);
             }
+            for buffer in unsaved_buffers.iter() {
+                if let BufferContent::Scratch(id, _) = &buffer.content {
+                    if let Some(doc) = main_split_data.scratch_docs.get_mut(id) {
+                        let doc = Arc::make_mut(doc);
+                        buffer.restore(doc);
+                        for editor in main_split_data.editors.values_mut() {
+                            if editor.content == buffer.content {
+                                buffer.restore_cursor(
+                                    Arc::make_mut(editor),
+                                    doc,
+                                    config,
+                                );
+                            }
+                        }
+                        restored.push(buffer.name());
+                    }
+                }
+            }
+
+            if !restored.is_empty() {
+                restored.sort();
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::NewMessage {
+                        kind: MessageType::INFO,
+                        title: "Restored Unsaved Changes".to_string(),
+                        message: format!(
+                            "The unsaved changes from the last session were restored in {}",
+                            restored.join(", ")
+                        ),
+                    },
+                    Target::Widget(tab_id),
+                );
+            }
         } else {
             main_split_data.splits.insert(
                 *main_split_data.split_id,
@@ -4013,6 +4690,177 @@ fn split_plugin(
             .insert(new_editor_tab.widget_id, Arc::new(new_editor_tab));
     }
 
+    /// Opens a terminal in a new editor tab next to the one of the terminal,
+    /// with the same profile and working directory.
+    fn split_terminal(
+        &mut self,
+        ctx: &mut EventCtx,
+        editor_tab_id: WidgetId,
+        terminal_split_id: WidgetId,
+        term_id: TermId,
+        direction: SplitDirection,
+        config: &LapceConfig,
+    ) {
+        let (profile, cwd) = self
+            .get_terminal(&terminal_split_id, &term_id)
+            .map(|t| (t.profile_name.clone(), t.cwd()))
+            .unwrap_or_default();
+        let terminal = LapceTerminalData::new(
+            self.workspace.clone(),
+            WidgetId::next(),
+            ctx.get_external_handle(),
+            self.proxy.clone(),
+            config,
+            profile,
+            cwd,
+        );
+
+        let editor_tab = self.editor_tabs.get(&editor_tab_id).unwrap();
+        let split_id = editor_tab.split;
+
+        let new_editor_tab_id = WidgetId::next();
+        let child = self.insert_terminal(terminal, new_editor_tab_id);
+        let mut new_editor_tab = LapceEditorTabData {
+            widget_id: new_editor_tab_id,
+            split: split_id,
+            active: 0,
+            children: vec![child].into(),
+            layout_rect: Rc::new(RefCell::new(Rect::ZERO)),
+            content_is_hot: Rc::new(RefCell::new(false)),
+        };
+
+        let new_split_id = self.split(
+            ctx,
+            split_id,
+            SplitContent::EditorTab(editor_tab_id),
+            SplitContent::EditorTab(new_editor_tab.widget_id),
+            direction,
+            false,
+            true,
+        );
+
+        new_editor_tab.split = new_split_id;
+        if split_id != new_split_id {
+            let editor_tab = self.editor_tabs.get_mut(&editor_tab_id).unwrap();
+            let editor_tab = Arc::make_mut(editor_tab);
+            editor_tab.split = new_split_id;
+        }
+        self.editor_tabs
+            .insert(new_editor_tab.widget_id, Arc::new(new_editor_tab));
+    }
+
+    /// Adds the terminal to the terminals in the editor area, returning the
+    /// editor tab child showing it.
+    pub fn insert_terminal(
+        &mut self,
+        mut terminal: LapceTerminalData,
+        editor_tab_id: WidgetId,
+    ) -> EditorTabChild {
+        let split_id = WidgetId::next();
+        terminal.relocate(split_id, Some(editor_tab_id));
+        let child = EditorTabChild::Terminal {
+            widget_id: terminal.widget_id,
+            split_id,
+            term_id: terminal.term_id,
+            editor_tab_id,
+        };
+        self.terminals.insert(
+            split_id,
+            Arc::new(TerminalSplitData::with_terminal(split_id, terminal)),
+        );
+        child
+    }
+
+    /// Opens the terminal as a tab of the active editor tab.
+    pub fn open_terminal(
+        &mut self,
+        ctx: &mut EventCtx,
+        terminal: LapceTerminalData,
+    ) {
+        let editor_tab_id = self
+            .active_tab
+            .as_ref()
+            .map(|id| id)
+            .unwrap_or_else(|| self.new_editor_tab(ctx, *self.split_id));
+        let child = self.insert_terminal(terminal, editor_tab_id);
+
+        let editor_tab =
+            Arc::make_mut(self.editor_tabs.get_mut(&editor_tab_id).unwrap());
+        let new_tab = editor_tab.children.is_empty();
+        let index = if new_tab { 0 } else { editor_tab.active + 1 };
+        editor_tab.children.insert(index, child.clone());
+        if !new_tab {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::EditorTabAdd(index, child.clone()),
+                Target::Widget(editor_tab.widget_id),
+            ));
+        }
+        editor_tab.active = index;
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(child.widget_id()),
+        ));
+    }
+
+    pub fn get_terminal(
+        &self,
+        split_id: &WidgetId,
+        term_id: &TermId,
+    ) -> Option<&Arc<LapceTerminalData>> {
+        self.terminals.get(split_id)?.terminals.get(term_id)
+    }
+
+    /// Removes a terminal from the editor area without closing it, so it
+    /// can be moved to the terminal panel.
+    pub fn take_terminal(
+        &mut self,
+        ctx: &mut EventCtx,
+        term_id: &TermId,
+    ) -> Option<LapceTerminalData> {
+        let terminal = self.find_terminal(term_id)?.clone();
+        let editor_tab_id = terminal.editor_tab_id?;
+        let index = self
+            .editor_tabs
+            .get(&editor_tab_id)?
+            .children
+            .iter()
+            .position(|child| child.widget_id() == terminal.widget_id)?;
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::EditorTabRemove(index, false, false),
+            Target::Widget(editor_tab_id),
+        ));
+        self.terminals.remove(&terminal.split_id);
+        Some((*terminal).clone())
+    }
+
+    /// The title of the tab of a terminal in the editor area.
+    pub fn terminal_tab_title(
+        &self,
+        split_id: &WidgetId,
+        term_id: &TermId,
+    ) -> String {
+        match self
+            .get_terminal(split_id, term_id)
+            .map(|t| t.display_title())
+        {
+            Some(title) if !title.is_empty() => format!("Terminal: {title}"),
+            _ => "Terminal".to_string(),
+        }
+    }
+
+    /// Finds a terminal in the editor area by its id.
+    pub fn find_terminal(
+        &self,
+        term_id: &TermId,
+    ) -> Option<&Arc<LapceTerminalData>> {
+        self.terminals
+            .values()
+            .find_map(|split| split.terminals.get(term_id))
+    }
+
     pub fn split_settings(
         &mut self,
         ctx: &mut EventCtx,
@@ -4099,6 +4947,21 @@ pub fn tab_split(
                         config,
                     );
                 }
+                EditorTabChild::Terminal {
+                    editor_tab_id,
+                    split_id,
+                    term_id,
+                    ..
+                } => {
+                    self.split_terminal(
+                        ctx,
+                        *editor_tab_id,
+                        *split_id,
+                        *term_id,
+                        direction,
+                        config,
+                    );
+                }
             }
         }
     }
@@ -4166,6 +5029,20 @@ pub enum InlineFindDirection {
     Right,
 }
 
+/// A surround command waiting for the pairs to be typed
+#[derive(Clone, Debug)]
+pub enum PendingSurround {
+    /// Waiting for the pair to add, `tag` collects a tag until `>` is typed
+    Add { tag: Option<String> },
+    /// Waiting for the pair to delete
+    Delete,
+    /// Waiting for the pair to replace, then for the pair to replace it with
+    Change {
+        target: Option<Surround>,
+        tag: Option<String>,
+    },
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EditorTabChild {
     Editor(WidgetId, WidgetId, Option<(WidgetId, WidgetId)>),
@@ -4180,6 +5057,12 @@ pub enum EditorTabChild {
         volt_name: String,
         editor_tab_id: WidgetId,
     },
+    Terminal {
+        widget_id: WidgetId,
+        split_id: WidgetId,
+        term_id: TermId,
+        editor_tab_id: WidgetId,
+    },
 }
 
 impl EditorTabChild {
@@ -4190,11 +5073,14 @@ pub fn widget_id(&self) -> WidgetId {
                 settings_widget_id, ..
             } => *settings_widget_id,
             EditorTabChild::Plugin { widget_id, .. } => *widget_id,
+            EditorTabChild::Terminal { widget_id, .. } => *widget_id,
         }
     }
 
-    pub fn child_info(&self, data: &LapceTabData) -> EditorTabChildInfo {
-        match &self {
+    /// The info to save about the child, which is `None` for a terminal
+    /// that's already gone.
+    pub fn child_info(&self, data: &LapceTabData) -> Option<EditorTabChildInfo> {
+        let info = match &self {
             EditorTabChild::Editor(view_id, _, _) => {
                 let editor_data = data.main_split.editors.get(view_id).unwrap();
                 EditorTabChildInfo::Editor(editor_data.editor_info(data))
@@ -4206,7 +5092,16 @@ pub fn child_info(&self, data: &LapceTabData) -> EditorTabChildInfo {
                 volt_id: volt_id.to_string(),
                 volt_name: volt_name.to_string(),
             },
-        }
+            EditorTabChild::Terminal {
+                split_id, term_id, ..
+            } => {
+                let terminal = data.main_split.get_terminal(split_id, term_id)?;
+                EditorTabChildInfo::Terminal(
+                    terminal.info(data.config.terminal.restore_scrollback),
+                )
+            }
+        };
+        Some(info)
     }
 
     pub fn set_editor_tab(
@@ -4226,6 +5121,25 @@ pub fn set_editor_tab(
             EditorTabChild::Plugin { editor_tab_id, .. } => {
                 *editor_tab_id = editor_tab_widget_id;
             }
+            EditorTabChild::Terminal {
+                split_id,
+                term_id,
+                editor_tab_id,
+                ..
+            } => {
+                *editor_tab_id = editor_tab_widget_id;
+                if let Some(terminal) = data
+                    .main_split
+                    .terminals
+                    .get_mut(split_id)
+                    .and_then(|split| {
+                        Arc::make_mut(split).terminals.get_mut(term_id)
+                    })
+                {
+                    Arc::make_mut(terminal).editor_tab_id =
+                        Some(editor_tab_widget_id);
+                }
+            }
         }
     }
 }
@@ -4243,14 +5157,19 @@ pub struct LapceEditorTabData {
 
 impl LapceEditorTabData {
     pub fn tab_info(&self, data: &LapceTabData) -> EditorTabInfo {
+        let mut active = self.active;
+        let mut children = Vec::new();
+        for (i, child) in self.children.iter().enumerate() {
+            match child.child_info(data) {
+                Some(info) => children.push(info),
+                None if i < self.active => active -= 1,
+                None => {}
+            }
+        }
         let info = EditorTabInfo {
-            active: self.active,
+            active: active.min(children.len().saturating_sub(1)),
             is_focus: *data.main_split.active_tab == Some(self.widget_id),
-            children: self
-                .children
-                .iter()
-                .map(|child| child.child_info(data))
-                .collect(),
+            children,
         };
         info
     }
@@ -4304,9 +5223,12 @@ pub struct LapceEditorData {
     pub sticky_header: Rc<RefCell<StickyHeaderInfo>>,
     pub window_origin: Rc<RefCell<Point>>,
     pub snippet: Option<Vec<(usize, (usize, usize))>>,
+    /// The choices of the placeholders of the snippet, by tab.
+    pub snippet_choices: HashMap<usize, Vec<String>>,
     pub last_movement_new: Movement,
     pub last_inline_find: Option<(InlineFindDirection, String)>,
     pub inline_find: Option<InlineFindDirection>,
+    pub surround: Option<PendingSurround>,
     pub motion_mode: Option<MotionMode>,
 }
 
@@ -4348,9 +5270,11 @@ pub fn new(
             compare: None,
             window_origin: Rc::new(RefCell::new(Point::ZERO)),
             snippet: None,
+            snippet_choices: HashMap::new(),
             last_movement_new: Movement::Left,
             inline_find: None,
             last_inline_find: None,
+            surround: None,
             motion_mode: None,
         }
     }
@@ -4374,14 +5298,23 @@ pub fn is_code_lens(&self) -> bool {
     pub fn add_snippet_placeholders(
         &mut self,
         new_placeholders: Vec<(usize, (usize, usize))>,
+        choices: Vec<(usize, Vec<String>)>,
     ) {
         if self.snippet.is_none() {
             if new_placeholders.len() > 1 {
                 self.snippet = Some(new_placeholders);
+                self.snippet_choices = choices.into_iter().collect();
             }
             return;
         }
 
+        // The tabs of a snippet inserted within another one can clash with
+        // the tabs of the outer snippet, so their choices are dropped rather
+        // than listed for the wrong placeholder.
+        for (tab, _) in new_placeholders.iter() {
+            self.snippet_choices.remove(tab);
+        }
+
         let placeholders = self.snippet.as_mut().unwrap();
 
         let mut current = 0;
@@ -4463,18 +5396,48 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
+/// A remote reached by running commands through a wrapper, such as
+/// `docker exec -i <container>`, for remotes that can't be reached by SSH.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct CommandRemoteHost {
+    /// The name the remote is configured under.
+    #[serde(default)]
+    pub name: String,
+    /// The command the commands to run on the remote are appended to.
+    pub command: String,
+    /// The command that copies the `{local}` file to `{remote}`. If empty,
+    /// the file is piped to `cat` on the remote.
+    #[serde(default)]
+    pub upload: String,
+    /// A locally built proxy to upload rather than downloading a release.
+    #[serde(default)]
+    pub proxy_path: Option<PathBuf>,
+}
+
+impl Display for CommandRemoteHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LapceWorkspaceType {
     Local,
     RemoteSSH(SshHost),
     RemoteWSL,
+    /// A command remote, by the name it's configured under in
+    /// `core.remotes`
+    RemoteCommand(String),
 }
 
 impl LapceWorkspaceType {
     pub fn is_remote(&self) -> bool {
         matches!(
             self,
-            LapceWorkspaceType::RemoteSSH(_) | LapceWorkspaceType::RemoteWSL
+            LapceWorkspaceType::RemoteSSH(_)
+                | LapceWorkspaceType::RemoteWSL
+                | LapceWorkspaceType::RemoteCommand(_)
         )
     }
 }
@@ -4487,6 +5450,9 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 write!(f, "ssh://{ssh}")
             }
             LapceWorkspaceType::RemoteWSL => f.write_str("WSL"),
+            LapceWorkspaceType::RemoteCommand(remote) => {
+                write!(f, "command://{remote}")
+            }
         }
     }
 }
@@ -4,6 +4,7 @@
     path::{Path, PathBuf},
     rc::Rc,
     sync::Arc,
+    time::Instant,
 };
 
 use anyhow::{anyhow, Result};
@@ -11,9 +12,11 @@
 use druid::{ExtEventSink, Point, Rect, Size, Vec2, WidgetId};
 use lapce_core::directory::Directory;
 use lapce_xi_rope::Rope;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    completion::CompletionHistory,
     config::LapceConfig,
     data::{
         EditorTabChild, LapceData, LapceEditorData, LapceEditorTabData,
@@ -22,8 +25,12 @@
     },
     document::{BufferContent, Document, LocalBufferKind},
     editor::EditorLocation,
+    hot_exit::{self, UnsavedBuffer},
     panel::{PanelData, PanelOrder},
+    proxy::LapceProxy,
     split::SplitDirection,
+    terminal::{LapceTerminalData, TerminalPanelData, TerminalSplitData},
+    undo,
 };
 
 pub enum SaveEvent {
@@ -31,12 +38,19 @@ pub enum SaveEvent {
     Tabs(TabsInfo),
     Buffer(BufferInfo),
     RecentWorkspace(LapceWorkspace),
+    CompletionHistory(LapceWorkspace, CompletionHistory),
+    /// The unsaved buffers of a tab under their key, when they were taken
+    /// and the most space in MB their undo histories can take
+    UnsavedBuffers(String, Vec<UnsavedBuffer>, Instant, u64),
 }
 
 #[derive(Clone)]
 pub struct LapceDb {
     save_tx: Sender<SaveEvent>,
     sled_db: Option<sled::Db>,
+    /// When the stored unsaved buffers of each tab were taken, so that a
+    /// snapshot still waiting to be saved doesn't replace a newer one
+    unsaved_buffers_taken: Arc<Mutex<HashMap<String, Instant>>>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -138,6 +152,7 @@ pub enum EditorTabChildInfo {
     Editor(EditorInfo),
     Settings,
     Plugin { volt_id: String, volt_name: String },
+    Terminal(TerminalInfo),
 }
 
 impl EditorTabChildInfo {
@@ -191,6 +206,17 @@ pub fn to_data(
                     editor_tab_id,
                 }
             }
+            EditorTabChildInfo::Terminal(info) => {
+                let terminal = LapceTerminalData::restore(
+                    data.workspace.clone(),
+                    WidgetId::next(),
+                    event_sink,
+                    data.proxy.clone(),
+                    config,
+                    info,
+                );
+                data.insert_terminal(terminal, editor_tab_id)
+            }
         }
     }
 }
@@ -241,6 +267,83 @@ pub fn to_data(
 pub struct WorkspaceInfo {
     pub split: SplitInfo,
     pub panel: PanelData,
+    #[serde(default)]
+    pub terminal: Option<TerminalPanelInfo>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TerminalPanelInfo {
+    pub active: usize,
+    pub tabs: Vec<TerminalSplitInfo>,
+}
+
+impl TerminalPanelInfo {
+    pub fn to_data(
+        &self,
+        workspace: Arc<LapceWorkspace>,
+        proxy: Arc<LapceProxy>,
+        config: &LapceConfig,
+        event_sink: ExtEventSink,
+    ) -> TerminalPanelData {
+        let mut tabs = im::HashMap::new();
+        let mut tabs_order = Vec::new();
+        for tab_info in self.tabs.iter().filter(|t| !t.terminals.is_empty()) {
+            let split = tab_info.to_data(
+                workspace.clone(),
+                proxy.clone(),
+                config,
+                event_sink.clone(),
+            );
+            tabs_order.push(split.split_id);
+            tabs.insert(split.split_id, split);
+        }
+        TerminalPanelData {
+            widget_id: WidgetId::next(),
+            tabs,
+            active: self.active.min(tabs_order.len().saturating_sub(1)),
+            tabs_order: Arc::new(tabs_order),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TerminalSplitInfo {
+    pub active: usize,
+    pub terminals: Vec<TerminalInfo>,
+}
+
+impl TerminalSplitInfo {
+    pub fn to_data(
+        &self,
+        workspace: Arc<LapceWorkspace>,
+        proxy: Arc<LapceProxy>,
+        config: &LapceConfig,
+        event_sink: ExtEventSink,
+    ) -> TerminalSplitData {
+        let split_id = WidgetId::next();
+        let terminals = self
+            .terminals
+            .iter()
+            .map(|info| {
+                LapceTerminalData::restore(
+                    workspace.clone(),
+                    split_id,
+                    event_sink.clone(),
+                    proxy.clone(),
+                    config,
+                    info,
+                )
+            })
+            .collect();
+        TerminalSplitData::with_terminals(split_id, terminals, self.active)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TerminalInfo {
+    pub profile_name: Option<String>,
+    pub cwd: Option<PathBuf>,
+    pub scrollback: Vec<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -343,6 +446,12 @@ pub fn to_data(
     }
 }
 
+/// The key the unsaved buffers of a tab are stored under, which starts with
+/// the same prefix for all the tabs of a workspace
+fn unsaved_buffers_key(workspace: &LapceWorkspace, tab_id: WidgetId) -> String {
+    format!("unsaved_buffers:{}:{}", workspace, tab_id.to_raw())
+}
+
 impl LapceDb {
     pub fn new() -> Result<Self> {
         let path = Directory::config_directory()
@@ -356,7 +465,11 @@ pub fn new() -> Result<Self> {
             .open()
             .ok();
 
-        let db = Self { save_tx, sled_db };
+        let db = Self {
+            save_tx,
+            sled_db,
+            unsaved_buffers_taken: Arc::new(Mutex::new(HashMap::new())),
+        };
         let local_db = db.clone();
         std::thread::spawn(move || -> Result<()> {
             loop {
@@ -374,6 +487,14 @@ pub fn new() -> Result<Self> {
                     SaveEvent::RecentWorkspace(workspace) => {
                         let _ = local_db.insert_recent_workspace(workspace);
                     }
+                    SaveEvent::CompletionHistory(workspace, history) => {
+                        let _ =
+                            local_db.insert_completion_history(&workspace, &history);
+                    }
+                    SaveEvent::UnsavedBuffers(key, buffers, taken, max_size) => {
+                        let _ = local_db
+                            .insert_unsaved_buffers(key, buffers, taken, max_size);
+                    }
                 }
             }
         });
@@ -452,35 +573,59 @@ pub fn get_workspace_info(
         Ok(info)
     }
 
-    /// fetches all the files stored in the database that are tagged as `unsaved_buffer`.<br>
-    /// Returns a hashmap of the form HashMap<path, file_content><br>
-    /// *Note: The file is deleted from the database right after as to prevent "ghost" buffers*
-    pub fn get_unsaved_buffers(&self) -> Result<im::HashMap<String, String>> {
+    /// Takes over the buffers of `workspace` that had unsaved changes in the
+    /// last snapshots of any of its tabs, storing them as the ones of
+    /// `tab_id`. They stay in the database until the next snapshot of the tab
+    /// replaces them, so that they aren't lost if Lapce stops again before.
+    pub fn take_unsaved_buffers(
+        &self,
+        workspace: &LapceWorkspace,
+        tab_id: WidgetId,
+    ) -> Result<Vec<UnsavedBuffer>> {
         let sled_db = self.get_db()?;
-        let mut buffers = im::HashMap::new();
-
-        let res = match sled_db.get("unsaved_buffers") {
-            Ok(val) => match val {
-                Some(buffers) => buffers,
-                None => return Ok(buffers),
-            },
-            Err(err) => return Err(anyhow!(err)),
-        };
-
-        let res = String::from_utf8(res.to_vec())
-            .expect("invalid utf-8 sequence retrieving unsaved buffer");
+        let mut batch = sled::Batch::default();
+        let mut buffers: Vec<UnsavedBuffer> = Vec::new();
+
+        for entry in sled_db.scan_prefix(format!("unsaved_buffers:{}:", workspace)) {
+            let (key, value) = entry?;
+            batch.remove(key);
+            let tab_buffers: Vec<UnsavedBuffer> =
+                serde_json::from_slice(&value).unwrap_or_default();
+            for buffer in tab_buffers {
+                if !buffers.iter().any(|b| b.content == buffer.content) {
+                    buffers.push(buffer);
+                }
+            }
+        }
 
-        let res: Vec<String> = serde_json::from_str(&res)?;
-        if res.len() % 2 != 0 {
-            return Err(anyhow!("Deserialized Unsaved buffer size is not even: this should never happen"));
+        // Older versions stored only the text of the unsaved files, as a flat
+        // list of paths and contents shared by all the workspaces
+        if let Some(legacy) = sled_db.get("unsaved_buffers")? {
+            batch.remove("unsaved_buffers");
+            let legacy: Vec<String> =
+                serde_json::from_slice(&legacy).unwrap_or_default();
+            for pair in legacy.chunks_exact(2) {
+                let content = BufferContent::File(PathBuf::from(&pair[0]));
+                if !buffers.iter().any(|b| b.content == content) {
+                    buffers.push(UnsavedBuffer {
+                        content,
+                        text: pair[1].clone(),
+                        language: None,
+                        cursor: None,
+                        history: None,
+                    });
+                }
+            }
         }
 
-        for i in (0..res.len()).step_by(2) {
-            let key = res.get(i).unwrap().clone();
-            let value = res.get(i + 1).unwrap().clone();
-            buffers.insert(key, value);
+        if !buffers.is_empty() {
+            batch.insert(
+                unsaved_buffers_key(workspace, tab_id).as_str(),
+                serde_json::to_vec(&buffers)?,
+            );
         }
-        sled_db.remove("unsaved_buffers")?;
+        sled_db.apply_batch(batch)?;
+        sled_db.flush()?;
         Ok(buffers)
     }
 
@@ -559,6 +704,46 @@ pub fn get_workspace_disabled_volts(
         Ok(volts)
     }
 
+    pub fn save_completion_history(
+        &self,
+        workspace: &LapceWorkspace,
+        history: &CompletionHistory,
+    ) -> Result<()> {
+        self.save_tx.send(SaveEvent::CompletionHistory(
+            workspace.clone(),
+            history.clone(),
+        ))?;
+        Ok(())
+    }
+
+    fn insert_completion_history(
+        &self,
+        workspace: &LapceWorkspace,
+        history: &CompletionHistory,
+    ) -> Result<()> {
+        let sled_db = self.get_db()?;
+        let history = serde_json::to_string(history)?;
+        sled_db.insert(
+            format!("completion_history:{}", workspace),
+            history.as_str(),
+        )?;
+        sled_db.flush()?;
+        Ok(())
+    }
+
+    pub fn get_completion_history(
+        &self,
+        workspace: &LapceWorkspace,
+    ) -> Result<CompletionHistory> {
+        let sled_db = self.get_db()?;
+        let history = sled_db
+            .get(format!("completion_history:{}", workspace))?
+            .ok_or_else(|| anyhow!("can't find completion history"))?;
+        let history = std::str::from_utf8(&history)?;
+        let history: CompletionHistory = serde_json::from_str(history)?;
+        Ok(history)
+    }
+
     pub fn save_last_window(&self, window: &LapceWindowData) {
         let info = window.info();
         let _ = self.insert_last_window_info(info);
@@ -621,31 +806,69 @@ pub fn save_workspace(&self, data: &LapceTabData) -> Result<()> {
         let main_split = &data.main_split;
 
         self.insert_workspace(&workspace, &workspace_info)?;
-        self.insert_unsaved_buffer(main_split)?;
+        if let Some(buffers) =
+            hot_exit::snapshot(main_split, data.config.editor.persistent_undo)
+        {
+            self.insert_unsaved_buffers(
+                unsaved_buffers_key(&workspace, data.id),
+                buffers,
+                Instant::now(),
+                data.config.editor.persistent_undo_size,
+            )?;
+        }
+        if data.config.editor.persistent_undo {
+            self.insert_undo_histories(
+                main_split,
+                data.config.editor.persistent_undo_size,
+            );
+        }
 
         Ok(())
     }
 
-    fn insert_unsaved_buffer(&self, main_split: &LapceMainSplitData) -> Result<()> {
+    fn insert_unsaved_buffers(
+        &self,
+        key: String,
+        mut buffers: Vec<UnsavedBuffer>,
+        taken: Instant,
+        max_size: u64,
+    ) -> Result<()> {
+        let mut unsaved_buffers_taken = self.unsaved_buffers_taken.lock();
+        if unsaved_buffers_taken
+            .get(&key)
+            .map_or(false, |last| *last > taken)
+        {
+            return Ok(());
+        }
+
         let sled_db = self.get_db()?;
-        // Vec of all unsaved buffers of format path_buff, file_content
-        let mut unsaved_buffers = Vec::new();
+        if buffers.is_empty() {
+            sled_db.remove(key.as_str())?;
+        } else {
+            let mut content = serde_json::to_vec(&buffers)?;
+            // The text is what matters, so the undo histories are left out
+            // when they take too much space
+            if content.len() as u64 > max_size * 1024 * 1024 {
+                for buffer in buffers.iter_mut() {
+                    buffer.history = None;
+                }
+                content = serde_json::to_vec(&buffers)?;
+            }
+            sled_db.insert(key.as_str(), content)?;
+        }
+        sled_db.flush()?;
+        unsaved_buffers_taken.insert(key, taken);
+        Ok(())
+    }
 
+    /// Stores the undo history of the saved files, as the history of an
+    /// unsaved file doesn't lead to the content on disk
+    fn insert_undo_histories(&self, main_split: &LapceMainSplitData, max_size: u64) {
         for (path, doc) in &main_split.open_docs {
-            if !doc.buffer().is_pristine() && doc.content().is_file() {
-                let path_str = path.to_str().unwrap();
-                let buf_text = doc.buffer().to_string();
-                unsaved_buffers.push(path_str.to_string());
-                unsaved_buffers.push(buf_text);
+            if doc.buffer().is_pristine() && doc.content().is_file() {
+                let _ = undo::save(path, doc.buffer(), max_size);
             }
         }
-        if !unsaved_buffers.is_empty() {
-            let tmp = serde_json::to_string(&unsaved_buffers).unwrap();
-            sled_db.insert("unsaved_buffers", tmp.as_str())?;
-            sled_db.flush()?;
-        }
-
-        Ok(())
     }
 
     pub fn save_workspace_async(&self, data: &LapceTabData) -> Result<()> {
@@ -657,6 +880,32 @@ pub fn save_workspace_async(&self, data: &LapceTabData) -> Result<()> {
         Ok(())
     }
 
+    /// Takes a snapshot of the unsaved buffers of the workspace and stores it
+    /// along with the layout of the workspace, so that every buffer has an
+    /// editor to be restored in
+    pub fn save_unsaved_buffers_async(&self, data: &LapceTabData) -> Result<()> {
+        let workspace = (*data.workspace).clone();
+        let buffers = match hot_exit::snapshot(
+            &data.main_split,
+            data.config.editor.persistent_undo,
+        ) {
+            Some(buffers) => buffers,
+            None => return Ok(()),
+        };
+
+        self.save_tx.send(SaveEvent::Workspace(
+            workspace.clone(),
+            data.workspace_info(),
+        ))?;
+        self.save_tx.send(SaveEvent::UnsavedBuffers(
+            unsaved_buffers_key(&workspace, data.id),
+            buffers,
+            Instant::now(),
+            data.config.editor.persistent_undo_size,
+        ))?;
+        Ok(())
+    }
+
     pub fn save_doc_position(&self, workspace: &LapceWorkspace, doc: &Document) {
         if let BufferContent::File(path) = doc.content() {
             let info = BufferInfo {
@@ -1,6 +1,7 @@
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
+    ops::Range,
     path::{Path, PathBuf},
     rc::Rc,
     sync::Arc,
@@ -14,17 +15,18 @@
 };
 use itertools::Itertools;
 use lapce_core::{
-    buffer::{Buffer, DiffLines, InvalLines},
+    buffer::{Buffer, DiffLines, InvalLines, UndoHistory},
     char_buffer::CharBuffer,
     command::{EditCommand, MultiSelectionCommand},
-    cursor::{ColPosition, Cursor, CursorMode},
+    cursor::{ColPosition, Cursor, CursorMode, LineLayout, TextColumns},
     editor::{EditType, Editor},
     language::LapceLanguage,
-    mode::{Mode, MotionMode},
+    mode::{Mode, MotionMode, VisualMode},
     movement::{LinePosition, Movement},
     register::{Clipboard, Register, RegisterData},
     selection::{SelRegion, Selection},
     style::line_styles,
+    surround::Surround,
     syntax::{
         edit::SyntaxEdit, highlight::HighlightIssue, util::matching_pair_direction,
         Syntax,
@@ -33,6 +35,7 @@
 };
 use lapce_rpc::{
     buffer::BufferId,
+    core::DiagnosticSource,
     plugin::PluginId,
     proxy::ProxyResponse,
     style::{LineStyle, LineStyles, Style},
@@ -42,8 +45,9 @@
     Interval, Rope, RopeDelta, Transformer,
 };
 use lsp_types::{
-    CodeActionOrCommand, CodeActionResponse, DiagnosticSeverity, InlayHint,
-    InlayHintLabel, MessageType, ShowMessageParams,
+    CodeActionOrCommand, CodeActionResponse, CodeLens, DiagnosticSeverity,
+    DocumentHighlight, DocumentHighlightKind, InlayHint, InlayHintLabel,
+    MessageType, ShowMessageParams,
 };
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
@@ -56,6 +60,7 @@
     editor::{EditorLocation, EditorPosition},
     find::{Find, FindProgress},
     history::DocumentHistory,
+    hot_exit::UnsavedBuffer,
     proxy::LapceProxy,
     selection_range::{SelectionRangeDirection, SyntaxSelectionRanges},
 };
@@ -78,6 +83,39 @@ fn put_string(&mut self, s: impl AsRef<str>) {
     }
 }
 
+/// The text layouts of the lines a block is pasted on, taken before the
+/// paste, to put the block where a block selection would be
+struct BlockPasteLayout {
+    first_line: usize,
+    layouts: Vec<Arc<TextLayoutLine>>,
+    space_width: f64,
+}
+
+impl BlockPasteLayout {
+    fn layout(&self, line: usize) -> Option<&Arc<TextLayoutLine>> {
+        self.layouts.get(line.checked_sub(self.first_line)?)
+    }
+}
+
+impl LineLayout for BlockPasteLayout {
+    fn col_x(&mut self, _buffer: &Buffer, line: usize, col: usize) -> f64 {
+        self.layout(line).map_or(0.0, |layout| {
+            layout.text.hit_test_text_position(col).point.x
+        })
+    }
+
+    fn x_col(&mut self, buffer: &Buffer, line: usize, x: f64) -> usize {
+        let col = self.layout(line).map_or(0, |layout| {
+            layout.text.hit_test_point(Point::new(x, 0.0)).idx
+        });
+        col.min(buffer.line_end_col(line, true))
+    }
+
+    fn space_width(&mut self) -> f64 {
+        self.space_width
+    }
+}
+
 #[derive(Clone)]
 pub struct LineExtraStyle {
     pub bg_color: Option<Color>,
@@ -94,6 +132,17 @@ pub struct TextLayoutLine {
     pub indent: f64,
 }
 
+/// The titles of the code lenses of a line, laid out for the virtual line
+/// shown above it
+pub struct CodeLensLayout {
+    /// Where the titles start, which is the indentation of the line
+    pub x: f64,
+    pub text: PietTextLayout,
+    /// The range of the title of each lens in the text, with the index of
+    /// the lens
+    pub titles: Vec<(Range<usize>, usize)>,
+}
+
 #[derive(Clone, Default)]
 pub struct TextLayoutCache {
     config_id: u64,
@@ -343,6 +392,22 @@ pub fn offset_size_iter(
     }
 }
 
+/// A code lens provided by a language server, anchored at `offset`
+#[derive(Clone, Debug)]
+pub struct DocumentCodeLens {
+    pub plugin_id: PluginId,
+    pub offset: usize,
+    pub lens: CodeLens,
+}
+
+/// An occurrence of the symbol under the cursor
+#[derive(Clone, Debug)]
+pub struct DocumentHighlightRange {
+    pub start: usize,
+    pub end: usize,
+    pub kind: DocumentHighlightKind,
+}
+
 #[derive(Clone)]
 pub struct Document {
     id: BufferId,
@@ -362,6 +427,13 @@ pub struct Document {
     pub code_actions: im::HashMap<usize, (PluginId, CodeActionResponse)>,
     pub inlay_hints: Option<Spans<InlayHint>>,
     pub diagnostics: Option<Arc<Vec<EditorDiagnostic>>>,
+    pub document_highlights: Option<Arc<Vec<DocumentHighlightRange>>>,
+    pub code_lens: Option<Arc<Vec<DocumentCodeLens>>>,
+    /// The lines with code lenses, in order, each with the visual line of
+    /// the virtual line above it in the normal view
+    code_lens_lines: Arc<Vec<(usize, usize)>>,
+    /// The indices of the code lenses with a pending `codeLens/resolve`
+    code_lens_resolving: Rc<RefCell<HashSet<usize>>>,
     ime_text: Option<Arc<str>>,
     ime_pos: (usize, usize, usize),
     pub syntax_selection_range: Option<SyntaxSelectionRanges>,
@@ -409,6 +481,10 @@ pub fn new(
             code_actions: im::HashMap::new(),
             inlay_hints: None,
             diagnostics: None,
+            document_highlights: None,
+            code_lens: None,
+            code_lens_lines: Arc::new(Vec::new()),
+            code_lens_resolving: Rc::new(RefCell::new(HashSet::new())),
             ime_text: None,
             ime_pos: (0, 0, 0),
             find: Rc::new(RefCell::new(Find::new(0))),
@@ -522,6 +598,18 @@ fn update_diagnostics(&mut self, delta: &RopeDelta) {
         }
     }
 
+    /// Restores the undo history of the buffer, letting the proxy know about
+    /// the revision it moved to
+    pub fn restore_undo_history(&mut self, history: UndoHistory) -> bool {
+        if !self.buffer.restore_undo_history(history) {
+            return false;
+        }
+        if let BufferContent::File(path) = &self.content {
+            self.proxy.proxy_rpc.update_rev(path.clone(), self.rev());
+        }
+        true
+    }
+
     pub fn reload(&mut self, content: Rope, set_pristine: bool) {
         self.code_actions.clear();
         self.inlay_hints = None;
@@ -538,7 +626,7 @@ pub fn handle_file_changed(&mut self, content: Rope) {
     pub fn retrieve_file<P: EditorPosition + Send + 'static>(
         &mut self,
         locations: Vec<(WidgetId, EditorLocation<P>)>,
-        unsaved_buffer: Option<Rope>,
+        unsaved_buffer: Option<UnsavedBuffer>,
         cb: Option<InitBufferContentCb>,
     ) {
         if self.loaded || *self.load_started.borrow() {
@@ -601,6 +689,16 @@ pub fn get_history(&self, version: &str) -> Option<&DocumentHistory> {
         self.histories.get(version)
     }
 
+    /// The versions the document was retrieved at to compare it to
+    pub fn history_versions(&self) -> impl Iterator<Item = &str> {
+        self.histories.keys().map(|version| version.as_str())
+    }
+
+    /// Drops the versions of the document for which `keep` is false
+    pub fn retain_histories(&mut self, keep: impl Fn(&str) -> bool) {
+        self.histories.retain(|version, _| keep(version));
+    }
+
     pub fn history_visual_line(&self, version: &str, line: usize) -> usize {
         let mut visual_line = 0;
         if let Some(history) = self.histories.get(version) {
@@ -689,9 +787,9 @@ pub fn history_actual_line_from_visual(
         }
     }
 
-    fn trigger_head_change(&self) {
-        if let Some(head) = self.histories.get("head") {
-            head.trigger_update_change(self);
+    fn trigger_history_change(&self) {
+        for history in self.histories.values() {
+            history.trigger_update_change(self);
         }
     }
 
@@ -826,16 +924,318 @@ pub fn get_inlay_hints(&self) {
         }
     }
 
+    pub fn get_code_lens(&self) {
+        if !self.loaded() {
+            return;
+        }
+
+        if let BufferContent::File(path) = self.content() {
+            let tab_id = self.tab_id;
+            let path = path.clone();
+            let buffer = self.buffer().clone();
+            let rev = buffer.rev();
+            let event_sink = self.event_sink.clone();
+            self.proxy
+                .proxy_rpc
+                .get_code_lens(path.clone(), move |result| {
+                    if let Ok(ProxyResponse::GetCodeLensResponse {
+                        plugin_id,
+                        resp,
+                    }) = result
+                    {
+                        let mut code_lens: Vec<DocumentCodeLens> = resp
+                            .into_iter()
+                            .map(|lens| DocumentCodeLens {
+                                plugin_id,
+                                offset: buffer.offset_of_position(&lens.range.start),
+                                lens,
+                            })
+                            .collect();
+                        code_lens.sort_by_key(|lens| lens.offset);
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateCodeLens {
+                                path,
+                                rev,
+                                code_lens,
+                            },
+                            Target::Widget(tab_id),
+                        );
+                    }
+                });
+        }
+    }
+
+    /// Pull the diagnostics from the language servers which support it, they
+    /// are kept apart from the pushed diagnostics and merged when shown.
+    /// Edits don't pull on their own, the editor view debounces it.
+    pub fn pull_diagnostics(&self) {
+        if !self.loaded() {
+            return;
+        }
+
+        if let BufferContent::File(path) = self.content() {
+            let tab_id = self.tab_id;
+            let event_sink = self.event_sink.clone();
+            self.proxy.proxy_rpc.get_document_diagnostics(
+                path.clone(),
+                move |result| {
+                    if let Ok(ProxyResponse::GetDocumentDiagnosticsResponse {
+                        plugin_id,
+                        diagnostics,
+                    }) = result
+                    {
+                        for diagnostics in diagnostics {
+                            let _ = event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::PublishDiagnostics {
+                                    source: DiagnosticSource::Pull(plugin_id),
+                                    diagnostics,
+                                },
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                },
+            );
+        }
+    }
+
+    /// Replace the code lenses of the document, keeping the commands of
+    /// the lenses that were already resolved
+    pub fn set_code_lens(&mut self, mut code_lens: Vec<DocumentCodeLens>) {
+        if let Some(old) = self.code_lens.as_ref() {
+            for lens in code_lens.iter_mut().filter(|l| l.lens.command.is_none()) {
+                if let Some(old) = old.iter().find(|old| {
+                    old.lens.range == lens.lens.range
+                        && old.lens.data == lens.lens.data
+                }) {
+                    lens.lens.command = old.lens.command.clone();
+                }
+            }
+        }
+        self.code_lens = Some(Arc::new(code_lens));
+        self.code_lens_resolving.borrow_mut().clear();
+        self.update_code_lens_lines();
+    }
+
+    /// Resolve the code lenses between the lines which don't have a command
+    /// yet, this is done lazily as the lines get painted
+    pub fn resolve_code_lens(&self, start_line: usize, end_line: usize) {
+        let code_lens = match self.code_lens.as_ref() {
+            Some(code_lens) => code_lens,
+            None => return,
+        };
+        let path = match self.content() {
+            BufferContent::File(path) => path,
+            _ => return,
+        };
+        let start = self.buffer.offset_of_line(start_line);
+        let end = self.buffer.offset_of_line(end_line + 1);
+        for (i, lens) in code_lens.iter().enumerate() {
+            if lens.offset < start || lens.offset >= end {
+                continue;
+            }
+            if lens.lens.command.is_some()
+                || !self.code_lens_resolving.borrow_mut().insert(i)
+            {
+                continue;
+            }
+
+            let tab_id = self.tab_id;
+            let path = path.clone();
+            let rev = self.rev();
+            let event_sink = self.event_sink.clone();
+            self.proxy.proxy_rpc.code_lens_resolve(
+                lens.lens.clone(),
+                lens.plugin_id,
+                move |result| {
+                    if let Ok(ProxyResponse::CodeLensResolveResponse { item }) =
+                        result
+                    {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateResolvedCodeLens {
+                                path,
+                                rev,
+                                index: i,
+                                code_lens: *item,
+                            },
+                            Target::Widget(tab_id),
+                        );
+                    }
+                },
+            );
+        }
+    }
+
+    pub fn set_resolved_code_lens(&mut self, index: usize, lens: CodeLens) {
+        if let Some(code_lens) = self.code_lens.as_mut() {
+            if let Some(code_lens) = Arc::make_mut(code_lens).get_mut(index) {
+                code_lens.lens = lens;
+            }
+        }
+    }
+
+    fn update_code_lens(&mut self, delta: &RopeDelta) {
+        if let Some(code_lens) = self.code_lens.as_mut() {
+            for lens in Arc::make_mut(code_lens).iter_mut() {
+                let mut transformer = Transformer::new(delta);
+                lens.offset = transformer.transform(lens.offset, false);
+            }
+            self.update_code_lens_lines();
+        }
+    }
+
+    fn update_code_lens_lines(&mut self) {
+        let mut lines: Vec<usize> = self
+            .code_lens
+            .iter()
+            .flat_map(|code_lens| code_lens.iter())
+            .map(|lens| self.buffer.line_of_offset(lens.offset))
+            .collect();
+        lines.dedup();
+        self.code_lens_lines = Arc::new(
+            lines
+                .into_iter()
+                .enumerate()
+                .map(|(i, line)| (line, line + i))
+                .collect(),
+        );
+    }
+
+    /// The lines with code lenses, in order, with their visual lines. In
+    /// the normal view each of them has a virtual line above it with the
+    /// titles of its lenses.
+    fn code_lens_lines(&self, config: &LapceConfig) -> &[(usize, usize)] {
+        if !config.editor.enable_lsp_code_lens {
+            return &[];
+        }
+        &self.code_lens_lines
+    }
+
+    /// The visual line of `line` in the normal view, which is pushed down
+    /// by the virtual lines of the code lenses up to it
+    pub fn visual_line(&self, line: usize, config: &LapceConfig) -> usize {
+        let code_lens_lines = self.code_lens_lines(config);
+        line + code_lens_lines.partition_point(|(l, _)| *l <= line)
+    }
+
+    /// The line shown at the visual line in the normal view, and whether
+    /// it's the virtual line with the code lenses of that line
+    pub fn line_of_visual_line(
+        &self,
+        visual_line: usize,
+        config: &LapceConfig,
+    ) -> (usize, bool) {
+        let code_lens_lines = self.code_lens_lines(config);
+        let i = code_lens_lines.partition_point(|(_, v)| *v < visual_line);
+        match code_lens_lines.get(i) {
+            Some((line, v)) if *v == visual_line => (*line, true),
+            _ => (visual_line - i, false),
+        }
+    }
+
+    /// The number of lines in the normal view, virtual ones included
+    pub fn num_visual_lines(&self, config: &LapceConfig) -> usize {
+        self.buffer.num_lines() + self.code_lens_lines(config).len()
+    }
+
+    /// Lays out the titles of the resolved code lenses of `line`
+    pub fn code_lens_layout(
+        &self,
+        text: &mut PietText,
+        line: usize,
+        config: &LapceConfig,
+    ) -> Option<CodeLensLayout> {
+        let code_lens = self.code_lens.as_ref()?;
+        let start_offset = self.buffer.offset_of_line(line);
+        let end_offset = self.buffer.offset_of_line(line + 1);
+
+        let mut content = String::new();
+        let mut titles = Vec::new();
+        for (i, lens) in code_lens.iter().enumerate() {
+            if lens.offset < start_offset || lens.offset >= end_offset {
+                continue;
+            }
+            let command = match lens.lens.command.as_ref() {
+                Some(command) => command,
+                None => continue,
+            };
+            if !content.is_empty() {
+                content.push_str(" | ");
+            }
+            let start = content.len();
+            content.push_str(&command.title);
+            titles.push((start..content.len(), i));
+        }
+        if titles.is_empty() {
+            return None;
+        }
+
+        let x = self
+            .get_text_layout(text, line, config.editor.font_size, config)
+            .indent;
+        let text = text
+            .new_text_layout(content)
+            .font(
+                config.editor.inlay_hint_font_family(),
+                config.editor.inlay_hint_font_size() as f64,
+            )
+            .text_color(config.get_color_unchecked(LapceTheme::EDITOR_DIM).clone())
+            .build()
+            .unwrap();
+        Some(CodeLensLayout { x, text, titles })
+    }
+
+    /// Get the code lens whose title is at the point, if any
+    pub fn code_lens_of_point(
+        &self,
+        text: &mut PietText,
+        point: Point,
+        view: &EditorView,
+        config: &LapceConfig,
+    ) -> Option<&DocumentCodeLens> {
+        if !matches!(view, EditorView::Normal) {
+            return None;
+        }
+        let code_lens = self.code_lens.as_ref()?;
+        let visual_line =
+            (point.y / config.editor.line_height() as f64).floor() as usize;
+        let (line, is_code_lens) = self.line_of_visual_line(visual_line, config);
+        if !is_code_lens {
+            return None;
+        }
+
+        let layout = self.code_lens_layout(text, line, config)?;
+        let hit_point = layout
+            .text
+            .hit_test_point(Point::new(point.x - layout.x, 0.0));
+        if !hit_point.is_inside {
+            return None;
+        }
+        layout
+            .titles
+            .iter()
+            .find(|(range, _)| range.contains(&hit_point.idx))
+            .and_then(|(_, i)| code_lens.get(*i))
+    }
+
     fn on_update(&mut self, edits: Option<SmallVec<[SyntaxEdit; 3]>>) {
         self.clear_code_actions();
+        self.document_highlights = None;
         self.find.borrow_mut().unset();
         *self.find_progress.borrow_mut() = FindProgress::Started;
         self.get_inlay_hints();
+        self.get_code_lens();
+        if edits.is_none() {
+            self.pull_diagnostics();
+        }
         self.clear_style_cache();
         self.trigger_syntax_change(edits);
         self.get_semantic_styles();
         self.clear_sticky_headers_cache();
-        self.trigger_head_change();
+        self.trigger_history_change();
         self.notify_special();
     }
 
@@ -944,6 +1344,39 @@ pub fn set_inlay_hints(&mut self, hints: Spans<InlayHint>) {
         self.clear_text_layout_cache();
     }
 
+    /// Set the occurrences of the symbol at `offset` as reported by the
+    /// language server, or find them with tree-sitter if no server could
+    /// provide them
+    pub fn set_document_highlights(
+        &mut self,
+        offset: usize,
+        highlights: Option<&[DocumentHighlight]>,
+    ) {
+        let ranges = match highlights {
+            Some(highlights) => highlights
+                .iter()
+                .map(|h| DocumentHighlightRange {
+                    start: self.buffer.offset_of_position(&h.range.start),
+                    end: self.buffer.offset_of_position(&h.range.end),
+                    kind: h.kind.unwrap_or(DocumentHighlightKind::TEXT),
+                })
+                .collect(),
+            None => self
+                .syntax
+                .as_ref()
+                .and_then(|syntax| syntax.find_identifier_occurrences(offset))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(start, end)| DocumentHighlightRange {
+                    start,
+                    end,
+                    kind: DocumentHighlightKind::TEXT,
+                })
+                .collect(),
+        };
+        self.document_highlights = Some(Arc::new(ranges));
+    }
+
     pub fn buffer(&self) -> &Buffer {
         &self.buffer
     }
@@ -1168,6 +1601,7 @@ fn apply_deltas(&mut self, deltas: &[(RopeDelta, InvalLines, SyntaxEdit)]) {
         for (i, (delta, _, _)) in deltas.iter().enumerate() {
             self.update_styles(delta);
             self.update_inlay_hints(delta);
+            self.update_code_lens(delta);
             self.update_diagnostics(delta);
             if let BufferContent::File(path) = &self.content {
                 self.proxy.proxy_rpc.update(
@@ -1217,13 +1651,33 @@ pub fn do_raw_edit(
 
     pub fn do_edit(
         &mut self,
+        text: &mut PietText,
         cursor: &mut Cursor,
         cmd: &EditCommand,
         modal: bool,
         register: &mut Register,
+        config: &LapceConfig,
     ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
         let mut clipboard = SystemClipboard {};
         let old_cursor = cursor.mode.clone();
+        let block = match cmd {
+            EditCommand::Paste | EditCommand::PasteBefore => {
+                let data = &register.unnamed;
+                (data.mode == VisualMode::Blockwise).then(|| data.content.clone())
+            }
+            EditCommand::ClipboardPaste => clipboard
+                .get_string()
+                .filter(|s| register.clipboard_mode(s) == VisualMode::Blockwise),
+            _ => None,
+        };
+        let mut text_columns = TextColumns;
+        let mut block_layout = block.map(|content| {
+            self.block_paste_layout(text, cursor.offset(), &content, config)
+        });
+        let layout: &mut dyn LineLayout = match block_layout.as_mut() {
+            Some(layout) => layout,
+            None => &mut text_columns,
+        };
         let deltas = Editor::do_edit(
             cursor,
             &mut self.buffer,
@@ -1232,6 +1686,7 @@ pub fn do_edit(
             &mut clipboard,
             modal,
             register,
+            layout,
         );
 
         if !deltas.is_empty() {
@@ -1243,6 +1698,25 @@ pub fn do_edit(
         deltas
     }
 
+    /// Moves the buffer to the state after the group `node` of its undo tree
+    pub fn do_undo_to(
+        &mut self,
+        cursor: &mut Cursor,
+        node: usize,
+        modal: bool,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let old_cursor = cursor.mode.clone();
+        let deltas = Editor::undo_to(cursor, &mut self.buffer, node, modal);
+
+        if !deltas.is_empty() {
+            self.buffer_mut().set_cursor_before(old_cursor);
+            self.buffer_mut().set_cursor_after(cursor.mode.clone());
+        }
+
+        self.apply_deltas(&deltas);
+        deltas
+    }
+
     pub fn do_multi_selection(
         &self,
         text: &mut PietText,
@@ -1305,6 +1779,32 @@ pub fn do_multi_selection(
                     cursor.set_insert(selection);
                 }
             }
+            SplitSelectionIntoLines => {
+                if let CursorMode::Insert(selection) = cursor.mode.clone() {
+                    cursor.set_insert(selection.split_lines(self.buffer.text()));
+                }
+            }
+            SelectSearchMatchesInSelection => {
+                if let CursorMode::Insert(selection) = cursor.mode.clone() {
+                    let matches = self.find.borrow().occurrences_in(
+                        self.buffer.text(),
+                        selection.min_offset(),
+                        selection.max_offset(),
+                    );
+                    let selection = selection.select_matches(matches);
+                    if !selection.is_empty() {
+                        cursor.set_insert(selection);
+                    }
+                }
+            }
+            ColumnSelectUp | ColumnSelectDown | ColumnSelectLeft
+            | ColumnSelectRight => {
+                if let CursorMode::Insert(selection) = cursor.mode.clone() {
+                    let selection =
+                        self.column_select(text, &selection, cmd, config);
+                    cursor.set_insert(selection);
+                }
+            }
             InsertCursorEndOfLine => {
                 if let CursorMode::Insert(selection) = cursor.mode.clone() {
                     let mut new_selection = Selection::new();
@@ -1497,11 +1997,89 @@ pub fn do_motion_mode(
         }
     }
 
-    pub fn do_paste(&mut self, cursor: &mut Cursor, data: &RegisterData) {
-        let deltas = Editor::do_paste(cursor, &mut self.buffer, data);
+    pub fn do_surround_add(
+        &mut self,
+        cursor: &mut Cursor,
+        surround: &Surround,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let old_cursor = cursor.mode.clone();
+        let deltas = Editor::surround_add(cursor, &mut self.buffer, surround);
+        self.buffer_mut().set_cursor_before(old_cursor);
+        self.buffer_mut().set_cursor_after(cursor.mode.clone());
+        self.apply_deltas(&deltas);
+        deltas
+    }
+
+    pub fn do_surround_delete(
+        &mut self,
+        cursor: &mut Cursor,
+        target: &Surround,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let old_cursor = cursor.mode.clone();
+        let deltas = Editor::surround_delete(cursor, &mut self.buffer, target);
+        self.buffer_mut().set_cursor_before(old_cursor);
+        self.buffer_mut().set_cursor_after(cursor.mode.clone());
+        self.apply_deltas(&deltas);
+        deltas
+    }
+
+    pub fn do_surround_change(
+        &mut self,
+        cursor: &mut Cursor,
+        target: &Surround,
+        surround: &Surround,
+    ) -> Vec<(RopeDelta, InvalLines, SyntaxEdit)> {
+        let old_cursor = cursor.mode.clone();
+        let deltas =
+            Editor::surround_change(cursor, &mut self.buffer, target, surround);
+        self.buffer_mut().set_cursor_before(old_cursor);
+        self.buffer_mut().set_cursor_after(cursor.mode.clone());
+        self.apply_deltas(&deltas);
+        deltas
+    }
+
+    pub fn do_paste(
+        &mut self,
+        text: &mut PietText,
+        cursor: &mut Cursor,
+        data: &RegisterData,
+        config: &LapceConfig,
+    ) {
+        let mut text_columns = TextColumns;
+        let mut block_layout = (data.mode == VisualMode::Blockwise).then(|| {
+            self.block_paste_layout(text, cursor.offset(), &data.content, config)
+        });
+        let layout: &mut dyn LineLayout = match block_layout.as_mut() {
+            Some(layout) => layout,
+            None => &mut text_columns,
+        };
+        let deltas = Editor::do_paste(cursor, &mut self.buffer, data, layout);
         self.apply_deltas(&deltas)
     }
 
+    /// The layouts of the lines the block `content` is pasted on, when it's
+    /// pasted at `offset`, so that they're measured before the paste
+    /// changes them
+    fn block_paste_layout(
+        &self,
+        text: &mut PietText,
+        offset: usize,
+        content: &str,
+        config: &LapceConfig,
+    ) -> BlockPasteLayout {
+        let font_size = config.editor.font_size;
+        let first_line = self.buffer.line_of_offset(offset);
+        let last_line =
+            (first_line + content.lines().count()).min(self.buffer.last_line());
+        BlockPasteLayout {
+            first_line,
+            layouts: (first_line..last_line + 1)
+                .map(|line| self.get_text_layout(text, line, font_size, config))
+                .collect(),
+            space_width: config.editor_text_width(text, " "),
+        }
+    }
+
     pub fn styles(&self) -> Option<&Arc<Spans<Style>>> {
         if let Some(semantic_styles) = self.semantic_styles.as_ref() {
             Some(semantic_styles)
@@ -1592,10 +2170,14 @@ pub fn line_col_of_point(
                     )
                 }
             }
-            EditorView::Normal => (
-                (point.y / config.editor.line_height() as f64).floor() as usize,
-                config.editor.font_size,
-            ),
+            EditorView::Normal => {
+                let visual_line =
+                    (point.y / config.editor.line_height() as f64).floor() as usize;
+                (
+                    self.line_of_visual_line(visual_line, config).0,
+                    config.editor.font_size,
+                )
+            }
         };
 
         let line = line.min(self.buffer.last_line());
@@ -1735,7 +2317,7 @@ pub fn points_of_line_col(
                 }
             }
             EditorView::Normal => (
-                config.editor.line_height() * line,
+                config.editor.line_height() * self.visual_line(line, config),
                 config.editor.line_height(),
                 config.editor.font_size,
             ),
@@ -2143,6 +2725,115 @@ fn new_whitespace_layout(
         Some(rendered_whitespaces)
     }
 
+    /// The column (block) selection between an anchor and a head, given as a
+    /// line and a horizontal position, with a region on every line between them.
+    /// The regions go from the anchor towards the head, lines ending before
+    /// the block get a caret at their end, and the head is the last inserted
+    /// region.
+    pub fn block_selection(
+        &self,
+        text: &mut PietText,
+        anchor: (usize, f64),
+        head: (usize, f64),
+        config: &LapceConfig,
+    ) -> Selection {
+        let font_size = config.editor.font_size;
+        let (anchor_line, anchor_x) = anchor;
+        let (head_line, head_x) = head;
+        let lines: Vec<usize> = if anchor_line <= head_line {
+            (anchor_line..head_line + 1).collect()
+        } else {
+            (head_line..anchor_line + 1).rev().collect()
+        };
+
+        let mut selection = Selection::new();
+        for line in lines {
+            let start_col = self.line_horiz_col(
+                text,
+                line,
+                font_size,
+                &ColPosition::Col(anchor_x),
+                true,
+                config,
+            );
+            let end_col = self.line_horiz_col(
+                text,
+                line,
+                font_size,
+                &ColPosition::Col(head_x),
+                true,
+                config,
+            );
+            selection.add_region(SelRegion::new(
+                self.buffer.offset_of_line_col(line, start_col),
+                self.buffer.offset_of_line_col(line, end_col),
+                Some(ColPosition::Col(head_x)),
+            ));
+        }
+        selection
+    }
+
+    /// Moves the head of a column selection, keeping the region at the
+    /// other end as its anchor.
+    fn column_select(
+        &self,
+        text: &mut PietText,
+        selection: &Selection,
+        cmd: &MultiSelectionCommand,
+        config: &LapceConfig,
+    ) -> Selection {
+        let (head, first, last) = match (
+            selection.last_inserted(),
+            selection.first(),
+            selection.last(),
+        ) {
+            (Some(head), Some(first), Some(last)) => (head, first, last),
+            _ => return selection.clone(),
+        };
+        let anchor = if head == first { last } else { first };
+        let font_size = config.editor.font_size;
+        let x_of_offset = |text: &mut PietText, offset: usize| {
+            let (line, col) = self.buffer.offset_to_line_col(offset);
+            self.line_point_of_line_col(text, line, col, font_size, config)
+                .x
+        };
+
+        let anchor_line = self.buffer.line_of_offset(anchor.start);
+        let anchor_x = x_of_offset(text, anchor.start);
+        let mut head_line = self.buffer.line_of_offset(head.end);
+        let mut head_x = match head.horiz {
+            Some(ColPosition::Col(x)) => x,
+            _ => x_of_offset(text, head.end),
+        };
+        match cmd {
+            MultiSelectionCommand::ColumnSelectUp => {
+                head_line = head_line.saturating_sub(1);
+            }
+            MultiSelectionCommand::ColumnSelectDown => {
+                head_line = (head_line + 1).min(self.buffer.last_line());
+            }
+            MultiSelectionCommand::ColumnSelectLeft => {
+                let line_start = self.buffer.offset_of_line(head_line);
+                let offset =
+                    self.buffer.prev_grapheme_offset(head.end, 1, line_start);
+                head_x = x_of_offset(text, offset);
+            }
+            MultiSelectionCommand::ColumnSelectRight => {
+                let line_end = self.buffer.line_end_offset(head_line, true);
+                let offset = self.buffer.next_grapheme_offset(head.end, 1, line_end);
+                head_x = x_of_offset(text, offset);
+            }
+            _ => {}
+        }
+
+        self.block_selection(
+            text,
+            (anchor_line, anchor_x),
+            (head_line, head_x),
+            config,
+        )
+    }
+
     pub fn line_horiz_col(
         &self,
         text: &mut PietText,
@@ -1,16 +1,16 @@
 use std::{
     cmp::Ordering,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     iter::Iterator,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     str::FromStr,
-    sync::Arc,
+    sync::{atomic::AtomicU64, Arc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
-use crossbeam_channel::{self, bounded};
+use crossbeam_channel::{self, bounded, Receiver};
 use druid::{
     piet::{PietText, PietTextLayout, Svg},
     Color, Command, Env, EventCtx, ExtEventSink, FileDialogOptions, Modifiers,
@@ -19,21 +19,25 @@
 use indexmap::IndexMap;
 pub use lapce_core::syntax::Syntax;
 use lapce_core::{
-    buffer::{Buffer, DiffLines, InvalLines},
+    buffer::{rope_diff, rope_text::RopeText, Buffer, DiffLines, InvalLines},
     command::{EditCommand, FocusCommand, MotionModeCommand, MultiSelectionCommand},
     editor::EditType,
-    mode::{Mode, MotionMode},
-    selection::{InsertDrift, Selection},
+    mode::{Mode, MotionMode, VisualMode},
+    register::Clipboard,
+    selection::{InsertDrift, SelRegion, Selection},
+    surround::Surround,
     syntax::edit::SyntaxEdit,
 };
-use lapce_rpc::{plugin::PluginId, proxy::ProxyResponse};
+use lapce_rpc::{plugin::PluginId, proxy::ProxyResponse, RpcError};
 use lapce_xi_rope::{Rope, RopeDelta, Transformer};
 use lsp_types::{
     request::GotoTypeDefinitionResponse, CodeAction, CodeActionOrCommand,
-    CodeActionResponse, CompletionItem, CompletionTextEdit, DiagnosticSeverity,
-    DocumentChangeOperation, DocumentChanges, GotoDefinitionResponse, Location,
-    OneOf, Position, ResourceOp, TextEdit, Url, WorkspaceEdit,
+    CodeActionResponse, CompletionItem, CompletionItemKind, CompletionTextEdit,
+    DiagnosticSeverity, DocumentChangeOperation, DocumentChanges, Documentation,
+    GotoDefinitionResponse, InsertTextFormat, Location, OneOf, Position, Range,
+    ResourceOp, TextEdit, TypeHierarchyItem, Url, WorkspaceEdit,
 };
+use serde_json::Value;
 
 use crate::{
     command::{
@@ -41,14 +45,22 @@
         InitBufferContentCb, LapceCommand, LapceUICommand, LAPCE_COMMAND,
         LAPCE_SAVE_FILE_AS, LAPCE_UI_COMMAND,
     },
-    completion::{CompletionData, CompletionStatus, Snippet},
+    completion::{
+        buffer_word_completion_items, path_completion_items,
+        syntax_completion_items, CompletionData, CompletionSource, CompletionStatus,
+        ScoredCompletionItem, Snippet, WordSource,
+    },
     config::LapceConfig,
     data::{
         EditorDiagnostic, EditorView, FocusArea, InlineFindDirection,
-        LapceEditorData, LapceMainSplitData, SplitContent,
+        LapceEditorData, LapceMainSplitData, PendingSurround, SplitContent,
+    },
+    document::{
+        BufferContent, Document, DocumentCodeLens, LocalBufferKind, SystemClipboard,
     },
-    document::{BufferContent, Document, LocalBufferKind},
     find::Find,
+    history::changed_range,
+    hot_exit::UnsavedBuffer,
     hover::{HoverData, HoverStatus},
     keypress::{KeyMap, KeyPressFocus},
     palette::PaletteData,
@@ -58,6 +70,7 @@
     signature::{SignatureData, SignatureStatus},
     source_control::SourceControlData,
     split::{SplitDirection, SplitMoveDirection},
+    task::TaskConfig,
 };
 
 pub struct LapceUI {}
@@ -79,7 +92,7 @@ fn init_buffer_content_cmd(
         path: PathBuf,
         content: Rope,
         locations: Vec<(WidgetId, EditorLocation<Self>)>,
-        edits: Option<Rope>,
+        unsaved: Option<UnsavedBuffer>,
         cb: Option<InitBufferContentCb>,
     ) -> LapceUICommand;
 }
@@ -94,14 +107,14 @@ fn init_buffer_content_cmd(
         path: PathBuf,
         content: Rope,
         locations: Vec<(WidgetId, EditorLocation<Self>)>,
-        unsaved_buffers: Option<Rope>,
+        unsaved: Option<UnsavedBuffer>,
         cb: Option<InitBufferContentCb>,
     ) -> LapceUICommand {
         LapceUICommand::InitBufferContent(InitBufferContent {
             path,
             content,
             locations,
-            edits: unsaved_buffers,
+            unsaved,
             cb,
         })
     }
@@ -121,21 +134,21 @@ fn init_buffer_content_cmd(
         path: PathBuf,
         content: Rope,
         locations: Vec<(WidgetId, EditorLocation<Self>)>,
-        edits: Option<Rope>,
+        unsaved: Option<UnsavedBuffer>,
         cb: Option<InitBufferContentCb>,
     ) -> LapceUICommand {
         LapceUICommand::InitBufferContentLine(InitBufferContent {
             path,
             content,
             locations,
-            edits,
+            unsaved,
             cb,
         })
     }
 }
 
 /// UTF8 line and column-offset
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LineCol {
     pub line: usize,
     pub column: usize,
@@ -150,14 +163,14 @@ fn init_buffer_content_cmd(
         path: PathBuf,
         content: Rope,
         locations: Vec<(WidgetId, EditorLocation<Self>)>,
-        edits: Option<Rope>,
+        unsaved: Option<UnsavedBuffer>,
         cb: Option<InitBufferContentCb>,
     ) -> LapceUICommand {
         LapceUICommand::InitBufferContentLineCol(InitBufferContent {
             path,
             content,
             locations,
-            edits,
+            unsaved,
             cb,
         })
     }
@@ -172,14 +185,14 @@ fn init_buffer_content_cmd(
         path: PathBuf,
         content: Rope,
         locations: Vec<(WidgetId, EditorLocation<Self>)>,
-        edits: Option<Rope>,
+        unsaved: Option<UnsavedBuffer>,
         cb: Option<InitBufferContentCb>,
     ) -> LapceUICommand {
         LapceUICommand::InitBufferContentLsp(InitBufferContent {
             path,
             content,
             locations,
-            edits,
+            unsaved,
             cb,
         })
     }
@@ -277,6 +290,63 @@ fn inline_find(
         }
     }
 
+    /// Takes a char typed for a pending surround command, either finishing
+    /// the edit or waiting for the rest of the pairs.
+    fn receive_surround_char(&mut self, pending: PendingSurround, c: char) {
+        let editor = Arc::make_mut(&mut self.editor);
+        editor.surround = None;
+        let (target, tag) = match pending {
+            PendingSurround::Delete => {
+                if let Some(target) = Surround::target_from_char(c) {
+                    let doc = Arc::make_mut(&mut self.doc);
+                    let deltas = doc.do_surround_delete(&mut editor.cursor, &target);
+                    self.apply_deltas(&deltas);
+                }
+                return;
+            }
+            PendingSurround::Change { target: None, .. } => {
+                editor.surround = Surround::target_from_char(c).map(|target| {
+                    PendingSurround::Change {
+                        target: Some(target),
+                        tag: None,
+                    }
+                });
+                return;
+            }
+            PendingSurround::Change {
+                target: Some(target),
+                tag,
+            } => (Some(target), tag),
+            PendingSurround::Add { tag } => (None, tag),
+        };
+
+        let surround = match tag {
+            Some(tag) if c == '>' => Surround::Tag(tag.trim().to_string()),
+            Some(mut tag) => {
+                tag.push(c);
+                editor.surround = Some(pending_surround(target, tag));
+                return;
+            }
+            None if Surround::is_tag_char(c) => {
+                editor.surround = Some(pending_surround(target, String::new()));
+                return;
+            }
+            None => match Surround::from_char(c) {
+                Some(surround) => surround,
+                None => return,
+            },
+        };
+
+        let doc = Arc::make_mut(&mut self.doc);
+        let deltas = match target {
+            Some(target) => {
+                doc.do_surround_change(&mut editor.cursor, &target, &surround)
+            }
+            None => doc.do_surround_add(&mut editor.cursor, &surround),
+        };
+        self.apply_deltas(&deltas);
+    }
+
     pub fn get_code_actions(&mut self, ctx: &mut EventCtx) {
         if !self.doc.loaded() {
             return;
@@ -352,6 +422,49 @@ pub fn get_code_actions(&mut self, ctx: &mut EventCtx) {
         }
     }
 
+    /// Request the occurrences of the symbol under the cursor, so that they
+    /// can be highlighted
+    pub fn get_document_highlights(&mut self, ctx: &mut EventCtx) {
+        if !self.doc.loaded() || !self.config.editor.highlight_symbol_occurrences {
+            return;
+        }
+        if let BufferContent::File(path) = self.doc.content() {
+            let path = path.clone();
+            let offset = self.editor.cursor.offset();
+            if self.doc.document_highlights.as_ref().map_or(false, |h| {
+                h.iter().any(|h| h.start <= offset && offset <= h.end)
+            }) {
+                return;
+            }
+
+            let position = self.doc.buffer().offset_to_position(offset);
+            let rev = self.doc.rev();
+            let event_sink = ctx.get_external_handle();
+            self.proxy.proxy_rpc.get_document_highlights(
+                path.clone(),
+                position,
+                move |result| {
+                    let highlights = match result {
+                        Ok(ProxyResponse::GetDocumentHighlights { highlights }) => {
+                            Some(highlights)
+                        }
+                        _ => None,
+                    };
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::UpdateDocumentHighlights {
+                            path,
+                            rev,
+                            offset,
+                            highlights,
+                        },
+                        Target::Auto,
+                    );
+                },
+            );
+        }
+    }
+
     fn inactive_apply_delta(&mut self, delta: &RopeDelta) {
         for (view_id, editor) in self.main_split.editors.iter_mut() {
             if view_id != &self.editor.view_id
@@ -519,6 +632,428 @@ fn completion_do_edit(
         self.apply_deltas(&[(delta, inval_lines, edits)]);
     }
 
+    /// Replaces `selection` with the text of `snippet`, along with the
+    /// `additional_edit`, and selects its first placeholder. The snippet
+    /// starts at `start_offset` before the edit.
+    fn do_snippet_edit(
+        &mut self,
+        selection: &Selection,
+        additional_edit: &[(&Selection, &str)],
+        start_offset: usize,
+        snippet: Snippet,
+    ) {
+        let text = snippet.text();
+        let old_cursor = self.editor.cursor.mode.clone();
+        let (delta, inval_lines, edits) = Arc::make_mut(&mut self.doc).do_raw_edit(
+            &[&[(selection, text.as_str())][..], additional_edit].concat(),
+            EditType::Completion,
+        );
+
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+
+        let mut transformer = Transformer::new(&delta);
+        let offset = transformer.transform(start_offset, false);
+        let snippet_tabs = snippet.tabs(offset);
+
+        if snippet_tabs.is_empty() {
+            Arc::make_mut(&mut self.editor)
+                .cursor
+                .update_selection(self.doc.buffer(), selection);
+
+            let doc = Arc::make_mut(&mut self.doc);
+            doc.buffer_mut().set_cursor_before(old_cursor);
+            doc.buffer_mut()
+                .set_cursor_after(self.editor.cursor.mode.clone());
+
+            self.apply_deltas(&[(delta, inval_lines, edits)]);
+            return;
+        }
+
+        let mut selection = lapce_core::selection::Selection::new();
+        let (tab, (start, end)) = snippet_tabs[0];
+        let region = lapce_core::selection::SelRegion::new(start, end, None);
+        selection.add_region(region);
+        Arc::make_mut(&mut self.editor).cursor.set_insert(selection);
+
+        let doc = Arc::make_mut(&mut self.doc);
+        doc.buffer_mut().set_cursor_before(old_cursor);
+        doc.buffer_mut()
+            .set_cursor_after(self.editor.cursor.mode.clone());
+
+        self.apply_deltas(&[(delta, inval_lines, edits)]);
+
+        let choices = snippet.choices();
+        let first_choices = choices
+            .iter()
+            .find(|(choice_tab, _)| *choice_tab == tab)
+            .map(|(_, choices)| choices.clone());
+        Arc::make_mut(&mut self.editor)
+            .add_snippet_placeholders(snippet_tabs, choices);
+        if let Some(choices) = first_choices {
+            self.show_snippet_choices(start, end, &choices);
+        }
+    }
+
+    /// Inserts the snippet `body` in place of the selection, switching to
+    /// insert mode so that its placeholders can be filled in.
+    pub fn insert_snippet(&mut self, body: &str) -> Result<()> {
+        let (start, end) = match &self.editor.cursor.mode {
+            lapce_core::cursor::CursorMode::Normal(offset) => (*offset, *offset),
+            _ => self
+                .editor
+                .cursor
+                .edit_selection(self.doc.buffer())
+                .first()
+                .map(|region| (region.min(), region.max()))
+                .ok_or_else(|| anyhow!("no selection"))?,
+        };
+
+        let body = self.indent_snippet(body, start);
+        let mut snippet = Snippet::from_str(&body)?;
+        snippet.resolve_variables(&|name| self.snippet_variable(name));
+
+        let selection = Selection::region(start, end);
+        Arc::make_mut(&mut self.editor)
+            .cursor
+            .set_insert(selection.clone());
+        self.do_snippet_edit(&selection, &[], start, snippet);
+        Ok(())
+    }
+
+    /// Moves the buffer to the state after the group `node` of its undo
+    /// tree, as picked in the undo tree panel or the palette.
+    pub fn undo_to(&mut self, node: usize) {
+        let modal = self.config.core.modal && !self.editor.content.is_input();
+        let doc = Arc::make_mut(&mut self.doc);
+        let cursor = &mut Arc::make_mut(&mut self.editor).cursor;
+        let deltas = doc.do_undo_to(cursor, node, modal);
+        self.cancel_completion();
+        self.apply_deltas(&deltas);
+    }
+
+    /// Replaces the content of the buffer with its content at `version`, as
+    /// an edit that can be undone. Only the part that differs is replaced,
+    /// so that the cursor stays where it is when it's outside of it.
+    pub fn restore_version(&mut self, version: &str) {
+        let content = match self.doc.get_history(version).and_then(|h| h.text()) {
+            Some(text) => text.slice_to_cow(..).to_string(),
+            None => return,
+        };
+        let current = self.doc.buffer().text().slice_to_cow(..).to_string();
+        let (old_range, new_range) = changed_range(&current, &content);
+        if old_range.is_empty() && new_range.is_empty() {
+            return;
+        }
+
+        let old_cursor = self.editor.cursor.mode.clone();
+        let doc = Arc::make_mut(&mut self.doc);
+        let (delta, inval_lines, edits) = doc.do_raw_edit(
+            &[(
+                Selection::region(old_range.start, old_range.end),
+                &content[new_range],
+            )],
+            EditType::Other,
+        );
+        let cursor = &mut Arc::make_mut(&mut self.editor).cursor;
+        cursor.apply_delta(&delta);
+
+        let doc = Arc::make_mut(&mut self.doc);
+        doc.buffer_mut().set_cursor_before(old_cursor);
+        doc.buffer_mut()
+            .set_cursor_after(self.editor.cursor.mode.clone());
+
+        self.cancel_completion();
+        self.apply_deltas(&[(delta, inval_lines, edits)]);
+    }
+
+    /// `body` with the lines after its first indented like the line at
+    /// `offset`, where the snippet is inserted.
+    fn indent_snippet(&self, body: &str, offset: usize) -> String {
+        let line = self.doc.buffer().line_of_offset(offset);
+        let indent = self.doc.buffer().indent_on_line(line);
+        body.replace('\n', &format!("\n{indent}"))
+    }
+
+    /// The value of a snippet variable, or `None` if the variable is
+    /// unknown.
+    fn snippet_variable(&self, name: &str) -> Option<String> {
+        let buffer = self.doc.buffer();
+        let offset = self.editor.cursor.offset();
+        let line = buffer.line_of_offset(offset);
+        let path = self.doc.content().path();
+        let now = chrono::Local::now();
+        let value = match name {
+            "TM_SELECTED_TEXT" => match &self.editor.cursor.mode {
+                lapce_core::cursor::CursorMode::Insert(selection) => selection
+                    .regions()
+                    .iter()
+                    .filter(|region| !region.is_caret())
+                    .map(|region| {
+                        buffer.slice_to_cow(region.min()..region.max()).to_string()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                lapce_core::cursor::CursorMode::Visual { .. } => {
+                    self.editor.cursor.yank(buffer).content
+                }
+                lapce_core::cursor::CursorMode::Normal(_) => String::new(),
+            },
+            "TM_CURRENT_LINE" => buffer
+                .line_content(line)
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            "TM_CURRENT_WORD" => {
+                let (start, end) = buffer.select_word(offset);
+                buffer.slice_to_cow(start..end).to_string()
+            }
+            "TM_LINE_INDEX" => line.to_string(),
+            "TM_LINE_NUMBER" => (line + 1).to_string(),
+            "TM_FILENAME" => path
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            "TM_FILENAME_BASE" => path
+                .and_then(|path| path.file_stem())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            "TM_DIRECTORY" => path
+                .and_then(|path| path.parent())
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            "TM_FILEPATH" => path
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "CLIPBOARD" => SystemClipboard {}.get_string().unwrap_or_default(),
+            "LINE_COMMENT" => self
+                .doc
+                .syntax()
+                .map(|syntax| syntax.language.comment_token().to_string())
+                .unwrap_or_default(),
+            "CURRENT_YEAR" => now.format("%Y").to_string(),
+            "CURRENT_YEAR_SHORT" => now.format("%y").to_string(),
+            "CURRENT_MONTH" => now.format("%m").to_string(),
+            "CURRENT_MONTH_NAME" => now.format("%B").to_string(),
+            "CURRENT_MONTH_NAME_SHORT" => now.format("%b").to_string(),
+            "CURRENT_DATE" => now.format("%d").to_string(),
+            "CURRENT_DAY_NAME" => now.format("%A").to_string(),
+            "CURRENT_DAY_NAME_SHORT" => now.format("%a").to_string(),
+            "CURRENT_HOUR" => now.format("%H").to_string(),
+            "CURRENT_MINUTE" => now.format("%M").to_string(),
+            "CURRENT_SECOND" => now.format("%S").to_string(),
+            "CURRENT_SECONDS_UNIX" => now.timestamp().to_string(),
+            "UUID" => uuid::Uuid::new_v4().to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// The user snippets for the language of the document, as completion
+    /// items for the word starting at `offset`.
+    fn snippet_completion_items(
+        &self,
+        offset: usize,
+    ) -> im::Vector<ScoredCompletionItem> {
+        let language = self.doc.syntax().map(|syntax| syntax.language);
+        let position = self.doc.buffer().offset_to_position(offset);
+        self.config
+            .snippets
+            .for_language(language)
+            .flat_map(|snippet| {
+                let body = self.indent_snippet(&snippet.body, offset);
+                snippet.prefix.iter().map(move |prefix| {
+                    ScoredCompletionItem::builtin(
+                        CompletionSource::Snippet,
+                        CompletionItem {
+                            label: prefix.clone(),
+                            kind: Some(CompletionItemKind::SNIPPET),
+                            detail: Some(
+                                snippet
+                                    .description
+                                    .clone()
+                                    .unwrap_or_else(|| snippet.name.clone()),
+                            ),
+                            documentation: Some(Documentation::String(
+                                snippet.body.clone(),
+                            )),
+                            insert_text_format: Some(InsertTextFormat::SNIPPET),
+                            text_edit: Some(CompletionTextEdit::Edit(
+                                TextEdit::new(
+                                    Range::new(position, position),
+                                    body.clone(),
+                                ),
+                            )),
+                            ..Default::default()
+                        },
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Lists the completion items that don't come from the plugins, for the
+    /// word starting at `offset`, in the background, as they walk the whole
+    /// documents or read a directory. They're sent back for the completion
+    /// request `request_id`. Only the files are listed when typing a path
+    /// in a string, and `words` is whether the identifiers and the words of
+    /// the documents are listed.
+    fn request_builtin_completion_items(
+        &self,
+        request_id: usize,
+        offset: usize,
+        words: bool,
+    ) {
+        const MAX_DOCUMENT_LEN: usize = 1024 * 1024;
+
+        let tab_id = *self.main_split.tab_id;
+        let event_sink = self.doc.event_sink.clone();
+        let send = move |items: Vec<ScoredCompletionItem>| {
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::UpdateBuiltinCompletion { request_id, items },
+                Target::Widget(tab_id),
+            );
+        };
+
+        if self.config.editor.completion_paths {
+            if let Some((dir, range)) = self.path_completion_dir(offset) {
+                self.proxy.proxy_rpc.read_dir(dir, move |result| {
+                    if let Ok(ProxyResponse::ReadDirResponse { items }) = result {
+                        send(path_completion_items(items.into_values(), range));
+                    }
+                });
+                return;
+            }
+        }
+        if !words {
+            return;
+        }
+
+        let syntax = self
+            .doc
+            .syntax()
+            .filter(|_| self.config.editor.completion_syntax_symbols)
+            .cloned();
+        let mut sources = Vec::new();
+        if self.config.editor.completion_buffer_words {
+            let path = self.doc.content().path();
+            let docs = std::iter::once(&self.doc).chain(
+                self.main_split
+                    .open_docs
+                    .iter()
+                    .filter(|(doc_path, _)| Some(doc_path.as_path()) != path)
+                    .map(|(_, doc)| doc),
+            );
+            for doc in docs {
+                if doc.buffer().len() > MAX_DOCUMENT_LEN {
+                    continue;
+                }
+                let current = Arc::ptr_eq(doc, &self.doc);
+                sources.push(WordSource {
+                    text: doc.buffer().text().clone(),
+                    exclude: if current { offset } else { usize::MAX },
+                    detail: doc
+                        .content()
+                        .path()
+                        .filter(|_| !current)
+                        .and_then(|path| path.file_name())
+                        .map(|name| name.to_string_lossy().to_string()),
+                });
+            }
+        }
+        if syntax.is_none() && sources.is_empty() {
+            return;
+        }
+
+        rayon::spawn(move || {
+            let mut labels = HashSet::new();
+            let mut items = Vec::new();
+            if let Some(syntax) = syntax {
+                items.extend(syntax_completion_items(&syntax, offset, &mut labels));
+            }
+            items.extend(buffer_word_completion_items(&sources, &mut labels));
+            send(items);
+        });
+    }
+
+    /// The directory typed in a string and the range of the file name
+    /// being typed after it, for the file name starting at `offset`, or
+    /// `None` if it's not a path in a string.
+    fn path_completion_dir(&self, offset: usize) -> Option<(PathBuf, Range)> {
+        let buffer = self.doc.buffer();
+        let line_start = buffer.offset_of_line(buffer.line_of_offset(offset));
+        let before = buffer.slice_to_cow(line_start..offset);
+        let quotes = ['"', '\'', '`'];
+        let in_string = match self.doc.syntax() {
+            Some(syntax) => syntax.in_string(offset),
+            None => before.chars().filter(|c| quotes.contains(c)).count() % 2 == 1,
+        };
+        if !in_string {
+            return None;
+        }
+
+        let string_start = before.rfind(quotes).map(|i| i + 1).unwrap_or(0);
+        let text = &before[string_start..];
+        let name_start = text.rfind(std::path::is_separator)? + 1;
+        let typed = Path::new(&text[..name_start]);
+        // The path of the document is the one in the workspace, which is
+        // on the remote machine for a remote workspace, where the proxy
+        // reads the directory.
+        let mut dir = if typed.has_root() {
+            PathBuf::new()
+        } else {
+            self.doc.content().path()?.parent()?.to_path_buf()
+        };
+        for component in typed.components() {
+            match component {
+                Component::ParentDir => {
+                    dir.pop();
+                }
+                Component::CurDir => {}
+                component => dir.push(component),
+            }
+        }
+
+        let range = Range::new(
+            buffer.offset_to_position(line_start + string_start + name_start),
+            buffer.offset_to_position(self.editor.cursor.offset()),
+        );
+        Some((dir, range))
+    }
+
+    /// Lists the `choices` of the snippet placeholder at `start..end` as
+    /// completion items that replace it.
+    fn show_snippet_choices(
+        &mut self,
+        start: usize,
+        end: usize,
+        choices: &[String],
+    ) {
+        let range = Range::new(
+            self.doc.buffer().offset_to_position(start),
+            self.doc.buffer().offset_to_position(end),
+        );
+        let items = choices
+            .iter()
+            .map(|choice| {
+                ScoredCompletionItem::builtin(
+                    CompletionSource::Snippet,
+                    CompletionItem {
+                        label: choice.clone(),
+                        kind: Some(CompletionItemKind::ENUM_MEMBER),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                            range,
+                            choice.clone(),
+                        ))),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        let offset = self.doc.buffer().prev_code_boundary(end);
+        let buffer_id = self.doc.id();
+        Arc::make_mut(&mut self.completion).show_items(buffer_id, offset, items);
+    }
+
     pub fn apply_completion_item(&mut self, item: &CompletionItem) -> Result<()> {
         let additional_edit: Option<Vec<_>> =
             item.additional_text_edits.as_ref().map(|edits| {
@@ -572,72 +1107,17 @@ pub fn apply_completion_item(&mut self, item: &CompletionItem) -> Result<()> {
                             return Ok(());
                         }
                         lsp_types::InsertTextFormat::SNIPPET => {
-                            let snippet = Snippet::from_str(&edit.new_text)?;
-                            let text = snippet.text();
-                            let old_cursor = self.editor.cursor.mode.clone();
-                            let (delta, inval_lines, edits) =
-                                Arc::make_mut(&mut self.doc).do_raw_edit(
-                                    &[
-                                        &[(&selection, text.as_str())][..],
-                                        &additional_edit[..],
-                                    ]
-                                    .concat(),
-                                    EditType::Completion,
-                                );
-
-                            let selection = selection.apply_delta(
-                                &delta,
-                                true,
-                                InsertDrift::Default,
-                            );
+                            let mut snippet = Snippet::from_str(&edit.new_text)?;
+                            snippet.resolve_variables(&|name| {
+                                self.snippet_variable(name)
+                            });
 
-                            let start_offset = additional_edit
-                                .iter()
-                                .map(|(selection, _)| selection.min_offset())
-                                .min()
-                                .map(|offset| {
-                                    offset.min(start_offset).min(edit_start)
-                                })
-                                .unwrap_or(start_offset);
-
-                            let mut transformer = Transformer::new(&delta);
-                            let offset = transformer.transform(start_offset, false);
-                            let snippet_tabs = snippet.tabs(offset);
-
-                            if snippet_tabs.is_empty() {
-                                Arc::make_mut(&mut self.editor)
-                                    .cursor
-                                    .update_selection(self.doc.buffer(), selection);
-
-                                let doc = Arc::make_mut(&mut self.doc);
-                                doc.buffer_mut().set_cursor_before(old_cursor);
-                                doc.buffer_mut().set_cursor_after(
-                                    self.editor.cursor.mode.clone(),
-                                );
-
-                                self.apply_deltas(&[(delta, inval_lines, edits)]);
-                                return Ok(());
-                            }
-
-                            let mut selection =
-                                lapce_core::selection::Selection::new();
-                            let (_tab, (start, end)) = &snippet_tabs[0];
-                            let region = lapce_core::selection::SelRegion::new(
-                                *start, *end, None,
+                            self.do_snippet_edit(
+                                &selection,
+                                &additional_edit,
+                                start_offset,
+                                snippet,
                             );
-                            selection.add_region(region);
-                            Arc::make_mut(&mut self.editor)
-                                .cursor
-                                .set_insert(selection);
-
-                            let doc = Arc::make_mut(&mut self.doc);
-                            doc.buffer_mut().set_cursor_before(old_cursor);
-                            doc.buffer_mut()
-                                .set_cursor_after(self.editor.cursor.mode.clone());
-
-                            self.apply_deltas(&[(delta, inval_lines, edits)]);
-                            Arc::make_mut(&mut self.editor)
-                                .add_snippet_placeholders(snippet_tabs);
                             return Ok(());
                         }
                         _ => {}
@@ -716,6 +1196,18 @@ pub fn completion_item_select(&mut self, ctx: &mut EventCtx) {
         };
 
         self.cancel_completion();
+
+        let history =
+            Arc::make_mut(&mut Arc::make_mut(&mut self.completion).history);
+        history.record(&item.item.label);
+        if let Err(err) = self
+            .main_split
+            .db
+            .save_completion_history(&self.main_split.workspace, history)
+        {
+            log::error!("failed to save the completion history: {err}");
+        }
+
         if item.item.data.is_some() {
             let view_id = self.editor.view_id;
             let buffer_id = self.doc.id();
@@ -822,11 +1314,17 @@ fn update_completion(
             return;
         }
 
+        // The identifiers and words are left out when completing the member
+        // of something, where only the plugins know what fits.
+        let words = display_if_empty_input || !input.is_empty();
+        let builtin_items = self.snippet_completion_items(start_offset);
+        let completion = Arc::make_mut(&mut self.completion);
         completion.buffer_id = self.doc.id();
         completion.offset = start_offset;
         completion.input = input.clone();
         completion.status = CompletionStatus::Started;
         completion.input_items.clear();
+        completion.builtin_items = builtin_items;
         completion.request_id += 1;
         let start_pos = self.doc.buffer().offset_to_position(start_offset);
         completion.request(
@@ -845,6 +1343,9 @@ fn update_completion(
                 position,
             );
         }
+        completion.filter_items();
+        let request_id = completion.request_id;
+        self.request_builtin_completion_items(request_id, start_offset, words);
     }
 
     fn update_signature(&mut self) {
@@ -1187,19 +1688,31 @@ fn scroll(
         let top = self.editor.scroll_offset.y + diff;
         let bottom = top + self.editor.size.borrow().height;
 
-        let new_line = if (line + 1) as f64 * line_height + line_height > bottom {
-            let line = (bottom / line_height).floor() as usize;
-            if line > 2 {
-                line - 2
-            } else {
-                0
-            }
-        } else if line as f64 * line_height - line_height < top {
-            let line = (top / line_height).ceil() as usize;
-            line + 1
+        // the code lenses only take up lines of their own in the normal view
+        let is_normal = self.editor.view.is_normal();
+        let visual_line = if is_normal {
+            self.doc.visual_line(line, &self.config)
         } else {
             line
         };
+        let line_of_visual_line = |visual_line: usize| {
+            if is_normal {
+                self.doc.line_of_visual_line(visual_line, &self.config).0
+            } else {
+                visual_line
+            }
+        };
+
+        let new_line =
+            if (visual_line + 1) as f64 * line_height + line_height > bottom {
+                let line = (bottom / line_height).floor() as usize;
+                line_of_visual_line(if line > 2 { line - 2 } else { 0 })
+            } else if visual_line as f64 * line_height - line_height < top {
+                let line = (top / line_height).ceil() as usize;
+                line_of_visual_line(line + 1)
+            } else {
+                line
+            };
 
         match new_line.cmp(&line) {
             Ordering::Greater => {
@@ -1279,6 +1792,7 @@ pub fn offset_of_mouse(
             (line, config.editor_char_width(text))
         } else {
             let line = (pos.y / config.editor.line_height() as f64).floor() as usize;
+            let line = self.doc.line_of_visual_line(line, config).0;
             (line, config.editor_char_width(text))
         };
 
@@ -1308,6 +1822,20 @@ pub fn single_click(
         mouse_event: &MouseEvent,
         config: &LapceConfig,
     ) {
+        if let Some(lens) = self
+            .doc
+            .code_lens_of_point(
+                ctx.text(),
+                mouse_event.pos,
+                &self.editor.view,
+                config,
+            )
+            .cloned()
+        {
+            self.run_code_lens(ctx, lens);
+            return;
+        }
+
         let (new_offset, _) = self.doc.offset_of_point(
             ctx.text(),
             self.get_mode(),
@@ -1346,6 +1874,90 @@ pub fn single_click(
         }
     }
 
+    /// Run the command of a code lens. The `showReferences` commands are
+    /// client side, so they are shown in the palette like references, and
+    /// the runnables of rust-analyzer are run as tasks.
+    fn run_code_lens(&mut self, ctx: &mut EventCtx, lens: DocumentCodeLens) {
+        let command = match lens.lens.command {
+            Some(command) => command,
+            None => return,
+        };
+        let arguments = command.arguments.unwrap_or_default();
+        if command.command.ends_with("showReferences") {
+            let locations = arguments
+                .get(2)
+                .cloned()
+                .and_then(|v| serde_json::from_value::<Vec<Location>>(v).ok())
+                .unwrap_or_default();
+            if !locations.is_empty() {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::PaletteReferences(
+                        self.editor.cursor.offset(),
+                        locations,
+                    ),
+                    Target::Auto,
+                ));
+            }
+        } else if command.command == "rust-analyzer.runSingle"
+            || command.command == "rust-analyzer.debugSingle"
+        {
+            // there's no debugger, so debugging a runnable just runs it
+            if let Some((name, task)) =
+                arguments.get(0).and_then(rust_analyzer_runnable)
+            {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunTaskConfig { name, task },
+                    Target::Auto,
+                ));
+            }
+        } else {
+            self.proxy.proxy_rpc.execute_command(
+                lens.plugin_id,
+                command.command,
+                arguments,
+                |_| {},
+            );
+        }
+    }
+
+    /// Selects the column (block) between the point where an alt+drag
+    /// started and the current mouse position.
+    pub fn column_select_drag(
+        &mut self,
+        text: &mut PietText,
+        start: Point,
+        end: Point,
+        config: &LapceConfig,
+    ) {
+        let mode = self.get_mode();
+        let view = self.editor.view.clone();
+        let editor = Arc::make_mut(&mut self.editor);
+        if let lapce_core::cursor::CursorMode::Insert(_) = editor.cursor.mode {
+            let ((start_line, _), _) =
+                self.doc.line_col_of_point(text, mode, start, &view, config);
+            let ((end_line, _), _) =
+                self.doc.line_col_of_point(text, mode, end, &view, config);
+            let selection = self.doc.block_selection(
+                text,
+                (start_line, start.x),
+                (end_line, end.x),
+                config,
+            );
+            editor.cursor.set_insert(selection);
+        } else {
+            let (start, _) =
+                self.doc.offset_of_point(text, mode, start, &view, config);
+            let (end, _) = self.doc.offset_of_point(text, mode, end, &view, config);
+            editor.cursor.mode = lapce_core::cursor::CursorMode::Visual {
+                start,
+                end,
+                mode: VisualMode::Blockwise,
+            };
+        }
+    }
+
     pub fn double_click(
         &mut self,
         ctx: &mut EventCtx,
@@ -1422,40 +2034,37 @@ fn save(&mut self, ctx: &mut EventCtx, exit: bool, allow_formatting: bool) {
         if let BufferContent::File(path) = self.doc.content() {
             let format_on_save =
                 allow_formatting && self.config.editor.format_on_save;
+            let modified_only =
+                format_on_save && self.config.editor.format_modified_lines_on_save;
+            // The content at git head to diff against, if only the modified
+            // lines should be formatted
+            let head = if modified_only {
+                self.doc
+                    .get_history("head")
+                    .and_then(|history| history.text())
+                    .cloned()
+            } else {
+                None
+            };
+            // Without the content at git head, which lines are modified isn't
+            // known, so none of them are formatted.
+            let format_on_save =
+                format_on_save && (!modified_only || head.is_some());
+            let text = self.doc.buffer().text().clone();
+            let atomic_rev = self.doc.buffer().atomic_rev();
             let path = path.clone();
             let proxy = self.proxy.clone();
             let rev = self.doc.rev();
             let event_sink = ctx.get_external_handle();
             let view_id = self.editor.view_id;
             let tab_id = self.main_split.tab_id.clone();
-            let (sender, receiver) = bounded(1);
             thread::spawn(move || {
-                proxy.proxy_rpc.get_document_formatting(
-                    path.clone(),
-                    Box::new(move |result| {
-                        let _ = sender.send(result);
-                    }),
-                );
-
-                let result =
-                    receiver.recv_timeout(Duration::from_secs(1)).map_or_else(
-                        |e| Err(anyhow!("{}", e)),
-                        |v| {
-                            v.map_err(|e| anyhow!("{:?}", e)).and_then(|r| {
-                                if let ProxyResponse::GetDocumentFormatting {
-                                    edits,
-                                } = r
-                                {
-                                    Ok(edits)
-                                } else {
-                                    Err(anyhow!("wrong response"))
-                                }
-                            })
-                        },
-                    );
-
                 let exit = if exit { Some(view_id) } else { None };
                 let cmd = if format_on_save {
+                    let ranges = head.map(|head| {
+                        modified_line_ranges(head, text, rev, atomic_rev)
+                    });
+                    let result = get_formatting_edits(&proxy, &path, ranges);
                     LapceUICommand::DocumentFormatAndSave(path, rev, result, exit)
                 } else {
                     LapceUICommand::DocumentSave(path, exit)
@@ -1478,6 +2087,49 @@ fn save(&mut self, ctx: &mut EventCtx, exit: bool, allow_formatting: bool) {
         }
     }
 
+    /// Ask the language server for the edits to make after typing `ch`, if
+    /// `ch` is one of the trigger characters of a server
+    fn on_type_formatting(&self, ctx: &mut EventCtx, ch: &str) {
+        if !self.config.editor.format_on_type
+            || !self
+                .main_split
+                .on_type_formatting_triggers
+                .values()
+                .any(|characters| characters.iter().any(|c| c == ch))
+        {
+            return;
+        }
+
+        if let BufferContent::File(path) = self.doc.content() {
+            let path = path.clone();
+            let position = self
+                .doc
+                .buffer()
+                .offset_to_position(self.editor.cursor.offset());
+            let rev = self.doc.rev();
+            let event_sink = ctx.get_external_handle();
+            let tab_id = *self.main_split.tab_id;
+            self.proxy.proxy_rpc.get_on_type_formatting(
+                path.clone(),
+                position,
+                ch.to_string(),
+                move |result| {
+                    if let Ok(ProxyResponse::GetDocumentFormatting { edits }) =
+                        result
+                    {
+                        if !edits.is_empty() {
+                            let _ = event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::DocumentFormat(path, rev, Ok(edits)),
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                },
+            );
+        }
+    }
+
     fn run_move_command(
         &mut self,
         ctx: &mut EventCtx,
@@ -1545,7 +2197,8 @@ fn run_edit_command(
                 None
             };
 
-        let deltas = doc.do_edit(cursor, cmd, modal, register);
+        let deltas =
+            doc.do_edit(ctx.text(), cursor, cmd, modal, register, &self.config);
 
         if !deltas.is_empty() {
             if let Some(data) = yank_data {
@@ -1560,7 +2213,12 @@ fn run_edit_command(
         }
         self.apply_deltas(&deltas);
         if let EditCommand::NormalMode = cmd {
-            Arc::make_mut(&mut self.editor).snippet = None;
+            let editor = Arc::make_mut(&mut self.editor);
+            editor.snippet = None;
+            editor.surround = None;
+        }
+        if let EditCommand::InsertNewLine = cmd {
+            self.on_type_formatting(ctx, "\n");
         }
 
         CommandExecuted::Yes
@@ -1924,7 +2582,13 @@ fn run_focus_command(
 
                     let last_placeholder = current + 1 >= snippet.len() - 1;
 
-                    if let Some((_, (start, end))) = snippet.get(current + 1) {
+                    let mut choices = None;
+                    if let Some((tab, (start, end))) = snippet.get(current + 1) {
+                        choices = self
+                            .editor
+                            .snippet_choices
+                            .get(tab)
+                            .map(|choices| (*start, *end, choices.clone()));
                         let mut selection = lapce_core::selection::Selection::new();
                         let region = lapce_core::selection::SelRegion::new(
                             *start, *end, None,
@@ -1938,6 +2602,9 @@ fn run_focus_command(
                     }
                     self.update_signature();
                     self.cancel_completion();
+                    if let Some((start, end, choices)) = choices {
+                        self.show_snippet_choices(start, end, &choices);
+                    }
                 }
             }
             JumpToPrevSnippetPlaceholder => {
@@ -1952,7 +2619,13 @@ fn run_focus_command(
                     }
 
                     if current > 0 {
-                        if let Some((_, (start, end))) = snippet.get(current - 1) {
+                        let mut choices = None;
+                        if let Some((tab, (start, end))) = snippet.get(current - 1) {
+                            choices = self
+                                .editor
+                                .snippet_choices
+                                .get(tab)
+                                .map(|choices| (*start, *end, choices.clone()));
                             let mut selection =
                                 lapce_core::selection::Selection::new();
                             let region = lapce_core::selection::SelRegion::new(
@@ -1965,6 +2638,9 @@ fn run_focus_command(
                         }
                         self.update_signature();
                         self.cancel_completion();
+                        if let Some((start, end, choices)) = choices {
+                            self.show_snippet_choices(start, end, &choices);
+                        }
                     }
                 }
             }
@@ -2201,6 +2877,83 @@ fn run_focus_command(
                     );
                 }
             }
+            GotoImplementation => {
+                if let BufferContent::File(path) = self.doc.content() {
+                    let offset = self.editor.cursor.offset();
+                    let event_sink = ctx.get_external_handle();
+                    let position = self.doc.buffer().offset_to_position(offset);
+                    let editor_view_id = self.editor.view_id;
+                    self.proxy.proxy_rpc.get_implementation(
+                        offset,
+                        path.clone(),
+                        position,
+                        move |result| {
+                            if let Ok(ProxyResponse::GetImplementation {
+                                resp,
+                                ..
+                            }) = result
+                            {
+                                process_goto_locations(
+                                    editor_view_id,
+                                    offset,
+                                    resp,
+                                    event_sink,
+                                );
+                            }
+                        },
+                    );
+                }
+            }
+            GotoDeclaration => {
+                if let BufferContent::File(path) = self.doc.content() {
+                    let offset = self.editor.cursor.offset();
+                    let event_sink = ctx.get_external_handle();
+                    let position = self.doc.buffer().offset_to_position(offset);
+                    let editor_view_id = self.editor.view_id;
+                    self.proxy.proxy_rpc.get_declaration(
+                        offset,
+                        path.clone(),
+                        position,
+                        move |result| {
+                            if let Ok(ProxyResponse::GetDeclaration {
+                                resp, ..
+                            }) = result
+                            {
+                                process_goto_locations(
+                                    editor_view_id,
+                                    offset,
+                                    resp,
+                                    event_sink,
+                                );
+                            }
+                        },
+                    );
+                }
+            }
+            ShowTypeHierarchy => {
+                if let BufferContent::File(path) = self.doc.content() {
+                    let offset = self.editor.cursor.offset();
+                    let event_sink = ctx.get_external_handle();
+                    let position = self.doc.buffer().offset_to_position(offset);
+                    let proxy = self.proxy.clone();
+                    self.proxy.proxy_rpc.prepare_type_hierarchy(
+                        path.clone(),
+                        position,
+                        move |result| {
+                            if let Ok(ProxyResponse::PrepareTypeHierarchy {
+                                items,
+                            }) = result
+                            {
+                                if let Some(root) = items.into_iter().next() {
+                                    get_type_hierarchy(
+                                        proxy, offset, root, event_sink,
+                                    );
+                                }
+                            }
+                        },
+                    );
+                }
+            }
             ShowHover => {
                 let offset = self.editor.cursor.offset();
                 self.update_hover(ctx, offset);
@@ -2231,33 +2984,52 @@ fn run_focus_command(
                     let proxy = self.proxy.clone();
                     let rev = self.doc.rev();
                     let event_sink = ctx.get_external_handle();
-                    let (sender, receiver) = bounded(1);
                     let tab_id = self.main_split.tab_id.clone();
                     thread::spawn(move || {
-                        proxy.proxy_rpc.get_document_formatting(
-                            path.clone(),
-                            Box::new(move |result| {
-                                let _ = sender.send(result);
-                            }),
+                        let result = get_formatting_edits(&proxy, &path, None);
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::DocumentFormat(path, rev, result),
+                            Target::Widget(*tab_id),
                         );
+                    });
+                }
+            }
+            FormatSelection => {
+                if let BufferContent::File(path) = self.doc.content() {
+                    let buffer = self.doc.buffer();
+                    let (start, end) = match &self.editor.cursor.mode {
+                        lapce_core::cursor::CursorMode::Normal(offset) => {
+                            (*offset, *offset)
+                        }
+                        lapce_core::cursor::CursorMode::Visual {
+                            start,
+                            end,
+                            ..
+                        } => (*start.min(end), *start.max(end)),
+                        lapce_core::cursor::CursorMode::Insert(selection) => {
+                            (selection.min_offset(), selection.max_offset())
+                        }
+                    };
+                    // Format whole lines, since that is what most formatters
+                    // are able to do
+                    let start_line = buffer.line_of_offset(start);
+                    let end_line = buffer.line_of_offset(end);
+                    let range = Range {
+                        start: buffer
+                            .offset_to_position(buffer.offset_of_line(start_line)),
+                        end: buffer
+                            .offset_to_position(buffer.offset_of_line(end_line + 1)),
+                    };
 
-                        let result = receiver
-                            .recv_timeout(Duration::from_secs(1))
-                            .map_or_else(
-                                |e| Err(anyhow!("{}", e)),
-                                |v| {
-                                    v.map_err(|e| anyhow!("{:?}", e)).and_then(|r| {
-                                        if let ProxyResponse::GetDocumentFormatting {
-                                            edits,
-                                        } = r
-                                        {
-                                            Ok(edits)
-                                        } else {
-                                            Err(anyhow!("wrong response"))
-                                        }
-                                    })
-                                },
-                            );
+                    let path = path.clone();
+                    let proxy = self.proxy.clone();
+                    let rev = self.doc.rev();
+                    let event_sink = ctx.get_external_handle();
+                    let tab_id = self.main_split.tab_id.clone();
+                    thread::spawn(move || {
+                        let result =
+                            get_formatting_edits(&proxy, &path, Some(vec![range]));
                         let _ = event_sink.submit_command(
                             LAPCE_UI_COMMAND,
                             LapceUICommand::DocumentFormat(path, rev, result),
@@ -2337,6 +3109,56 @@ fn run_focus_command(
                     self.inline_find(ctx, direction, &c);
                 }
             }
+            SurroundAdd => {
+                let editor = Arc::make_mut(&mut self.editor);
+                if editor.cursor.is_normal() {
+                    // `ys` waits for a motion to select the text first,
+                    // and `yss` selects the whole line
+                    if editor.cursor.motion_mode == Some(MotionMode::Yank) {
+                        editor.cursor.motion_mode = None;
+                    }
+                    let doc = Arc::make_mut(&mut self.doc);
+                    let register = Arc::make_mut(&mut self.main_split.register);
+                    doc.do_motion_mode(
+                        &mut editor.cursor,
+                        MotionMode::Surround,
+                        register,
+                    );
+                } else if let lapce_core::cursor::CursorMode::Insert(selection) =
+                    &editor.cursor.mode
+                {
+                    // A caret surrounds the word it's in, and without one
+                    // there's nothing to surround, so the next char is
+                    // typed as usual instead of being taken for the pair.
+                    let mut new_selection = Selection::new();
+                    for region in selection.regions() {
+                        let (start, end) = if region.is_caret() {
+                            self.doc.buffer().select_word(region.start)
+                        } else {
+                            (region.start, region.end)
+                        };
+                        new_selection.add_region(SelRegion::new(start, end, None));
+                    }
+                    if new_selection.regions().iter().all(|r| r.is_caret()) {
+                        return CommandExecuted::Yes;
+                    }
+                    editor.cursor.set_insert(new_selection);
+                }
+                editor.surround = Some(PendingSurround::Add { tag: None });
+            }
+            SurroundDelete => {
+                let editor = Arc::make_mut(&mut self.editor);
+                editor.cursor.motion_mode = None;
+                editor.surround = Some(PendingSurround::Delete);
+            }
+            SurroundChange => {
+                let editor = Arc::make_mut(&mut self.editor);
+                editor.cursor.motion_mode = None;
+                editor.surround = Some(PendingSurround::Change {
+                    target: None,
+                    tag: None,
+                });
+            }
             SaveAndExit => {
                 self.save(ctx, true, true);
             }
@@ -2421,6 +3243,25 @@ fn run_focus_command(
             }
             SelectPreviousSyntaxItem => self
                 .run_selection_range_command(ctx, SelectionRangeDirection::Previous),
+            RunSelectionInTerminal => {
+                let buffer = self.doc.buffer();
+                let text = match &self.editor.cursor.mode {
+                    lapce_core::cursor::CursorMode::Normal(offset) => buffer
+                        .line_content(buffer.line_of_offset(*offset))
+                        .to_string(),
+                    _ => self.editor.cursor.yank(buffer).content,
+                };
+                if !text.trim().is_empty() {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunInTerminal {
+                            text,
+                            language: self.doc.syntax().map(|s| s.language),
+                        },
+                        Target::Widget(*self.main_split.tab_id),
+                    ));
+                }
+            }
             _ => return CommandExecuted::No,
         }
         CommandExecuted::Yes
@@ -2531,6 +3372,8 @@ fn focus_only(&self) -> bool {
 
     fn expect_char(&self) -> bool {
         self.editor.inline_find.is_some()
+            || (self.editor.surround.is_some()
+                && self.editor.cursor.motion_mode.is_none())
     }
 
     fn check_condition(&self, condition: &str) -> bool {
@@ -2563,6 +3406,15 @@ fn check_condition(&self, condition: &str) -> bool {
                     == BufferContent::Local(LocalBufferKind::SourceControl)
             }
             "in_snippet" => self.editor.snippet.is_some(),
+            "motion_mode_delete" => {
+                self.editor.cursor.motion_mode == Some(MotionMode::Delete)
+            }
+            "motion_mode_yank" => {
+                self.editor.cursor.motion_mode == Some(MotionMode::Yank)
+            }
+            "motion_mode_surround" => {
+                self.editor.cursor.motion_mode == Some(MotionMode::Surround)
+            }
             "completion_focus" => self.has_completions(),
             "hover_focus" => self.has_hover(),
             "list_focus" => self.has_completions() || self.is_palette(),
@@ -2578,7 +3430,11 @@ fn check_condition(&self, condition: &str) -> bool {
     }
 
     fn receive_char(&mut self, ctx: &mut EventCtx, c: &str) {
-        if self.get_mode() == Mode::Insert {
+        if let Some(pending) = self.editor.surround.clone() {
+            if let Some(c) = c.chars().next() {
+                self.receive_surround_char(pending, c);
+            }
+        } else if self.get_mode() == Mode::Insert {
             let doc = Arc::make_mut(&mut self.doc);
             let cursor = &mut Arc::make_mut(&mut self.editor).cursor;
             let deltas = doc.do_insert(cursor, c, &self.config);
@@ -2593,6 +3449,7 @@ fn receive_char(&mut self, ctx: &mut EventCtx, c: &str) {
             }
             self.cancel_hover();
             self.apply_deltas(&deltas);
+            self.on_type_formatting(ctx, c);
         } else if let Some(direction) = self.editor.inline_find.clone() {
             self.inline_find(ctx, direction.clone(), c);
             let editor = Arc::make_mut(&mut self.editor);
@@ -2729,6 +3586,227 @@ fn process_get_references(
     }
 }
 
+/// Request the formatting edits of the ranges, or of the whole document if
+/// there are no ranges, waiting up to a second for each response
+fn get_formatting_edits(
+    proxy: &LapceProxy,
+    path: &Path,
+    ranges: Option<Vec<Range>>,
+) -> Result<Vec<TextEdit>> {
+    let receive = |receiver: Receiver<Result<ProxyResponse, RpcError>>| {
+        receiver.recv_timeout(Duration::from_secs(1)).map_or_else(
+            |e| Err(anyhow!("{}", e)),
+            |v| {
+                v.map_err(|e| anyhow!("{:?}", e)).and_then(|r| {
+                    if let ProxyResponse::GetDocumentFormatting { edits } = r {
+                        Ok(edits)
+                    } else {
+                        Err(anyhow!("wrong response"))
+                    }
+                })
+            },
+        )
+    };
+
+    let ranges = match ranges {
+        Some(ranges) => ranges,
+        None => {
+            let (sender, receiver) = bounded(1);
+            proxy.proxy_rpc.get_document_formatting(
+                path.to_path_buf(),
+                Box::new(move |result| {
+                    let _ = sender.send(result);
+                }),
+            );
+            return receive(receiver);
+        }
+    };
+
+    // All the ranges are requested at once and share the timeout, and the
+    // ranges that didn't get an answer in time are left unformatted.
+    let (sender, receiver) = bounded(ranges.len());
+    for (i, range) in ranges.iter().enumerate() {
+        let sender = sender.clone();
+        proxy.proxy_rpc.get_document_range_formatting(
+            path.to_path_buf(),
+            *range,
+            Box::new(move |result| {
+                let _ = sender.send((i, result));
+            }),
+        );
+    }
+    drop(sender);
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut responses: Vec<Option<Vec<TextEdit>>> = vec![None; ranges.len()];
+    let mut last_error = None;
+    for _ in 0..ranges.len() {
+        let (i, result) = match receiver.recv_deadline(deadline) {
+            Ok(response) => response,
+            Err(e) => {
+                last_error = Some(anyhow!("{}", e));
+                break;
+            }
+        };
+        match result {
+            Ok(ProxyResponse::GetDocumentFormatting { edits }) => {
+                responses[i] = Some(edits);
+            }
+            Ok(_) => last_error = Some(anyhow!("wrong response")),
+            Err(e) => last_error = Some(anyhow!("{:?}", e)),
+        }
+    }
+    if responses.iter().all(Option::is_none) {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+    }
+
+    let mut edits: Vec<TextEdit> = Vec::new();
+    // Some servers format more than the requested range, so the same edit
+    // can come back for several ranges. The edits of one response never
+    // overlap, but those of different ones can, and only the first of them
+    // is kept.
+    for edit in responses.into_iter().flatten().flatten() {
+        let overlaps = edits.iter().any(|e| {
+            e == &edit
+                || (e.range.start < edit.range.end && edit.range.start < e.range.end)
+        });
+        if !overlaps {
+            edits.push(edit);
+        }
+    }
+    Ok(edits)
+}
+
+/// The ranges of the lines in `text` which are added or changed compared to
+/// `head`, empty if the text changed while diffing. Ranges which overlap or
+/// touch are merged, so that no two formatting requests cover the same line.
+fn modified_line_ranges(
+    head: Rope,
+    text: Rope,
+    rev: u64,
+    atomic_rev: Arc<AtomicU64>,
+) -> Vec<Range> {
+    let changes = match rope_diff(head, text.clone(), rev, atomic_rev) {
+        Some(changes) => changes,
+        None => return Vec::new(),
+    };
+    let mut lines: Vec<std::ops::Range<usize>> = changes
+        .iter()
+        .filter_map(|change| match change {
+            DiffLines::Right(r) => Some(r.clone()),
+            _ => None,
+        })
+        .collect();
+    lines.sort_by_key(|r| r.start);
+
+    let mut merged: Vec<std::ops::Range<usize>> = Vec::new();
+    for r in lines {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+
+    let text = RopeText::new(&text);
+    merged
+        .into_iter()
+        .map(|r| Range {
+            start: text.offset_to_position(text.offset_of_line(r.start)),
+            end: text.offset_to_position(text.offset_of_line(r.end)),
+        })
+        .collect()
+}
+
+/// Jump to the location if there is only one, otherwise list them all in the
+/// palette like references
+fn process_goto_locations(
+    editor_view_id: WidgetId,
+    offset: usize,
+    resp: GotoDefinitionResponse,
+    event_sink: ExtEventSink,
+) {
+    let locations = match resp {
+        GotoDefinitionResponse::Scalar(location) => vec![location],
+        GotoDefinitionResponse::Array(locations) => locations,
+        GotoDefinitionResponse::Link(location_links) => location_links
+            .into_iter()
+            .map(|l| Location {
+                uri: l.target_uri,
+                range: l.target_selection_range,
+            })
+            .collect(),
+    };
+    match locations.len() {
+        0 => {}
+        1 => {
+            let location = &locations[0];
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::GotoDefinition {
+                    editor_view_id,
+                    offset,
+                    location: EditorLocation {
+                        path: path_from_url(&location.uri),
+                        position: Some(location.range.start),
+                        scroll_offset: None,
+                        history: None,
+                    },
+                },
+                Target::Auto,
+            );
+        }
+        _ => {
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::PaletteReferences(offset, locations),
+                Target::Auto,
+            );
+        }
+    }
+}
+
+/// Fetch the direct supertypes and subtypes of `root` and show them in the
+/// palette
+fn get_type_hierarchy(
+    proxy: Arc<LapceProxy>,
+    offset: usize,
+    root: TypeHierarchyItem,
+    event_sink: ExtEventSink,
+) {
+    let subtypes_proxy = proxy.clone();
+    proxy
+        .proxy_rpc
+        .get_type_hierarchy_supertypes(root.clone(), move |result| {
+            let supertypes = match result {
+                Ok(ProxyResponse::GetTypeHierarchySupertypes { items }) => items,
+                _ => Vec::new(),
+            };
+            subtypes_proxy.proxy_rpc.get_type_hierarchy_subtypes(
+                root.clone(),
+                move |result| {
+                    let subtypes = match result {
+                        Ok(ProxyResponse::GetTypeHierarchySubtypes { items }) => {
+                            items
+                        }
+                        _ => Vec::new(),
+                    };
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::PaletteTypeHierarchy {
+                            offset,
+                            root,
+                            supertypes,
+                            subtypes,
+                        },
+                        Target::Auto,
+                    );
+                },
+            );
+        });
+}
+
 fn workspace_edits(edit: &WorkspaceEdit) -> Option<HashMap<Url, Vec<TextEdit>>> {
     if let Some(changes) = edit.changes.as_ref() {
         return Some(changes.clone());
@@ -2824,6 +3902,59 @@ fn apply_edit(main_split: &mut LapceMainSplitData, path: &Path, edits: &[TextEdi
     main_split.edit(path, &edits, lapce_core::editor::EditType::Other);
 }
 
+/// The name and the task of a cargo runnable from rust-analyzer, which is
+/// the argument of its client side `runSingle` command
+fn rust_analyzer_runnable(runnable: &Value) -> Option<(String, TaskConfig)> {
+    if runnable.get("kind")?.as_str()? != "cargo" {
+        return None;
+    }
+    let name = runnable.get("label")?.as_str()?.to_string();
+    let args = runnable.get("args")?;
+    let strings = |key: &str| {
+        args.get(key)
+            .and_then(|v| v.as_array())
+            .map(|v| {
+                v.iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut task_args = strings("cargoArgs");
+    task_args.extend(strings("cargoExtraArgs"));
+    let executable_args = strings("executableArgs");
+    if !executable_args.is_empty() {
+        task_args.push("--".to_string());
+        task_args.extend(executable_args);
+    }
+    let task = TaskConfig {
+        command: args
+            .get("overrideCargo")
+            .and_then(|v| v.as_str())
+            .unwrap_or("cargo")
+            .to_string(),
+        args: task_args,
+        cwd: args
+            .get("workspaceRoot")
+            .and_then(|v| v.as_str())
+            .map(PathBuf::from),
+        ..Default::default()
+    };
+    Some((name, task))
+}
+
+/// The pending surround which keeps collecting `tag` until `>` is typed
+fn pending_surround(target: Option<Surround>, tag: String) -> PendingSurround {
+    match target {
+        Some(target) => PendingSurround::Change {
+            target: Some(target),
+            tag: Some(tag),
+        },
+        None => PendingSurround::Add { tag: Some(tag) },
+    }
+}
+
 /// Checks if completion should be triggered if the received command
 /// is one that inserts whitespace or deletes whitespace
 fn show_completion(
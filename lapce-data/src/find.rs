@@ -300,6 +300,39 @@ pub fn next(
         None
     }
 
+    /// All the occurrences between `start` and `end`.
+    pub fn occurrences_in(
+        &self,
+        text: &Rope,
+        start: usize,
+        end: usize,
+    ) -> Vec<(usize, usize)> {
+        let search_string = match self.search_string.as_ref() {
+            Some(search_string) => search_string,
+            None => return Vec::new(),
+        };
+        let regex = match self.regex.clone() {
+            Some(regex) => regex,
+            None => match RegexBuilder::new(&regex::escape(search_string))
+                .size_limit(REGEX_SIZE_LIMIT)
+                .case_insensitive(!self.case_sensitive())
+                .build()
+            {
+                Ok(regex) => regex,
+                Err(_) => return Vec::new(),
+            },
+        };
+
+        let content = text.slice_to_cow(start..end);
+        regex
+            .find_iter(&content)
+            .map(|m| (start + m.start(), start + m.end()))
+            .filter(|(start, end)| {
+                !self.whole_words || self.is_matching_whole_words(text, *start, *end)
+            })
+            .collect()
+    }
+
     /// Execute the search on the provided text in the range provided by `start` and `end`.
     pub fn update_find(
         &mut self,
@@ -1,6 +1,7 @@
 use std::{
     cell::RefCell,
     collections::HashMap,
+    ops::Range,
     rc::Rc,
     sync::{atomic, Arc},
 };
@@ -26,6 +27,48 @@
     document::{BufferContent, Document, TextLayoutCache, TextLayoutLine},
 };
 
+/// The versions of a document kept in the local history are named after the
+/// time they were saved, with this prefix
+const LOCAL_HISTORY_PREFIX: &str = "local:";
+
+/// The name of the version of a document saved at `time` in the local history
+pub fn local_history_version(time: u64) -> String {
+    format!("{LOCAL_HISTORY_PREFIX}{time}")
+}
+
+/// The time the version was saved at, if it's from the local history
+pub fn local_history_time(version: &str) -> Option<u64> {
+    version.strip_prefix(LOCAL_HISTORY_PREFIX)?.parse().ok()
+}
+
+/// The ranges of `old` and `new` that differ, leaving out the text they
+/// start and end with in common
+pub fn changed_range(old: &str, new: &str) -> (Range<usize>, Range<usize>) {
+    let is_boundary =
+        |i: usize, j: usize| old.is_char_boundary(i) && new.is_char_boundary(j);
+
+    let mut prefix = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !is_boundary(prefix, prefix) {
+        prefix -= 1;
+    }
+
+    let mut suffix = old[prefix..]
+        .bytes()
+        .rev()
+        .zip(new[prefix..].bytes().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !is_boundary(old.len() - suffix, new.len() - suffix) {
+        suffix -= 1;
+    }
+
+    (prefix..old.len() - suffix, prefix..new.len() - suffix)
+}
+
 #[derive(Clone)]
 pub struct DocumentHistory {
     version: String,
@@ -170,6 +213,10 @@ fn line_style(&self, line: usize) -> Arc<Vec<LineStyle>> {
     }
 
     pub fn retrieve(&self, doc: &Document) {
+        if let Some(time) = local_history_time(&self.version) {
+            self.retrieve_local_history(doc, time);
+            return;
+        }
         if let BufferContent::File(path) = &doc.content() {
             let id = doc.id();
             let tab_id = doc.tab_id;
@@ -200,6 +247,35 @@ pub fn retrieve(&self, doc: &Document) {
         }
     }
 
+    fn retrieve_local_history(&self, doc: &Document, time: u64) {
+        if let BufferContent::File(path) = &doc.content() {
+            let tab_id = doc.tab_id;
+            let path = path.clone();
+            let version = self.version.clone();
+            let event_sink = doc.event_sink.clone();
+            doc.proxy.proxy_rpc.get_local_history_content(
+                path.clone(),
+                time,
+                move |result| {
+                    if let Ok(ProxyResponse::LocalHistoryContentResponse {
+                        content,
+                    }) = result
+                    {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::LoadBufferHead {
+                                path,
+                                content: Rope::from(content),
+                                version,
+                            },
+                            Target::Widget(tab_id),
+                        );
+                    }
+                },
+            );
+        }
+    }
+
     pub fn trigger_update_change(&self, doc: &Document) {
         if self.buffer.is_none() {
             return;
@@ -209,6 +285,7 @@ pub fn trigger_update_change(&self, doc: &Document) {
             let rev = doc.rev();
             let atomic_rev = doc.buffer().atomic_rev();
             let path = path.clone();
+            let version = self.version.clone();
             let left_rope = self.buffer.as_ref().unwrap().text().clone();
             let right_rope = doc.buffer().text().clone();
             let event_sink = doc.event_sink.clone();
@@ -233,7 +310,7 @@ pub fn trigger_update_change(&self, doc: &Document) {
                         id,
                         path,
                         rev,
-                        history: "head".to_string(),
+                        history: version,
                         changes: Arc::new(changes),
                     },
                     Target::Widget(tab_id),
@@ -242,6 +319,11 @@ pub fn trigger_update_change(&self, doc: &Document) {
         }
     }
 
+    /// The content of the document at this version, if it's been loaded
+    pub fn text(&self) -> Option<&Rope> {
+        self.buffer.as_ref().map(|buffer| buffer.text())
+    }
+
     pub fn changes(&self) -> &[DiffLines] {
         &self.changes
     }
@@ -287,3 +369,18 @@ fn retrieve_history_styles(&self, doc: &Document) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::changed_range;
+
+    #[test]
+    fn test_changed_range() {
+        assert_eq!(changed_range("abcdef", "abXYef"), (2..4, 2..4));
+        assert_eq!(changed_range("abc", "abc"), (3..3, 3..3));
+        assert_eq!(changed_range("aaa", "aa"), (2..3, 2..2));
+        assert_eq!(changed_range("", "new"), (0..0, 0..3));
+        // Doesn't split the two byte characters
+        assert_eq!(changed_range("xéy", "xèy"), (1..3, 1..3));
+    }
+}
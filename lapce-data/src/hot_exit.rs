@@ -0,0 +1,200 @@
+//! Snapshots of the buffers with unsaved changes, taken every few seconds so
+//! that they survive a crash or a power loss, and restored on the next start.
+
+use std::{str::FromStr, sync::Arc};
+
+use lapce_core::{
+    buffer::UndoHistory,
+    cursor::{Cursor, CursorMode},
+    language::LapceLanguage,
+    selection::Selection,
+};
+use lapce_rpc::buffer::BufferId;
+use lapce_xi_rope::Rope;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::LapceConfig,
+    data::{LapceEditorData, LapceMainSplitData},
+    document::{BufferContent, Document},
+};
+
+/// A buffer with unsaved changes as it's stored in the database
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UnsavedBuffer {
+    pub content: BufferContent,
+    pub text: String,
+    pub language: Option<String>,
+    pub cursor: Option<CursorMode>,
+    /// The undo history, which also tells which revision was pristine
+    pub history: Option<UndoHistory>,
+}
+
+impl UnsavedBuffer {
+    pub fn name(&self) -> String {
+        match &self.content {
+            BufferContent::File(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string_lossy().to_string()),
+            BufferContent::Scratch(_, name) => name.clone(),
+            BufferContent::Local(_) | BufferContent::SettingsValue(_) => {
+                String::new()
+            }
+        }
+    }
+
+    /// Brings `doc`, which has the content on disk for a file, back to the
+    /// snapshot. The document isn't pristine afterwards unless the snapshot
+    /// was taken at its pristine revision.
+    pub fn restore(&self, doc: &mut Document) {
+        if let Some(language) = self
+            .language
+            .as_deref()
+            .and_then(|language| LapceLanguage::from_str(language).ok())
+        {
+            doc.set_language(language);
+        }
+        doc.reload(Rope::from(&self.text), false);
+        if let Some(history) = &self.history {
+            doc.restore_undo_history(history.clone());
+        }
+    }
+
+    /// Puts the cursor of `editor` back where it was, unless it no longer
+    /// fits in the text of `doc`
+    pub fn restore_cursor(
+        &self,
+        editor: &mut LapceEditorData,
+        doc: &Document,
+        config: &LapceConfig,
+    ) {
+        let mode = match &self.cursor {
+            Some(mode) if cursor_fits(mode, doc.buffer().len()) => mode.clone(),
+            _ => return,
+        };
+        let mode = match mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. }
+                if !config.core.modal =>
+            {
+                CursorMode::Insert(Selection::caret(mode.offset()))
+            }
+            mode => mode,
+        };
+        editor.cursor = Cursor::new(mode, None, None);
+    }
+}
+
+fn cursor_fits(mode: &CursorMode, len: usize) -> bool {
+    match mode {
+        CursorMode::Normal(offset) => *offset <= len,
+        CursorMode::Visual { start, end, .. } => *start <= len && *end <= len,
+        CursorMode::Insert(selection) => {
+            !selection.regions().is_empty() && selection.max_offset() <= len
+        }
+    }
+}
+
+/// The documents worth keeping, which are the files with unsaved changes
+/// and the scratch buffers that aren't empty, each with the cursor of an
+/// editor it's in. The cursor of the active editor comes first.
+fn unsaved_docs(
+    main_split: &LapceMainSplitData,
+) -> Vec<(&Arc<Document>, &CursorMode)> {
+    let active =
+        (*main_split.active).and_then(|active| main_split.editors.get(&active));
+    let mut docs: Vec<(&Arc<Document>, &CursorMode)> = Vec::new();
+    for editor in active.into_iter().chain(main_split.editors.values()) {
+        let doc = match &editor.content {
+            BufferContent::File(path) => match main_split.open_docs.get(path) {
+                Some(doc) if doc.loaded() && !doc.buffer().is_pristine() => doc,
+                _ => continue,
+            },
+            BufferContent::Scratch(id, _) => match main_split.scratch_docs.get(id) {
+                Some(doc)
+                    if !doc.buffer().is_pristine() || !doc.buffer().is_empty() =>
+                {
+                    doc
+                }
+                _ => continue,
+            },
+            BufferContent::Local(_) | BufferContent::SettingsValue(_) => continue,
+        };
+        if !docs.iter().any(|(d, _)| d.id() == doc.id()) {
+            docs.push((doc, &editor.cursor.mode));
+        }
+    }
+    docs
+}
+
+/// Whether a file in an editor is still loading, in which case no snapshot
+/// is taken, as its unsaved changes from the last one may not have been
+/// restored yet
+fn is_loading(main_split: &LapceMainSplitData) -> bool {
+    main_split.editors.values().any(|editor| {
+        editor
+            .content
+            .path()
+            .and_then(|path| main_split.open_docs.get(path))
+            .map_or(false, |doc| !doc.loaded())
+    })
+}
+
+/// The revision of each of the documents worth keeping, to tell whether
+/// anything changed since the last snapshot
+pub fn unsaved_revs(
+    main_split: &LapceMainSplitData,
+) -> Option<Vec<(BufferId, u64)>> {
+    if is_loading(main_split) {
+        return None;
+    }
+
+    Some(
+        unsaved_docs(main_split)
+            .into_iter()
+            .map(|(doc, _)| (doc.id(), doc.rev()))
+            .collect(),
+    )
+}
+
+/// Takes a snapshot of the documents worth keeping, with their undo
+/// histories when `with_history` is set
+pub fn snapshot(
+    main_split: &LapceMainSplitData,
+    with_history: bool,
+) -> Option<Vec<UnsavedBuffer>> {
+    if is_loading(main_split) {
+        return None;
+    }
+
+    Some(
+        unsaved_docs(main_split)
+            .into_iter()
+            .map(|(doc, cursor)| UnsavedBuffer {
+                content: doc.content().clone(),
+                text: doc.buffer().to_string(),
+                language: doc.syntax().map(|syntax| syntax.language.to_string()),
+                cursor: Some(cursor.clone()),
+                history: with_history.then(|| doc.buffer().undo_history()),
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_core::{cursor::CursorMode, selection::Selection};
+
+    use super::cursor_fits;
+
+    #[test]
+    fn test_cursor_fits() {
+        assert!(cursor_fits(&CursorMode::Normal(3), 3));
+        assert!(!cursor_fits(&CursorMode::Normal(4), 3));
+        assert!(cursor_fits(&CursorMode::Insert(Selection::region(1, 3)), 3));
+        assert!(!cursor_fits(
+            &CursorMode::Insert(Selection::region(1, 4)),
+            3
+        ));
+    }
+}
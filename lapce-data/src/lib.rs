@@ -14,6 +14,7 @@
 pub mod explorer;
 pub mod find;
 pub mod history;
+pub mod hot_exit;
 pub mod hover;
 pub mod keypress;
 pub mod list;
@@ -31,9 +32,12 @@
 pub mod selection_range;
 pub mod settings;
 pub mod signature;
+pub mod snippet;
 pub mod source_control;
 pub mod split;
 mod svg;
+pub mod task;
 pub mod terminal;
 pub mod title;
+pub mod undo;
 pub mod update;
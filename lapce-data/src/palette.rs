@@ -10,6 +10,7 @@
 
 use alacritty_terminal::{grid::Dimensions, term::cell::Flags};
 use anyhow::Result;
+use chrono::{Local, LocalResult, TimeZone};
 use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
 use druid::{
     Command, Data, Env, EventCtx, ExtEventSink, Lens, Modifiers, Target, WidgetId,
@@ -23,7 +24,9 @@
     mode::Mode,
 };
 use lapce_rpc::proxy::ProxyResponse;
-use lsp_types::{DocumentSymbolResponse, Position, Range, SymbolKind};
+use lsp_types::{
+    DocumentSymbolResponse, Position, Range, SymbolKind, TypeHierarchyItem,
+};
 use uuid::Uuid;
 
 use crate::{
@@ -33,18 +36,21 @@
     },
     config::LapceConfig,
     data::{
-        FocusArea, LapceMainSplitData, LapceTabData, LapceWorkspace,
-        LapceWorkspaceType, SshHost,
+        CommandRemoteHost, FocusArea, LapceMainSplitData, LapceTabData,
+        LapceWorkspace, LapceWorkspaceType, SshHost,
     },
     db::LapceDb,
     document::BufferContent,
     editor::EditorLocation,
     find::Find,
+    history::local_history_version,
     keypress::{KeyMap, KeyPressData, KeyPressFocus},
     list::ListData,
     panel::PanelKind,
     proxy::{path_from_url, LapceProxy},
+    task::TasksConfig,
     terminal::TerminalPanelData,
+    undo::{self, UndoJump},
 };
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -57,10 +63,17 @@ pub enum PaletteType {
     Workspace,
     Command,
     Reference,
+    TypeHierarchy,
     ColorTheme,
     IconTheme,
     SshHost,
+    CommandRemote,
     Language,
+    TerminalProfile,
+    Task,
+    Snippet,
+    UndoState,
+    LocalHistory,
 }
 
 impl PaletteType {
@@ -74,10 +87,17 @@ fn string(&self) -> String {
             PaletteType::Command => ":".to_string(),
             PaletteType::File
             | PaletteType::Reference
+            | PaletteType::TypeHierarchy
             | PaletteType::ColorTheme
             | PaletteType::IconTheme
             | PaletteType::SshHost
-            | PaletteType::Language => "".to_string(),
+            | PaletteType::CommandRemote
+            | PaletteType::Language
+            | PaletteType::TerminalProfile
+            | PaletteType::Task
+            | PaletteType::Snippet
+            | PaletteType::UndoState
+            | PaletteType::LocalHistory => "".to_string(),
         }
     }
 
@@ -89,6 +109,7 @@ pub fn has_preview(&self) -> bool {
                 | PaletteType::WorkspaceSymbol
                 | PaletteType::GlobalSearch
                 | PaletteType::Reference
+                | PaletteType::TypeHierarchy
         )
     }
 
@@ -97,10 +118,17 @@ pub fn has_preview(&self) -> bool {
     fn get_palette_type(current_type: &PaletteType, input: &str) -> PaletteType {
         match current_type {
             PaletteType::Reference
+            | PaletteType::TypeHierarchy
             | PaletteType::SshHost
+            | PaletteType::CommandRemote
             | PaletteType::ColorTheme
             | PaletteType::IconTheme
-            | PaletteType::Language => {
+            | PaletteType::Language
+            | PaletteType::TerminalProfile
+            | PaletteType::Task
+            | PaletteType::Snippet
+            | PaletteType::UndoState
+            | PaletteType::LocalHistory => {
                 return current_type.clone();
             }
             _ => (),
@@ -152,12 +180,44 @@ pub enum PaletteItemContent {
         location: EditorLocation<Position>,
     },
     ReferenceLocation(PathBuf, EditorLocation<Position>),
+    /// An entry of the type hierarchy, where `text` is the name indented
+    /// according to its place in the tree
+    TypeHierarchyItem {
+        kind: SymbolKind,
+        text: String,
+        location: EditorLocation<Position>,
+    },
     Workspace(LapceWorkspace),
     SshHost(SshHost),
+    CommandRemote(CommandRemoteHost),
     Command(LapceCommand),
     ColorTheme(String),
     IconTheme(String),
     Language(String),
+    TerminalProfile(String),
+    Task(String),
+    /// A user snippet, inserted into the editor `view_id`
+    Snippet {
+        view_id: WidgetId,
+        name: String,
+        prefix: String,
+        description: Option<String>,
+        body: String,
+    },
+    /// A state of the undo tree of the buffer of the editor `view_id`
+    UndoState {
+        view_id: WidgetId,
+        node: usize,
+        text: String,
+        hint: String,
+    },
+    /// A version of `path` in the local history, saved at `time`
+    LocalHistory {
+        path: PathBuf,
+        time: u64,
+        text: String,
+        hint: String,
+    },
 }
 
 impl PaletteItemContent {
@@ -217,7 +277,8 @@ fn select(
                     Target::Auto,
                 ));
             }
-            PaletteItemContent::ReferenceLocation(_rel_path, location) => {
+            PaletteItemContent::ReferenceLocation(_rel_path, location)
+            | PaletteItemContent::TypeHierarchyItem { location, .. } => {
                 let editor_id = if preview {
                     Some(preview_editor_id)
                 } else {
@@ -266,6 +327,54 @@ fn select(
                     ))
                 }
             }
+            PaletteItemContent::TerminalProfile(name) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::NewTerminalTabWithProfile(name.to_string()),
+                        Target::Auto,
+                    ))
+                }
+            }
+            PaletteItemContent::Task(name) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunTask(name.to_string()),
+                        Target::Auto,
+                    ))
+                }
+            }
+            PaletteItemContent::Snippet { view_id, body, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::InsertSnippet(body.to_string()),
+                        Target::Widget(*view_id),
+                    ))
+                }
+            }
+            PaletteItemContent::UndoState { view_id, node, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::UndoTo(*node),
+                        Target::Widget(*view_id),
+                    ))
+                }
+            }
+            PaletteItemContent::LocalHistory { path, time, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::OpenFileDiff(
+                            path.clone(),
+                            local_history_version(*time),
+                        ),
+                        Target::Auto,
+                    ))
+                }
+            }
             PaletteItemContent::Command(command) => {
                 if !preview {
                     ctx.submit_command(Command::new(
@@ -298,6 +407,21 @@ fn select(
                     ));
                 }
             }
+            PaletteItemContent::CommandRemote(remote) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SetWorkspace(LapceWorkspace {
+                            kind: LapceWorkspaceType::RemoteCommand(
+                                remote.name.clone(),
+                            ),
+                            path: None,
+                            last_open: 0,
+                        }),
+                        Target::Auto,
+                    ));
+                }
+            }
         }
         true
     }
@@ -507,10 +631,17 @@ pub fn get_input(&self) -> &str {
         match &self.palette_type {
             PaletteType::File
             | PaletteType::Reference
+            | PaletteType::TypeHierarchy
             | PaletteType::ColorTheme
             | PaletteType::IconTheme
             | PaletteType::Language
-            | PaletteType::SshHost => &self.input,
+            | PaletteType::TerminalProfile
+            | PaletteType::Task
+            | PaletteType::Snippet
+            | PaletteType::UndoState
+            | PaletteType::LocalHistory
+            | PaletteType::SshHost
+            | PaletteType::CommandRemote => &self.input,
             PaletteType::Line
             | PaletteType::DocumentSymbol
             | PaletteType::WorkspaceSymbol
@@ -587,6 +718,50 @@ pub fn run_references(
         self.fill_list();
     }
 
+    /// Show the type hierarchy of `root` as a tree, with the supertypes above
+    /// it and the subtypes below it
+    pub fn run_type_hierarchy(
+        &mut self,
+        ctx: &mut EventCtx,
+        root: &TypeHierarchyItem,
+        supertypes: &[TypeHierarchyItem],
+        subtypes: &[TypeHierarchyItem],
+    ) {
+        self.run(ctx, Some(PaletteType::TypeHierarchy), None, true);
+        let items = supertypes
+            .iter()
+            .map(|item| (0, item))
+            .chain(std::iter::once((1, root)))
+            .chain(subtypes.iter().map(|item| (2, item)))
+            .map(|(depth, item)| {
+                let text = if depth == 0 {
+                    item.name.clone()
+                } else {
+                    format!("{}└ {}", "  ".repeat(depth - 1), item.name)
+                };
+                PaletteItem {
+                    content: PaletteItemContent::TypeHierarchyItem {
+                        kind: item.kind,
+                        text: text.clone(),
+                        location: EditorLocation {
+                            path: path_from_url(&item.uri),
+                            position: Some(item.selection_range.start),
+                            scroll_offset: None,
+                            history: None,
+                        },
+                    },
+                    filter_text: text,
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.total_items = items;
+        palette.preview(ctx);
+        self.fill_list();
+    }
+
     pub fn run(
         &mut self,
         ctx: &mut EventCtx,
@@ -644,10 +819,14 @@ pub fn run(
             PaletteType::Workspace => {
                 self.get_workspaces(ctx);
             }
-            PaletteType::Reference => {}
+            PaletteType::Reference | PaletteType::TypeHierarchy => {}
             PaletteType::SshHost => {
                 self.get_ssh_hosts(ctx);
             }
+            PaletteType::CommandRemote => {
+                let config = self.config.clone();
+                self.get_command_remotes(ctx, &config);
+            }
             PaletteType::GlobalSearch => {
                 self.get_global_search(ctx);
             }
@@ -674,6 +853,23 @@ pub fn run(
                     }
                 }
             }
+            PaletteType::TerminalProfile => {
+                let config = self.config.clone();
+                self.get_terminal_profiles(ctx, &config);
+                self.preselect_matching(ctx, &config.terminal.default_profile);
+            }
+            PaletteType::Task => {
+                self.get_tasks(ctx);
+            }
+            PaletteType::Snippet => {
+                self.get_snippets(ctx);
+            }
+            PaletteType::UndoState => {
+                self.get_undo_states(ctx);
+            }
+            PaletteType::LocalHistory => {
+                self.get_local_history(ctx);
+            }
         }
 
         self.fill_list();
@@ -707,10 +903,17 @@ pub fn delete_to_beginning_of_line(&mut self, ctx: &mut EventCtx) {
         let start = match palette.palette_type {
             PaletteType::File
             | PaletteType::Reference
+            | PaletteType::TypeHierarchy
             | PaletteType::ColorTheme
             | PaletteType::IconTheme
             | PaletteType::Language
-            | PaletteType::SshHost => 0,
+            | PaletteType::TerminalProfile
+            | PaletteType::Task
+            | PaletteType::Snippet
+            | PaletteType::UndoState
+            | PaletteType::LocalHistory
+            | PaletteType::SshHost
+            | PaletteType::CommandRemote => 0,
             PaletteType::Line
             | PaletteType::DocumentSymbol
             | PaletteType::WorkspaceSymbol
@@ -820,6 +1023,16 @@ pub fn update_palette(&mut self, ctx: &mut EventCtx) {
             return;
         }
 
+        if let PaletteType::UndoState = self.palette.palette_type {
+            // A time or a number of changes to go back or forward, like
+            // vim's `:earlier 10m`, instead of a filter
+            if let Some(jump) = UndoJump::parse(self.palette.get_input()) {
+                let items = self.undo_jump_items(jump);
+                Arc::make_mut(&mut self.palette).list_data.items = items;
+                return;
+            }
+        }
+
         if self.palette.get_input() == "" {
             self.palette.preview(ctx);
             Arc::make_mut(&mut self.palette).list_data.items =
@@ -893,6 +1106,24 @@ fn get_ssh_hosts(&mut self, _ctx: &mut EventCtx) {
             .collect();
     }
 
+    fn get_command_remotes(&mut self, _ctx: &mut EventCtx, config: &LapceConfig) {
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.total_items = config
+            .core
+            .remotes
+            .iter()
+            .map(|(name, remote)| PaletteItem {
+                content: PaletteItemContent::CommandRemote(CommandRemoteHost {
+                    name: name.to_string(),
+                    ..remote.clone()
+                }),
+                filter_text: name.to_string(),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+    }
+
     fn get_workspaces(&mut self, _ctx: &mut EventCtx) {
         let workspaces = self.db.recent_workspaces().unwrap_or_default();
         let palette = Arc::make_mut(&mut self.palette);
@@ -908,6 +1139,9 @@ fn get_workspaces(&mut self, _ctx: &mut EventCtx) {
                     LapceWorkspaceType::RemoteWSL => {
                         format!("[wsl] {text}")
                     }
+                    LapceWorkspaceType::RemoteCommand(remote) => {
+                        format!("[{remote}] {text}")
+                    }
                 };
                 Some(PaletteItem {
                     content: PaletteItemContent::Workspace(w),
@@ -965,6 +1199,183 @@ fn get_languages(&mut self, _ctx: &mut EventCtx) {
             .collect();
     }
 
+    fn get_terminal_profiles(&mut self, _ctx: &mut EventCtx, config: &LapceConfig) {
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.total_items = config
+            .terminal
+            .profiles
+            .keys()
+            .map(|n| PaletteItem {
+                content: PaletteItemContent::TerminalProfile(n.to_string()),
+                filter_text: n.to_string(),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+    }
+
+    fn get_tasks(&mut self, _ctx: &mut EventCtx) {
+        let tasks = TasksConfig::load(&self.workspace);
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.total_items = tasks
+            .tasks
+            .keys()
+            .map(|n| PaletteItem {
+                content: PaletteItemContent::Task(n.to_string()),
+                filter_text: n.to_string(),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+    }
+
+    fn get_snippets(&mut self, _ctx: &mut EventCtx) {
+        let editor = match self.main_split.active_editor() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let view_id = editor.view_id;
+        let language = self
+            .main_split
+            .content_doc(&editor.content)
+            .syntax()
+            .map(|syntax| syntax.language);
+        let snippets = self.config.snippets.clone();
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.total_items = snippets
+            .for_language(language)
+            .map(|snippet| PaletteItem {
+                content: PaletteItemContent::Snippet {
+                    view_id,
+                    name: snippet.name.clone(),
+                    prefix: snippet.prefix.join(", "),
+                    description: snippet.description.clone(),
+                    body: snippet.body.clone(),
+                },
+                filter_text: snippet.name.clone(),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+    }
+
+    fn get_undo_states(&mut self, _ctx: &mut EventCtx) {
+        let editor = match self.main_split.active_editor() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let view_id = editor.view_id;
+        let doc = self.main_split.content_doc(&editor.content);
+        let buffer = doc.buffer();
+        let current = buffer.current_undo_node();
+        let items = buffer
+            .undo_tree()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(node, undo_node)| {
+                let mut text = undo::node_label(node, undo_node);
+                if node == current {
+                    text.push_str(" (current)");
+                }
+                PaletteItem {
+                    content: PaletteItemContent::UndoState {
+                        view_id,
+                        node,
+                        text: text.clone(),
+                        hint: undo::node_time(undo_node),
+                    },
+                    filter_text: text,
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+        Arc::make_mut(&mut self.palette).total_items = items;
+    }
+
+    fn get_local_history(&self, ctx: &mut EventCtx) {
+        let path = match self.main_split.active_editor().map(|e| &e.content) {
+            Some(BufferContent::File(path)) => path.clone(),
+            _ => return,
+        };
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.proxy_rpc.get_local_history(
+            path.clone(),
+            move |result| {
+                if let Ok(ProxyResponse::LocalHistoryResponse { versions }) = result
+                {
+                    let now = Local::now();
+                    let items: im::Vector<PaletteItem> = versions
+                        .into_iter()
+                        .filter_map(|time| {
+                            let saved = match Local.timestamp_millis_opt(time as i64)
+                            {
+                                LocalResult::Single(saved) => saved,
+                                _ => return None,
+                            };
+                            let text = saved.format("%Y-%m-%d %H:%M:%S").to_string();
+                            Some(PaletteItem {
+                                content: PaletteItemContent::LocalHistory {
+                                    path: path.clone(),
+                                    time,
+                                    text: text.clone(),
+                                    hint: time_ago(now - saved),
+                                },
+                                filter_text: text,
+                                score: 0,
+                                indices: Vec::new(),
+                            })
+                        })
+                        .collect();
+
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::UpdatePaletteItems(run_id, items),
+                        Target::Widget(widget_id),
+                    );
+                }
+            },
+        );
+    }
+
+    /// The items going `jump` earlier and later in the undo tree of the
+    /// active editor
+    fn undo_jump_items(&self, jump: UndoJump) -> im::Vector<PaletteItem> {
+        let editor = match self.main_split.active_editor() {
+            Some(editor) => editor,
+            None => return im::Vector::new(),
+        };
+        let view_id = editor.view_id;
+        let doc = self.main_split.content_doc(&editor.content);
+        let buffer = doc.buffer();
+        [("earlier", true), ("later", false)]
+            .into_iter()
+            .map(|(direction, earlier)| {
+                let node = jump.node(buffer, earlier);
+                let undo_node = &buffer.undo_tree()[node];
+                let text = format!("Go {jump} {direction}");
+                PaletteItem {
+                    content: PaletteItemContent::UndoState {
+                        view_id,
+                        node,
+                        text: text.clone(),
+                        hint: format!(
+                            "{} {}",
+                            undo::node_label(node, undo_node),
+                            undo::node_time(undo_node)
+                        ),
+                    },
+                    filter_text: text,
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect()
+    }
+
     fn get_commands(&mut self, _ctx: &mut EventCtx) {
         const EXCLUDED_ITEMS: &[&str] = &["palette.command"];
 
@@ -1296,6 +1707,17 @@ fn filter_items(
     }
 }
 
+/// How long ago something happened, like `5 minutes ago`
+fn time_ago(duration: chrono::Duration) -> String {
+    let (n, unit) = match duration.num_seconds() {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s => (s / (24 * 60 * 60), "day"),
+    };
+    format!("{n} {unit}{} ago", if n == 1 { "" } else { "s" })
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1318,4 +1740,12 @@ fn filter_items_can_handle_large_number_of_items() {
         // Previous implementation of this function would crash the program
         let _view = PaletteViewData::filter_items("1", "s", items, &matcher);
     }
+
+    #[test]
+    fn test_time_ago() {
+        assert_eq!(time_ago(chrono::Duration::seconds(10)), "just now");
+        assert_eq!(time_ago(chrono::Duration::minutes(1)), "1 minute ago");
+        assert_eq!(time_ago(chrono::Duration::minutes(150)), "2 hours ago");
+        assert_eq!(time_ago(chrono::Duration::days(3)), "3 days ago");
+    }
 }
@@ -13,6 +13,7 @@ pub enum PanelKind {
     Terminal,
     Search,
     Problem,
+    UndoTree,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -34,6 +35,7 @@ pub fn svg_name(&self) -> &'static str {
             PanelKind::Terminal => LapceIcons::TERMINAL,
             PanelKind::Search => LapceIcons::SEARCH,
             PanelKind::Problem => LapceIcons::PROBLEM,
+            PanelKind::UndoTree => LapceIcons::UNDO_TREE,
         }
     }
 }
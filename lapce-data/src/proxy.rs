@@ -4,24 +4,28 @@
     collections::HashMap,
     io::{BufReader, Write},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::{Child, Command, Stdio},
     str::FromStr,
     sync::Arc,
     thread,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
-use crossbeam_channel::Sender;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
 use druid::{ExtEventSink, Target, WidgetId, WindowId};
 use flate2::read::GzDecoder;
+use indexmap::IndexMap;
 use lapce_core::{directory::Directory, meta};
 use lapce_proxy::dispatch::Dispatcher;
 use lapce_rpc::{
-    core::{CoreHandler, CoreNotification, CoreRequest, CoreRpcHandler},
-    proxy::{ProxyRpc, ProxyRpcHandler},
+    core::{
+        CoreHandler, CoreNotification, CoreRequest, CoreResponse, CoreRpcHandler,
+    },
+    proxy::{ProxyNotification, ProxyRequest, ProxyRpc, ProxyRpcHandler},
     stdio::stdio_transport,
-    terminal::TermId,
-    RequestId, RpcMessage,
+    terminal::{TermId, TerminalProfile},
+    RequestId, RpcError, RpcMessage,
 };
 use lapce_xi_rope::Rope;
 use lsp_types::Url;
@@ -31,13 +35,18 @@
 
 use crate::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
-    data::{LapceWorkspace, LapceWorkspaceType, SshHost},
+    data::{CommandRemoteHost, LapceWorkspace, LapceWorkspaceType, SshHost},
     terminal::RawTerminal,
 };
 
 const UNIX_PROXY_SCRIPT: &[u8] = include_bytes!("../../extra/proxy.sh");
 const WINDOWS_PROXY_SCRIPT: &[u8] = include_bytes!("../../extra/proxy.ps1");
 
+/// The delay before reconnecting to a remote proxy, doubled after each
+/// failed attempt up to the maximum.
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(60);
+
 pub enum TermEvent {
     NewTerminal(Arc<Mutex<RawTerminal>>),
     UpdateContent(String),
@@ -92,11 +101,64 @@ enum HostArchitecture {
     ARM32v6,
 }
 
+/// How remote hosts are reached and the proxy is installed on them.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyInstall {
+    /// Whether the proxy is uploaded from this machine rather than
+    /// downloaded on the remote host.
+    pub upload: bool,
+    /// The local proxy to upload to hosts of each platform, keyed like
+    /// `linux-x86_64`.
+    pub paths: IndexMap<String, PathBuf>,
+    /// The configured command remotes by name, which is all a workspace
+    /// keeps of its remote.
+    pub remotes: IndexMap<String, CommandRemoteHost>,
+}
+
+impl ProxyInstall {
+    /// The local proxy to upload to a host, which is the configured one for
+    /// its platform, otherwise the `lapce-proxy` next to this executable if
+    /// the host is of the same platform, and otherwise a release downloaded
+    /// before.
+    fn local_proxy(
+        &self,
+        platform: HostPlatform,
+        architecture: HostArchitecture,
+    ) -> Option<PathBuf> {
+        let target = format!("{platform}-{architecture}");
+        if let Some(path) = self.paths.get(&target) {
+            return Some(path.clone());
+        }
+
+        let local_os = match std::env::consts::OS {
+            "macos" => "darwin",
+            "windows" => "windows_nt",
+            os => os,
+        };
+        if parse_os(local_os) == platform
+            && parse_arch(std::env::consts::ARCH) == architecture
+        {
+            let proxy = std::env::current_exe().ok()?.with_file_name(format!(
+                "lapce-proxy{}",
+                std::env::consts::EXE_SUFFIX
+            ));
+            if proxy.exists() {
+                return Some(proxy);
+            }
+        }
+
+        let downloaded =
+            Directory::proxy_directory()?.join(format!("lapce-proxy-{target}"));
+        downloaded.exists().then_some(downloaded)
+    }
+}
+
 #[derive(Clone)]
 pub struct LapceProxy {
     pub tab_id: WidgetId,
     pub proxy_rpc: ProxyRpcHandler,
     pub core_rpc: CoreRpcHandler,
+    install: ProxyInstall,
     term_tx: Sender<(TermId, TermEvent)>,
     event_sink: ExtEventSink,
 }
@@ -160,10 +222,29 @@ fn handle_notification(&mut self, rpc: CoreNotification) {
                     Target::Widget(self.tab_id),
                 );
             }
-            PublishDiagnostics { diagnostics } => {
+            PublishDiagnostics {
+                source,
+                diagnostics,
+            } => {
+                let _ = self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::PublishDiagnostics {
+                        source,
+                        diagnostics,
+                    },
+                    Target::Widget(self.tab_id),
+                );
+            }
+            OnTypeFormattingTriggers {
+                plugin_id,
+                characters,
+            } => {
                 let _ = self.event_sink.submit_command(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::PublishDiagnostics(diagnostics),
+                    LapceUICommand::OnTypeFormattingTriggers {
+                        plugin_id,
+                        characters,
+                    },
                     Target::Widget(self.tab_id),
                 );
             }
@@ -238,12 +319,9 @@ fn handle_notification(&mut self, rpc: CoreNotification) {
                     .send((term_id, TermEvent::UpdateContent(content)));
             }
             CloseTerminal { term_id } => {
+                // The terminal update thread tells the UI once it has
+                // processed everything the terminal printed before exiting.
                 let _ = self.term_tx.send((term_id, TermEvent::CloseTerminal));
-                let _ = self.event_sink.submit_command(
-                    LAPCE_UI_COMMAND,
-                    LapceUICommand::CloseTerminal(term_id),
-                    Target::Widget(self.tab_id),
-                );
             }
             CompletionResponse {
                 request_id,
@@ -292,6 +370,7 @@ pub fn new(
         workspace: LapceWorkspace,
         disabled_volts: Vec<String>,
         plugin_configurations: HashMap<String, HashMap<String, serde_json::Value>>,
+        install: ProxyInstall,
         term_tx: Sender<(TermId, TermEvent)>,
         event_sink: ExtEventSink,
     ) -> Self {
@@ -302,6 +381,7 @@ pub fn new(
             tab_id,
             proxy_rpc,
             core_rpc,
+            install,
             term_tx,
             event_sink: event_sink.clone(),
         };
@@ -357,7 +437,7 @@ fn start(
                 });
             }
             LapceWorkspaceType::RemoteSSH(ssh) => {
-                self.start_remote(SshRemote { ssh })?;
+                self.connect_remote(SshRemote { ssh });
             }
             LapceWorkspaceType::RemoteWSL => {
                 let distro = WslDistro::all()?
@@ -365,7 +445,14 @@ fn start(
                     .find(|distro| distro.default)
                     .ok_or_else(|| anyhow!("no default distro found"))?
                     .name;
-                self.start_remote(WslRemote { distro })?;
+                self.connect_remote(WslRemote { distro });
+            }
+            LapceWorkspaceType::RemoteCommand(name) => {
+                let remote =
+                    self.install.remotes.get(&name).cloned().ok_or_else(|| {
+                        anyhow!("no remote named {name} in core.remotes")
+                    })?;
+                self.connect_remote(CommandRemote { remote });
             }
         }
 
@@ -375,7 +462,80 @@ fn start(
         Ok(())
     }
 
-    fn start_remote(&self, remote: impl Remote) -> Result<()> {
+    /// Keeps a connection to the proxy on the remote, reconnecting with a
+    /// growing delay whenever it's lost until the proxy is shut down.
+    fn connect_remote(&self, remote: impl Remote + Send + 'static) {
+        let proxy = self.clone();
+        thread::spawn(move || {
+            let mut delay = RECONNECT_DELAY_MIN;
+            let mut reconnect = false;
+            loop {
+                proxy.update_status(ProxyStatus::Connecting);
+                match proxy.start_remote(&remote) {
+                    Ok(connection) => {
+                        if reconnect {
+                            let _ = proxy.event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::ProxyReconnected,
+                                Target::Widget(proxy.tab_id),
+                            );
+                        }
+                        delay = RECONNECT_DELAY_MIN;
+                        let shutdown = connection.forward(&proxy.proxy_rpc);
+                        proxy.proxy_rpc.cancel_pending();
+                        if shutdown {
+                            return;
+                        }
+                        log::error!(target: "lapce_data::proxy::connect_remote", "lost the connection to the proxy");
+                    }
+                    Err(e) => {
+                        log::error!(target: "lapce_data::proxy::connect_remote", "{e}");
+                    }
+                }
+
+                reconnect = true;
+                proxy.update_status(ProxyStatus::Disconnected);
+                if proxy.wait_to_reconnect(delay) {
+                    return;
+                }
+                delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+            }
+        });
+    }
+
+    /// Fails the requests made while there's no connection to the proxy
+    /// until it's time to reconnect. Returns whether the proxy was shut
+    /// down meanwhile.
+    fn wait_to_reconnect(&self, delay: Duration) -> bool {
+        let deadline = Instant::now() + delay;
+        loop {
+            match self.proxy_rpc.rx().recv_deadline(deadline) {
+                Ok(ProxyRpc::Request(id, _)) => {
+                    self.proxy_rpc.handle_response(
+                        id,
+                        Err(RpcError {
+                            code: 0,
+                            message: "proxy disconnected".to_string(),
+                        }),
+                    );
+                }
+                Ok(ProxyRpc::Notification(_)) => {}
+                Ok(ProxyRpc::Shutdown) => return true,
+                Err(RecvTimeoutError::Timeout) => return false,
+                Err(RecvTimeoutError::Disconnected) => return true,
+            }
+        }
+    }
+
+    fn update_status(&self, status: ProxyStatus) {
+        let _ = self.event_sink.submit_command(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ProxyUpdateStatus(status),
+            Target::Widget(self.tab_id),
+        );
+    }
+
+    fn start_remote(&self, remote: &impl Remote) -> Result<RemoteConnection> {
         let proxy_version = match *meta::RELEASE {
             "Debug" | "Nightly" => "nightly".to_string(),
             _ => format!("v{}", *meta::VERSION),
@@ -406,7 +566,7 @@ fn start_remote(&self, remote: impl Remote) -> Result<()> {
         // shells retain similar syntax, although shells like Nushell might not
         // work (hopefully no one uses it as login shell)
         use HostPlatform::*;
-        let (platform, architecture) = self.host_specification(&remote).unwrap();
+        let (platform, architecture) = self.host_specification(remote).unwrap();
 
         if platform == UnknownOS || architecture == HostArchitecture::UnknownArch {
             log::error!(target: "lapce_data::proxy::start_remote", "detected remote host: {platform}/{architecture}");
@@ -428,6 +588,139 @@ fn start_remote(&self, remote: impl Remote) -> Result<()> {
                 format!("~/.local/share/{}/proxy", (*meta::NAME).to_lowercase())
             }
         };
+        let remote_proxy_path = remote.expand_home(remote_proxy_path)?;
+
+        let remote_proxy_file = match platform {
+            Windows => format!("{remote_proxy_path}\\lapce.exe"),
+            _ => format!("{remote_proxy_path}/lapce"),
+        };
+
+        log::debug!(target: "lapce_data::proxy::start_remote", "remote proxy path: {remote_proxy_path}");
+
+        if let Some(local_proxy_file) = remote.local_proxy() {
+            upload_proxy(
+                remote,
+                platform,
+                local_proxy_file,
+                &remote_proxy_path,
+                &remote_proxy_file,
+            )?;
+        } else if self.install.upload {
+            let local_proxy_file = self
+                .install
+                .local_proxy(platform, architecture)
+                .ok_or_else(|| {
+                    anyhow!("no local proxy for {platform}-{architecture}")
+                })?;
+            upload_proxy(
+                remote,
+                platform,
+                &local_proxy_file,
+                &remote_proxy_path,
+                &remote_proxy_file,
+            )?;
+        } else {
+            self.install_proxy(
+                remote,
+                platform,
+                architecture,
+                &proxy_version,
+                &remote_proxy_path,
+                &remote_proxy_file,
+            )?;
+        }
+
+        let child = match platform {
+            // Force cmd.exe usage to resolve %envvar% variables
+            Windows => remote
+                .command_builder()
+                .args(["cmd", "/c"])
+                .arg(&remote_proxy_file)
+                .arg("--proxy")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?,
+            _ => remote
+                .command_builder()
+                .arg(&remote_proxy_file)
+                .arg("--proxy")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?,
+        };
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("can't find stdin"))?;
+        let stdout = BufReader::new(
+            child
+                .stdout
+                .take()
+                .ok_or_else(|| anyhow!("can't find stdout"))?,
+        );
+        log::debug!(target: "lapce_data::proxy::start_remote", "process id: {}", child.id());
+
+        let (writer_tx, writer_rx) = crossbeam_channel::unbounded();
+        let (reader_tx, reader_rx) = crossbeam_channel::unbounded();
+        let (closed_tx, closed_rx) = crossbeam_channel::bounded(0);
+        stdio_transport(stdin, writer_rx, stdout, reader_tx);
+
+        let connection = RemoteConnection {
+            child,
+            writer_tx: writer_tx.clone(),
+            closed_rx,
+        };
+
+        let core_rpc = self.core_rpc.clone();
+        let proxy_rpc = self.proxy_rpc.clone();
+        thread::spawn(move || {
+            // The connection is closed when this is dropped at the end of
+            // the output of the proxy.
+            let _closed_tx = closed_tx;
+            for msg in reader_rx {
+                match msg {
+                    RpcMessage::Request(id, req) => {
+                        let writer_tx = writer_tx.clone();
+                        let core_rpc = core_rpc.clone();
+                        thread::spawn(move || match core_rpc.request(req) {
+                            Ok(resp) => {
+                                let _ =
+                                    writer_tx.send(RpcMessage::Response(id, resp));
+                            }
+                            Err(e) => {
+                                let _ = writer_tx.send(RpcMessage::Error(id, e));
+                            }
+                        });
+                    }
+                    RpcMessage::Notification(n) => {
+                        core_rpc.notification(n);
+                    }
+                    RpcMessage::Response(id, resp) => {
+                        proxy_rpc.handle_response(id, Ok(resp));
+                    }
+                    RpcMessage::Error(id, err) => {
+                        proxy_rpc.handle_response(id, Err(err));
+                    }
+                }
+            }
+        });
+
+        Ok(connection)
+    }
+
+    /// Installs the proxy release matching this version on the remote with
+    /// the install script, downloading it locally and uploading it if the
+    /// remote can't download it itself.
+    fn install_proxy(
+        &self,
+        remote: &impl Remote,
+        platform: HostPlatform,
+        architecture: HostArchitecture,
+        proxy_version: &str,
+        remote_proxy_path: &str,
+        remote_proxy_file: &str,
+    ) -> Result<()> {
+        use HostPlatform::*;
 
         let script_install = match platform {
             Windows => {
@@ -450,9 +743,9 @@ fn start_remote(&self, remote: impl Remote) -> Result<()> {
                         "-c",
                         remote_proxy_script,
                         "-version",
-                        &proxy_version,
+                        proxy_version,
                         "-directory",
-                        &remote_proxy_path,
+                        remote_proxy_path,
                     ])
                     .output()?;
                 log::debug!(target: "lapce_data::proxy::upload_file", "{}", String::from_utf8_lossy(&cmd.stderr));
@@ -482,7 +775,7 @@ fn start_remote(&self, remote: impl Remote) -> Result<()> {
 
                 let cmd = remote
                     .command_builder()
-                    .args([remote_proxy_script, &proxy_version, &remote_proxy_path])
+                    .args([remote_proxy_script, proxy_version, remote_proxy_path])
                     .output()?;
                 log::debug!(target: "lapce_data::proxy::upload_file", "{}", String::from_utf8_lossy(&cmd.stderr));
                 log::debug!(target: "lapce_data::proxy::upload_file", "{}", String::from_utf8_lossy(&cmd.stdout));
@@ -491,26 +784,19 @@ fn start_remote(&self, remote: impl Remote) -> Result<()> {
             }
         };
 
-        let remote_proxy_file = match platform {
-            Windows => format!("{remote_proxy_path}\\lapce.exe"),
-            _ => format!("{remote_proxy_path}/lapce"),
-        };
-
         let proxy_filename = format!("lapce-proxy-{}-{}", platform, architecture);
 
-        log::debug!(target: "lapce_data::proxy::start_remote", "remote proxy path: {remote_proxy_path}");
-
         if !script_install.success() {
             let cmd = match platform {
                 Windows => remote
                     .command_builder()
-                    .args(["dir", &remote_proxy_file])
+                    .args(["dir", remote_proxy_file])
                     .status()?,
                 _ => remote
                     .command_builder()
                     .arg("test")
                     .arg("-e")
-                    .arg(&remote_proxy_file)
+                    .arg(remote_proxy_file)
                     .status()?,
             };
             if !cmd.success() {
@@ -536,119 +822,16 @@ fn start_remote(&self, remote: impl Remote) -> Result<()> {
                     log::error!(target: "lapce_data::proxy::start_remote", "proxy download failed with: {}", resp.status());
                 }
 
-                match platform {
-                    // Windows creates all dirs in provided path
-                    Windows => remote
-                        .command_builder()
-                        .arg("mkdir")
-                        .arg(remote_proxy_path)
-                        .status()?,
-                    // Unix needs -p to do same
-                    _ => remote
-                        .command_builder()
-                        .arg("mkdir")
-                        .arg("-p")
-                        .arg(remote_proxy_path)
-                        .status()?,
-                };
-
-                remote.upload_file(&local_proxy_file, &remote_proxy_file)?;
-                if platform != Windows {
-                    remote
-                        .command_builder()
-                        .arg("chmod")
-                        .arg("+x")
-                        .arg(&remote_proxy_file)
-                        .status()?;
-                }
+                upload_proxy(
+                    remote,
+                    platform,
+                    &local_proxy_file,
+                    remote_proxy_path,
+                    remote_proxy_file,
+                )?;
             }
         }
 
-        let mut child = match platform {
-            // Force cmd.exe usage to resolve %envvar% variables
-            Windows => remote
-                .command_builder()
-                .args(["cmd", "/c"])
-                .arg(&remote_proxy_file)
-                .arg("--proxy")
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()?,
-            _ => remote
-                .command_builder()
-                .arg(&remote_proxy_file)
-                .arg("--proxy")
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()?,
-        };
-        let stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("can't find stdin"))?;
-        let stdout = BufReader::new(
-            child
-                .stdout
-                .take()
-                .ok_or_else(|| anyhow!("can't find stdout"))?,
-        );
-        log::debug!(target: "lapce_data::proxy::start_remote", "process id: {}", child.id());
-
-        let (writer_tx, writer_rx) = crossbeam_channel::unbounded();
-        let (reader_tx, reader_rx) = crossbeam_channel::unbounded();
-        stdio_transport(stdin, writer_rx, stdout, reader_tx);
-
-        let local_proxy_rpc = self.proxy_rpc.clone();
-        let local_writer_tx = writer_tx.clone();
-        thread::spawn(move || {
-            for msg in local_proxy_rpc.rx() {
-                match msg {
-                    ProxyRpc::Request(id, rpc) => {
-                        let _ = local_writer_tx.send(RpcMessage::Request(id, rpc));
-                    }
-                    ProxyRpc::Notification(rpc) => {
-                        let _ = local_writer_tx.send(RpcMessage::Notification(rpc));
-                    }
-                    ProxyRpc::Shutdown => {
-                        let _ = child.kill();
-                        let _ = child.wait();
-                        return;
-                    }
-                }
-            }
-        });
-
-        let core_rpc = self.core_rpc.clone();
-        let proxy_rpc = self.proxy_rpc.clone();
-        thread::spawn(move || {
-            for msg in reader_rx {
-                match msg {
-                    RpcMessage::Request(id, req) => {
-                        let writer_tx = writer_tx.clone();
-                        let core_rpc = core_rpc.clone();
-                        thread::spawn(move || match core_rpc.request(req) {
-                            Ok(resp) => {
-                                let _ =
-                                    writer_tx.send(RpcMessage::Response(id, resp));
-                            }
-                            Err(e) => {
-                                let _ = writer_tx.send(RpcMessage::Error(id, e));
-                            }
-                        });
-                    }
-                    RpcMessage::Notification(n) => {
-                        core_rpc.notification(n);
-                    }
-                    RpcMessage::Response(id, resp) => {
-                        proxy_rpc.handle_response(id, Ok(resp));
-                    }
-                    RpcMessage::Error(id, err) => {
-                        proxy_rpc.handle_response(id, Err(err));
-                    }
-                }
-            }
-        });
-
         Ok(())
     }
 
@@ -746,12 +929,11 @@ fn host_specification(
     pub fn new_terminal(
         &self,
         term_id: TermId,
-        cwd: Option<PathBuf>,
-        shell: String,
+        profile: TerminalProfile,
         raw: Arc<Mutex<RawTerminal>>,
     ) {
         let _ = self.term_tx.send((term_id, TermEvent::NewTerminal(raw)));
-        self.proxy_rpc.new_terminal(term_id, cwd, shell);
+        self.proxy_rpc.new_terminal(term_id, profile);
     }
 
     pub fn stop(&self) {
@@ -760,6 +942,110 @@ pub fn stop(&self) {
     }
 }
 
+/// Whether the proxy on the remote is of the same version as this build.
+/// Debug builds all have the same version, so their proxy never matches.
+fn remote_proxy_matches(
+    remote: &impl Remote,
+    platform: HostPlatform,
+    remote_proxy_file: &str,
+) -> bool {
+    if *meta::RELEASE == "Debug" {
+        return false;
+    }
+
+    let mut cmd = remote.command_builder();
+    if platform == HostPlatform::Windows {
+        cmd.args(["cmd", "/c"]);
+    }
+    match cmd.arg(remote_proxy_file).arg("--version").output() {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            log::debug!(target: "lapce_data::proxy::remote_proxy_matches", "{stdout}");
+            output.status.success()
+                && stdout.split_whitespace().last() == Some(*meta::VERSION)
+        }
+        Err(e) => {
+            log::error!(target: "lapce_data::proxy::remote_proxy_matches", "{e}");
+            false
+        }
+    }
+}
+
+/// The connection to a proxy running on a remote.
+struct RemoteConnection {
+    child: Child,
+    writer_tx: Sender<RpcMessage<ProxyRequest, ProxyNotification, CoreResponse>>,
+    /// Disconnected once the output of the proxy ends.
+    closed_rx: Receiver<()>,
+}
+
+impl RemoteConnection {
+    /// Sends the messages for the proxy until the connection is lost or
+    /// the proxy is shut down. Returns whether it was shut down.
+    fn forward(mut self, proxy_rpc: &ProxyRpcHandler) -> bool {
+        let shutdown = loop {
+            let msg = crossbeam_channel::select! {
+                recv(proxy_rpc.rx()) -> msg => Some(msg.unwrap_or(ProxyRpc::Shutdown)),
+                recv(self.closed_rx) -> _ => None,
+            };
+            match msg {
+                Some(ProxyRpc::Request(id, rpc)) => {
+                    let _ = self.writer_tx.send(RpcMessage::Request(id, rpc));
+                }
+                Some(ProxyRpc::Notification(rpc)) => {
+                    let _ = self.writer_tx.send(RpcMessage::Notification(rpc));
+                }
+                Some(ProxyRpc::Shutdown) => break true,
+                None => break false,
+            }
+        };
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        shutdown
+    }
+}
+
+/// Uploads a proxy binary to where the remote proxy is run from, unless
+/// the proxy there is already of the same version.
+fn upload_proxy(
+    remote: &impl Remote,
+    platform: HostPlatform,
+    local_proxy_file: &Path,
+    remote_proxy_path: &str,
+    remote_proxy_file: &str,
+) -> Result<()> {
+    if remote_proxy_matches(remote, platform, remote_proxy_file) {
+        return Ok(());
+    }
+
+    match platform {
+        // Windows creates all dirs in provided path
+        HostPlatform::Windows => remote
+            .command_builder()
+            .arg("mkdir")
+            .arg(remote_proxy_path)
+            .status()?,
+        // Unix needs -p to do same
+        _ => remote
+            .command_builder()
+            .arg("mkdir")
+            .arg("-p")
+            .arg(remote_proxy_path)
+            .status()?,
+    };
+
+    remote.upload_file(local_proxy_file, remote_proxy_file)?;
+    if platform != HostPlatform::Windows {
+        remote
+            .command_builder()
+            .arg("chmod")
+            .arg("+x")
+            .arg(remote_proxy_file)
+            .status()?;
+    }
+    Ok(())
+}
+
 fn new_command(program: &str) -> Command {
     #[allow(unused_mut)]
     let mut cmd = Command::new(program);
@@ -781,6 +1067,17 @@ fn home_dir(&self) -> Result<String> {
         Ok(String::from_utf8(cmd.stdout)?)
     }
 
+    /// Expands a leading `~` of a remote path, for remotes that don't run
+    /// commands in a shell which would do it.
+    fn expand_home(&self, path: String) -> Result<String> {
+        Ok(path)
+    }
+
+    /// A proxy binary to upload instead of installing a release.
+    fn local_proxy(&self) -> Option<&Path> {
+        None
+    }
+
     fn upload_file(&self, local: impl AsRef<Path>, remote: &str) -> Result<()>;
 
     fn command_builder(&self) -> Command;
@@ -913,6 +1210,144 @@ fn command_builder(&self) -> Command {
     }
 }
 
+struct CommandRemote {
+    remote: CommandRemoteHost,
+}
+
+impl Remote for CommandRemote {
+    fn home_dir(&self) -> Result<String> {
+        let cmd = self
+            .command_builder()
+            .arg("printenv")
+            .arg("HOME")
+            .stdout(Stdio::piped())
+            .output()?;
+
+        Ok(String::from_utf8(cmd.stdout)?.trim_end().to_string())
+    }
+
+    fn expand_home(&self, path: String) -> Result<String> {
+        match path.strip_prefix('~') {
+            Some(rest) => Ok(format!("{}{rest}", self.home_dir()?)),
+            None => Ok(path),
+        }
+    }
+
+    fn local_proxy(&self) -> Option<&Path> {
+        self.remote.proxy_path.as_deref()
+    }
+
+    fn upload_file(&self, local: impl AsRef<Path>, remote: &str) -> Result<()> {
+        let local = local.as_ref();
+        let output = if self.remote.upload.trim().is_empty() {
+            let mut child = self
+                .command_builder()
+                .arg("sh")
+                .arg("-c")
+                .arg(format!("cat > {}", shell_quote(remote)))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| anyhow!("can't find stdin"))?;
+            std::io::copy(&mut std::fs::File::open(local)?, &mut stdin)?;
+            drop(stdin);
+            child.wait_with_output()?
+        } else {
+            let local = local.to_string_lossy();
+            // The paths are put in after splitting, so that they stay one
+            // argument whatever they contain
+            let mut parts =
+                shell_words(&self.remote.upload)?.into_iter().map(|part| {
+                    part.replace("{local}", &local).replace("{remote}", remote)
+                });
+            let program = parts
+                .next()
+                .ok_or_else(|| anyhow!("empty upload command"))?;
+            new_command(&program).args(parts).output()?
+        };
+
+        log::debug!(target: "lapce_data::proxy::upload_file", "{}", String::from_utf8_lossy(&output.stderr));
+        log::debug!(target: "lapce_data::proxy::upload_file", "{}", String::from_utf8_lossy(&output.stdout));
+
+        if !output.status.success() {
+            return Err(anyhow!("failed to upload {} to {remote}", local.display()));
+        }
+        Ok(())
+    }
+
+    fn command_builder(&self) -> Command {
+        let parts = shell_words(&self.remote.command).unwrap_or_else(|err| {
+            log::error!("can't split the command of remote {}: {err}", self.remote);
+            Vec::new()
+        });
+        let mut parts = parts.iter();
+        let mut cmd = new_command(parts.next().map_or("", String::as_str));
+        cmd.args(parts);
+        cmd
+    }
+}
+
+/// Splits `command` into words like a POSIX shell, with quotes and
+/// backslashes keeping spaces in a word, but without expanding anything.
+fn shell_words(command: &str) -> Result<Vec<String>> {
+    let unmatched = |quote: char| anyhow!("unmatched {quote} in `{command}`");
+
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(unmatched('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(unmatched('"')),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(unmatched('"')),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(anyhow!("trailing \\ in `{command}`")),
+            },
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Quotes `s` as a single word for a POSIX shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
 // Rust-analyzer returns paths in the form of "file:///<drive>:/...", which gets parsed into URL
 // as "/<drive>://" which is then interpreted by PathBuf::new() as a UNIX-like path from root.
 // This function strips the additional / from the beginning, if the first segment is a drive letter.
@@ -965,3 +1400,29 @@ fn parse_os(os: &str) -> HostPlatform {
         _ => UnknownOS,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{shell_quote, shell_words};
+
+    #[test]
+    fn test_shell_words() {
+        assert_eq!(
+            shell_words("docker exec -i 'my box'").unwrap(),
+            vec!["docker", "exec", "-i", "my box"]
+        );
+        assert_eq!(
+            shell_words(r#"kubectl cp "{local}" pod:\"{remote}\" a\ b"#).unwrap(),
+            vec!["kubectl", "cp", "{local}", r#"pod:"{remote}""#, "a b"]
+        );
+        assert_eq!(shell_words("  ").unwrap(), Vec::<String>::new());
+        assert_eq!(shell_words("''").unwrap(), vec![""]);
+        assert!(shell_words("docker exec 'box").is_err());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/tmp/a b"), "'/tmp/a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,222 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use lapce_core::{directory::Directory, language::LapceLanguage};
+use serde::{Deserialize, Deserializer};
+use toml_edit::easy as toml;
+
+use crate::data::{LapceWorkspace, LapceWorkspaceType};
+
+/// A snippet defined by the user. Snippet files have the format of VS Code
+/// snippets, whether they are written in TOML or JSON.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserSnippet {
+    /// The key of the snippet in its file.
+    #[serde(skip)]
+    pub name: String,
+    /// The words completed to the snippet.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub prefix: Vec<String>,
+    /// The snippet, whose lines can be given as an array.
+    #[serde(deserialize_with = "lines")]
+    pub body: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The comma-separated languages of a global snippet, which is for all
+    /// languages when it's not set.
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl UserSnippet {
+    fn is_for(&self, language: Option<LapceLanguage>) -> bool {
+        match &self.scope {
+            Some(scope) => language
+                .map(|language| {
+                    scope.split(',').any(|s| {
+                        s.trim().parse::<LapceLanguage>().ok() == Some(language)
+                    })
+                })
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    Ok(match OneOrMany::deserialize(d)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+fn lines<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    Ok(match OneOrMany::deserialize(d)? {
+        OneOrMany::One(s) => s,
+        OneOrMany::Many(v) => v.join("\n"),
+    })
+}
+
+/// The snippets from the `snippets` directories of the config and of the
+/// workspace's `.lapce`. A file there is named after the language of its
+/// snippets, like `rust.toml` or `python.json`, while the snippets of
+/// `global.toml`, `global.json` and `*.code-snippets` are for all
+/// languages.
+#[derive(Clone, Debug, Default)]
+pub struct UserSnippets {
+    global: Vec<UserSnippet>,
+    languages: HashMap<LapceLanguage, Vec<UserSnippet>>,
+}
+
+impl UserSnippets {
+    pub fn load(workspace: &LapceWorkspace) -> Self {
+        let mut snippets = Self::default();
+        if let Some(dir) = Directory::snippets_directory() {
+            snippets.load_dir(&dir);
+        }
+        if let LapceWorkspaceType::Local = workspace.kind {
+            if let Some(path) = workspace.path.as_ref() {
+                snippets.load_dir(&path.join("./.lapce/snippets"));
+            }
+        }
+        snippets
+    }
+
+    fn load_dir(&mut self, dir: &Path) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.flatten().map(|e| e.path()).sorted() {
+            let (stem, extension) = match (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) {
+                (Some(stem), Some(extension)) => (stem, extension),
+                _ => continue,
+            };
+            let language = if stem == "global" || extension == "code-snippets" {
+                None
+            } else if let Ok(language) = stem.parse::<LapceLanguage>() {
+                Some(language)
+            } else {
+                log::error!("unknown language of snippets {}", path.display());
+                continue;
+            };
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let snippets = match parse(&content, extension) {
+                Ok(snippets) => snippets,
+                Err(e) => {
+                    log::error!("failed to parse {}: {e}", path.display());
+                    continue;
+                }
+            };
+            match language {
+                Some(language) => {
+                    self.languages.entry(language).or_default().extend(snippets)
+                }
+                None => self.global.extend(snippets),
+            }
+        }
+    }
+
+    /// The snippets for `language`, which are only the global ones that
+    /// aren't scoped when there's no language.
+    pub fn for_language(
+        &self,
+        language: Option<LapceLanguage>,
+    ) -> impl Iterator<Item = &UserSnippet> {
+        self.global
+            .iter()
+            .filter(move |s| s.is_for(language))
+            .chain(
+                language
+                    .and_then(|language| self.languages.get(&language))
+                    .into_iter()
+                    .flatten(),
+            )
+    }
+}
+
+/// Parses a snippets file with `extension`. The line comments VS Code puts
+/// in its JSON snippet files are skipped.
+fn parse(content: &str, extension: &str) -> Result<Vec<UserSnippet>> {
+    let snippets: IndexMap<String, UserSnippet> = match extension {
+        "toml" => toml::from_str(content)?,
+        "json" | "code-snippets" => serde_json::from_str(
+            &content
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("//"))
+                .join("\n"),
+        )?,
+        _ => return Ok(Vec::new()),
+    };
+    Ok(snippets
+        .into_iter()
+        .map(|(name, mut snippet)| {
+            snippet.name = name;
+            snippet
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse() {
+        let snippets = parse(
+            r#"
+            [license]
+            prefix = "license"
+            body = ["// Copyright $CURRENT_YEAR", "$0"]
+
+            [test]
+            prefix = ["test", "tfn"]
+            body = "fn ${1:name}() {}"
+            description = "A test function"
+            "#,
+            "toml",
+        )
+        .unwrap();
+        assert_eq!(2, snippets.len());
+        assert_eq!("license", snippets[0].name);
+        assert_eq!(vec!["license".to_string()], snippets[0].prefix);
+        assert_eq!("// Copyright $CURRENT_YEAR\n$0", snippets[0].body);
+        assert_eq!(
+            vec!["test".to_string(), "tfn".to_string()],
+            snippets[1].prefix
+        );
+        assert_eq!(Some("A test function"), snippets[1].description.as_deref());
+
+        let snippets = parse(
+            r#"{
+                // Place your snippets here.
+                "Print": {
+                    "scope": "rust,python",
+                    "prefix": "print",
+                    "body": ["print(\"$1\")"]
+                }
+            }"#,
+            "code-snippets",
+        )
+        .unwrap();
+        assert_eq!(1, snippets.len());
+        assert_eq!("Print", snippets[0].name);
+        assert_eq!("print(\"$1\")", snippets[0].body);
+        assert_eq!(Some("rust,python"), snippets[0].scope.as_deref());
+    }
+}
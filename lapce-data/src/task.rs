@@ -0,0 +1,429 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use indexmap::IndexMap;
+use lapce_core::directory::Directory;
+use lapce_rpc::terminal::TerminalProfile;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+use regex::Regex;
+use serde::Deserialize;
+use toml_edit::easy as toml;
+
+use crate::data::{LapceWorkspace, LapceWorkspaceType};
+
+/// A named command from `tasks.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskConfig {
+    /// The program to run. Without `args` it's a command line run by the
+    /// shell instead.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The working directory, relative to the workspace.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Tasks to run, in order, before this one.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Names of the problem matchers applied to the output.
+    #[serde(default)]
+    pub problem_matchers: Vec<String>,
+}
+
+impl TaskConfig {
+    /// The profile of the terminal running the task. The command is run by
+    /// `sh`, or by `cmd` on Windows, which reports the exit code with
+    /// OSC 133 once it's done.
+    pub fn terminal_profile(
+        &self,
+        name: &str,
+        workspace: &LapceWorkspace,
+    ) -> TerminalProfile {
+        let workdir = match (self.cwd.as_ref(), workspace.path.as_ref()) {
+            (Some(cwd), Some(path)) => Some(path.join(cwd)),
+            (Some(cwd), None) => Some(cwd.clone()),
+            (None, path) => path.cloned(),
+        };
+
+        let (program, arguments) = if cfg!(windows) && !workspace.kind.is_remote() {
+            let mut line = self.command.clone();
+            for arg in &self.args {
+                if arg.contains(' ') {
+                    line.push_str(&format!(r#" "{arg}""#));
+                } else {
+                    line.push_str(&format!(" {arg}"));
+                }
+            }
+            // `%^errorlevel%` is only expanded by `call`, after the command
+            // ran, rather than when the line is read.
+            line.push_str(" & call echo \x1b]133;D;%^errorlevel%\x07");
+            ("cmd".to_string(), vec!["/C".to_string(), line])
+        } else {
+            let run = if self.args.is_empty() {
+                r#"eval "$1""#
+            } else {
+                r#""$@""#
+            };
+            let mut arguments = vec![
+                "-c".to_string(),
+                format!(
+                    r#"{run}; status=$?; printf '\033]133;D;%d\007' "$status"; exit "$status""#
+                ),
+                "sh".to_string(),
+                self.command.clone(),
+            ];
+            arguments.extend(self.args.iter().cloned());
+            ("sh".to_string(), arguments)
+        };
+
+        TerminalProfile {
+            title: Some(format!("Task: {name}")),
+            icon: None,
+            program,
+            arguments,
+            workdir,
+            environment: self.env.clone(),
+        }
+    }
+}
+
+/// Regexes matched against consecutive lines of the output, whose named
+/// groups `file`, `line`, `column`, `end_line`, `end_column`, `severity`,
+/// `code` and `message` together describe a problem.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProblemMatcherConfig {
+    pub pattern: Vec<String>,
+    /// The severity when the pattern doesn't capture one.
+    #[serde(default)]
+    pub severity: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TasksConfig {
+    #[serde(default)]
+    pub tasks: IndexMap<String, TaskConfig>,
+    #[serde(default)]
+    pub problem_matchers: IndexMap<String, ProblemMatcherConfig>,
+}
+
+impl TasksConfig {
+    /// Loads the global `tasks.toml` and the workspace's
+    /// `.lapce/tasks.toml`, whose entries take precedence.
+    pub fn load(workspace: &LapceWorkspace) -> Self {
+        let mut config = Self::default();
+        if let Some(path) = Directory::config_directory() {
+            config.merge(Self::load_file(&path.join("tasks.toml")));
+        }
+        if let LapceWorkspaceType::Local = workspace.kind {
+            if let Some(path) = workspace.path.as_ref() {
+                config.merge(Self::load_file(&path.join("./.lapce/tasks.toml")));
+            }
+        }
+        config
+    }
+
+    fn load_file(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        match toml::from_str(&content) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                log::error!("failed to parse {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Option<Self>) {
+        if let Some(other) = other {
+            self.tasks.extend(other.tasks);
+            self.problem_matchers.extend(other.problem_matchers);
+        }
+    }
+
+    /// The tasks to run for `name`, its dependencies first.
+    pub fn run_order(&self, name: &str) -> Result<Vec<String>> {
+        fn visit(
+            config: &TasksConfig,
+            name: &str,
+            visiting: &mut HashSet<String>,
+            order: &mut Vec<String>,
+        ) -> Result<()> {
+            if order.iter().any(|n| n == name) {
+                return Ok(());
+            }
+            if !visiting.insert(name.to_string()) {
+                return Err(anyhow!("task {name} depends on itself"));
+            }
+            let task = config
+                .tasks
+                .get(name)
+                .ok_or_else(|| anyhow!("task {name} doesn't exist"))?;
+            for dependency in task.depends_on.iter() {
+                visit(config, dependency, visiting, order)?;
+            }
+            visiting.remove(name);
+            order.push(name.to_string());
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        visit(self, name, &mut HashSet::new(), &mut order)?;
+        Ok(order)
+    }
+
+    /// The profile of the terminal running the task.
+    pub fn terminal_profile(
+        &self,
+        name: &str,
+        workspace: &LapceWorkspace,
+    ) -> Option<TerminalProfile> {
+        Some(self.tasks.get(name)?.terminal_profile(name, workspace))
+    }
+
+    /// Applies the problem matchers of the task to its output. Relative
+    /// file paths are resolved against the task's working directory and
+    /// then the workspace.
+    pub fn match_problems(
+        &self,
+        name: &str,
+        lines: &[String],
+        cwd: Option<&Path>,
+        workspace: &LapceWorkspace,
+    ) -> HashMap<PathBuf, Vec<Diagnostic>> {
+        let mut problems: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        let task = match self.tasks.get(name) {
+            Some(task) => task,
+            None => return problems,
+        };
+
+        for matcher_name in task.problem_matchers.iter() {
+            let matcher = match self
+                .problem_matchers
+                .get(matcher_name)
+                .cloned()
+                .or_else(|| builtin_problem_matcher(matcher_name))
+                .and_then(|config| ProblemMatcher::new(&config).ok())
+            {
+                Some(matcher) => matcher,
+                None => {
+                    log::error!("problem matcher {matcher_name} doesn't exist");
+                    continue;
+                }
+            };
+            for (file, mut diagnostic) in matcher.matches(lines) {
+                diagnostic.source = Some(name.to_string());
+                let path = resolve_problem_path(&file, cwd, workspace);
+                problems.entry(path).or_default().push(diagnostic);
+            }
+        }
+        problems
+    }
+}
+
+/// Matchers for common output, which can be used by name without being
+/// configured.
+fn builtin_problem_matcher(name: &str) -> Option<ProblemMatcherConfig> {
+    let pattern = match name {
+        "rustc" => vec![
+            r"^(?P<severity>error|warning)(?:\[(?P<code>[^\]]+)\])?: (?P<message>.+)$",
+            r"^\s*--> (?P<file>.+?):(?P<line>\d+):(?P<column>\d+)$",
+        ],
+        "gcc" => vec![
+            r"^(?P<file>[^:\s][^:]*):(?P<line>\d+):(?:(?P<column>\d+):)? (?:fatal )?(?P<severity>error|warning|note): (?P<message>.+)$",
+        ],
+        _ => return None,
+    };
+    Some(ProblemMatcherConfig {
+        pattern: pattern.into_iter().map(|p| p.to_string()).collect(),
+        severity: None,
+    })
+}
+
+fn resolve_problem_path(
+    file: &str,
+    cwd: Option<&Path>,
+    workspace: &LapceWorkspace,
+) -> PathBuf {
+    let path = PathBuf::from(file);
+    if path.is_absolute() {
+        return path;
+    }
+    let candidates: Vec<PathBuf> = [cwd, workspace.path.as_deref()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(&path))
+        .collect();
+    if !workspace.kind.is_remote() {
+        if let Some(path) = candidates.iter().find(|p| p.exists()) {
+            return path.clone();
+        }
+    }
+    candidates.into_iter().next().unwrap_or(path)
+}
+
+struct ProblemMatcher {
+    patterns: Vec<Regex>,
+    severity: DiagnosticSeverity,
+}
+
+impl ProblemMatcher {
+    fn new(config: &ProblemMatcherConfig) -> Result<Self> {
+        let patterns = config
+            .pattern
+            .iter()
+            .map(|p| Regex::new(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        if patterns.is_empty() {
+            return Err(anyhow!("problem matcher has no pattern"));
+        }
+        let severity = config
+            .severity
+            .as_deref()
+            .and_then(parse_severity)
+            .unwrap_or(DiagnosticSeverity::ERROR);
+        Ok(Self { patterns, severity })
+    }
+
+    fn matches(&self, lines: &[String]) -> Vec<(String, Diagnostic)> {
+        let mut problems = Vec::new();
+        let mut i = 0;
+        while i + self.patterns.len() <= lines.len() {
+            let mut captures = HashMap::new();
+            let matched = self.patterns.iter().enumerate().all(|(j, pattern)| {
+                if let Some(c) = pattern.captures(&lines[i + j]) {
+                    for name in pattern.capture_names().flatten() {
+                        if let Some(m) = c.name(name) {
+                            captures.insert(name, m.as_str());
+                        }
+                    }
+                    true
+                } else {
+                    false
+                }
+            });
+            if !matched {
+                i += 1;
+                continue;
+            }
+            i += self.patterns.len();
+
+            let file = match captures.get("file") {
+                Some(file) => file.to_string(),
+                None => continue,
+            };
+            let number = |name: &str| {
+                captures
+                    .get(name)
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .map(|n| n.saturating_sub(1))
+            };
+            let start = Position::new(
+                number("line").unwrap_or(0),
+                number("column").unwrap_or(0),
+            );
+            let end = Position::new(
+                number("end_line").unwrap_or(start.line),
+                number("end_column").unwrap_or(start.character),
+            );
+            problems.push((
+                file,
+                Diagnostic {
+                    range: Range::new(start, end),
+                    severity: Some(
+                        captures
+                            .get("severity")
+                            .and_then(|s| parse_severity(s))
+                            .unwrap_or(self.severity),
+                    ),
+                    code: captures
+                        .get("code")
+                        .map(|code| NumberOrString::String(code.to_string())),
+                    message: captures
+                        .get("message")
+                        .map(|m| m.to_string())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                },
+            ));
+        }
+        problems
+    }
+}
+
+fn parse_severity(severity: &str) -> Option<DiagnosticSeverity> {
+    match severity.to_lowercase().as_str() {
+        "error" | "fatal" => Some(DiagnosticSeverity::ERROR),
+        "warning" | "warn" => Some(DiagnosticSeverity::WARNING),
+        "info" | "information" | "note" => Some(DiagnosticSeverity::INFORMATION),
+        "hint" => Some(DiagnosticSeverity::HINT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{DiagnosticSeverity, NumberOrString, Position};
+
+    use super::{builtin_problem_matcher, ProblemMatcher, TasksConfig};
+
+    #[test]
+    fn test_run_order() {
+        let config: TasksConfig = toml_edit::easy::from_str(
+            r#"
+            [tasks.fmt]
+            command = "cargo fmt"
+            [tasks.build]
+            command = "cargo"
+            args = ["build"]
+            depends-on = ["fmt"]
+            [tasks.test]
+            command = "cargo"
+            args = ["test"]
+            depends-on = ["fmt", "build"]
+            [tasks.loop]
+            command = "true"
+            depends-on = ["loop"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["fmt".to_string(), "build".to_string(), "test".to_string()],
+            config.run_order("test").unwrap()
+        );
+        assert!(config.run_order("loop").is_err());
+        assert!(config.run_order("missing").is_err());
+    }
+
+    #[test]
+    fn test_rustc_problem_matcher() {
+        let matcher =
+            ProblemMatcher::new(&builtin_problem_matcher("rustc").unwrap()).unwrap();
+        let lines = [
+            "   Compiling lapce v0.2.4",
+            "error[E0308]: mismatched types",
+            "  --> src/main.rs:42:7",
+            "warning: unused variable: `x`",
+            " --> src/lib.rs:3:9",
+        ]
+        .map(|l| l.to_string());
+        let problems = matcher.matches(&lines);
+        assert_eq!(2, problems.len());
+        assert_eq!("src/main.rs", problems[0].0);
+        assert_eq!(Position::new(41, 6), problems[0].1.range.start);
+        assert_eq!(Some(DiagnosticSeverity::ERROR), problems[0].1.severity);
+        assert_eq!(
+            Some(NumberOrString::String("E0308".to_string())),
+            problems[0].1.code
+        );
+        assert_eq!("mismatched types", problems[0].1.message);
+        assert_eq!(Some(DiagnosticSeverity::WARNING), problems[1].1.severity);
+    }
+}
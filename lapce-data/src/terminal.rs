@@ -1,12 +1,12 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use alacritty_terminal::{
     ansi,
     event::EventListener,
     grid::{Dimensions, Scroll},
-    index::{Direction, Side},
+    index::{Column, Direction, Line, Point, Side},
     selection::{Selection, SelectionType},
-    term::{search::RegexSearch, test::TermSize, TermMode},
+    term::{cell::Flags, search::RegexSearch, test::TermSize, TermMode},
     vi_mode::ViMotion,
     Term,
 };
@@ -21,20 +21,25 @@
     movement::{LinePosition, Movement},
     register::Clipboard,
 };
-use lapce_rpc::terminal::TermId;
+use lapce_rpc::terminal::{TermId, TerminalProfile};
+use lsp_types::Url;
+use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use regex::Regex;
 
 use crate::{
     command::{
         CommandExecuted, CommandKind, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND,
     },
     config::{LapceConfig, LapceTheme},
-    data::LapceWorkspace,
+    data::{LapceMainSplitData, LapceWorkspace, SplitContent},
+    db::{TerminalInfo, TerminalPanelInfo, TerminalSplitInfo},
     document::SystemClipboard,
+    editor::LineCol,
     find::Find,
     keypress::KeyPressFocus,
-    proxy::LapceProxy,
-    split::SplitMoveDirection,
+    proxy::{path_from_url, LapceProxy},
+    split::{SplitDirection, SplitMoveDirection},
 };
 
 pub type TermConfig = alacritty_terminal::config::Config;
@@ -54,7 +59,8 @@ pub fn new(
         config: &LapceConfig,
         event_sink: ExtEventSink,
     ) -> Self {
-        let split = TerminalSplitData::new(worksapce, proxy, config, event_sink);
+        let split =
+            TerminalSplitData::new(worksapce, proxy, config, event_sink, None);
         let tabs_order = Arc::new(vec![split.split_id]);
         let mut tabs = im::HashMap::new();
         tabs.insert(split.split_id, split);
@@ -92,21 +98,107 @@ pub fn active_terminal_split_mut(&mut self) -> Option<&mut TerminalSplitData> {
             .and_then(|id| self.tabs.get_mut(id))
     }
 
+    /// Opens a new terminal tab launched with the named profile, or with
+    /// the default one if `profile` is `None`.
     pub fn new_tab(
         &mut self,
         workspace: Arc<LapceWorkspace>,
         proxy: Arc<LapceProxy>,
         config: &LapceConfig,
         event_sink: ExtEventSink,
+        profile: Option<String>,
     ) {
-        let active_index = (self.active + 1).min(self.tabs_order.len());
         let new_term_split =
-            TerminalSplitData::new(workspace, proxy, config, event_sink);
-        let new_term_tab_id = new_term_split.split_id;
+            TerminalSplitData::new(workspace, proxy, config, event_sink, profile);
+        self.insert_tab(new_term_split);
+    }
+
+    /// Opens a new terminal tab running a task.
+    pub fn new_task_tab(
+        &mut self,
+        workspace: Arc<LapceWorkspace>,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
+        profile: TerminalProfile,
+        task: TerminalTask,
+    ) {
+        let split_id = WidgetId::next();
+        let mut terminal_data = LapceTerminalData::with_profile(
+            workspace, split_id, event_sink, proxy, None, profile,
+        );
+        terminal_data.task = Some(task);
+        self.insert_tab(TerminalSplitData::with_terminal(split_id, terminal_data));
+    }
+
+    /// Moves a terminal from the editor area to a new tab, returning the id
+    /// of its new widget.
+    pub fn add_terminal_tab(&mut self, mut terminal: LapceTerminalData) -> WidgetId {
+        let split_id = WidgetId::next();
+        terminal.relocate(split_id, None);
+        let widget_id = terminal.widget_id;
+        self.insert_tab(TerminalSplitData::with_terminal(split_id, terminal));
+        widget_id
+    }
+
+    fn insert_tab(&mut self, split: TerminalSplitData) {
+        let active_index = (self.active + 1).min(self.tabs_order.len());
+        let new_term_tab_id = split.split_id;
         Arc::make_mut(&mut self.tabs_order).insert(active_index, new_term_tab_id);
-        self.tabs.insert(new_term_tab_id, new_term_split);
+        self.tabs.insert(new_term_tab_id, split);
         self.active = active_index;
     }
+
+    pub fn get_terminal(&self, term_id: &TermId) -> Option<&Arc<LapceTerminalData>> {
+        self.tabs
+            .values()
+            .find_map(|split| split.terminals.get(term_id))
+    }
+
+    pub fn get_terminal_mut(
+        &mut self,
+        term_id: &TermId,
+    ) -> Option<&mut LapceTerminalData> {
+        self.tabs
+            .iter_mut()
+            .find_map(|(_, split)| split.terminals.get_mut(term_id))
+            .map(Arc::make_mut)
+    }
+
+    /// The terminals to restore in the next session, with up to
+    /// `scrollback` lines of their output. Terminals running tasks aren't
+    /// restored.
+    pub fn panel_info(&self, scrollback: usize) -> Option<TerminalPanelInfo> {
+        let mut active = 0;
+        let mut tabs = Vec::new();
+        for (i, split_id) in self.tabs_order.iter().enumerate() {
+            let split = match self.tabs.get(split_id) {
+                Some(split) => split,
+                None => continue,
+            };
+            let terminals = split
+                .ordered_terminals()
+                .into_iter()
+                .filter(|t| t.task.is_none())
+                .collect::<Vec<_>>();
+            if terminals.is_empty() {
+                continue;
+            }
+            if i == self.active {
+                active = tabs.len();
+            }
+            tabs.push(TerminalSplitInfo {
+                active: terminals
+                    .iter()
+                    .position(|t| t.term_id == split.active_term_id)
+                    .unwrap_or(0),
+                terminals: terminals.iter().map(|t| t.info(scrollback)).collect(),
+            });
+        }
+        if tabs.is_empty() {
+            return None;
+        }
+        Some(TerminalPanelInfo { active, tabs })
+    }
 }
 
 #[derive(Clone)]
@@ -115,6 +207,8 @@ pub struct TerminalSplitData {
     pub active_term_id: TermId,
     pub split_id: WidgetId,
     pub terminals: im::HashMap<TermId, Arc<LapceTerminalData>>,
+    /// The terminals in the order they're laid out in the split.
+    order: im::Vector<TermId>,
     pub indexed_colors: Arc<HashMap<u8, Color>>,
 }
 
@@ -124,25 +218,75 @@ pub fn new(
         proxy: Arc<LapceProxy>,
         config: &LapceConfig,
         event_sink: ExtEventSink,
+        profile: Option<String>,
     ) -> Self {
         let split_id = WidgetId::next();
-        let terminal_data = Arc::new(LapceTerminalData::new(
-            workspace, split_id, event_sink, proxy, config,
-        ));
-        let term_id = terminal_data.term_id;
-        let widget_id = terminal_data.widget_id;
-        let mut terminals = im::HashMap::new();
-        terminals.insert(term_id, terminal_data);
+        let terminal_data = LapceTerminalData::new(
+            workspace, split_id, event_sink, proxy, config, profile, None,
+        );
+        Self::with_terminal(split_id, terminal_data)
+    }
+
+    pub fn with_terminal(
+        split_id: WidgetId,
+        terminal_data: LapceTerminalData,
+    ) -> Self {
+        Self::with_terminals(split_id, vec![terminal_data], 0)
+    }
+
+    /// Creates a split of the terminals, which mustn't be empty.
+    pub fn with_terminals(
+        split_id: WidgetId,
+        terminals: Vec<LapceTerminalData>,
+        active: usize,
+    ) -> Self {
+        let active = &terminals[active.min(terminals.len() - 1)];
+        let active_term_id = active.term_id;
+        let widget_id = active.widget_id;
+        let order = terminals.iter().map(|t| t.term_id).collect();
+        let terminals = terminals
+            .into_iter()
+            .map(|t| (t.term_id, Arc::new(t)))
+            .collect();
 
         Self {
-            active_term_id: term_id,
+            active_term_id,
             active: widget_id,
             split_id,
             terminals,
+            order,
             indexed_colors: Arc::new(Self::get_indexed_colors()),
         }
     }
 
+    /// The terminals in the order they're laid out in the split.
+    pub fn ordered_terminals(&self) -> Vec<&Arc<LapceTerminalData>> {
+        self.order
+            .iter()
+            .filter_map(|term_id| self.terminals.get(term_id))
+            .collect()
+    }
+
+    /// Adds the terminal at `index` in the layout of the split.
+    pub fn insert_terminal(
+        &mut self,
+        index: usize,
+        terminal: Arc<LapceTerminalData>,
+    ) {
+        self.order.retain(|term_id| *term_id != terminal.term_id);
+        self.order
+            .insert(index.min(self.order.len()), terminal.term_id);
+        self.terminals.insert(terminal.term_id, terminal);
+    }
+
+    pub fn remove_terminal(
+        &mut self,
+        term_id: &TermId,
+    ) -> Option<Arc<LapceTerminalData>> {
+        self.order.retain(|id| id != term_id);
+        self.terminals.remove(term_id)
+    }
+
     pub fn active_terminal(&self) -> Option<&Arc<LapceTerminalData>> {
         self.terminals.get(&self.active_term_id)
     }
@@ -272,6 +416,7 @@ fn get_named_color(
 
 pub struct LapceTerminalViewData {
     pub terminal: Arc<LapceTerminalData>,
+    pub main_split: LapceMainSplitData,
     pub config: Arc<LapceConfig>,
     pub find: Arc<Find>,
 }
@@ -281,6 +426,63 @@ fn terminal_mut(&mut self) -> &mut LapceTerminalData {
         Arc::make_mut(&mut self.terminal)
     }
 
+    /// Runs the split commands of a terminal in the editor area the same
+    /// way as for the other editor tabs.
+    fn run_editor_split_command(
+        &mut self,
+        ctx: &mut EventCtx,
+        command: &FocusCommand,
+        editor_tab_id: WidgetId,
+    ) -> CommandExecuted {
+        let content = SplitContent::EditorTab(editor_tab_id);
+        match command {
+            FocusCommand::SplitVertical => {
+                self.main_split.tab_split(
+                    ctx,
+                    editor_tab_id,
+                    SplitDirection::Vertical,
+                    &self.config,
+                );
+            }
+            FocusCommand::SplitHorizontal => {
+                self.main_split.tab_split(
+                    ctx,
+                    editor_tab_id,
+                    SplitDirection::Horizontal,
+                    &self.config,
+                );
+            }
+            FocusCommand::SplitExchange => {
+                self.main_split.split_exchange(ctx, content);
+            }
+            FocusCommand::SplitLeft => {
+                self.main_split
+                    .split_move(ctx, content, SplitMoveDirection::Left);
+            }
+            FocusCommand::SplitRight => {
+                self.main_split
+                    .split_move(ctx, content, SplitMoveDirection::Right);
+            }
+            FocusCommand::SplitUp => {
+                self.main_split
+                    .split_move(ctx, content, SplitMoveDirection::Up);
+            }
+            FocusCommand::SplitDown => {
+                self.main_split
+                    .split_move(ctx, content, SplitMoveDirection::Down);
+            }
+            FocusCommand::SplitClose => {
+                self.main_split.widget_close(
+                    ctx,
+                    self.terminal.widget_id,
+                    editor_tab_id,
+                );
+            }
+            _ => return CommandExecuted::No,
+        }
+        CommandExecuted::Yes
+    }
+
     fn toggle_visual(&mut self, visual_mode: VisualMode) {
         if !self.config.core.modal {
             return;
@@ -341,7 +543,11 @@ fn get_mode(&self) -> Mode {
     }
 
     fn check_condition(&self, condition: &str) -> bool {
-        matches!(condition, "terminal_focus" | "panel_focus")
+        match condition {
+            "terminal_focus" => true,
+            "panel_focus" => self.terminal.editor_tab_id.is_none(),
+            _ => false,
+        }
     }
 
     fn run_command(
@@ -354,6 +560,15 @@ fn run_command(
     ) -> CommandExecuted {
         let mut clipboard = SystemClipboard {};
         ctx.request_paint();
+        if let (CommandKind::Focus(cmd), Some(editor_tab_id)) =
+            (&command.kind, self.terminal.editor_tab_id)
+        {
+            if self.run_editor_split_command(ctx, cmd, editor_tab_id)
+                == CommandExecuted::Yes
+            {
+                return CommandExecuted::Yes;
+            }
+        }
         match &command.kind {
             CommandKind::Move(cmd) => {
                 let movement = cmd.to_movement(count);
@@ -554,6 +769,22 @@ fn run_command(
                         );
                     }
                 }
+                FocusCommand::TerminalPreviousCommand => {
+                    self.terminal.raw.lock().scroll_to_command(Direction::Left);
+                }
+                FocusCommand::TerminalNextCommand => {
+                    let mut raw = self.terminal.raw.lock();
+                    if !raw.scroll_to_command(Direction::Right) {
+                        raw.term.scroll_display(Scroll::Bottom);
+                    }
+                }
+                FocusCommand::TerminalCopyLastCommandOutput => {
+                    if let Some(output) =
+                        self.terminal.raw.lock().last_command_output()
+                    {
+                        clipboard.put_string(output);
+                    }
+                }
                 _ => return CommandExecuted::No,
             },
             _ => return CommandExecuted::No,
@@ -576,15 +807,208 @@ pub struct RawTerminal {
     pub parser: ansi::Processor,
     pub term: Term<EventProxy>,
     pub scroll_delta: f64,
+    pub shell_integration: ShellIntegration,
+    /// Text to run once the shell has started, for a terminal launched to
+    /// run it.
+    pub pending_run: Option<String>,
+    /// The output of a previous session, which waits for the terminal to
+    /// get its real size so that its lines wrap the way they're shown.
+    pending_scrollback: Option<Vec<String>>,
+    /// The output of the shell received while the scrollback is pending,
+    /// to be written below it.
+    held_output: Vec<u8>,
 }
 
 impl RawTerminal {
+    /// Writes the output of a previous session above the output of the new
+    /// shell, once the terminal is resized to its real size.
+    pub fn restore_scrollback(&mut self, lines: &[String]) {
+        if lines.is_empty() {
+            return;
+        }
+        self.pending_scrollback = Some(lines.to_vec());
+    }
+
+    /// Resizes the terminal, then writes the pending scrollback and the
+    /// output held back behind it.
+    pub fn resize(&mut self, size: TermSize) {
+        self.term.resize(size);
+        if let Some(lines) = self.pending_scrollback.take() {
+            let content = lines.join("\r\n") + "\r\n";
+            for byte in content.into_bytes() {
+                self.advance(byte);
+            }
+            self.write_marker("Restored");
+            for byte in std::mem::take(&mut self.held_output) {
+                self.advance(byte);
+                self.shell_integration.advance(byte, &self.term);
+            }
+        }
+    }
+
+    /// Writes a dimmed `[label]` line, to tell apart the output of the
+    /// shell before and after it was relaunched.
+    fn write_marker(&mut self, label: &str) {
+        let marker = format!("\x1b[2m[{label}]\x1b[0m\r\n");
+        for byte in marker.into_bytes() {
+            self.advance(byte);
+        }
+    }
+
     pub fn update_content(&mut self, content: &str) {
         if let Ok(content) = base64::decode(content) {
+            if self.pending_scrollback.is_some() {
+                self.held_output.extend(content);
+                return;
+            }
             for byte in content {
-                self.parser.advance(&mut self.term, byte);
+                self.advance(byte);
+                self.shell_integration.advance(byte, &self.term);
+            }
+        }
+    }
+
+    /// Feeds a byte of output to the terminal. A line feed on the bottom
+    /// line of a full scrollback drops the line at its top, so the command
+    /// marks, which are counted from there, are moved up with the text.
+    fn advance(&mut self, byte: u8) {
+        if byte != b'\n' || self.term.mode().contains(TermMode::ALT_SCREEN) {
+            self.parser.advance(&mut self.term, byte);
+            return;
+        }
+
+        let history_size = self.term.grid().history_size();
+        let cursor_line = self.term.grid().cursor.point.line;
+        self.parser.advance(&mut self.term, byte);
+        if cursor_line == self.term.bottommost_line()
+            && self.term.grid().cursor.point.line == cursor_line
+            && self.term.grid().history_size() == history_size
+        {
+            self.shell_integration.drop_lines(1);
+        }
+    }
+
+    /// Whether the shell is ready to take the text it was launched to run,
+    /// which it tells by drawing a prompt marked with OSC 133 or by turning
+    /// on bracketed paste when its line editor starts.
+    pub fn shell_ready(&self) -> bool {
+        self.shell_integration.prompt_seen
+            || self.term.mode().contains(TermMode::BRACKETED_PASTE)
+    }
+
+    /// The input that runs `text` in the shell, pasted as a whole when the
+    /// shell supports bracketed paste so that multi-line code isn't run
+    /// line by line.
+    pub fn run_input(&self, text: &str) -> String {
+        let text = text.trim_end_matches(['\r', '\n']).replace("\r\n", "\n");
+        if self.term.mode().contains(TermMode::BRACKETED_PASTE) {
+            format!("\x1b[200~{}\x1b[201~\r", text.replace('\x1b', ""))
+        } else {
+            format!("{}\r", text.replace('\n', "\r"))
+        }
+    }
+
+    /// The line of the cursor, counted from the top of the scrollback.
+    fn cursor_line(term: &Term<EventProxy>) -> usize {
+        (term.grid().history_size() as i32 + term.grid().cursor.point.line.0).max(0)
+            as usize
+    }
+
+    /// Converts a line counted from the top of the scrollback to a grid line.
+    pub fn grid_line(term: &Term<EventProxy>, line: usize) -> Line {
+        Line(line as i32 - term.grid().history_size() as i32)
+    }
+
+    /// Scrolls to the start of the command before or after the top of the
+    /// view, returning whether there was one.
+    pub fn scroll_to_command(&mut self, direction: Direction) -> bool {
+        let history_size = self.term.grid().history_size();
+        let display_offset = self.term.grid().display_offset();
+        let top = history_size - display_offset;
+        let commands = self.shell_integration.commands.iter();
+        let line = match direction {
+            Direction::Left => commands
+                .rev()
+                .map(|c| c.prompt_line)
+                .find(|line| *line < top),
+            Direction::Right => {
+                commands.map(|c| c.prompt_line).find(|line| *line > top)
+            }
+        };
+        let line = match line {
+            Some(line) => line,
+            None => return false,
+        };
+        let new_offset = history_size.saturating_sub(line);
+        self.term.scroll_display(Scroll::Delta(
+            new_offset as i32 - display_offset as i32,
+        ));
+        true
+    }
+
+    /// The text of the scrollback and the screen, with wrapped lines
+    /// joined.
+    pub fn text_lines(&self) -> Vec<String> {
+        let term = &self.term;
+        let mut lines = Vec::new();
+        let mut current = String::new();
+        for line in term.topmost_line().0..=term.bottommost_line().0 {
+            let row = &term.grid()[Line(line)];
+            for column in 0..term.columns() {
+                let cell = &row[Column(column)];
+                if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                    current.push(cell.c);
+                }
+            }
+            if !row[term.last_column()].flags.contains(Flags::WRAPLINE) {
+                lines.push(current.trim_end().to_string());
+                current.clear();
             }
         }
+        if !current.is_empty() {
+            lines.push(current.trim_end().to_string());
+        }
+        lines
+    }
+
+    /// The last `max_lines` lines of the text, without the empty lines at
+    /// the bottom of the screen.
+    pub fn scrollback(&self, max_lines: usize) -> Vec<String> {
+        if max_lines == 0 {
+            return Vec::new();
+        }
+        if let Some(lines) = self.pending_scrollback.as_ref() {
+            let start = lines.len().saturating_sub(max_lines);
+            return lines[start..].to_vec();
+        }
+        let mut lines = self.text_lines();
+        while lines.last().map(|l| l.is_empty()).unwrap_or(false) {
+            lines.pop();
+        }
+        let start = lines.len().saturating_sub(max_lines);
+        lines.split_off(start)
+    }
+
+    /// The output of the last command that finished.
+    pub fn last_command_output(&self) -> Option<String> {
+        let command = self
+            .shell_integration
+            .commands
+            .iter()
+            .rev()
+            .find(|c| c.end_line.is_some())?;
+        let start = command.output_line?;
+        let end = command.end_line?;
+        if end <= start {
+            return Some(String::new());
+        }
+        Some(self.term.bounds_to_string(
+            Point::new(Self::grid_line(&self.term, start), Column(0)),
+            Point::new(
+                Self::grid_line(&self.term, end - 1),
+                self.term.last_column(),
+            ),
+        ))
     }
 }
 
@@ -609,10 +1033,218 @@ pub fn new(
             parser,
             term,
             scroll_delta: 0.0,
+            shell_integration: ShellIntegration::default(),
+            pending_run: None,
+            pending_scrollback: None,
+            held_output: Vec::new(),
+        }
+    }
+}
+
+/// A command run in the shell as marked by OSC 133. Lines are counted from
+/// the top of the scrollback.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TerminalCommandMark {
+    pub prompt_line: usize,
+    pub output_line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub exit_code: Option<i32>,
+}
+
+/// The sequences shells send when they are set up for terminal
+/// integration, which alacritty's parser ignores.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShellEvent {
+    /// OSC 7, the working directory of the shell.
+    Cwd(PathBuf),
+    /// OSC 133;A, the prompt is about to be drawn.
+    PromptStart,
+    /// OSC 133;C, the command was entered and its output follows.
+    CommandExecuted,
+    /// OSC 133;D, the command finished with the exit code if given.
+    CommandFinished(Option<i32>),
+}
+
+impl ShellEvent {
+    pub fn parse(osc: &[u8]) -> Option<Self> {
+        let osc = std::str::from_utf8(osc).ok()?;
+        let (kind, rest) = osc.split_once(';')?;
+        match kind {
+            "7" => {
+                let mut url = Url::parse(rest).ok()?;
+                if url.scheme() != "file" {
+                    return None;
+                }
+                // The host is the one the shell runs on, which is the one
+                // the workspace is on.
+                let _ = url.set_host(None);
+                Some(ShellEvent::Cwd(path_from_url(&url)))
+            }
+            "133" => {
+                let mut args = rest.split(';');
+                match args.next()? {
+                    "A" => Some(ShellEvent::PromptStart),
+                    "C" => Some(ShellEvent::CommandExecuted),
+                    "D" => Some(ShellEvent::CommandFinished(
+                        args.next().and_then(|code| code.parse().ok()),
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OscState {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Picks OSC sequences out of the pty output, alongside the parser.
+#[derive(Clone, Debug)]
+pub struct OscScanner {
+    state: OscState,
+    buf: Vec<u8>,
+}
+
+impl Default for OscScanner {
+    fn default() -> Self {
+        Self {
+            state: OscState::Ground,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl OscScanner {
+    const MAX_LEN: usize = 4096;
+
+    /// Returns the content of an OSC sequence when `byte` terminates it.
+    pub fn advance(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match (self.state, byte) {
+            (OscState::Ground, 0x1b) => self.state = OscState::Escape,
+            (OscState::Ground, _) => {}
+            (OscState::Escape | OscState::OscEscape, b']') => {
+                self.buf.clear();
+                self.state = OscState::Osc;
+            }
+            (OscState::Escape, 0x1b) => {}
+            (OscState::OscEscape, b'\\') => {
+                self.state = OscState::Ground;
+                return Some(std::mem::take(&mut self.buf));
+            }
+            (OscState::Escape | OscState::OscEscape, _) => {
+                self.state = OscState::Ground;
+            }
+            (OscState::Osc, 0x07) => {
+                self.state = OscState::Ground;
+                return Some(std::mem::take(&mut self.buf));
+            }
+            (OscState::Osc, 0x1b) => self.state = OscState::OscEscape,
+            (OscState::Osc, _) if self.buf.len() < Self::MAX_LEN => {
+                self.buf.push(byte);
+            }
+            (OscState::Osc, _) => self.state = OscState::Ground,
+        }
+        None
+    }
+}
+
+/// The state the shell reports through OSC 7 and OSC 133.
+#[derive(Clone, Debug, Default)]
+pub struct ShellIntegration {
+    pub cwd: Option<PathBuf>,
+    pub commands: Vec<TerminalCommandMark>,
+    /// The exit code of the last command the shell reported as finished.
+    pub last_exit_code: Option<i32>,
+    /// Whether the shell has drawn a prompt marked with OSC 133.
+    pub prompt_seen: bool,
+    scanner: OscScanner,
+}
+
+impl ShellIntegration {
+    const MAX_COMMANDS: usize = 1000;
+
+    fn advance(&mut self, byte: u8, term: &Term<EventProxy>) {
+        if let Some(event) = self
+            .scanner
+            .advance(byte)
+            .and_then(|osc| ShellEvent::parse(&osc))
+        {
+            self.handle_event(event, RawTerminal::cursor_line(term));
+        }
+    }
+
+    /// Moves the marks up as `count` lines are dropped from the top of the
+    /// scrollback, forgetting the commands whose prompt was dropped.
+    fn drop_lines(&mut self, count: usize) {
+        self.commands.retain(|command| command.prompt_line >= count);
+        for command in self.commands.iter_mut() {
+            command.prompt_line -= count;
+            command.output_line =
+                command.output_line.map(|line| line.saturating_sub(count));
+            command.end_line =
+                command.end_line.map(|line| line.saturating_sub(count));
+        }
+    }
+
+    fn handle_event(&mut self, event: ShellEvent, line: usize) {
+        match event {
+            ShellEvent::Cwd(path) => {
+                self.cwd = Some(path);
+            }
+            ShellEvent::PromptStart => {
+                self.prompt_seen = true;
+                match self.commands.last_mut() {
+                    // The prompt was redrawn without running anything.
+                    Some(command) if command.output_line.is_none() => {
+                        command.prompt_line = line;
+                    }
+                    _ => {
+                        self.commands.push(TerminalCommandMark {
+                            prompt_line: line,
+                            ..Default::default()
+                        });
+                        if self.commands.len() > Self::MAX_COMMANDS {
+                            self.commands.remove(0);
+                        }
+                    }
+                }
+            }
+            ShellEvent::CommandExecuted => {
+                if let Some(command) = self.commands.last_mut() {
+                    if command.end_line.is_none() {
+                        command.output_line = Some(line);
+                    }
+                }
+            }
+            ShellEvent::CommandFinished(exit_code) => {
+                self.last_exit_code = exit_code;
+                if let Some(command) = self.commands.last_mut() {
+                    if command.output_line.is_some() && command.end_line.is_none() {
+                        command.end_line = Some(line);
+                        command.exit_code = exit_code;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The task a terminal was opened to run.
+#[derive(Clone, Debug)]
+pub struct TerminalTask {
+    pub name: String,
+    /// The tasks to run once this one succeeds.
+    pub queue: Vec<String>,
+    /// Whether the task finished, and its exit code if it's known.
+    pub finished: Option<Option<i32>>,
+}
+
 #[derive(Clone)]
 pub struct LapceTerminalData {
     pub term_id: TermId,
@@ -620,6 +1252,14 @@ pub struct LapceTerminalData {
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
     pub title: String,
+    /// The name of the profile the terminal was launched with, if it
+    /// wasn't the default one.
+    pub profile_name: Option<String>,
+    pub profile: TerminalProfile,
+    pub task: Option<TerminalTask>,
+    /// The editor tab the terminal is in, if it's in the editor area
+    /// rather than the terminal panel.
+    pub editor_tab_id: Option<WidgetId>,
     pub mode: Mode,
     pub visual_mode: VisualMode,
     pub raw: Arc<Mutex<RawTerminal>>,
@@ -627,28 +1267,109 @@ pub struct LapceTerminalData {
 }
 
 impl LapceTerminalData {
+    /// `cwd` overrides the working directory of the profile, which is used
+    /// to start a split in the directory of the terminal it's split from.
     pub fn new(
         workspace: Arc<LapceWorkspace>,
         split_id: WidgetId,
         event_sink: ExtEventSink,
         proxy: Arc<LapceProxy>,
         config: &LapceConfig,
+        profile_name: Option<String>,
+        cwd: Option<PathBuf>,
+    ) -> Self {
+        let mut profile = config.terminal.profile(profile_name.as_deref());
+        if cwd.is_some() {
+            profile.workdir = cwd;
+        }
+        Self::with_profile(
+            workspace,
+            split_id,
+            event_sink,
+            proxy,
+            profile_name,
+            profile,
+        )
+    }
+
+    /// Launches a terminal with `profile`, whose relative working
+    /// directory is resolved against the workspace.
+    pub fn with_profile(
+        workspace: Arc<LapceWorkspace>,
+        split_id: WidgetId,
+        event_sink: ExtEventSink,
+        proxy: Arc<LapceProxy>,
+        profile_name: Option<String>,
+        profile: TerminalProfile,
+    ) -> Self {
+        Self::launch(
+            workspace,
+            split_id,
+            event_sink,
+            proxy,
+            profile_name,
+            profile,
+            &[],
+        )
+    }
+
+    /// Launches a terminal from a previous session, in the directory it
+    /// was last in and below the output it had.
+    pub fn restore(
+        workspace: Arc<LapceWorkspace>,
+        split_id: WidgetId,
+        event_sink: ExtEventSink,
+        proxy: Arc<LapceProxy>,
+        config: &LapceConfig,
+        info: &TerminalInfo,
+    ) -> Self {
+        let mut profile = config.terminal.profile(info.profile_name.as_deref());
+        if info.cwd.is_some() {
+            profile.workdir = info.cwd.clone();
+        }
+        Self::launch(
+            workspace,
+            split_id,
+            event_sink,
+            proxy,
+            info.profile_name.clone(),
+            profile,
+            &info.scrollback,
+        )
+    }
+
+    fn launch(
+        workspace: Arc<LapceWorkspace>,
+        split_id: WidgetId,
+        event_sink: ExtEventSink,
+        proxy: Arc<LapceProxy>,
+        profile_name: Option<String>,
+        mut profile: TerminalProfile,
+        scrollback: &[String],
     ) -> Self {
-        let cwd = workspace.path.as_ref().cloned();
+        profile.workdir = match profile.workdir.take() {
+            Some(workdir) if workdir.is_relative() => Some(
+                workspace
+                    .path
+                    .as_ref()
+                    .map(|path| path.join(&workdir))
+                    .unwrap_or(workdir),
+            ),
+            Some(workdir) => Some(workdir),
+            None => workspace.path.as_ref().cloned(),
+        };
         let widget_id = WidgetId::next();
         let view_id = WidgetId::next();
         let term_id = TermId::next();
-        let raw = Arc::new(Mutex::new(RawTerminal::new(
-            term_id,
-            proxy.clone(),
-            event_sink,
-        )));
+        let mut raw = RawTerminal::new(term_id, proxy.clone(), event_sink);
+        raw.restore_scrollback(scrollback);
+        let raw = Arc::new(Mutex::new(raw));
 
         let local_proxy = proxy.clone();
         let local_raw = raw.clone();
-        let shell = config.terminal.shell.clone();
+        let local_profile = profile.clone();
         std::thread::spawn(move || {
-            local_proxy.new_terminal(term_id, cwd, shell, local_raw);
+            local_proxy.new_terminal(term_id, local_profile, local_raw);
         });
 
         Self {
@@ -657,6 +1378,10 @@ pub fn new(
             view_id,
             split_id,
             title: "".to_string(),
+            profile_name,
+            profile,
+            task: None,
+            editor_tab_id: None,
             mode: Mode::Terminal,
             visual_mode: VisualMode::Normal,
             raw,
@@ -664,6 +1389,71 @@ pub fn new(
         }
     }
 
+    /// The working directory reported by the shell, or the one the
+    /// terminal was launched in.
+    pub fn cwd(&self) -> Option<PathBuf> {
+        self.cwd_of(&self.raw.lock()).cloned()
+    }
+
+    /// Launches the shell again in the directory it was last in, after
+    /// the proxy running it was reconnected to.
+    pub fn relaunch(&self) {
+        let mut profile = self.profile.clone();
+        profile.workdir = self.cwd();
+
+        let mut raw = self.raw.lock();
+        raw.write_marker("Reconnected");
+        let columns = raw.term.columns();
+        let lines = raw.term.screen_lines();
+        drop(raw);
+
+        self.proxy.proxy_rpc.new_terminal(self.term_id, profile);
+        self.proxy
+            .proxy_rpc
+            .terminal_resize(self.term_id, columns, lines);
+    }
+
+    /// Runs `text` in the shell of the terminal.
+    pub fn run_text(&self, text: &str) {
+        let mut raw = self.raw.lock();
+        self.proxy
+            .proxy_rpc
+            .terminal_write(self.term_id, &raw.run_input(text));
+        raw.term.scroll_display(Scroll::Bottom);
+    }
+
+    /// Moves the terminal to another split. It gets new widget ids as its
+    /// old widgets may still be around until the move is done.
+    pub fn relocate(&mut self, split_id: WidgetId, editor_tab_id: Option<WidgetId>) {
+        self.split_id = split_id;
+        self.editor_tab_id = editor_tab_id;
+        self.widget_id = WidgetId::next();
+        self.view_id = WidgetId::next();
+    }
+
+    /// What's needed to restore the terminal in the next session.
+    pub fn info(&self, scrollback: usize) -> TerminalInfo {
+        let raw = self.raw.lock();
+        TerminalInfo {
+            profile_name: self.profile_name.clone(),
+            cwd: self.cwd_of(&raw).cloned(),
+            scrollback: raw.scrollback(scrollback),
+        }
+    }
+
+    fn cwd_of<'a>(&'a self, raw: &'a RawTerminal) -> Option<&'a PathBuf> {
+        raw.shell_integration
+            .cwd
+            .as_ref()
+            .or(self.profile.workdir.as_ref())
+    }
+
+    /// The title of the profile if it has one, otherwise the title set by
+    /// the program running in the terminal.
+    pub fn display_title(&self) -> &str {
+        self.profile.title.as_deref().unwrap_or(&self.title)
+    }
+
     pub fn resize(&self, width: usize, height: usize) {
         let width = width.max(1);
         let height = height.max(1);
@@ -674,7 +1464,7 @@ pub fn resize(&self, width: usize, height: usize) {
         let proxy = self.proxy.clone();
         let term_id = self.term_id;
         std::thread::spawn(move || {
-            raw.lock().term.resize(size);
+            raw.lock().resize(size);
             proxy.proxy_rpc.terminal_resize(term_id, width, height);
         });
     }
@@ -917,6 +1707,188 @@ macro_rules! term_sequence {
     }
 }
 
+/// A link in the terminal output, either an OSC 8 hyperlink or a file
+/// location or url found in the text of a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TerminalLink {
+    File {
+        path: PathBuf,
+        position: Option<LineCol>,
+    },
+    Uri(String),
+}
+
+/// A link and the cells it covers, which are on a single grid line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TerminalLinkMatch {
+    pub line: Line,
+    pub start: Column,
+    pub end: Column,
+    pub link: TerminalLink,
+}
+
+impl TerminalLinkMatch {
+    pub fn contains(&self, point: &Point) -> bool {
+        point.line == self.line
+            && point.column >= self.start
+            && point.column <= self.end
+    }
+}
+
+static TERMINAL_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:https?|file|ftp|ssh|git)://[^\s<>"'`]+"#).unwrap()
+});
+
+static TERMINAL_PATH_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?P<path>(?:[A-Za-z]:)?(?:\.{0,2}[/\\])?[\w.+@-]+(?:[/\\][\w.+@-]+)*)(?::(?P<line>\d+)(?::(?P<col>\d+))?|\((?P<pline>\d+)(?:,\s*(?P<pcol>\d+))?\))?",
+    )
+    .unwrap()
+});
+
+impl LapceTerminalData {
+    /// Finds the link at `point` in the grid. Relative paths are resolved
+    /// against the terminal's working directory and then the workspace.
+    pub fn link_at(
+        &self,
+        raw: &RawTerminal,
+        point: Point,
+        workspace: &LapceWorkspace,
+    ) -> Option<TerminalLinkMatch> {
+        let term = &raw.term;
+        if point.line < term.topmost_line()
+            || point.line > term.bottommost_line()
+            || point.column > term.last_column()
+        {
+            return None;
+        }
+
+        let row = &term.grid()[point.line];
+        if let Some(hyperlink) = row[point.column].hyperlink() {
+            let mut start = point.column;
+            while start.0 > 0
+                && row[start - 1].hyperlink().as_ref() == Some(&hyperlink)
+            {
+                start -= 1;
+            }
+            let mut end = point.column;
+            while end < term.last_column()
+                && row[end + 1].hyperlink().as_ref() == Some(&hyperlink)
+            {
+                end += 1;
+            }
+            let uri = hyperlink.uri().to_string();
+            let link = match Url::parse(&uri) {
+                Ok(url) if url.scheme() == "file" => TerminalLink::File {
+                    path: path_from_url(&url),
+                    position: None,
+                },
+                _ => TerminalLink::Uri(uri),
+            };
+            return Some(TerminalLinkMatch {
+                line: point.line,
+                start,
+                end,
+                link,
+            });
+        }
+
+        // The text of the line, with the column of every byte of it.
+        let mut text = String::new();
+        let mut columns = Vec::new();
+        for column in 0..term.columns() {
+            let cell = &row[Column(column)];
+            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                continue;
+            }
+            text.push(cell.c);
+            columns.resize(text.len(), Column(column));
+        }
+        let column_of = |offset: usize| columns[offset.min(columns.len() - 1)];
+
+        for m in TERMINAL_URL_REGEX.find_iter(&text) {
+            let uri = m
+                .as_str()
+                .trim_end_matches(|c| ".,;:!?)]}".contains(c))
+                .to_string();
+            let (start, end) =
+                (column_of(m.start()), column_of(m.start() + uri.len() - 1));
+            if point.column >= start && point.column <= end {
+                return Some(TerminalLinkMatch {
+                    line: point.line,
+                    start,
+                    end,
+                    link: TerminalLink::Uri(uri),
+                });
+            }
+        }
+
+        for captures in TERMINAL_PATH_REGEX.captures_iter(&text) {
+            let m = captures.get(0)?;
+            let (start, end) = (column_of(m.start()), column_of(m.end() - 1));
+            if point.column < start || point.column > end {
+                continue;
+            }
+
+            let path = captures.name("path")?.as_str();
+            let line = captures
+                .name("line")
+                .or_else(|| captures.name("pline"))
+                .and_then(|l| l.as_str().parse::<usize>().ok());
+            let column = captures
+                .name("col")
+                .or_else(|| captures.name("pcol"))
+                .and_then(|c| c.as_str().parse::<usize>().ok());
+            let path =
+                self.resolve_link_path(raw, path, line.is_some(), workspace)?;
+            return Some(TerminalLinkMatch {
+                line: point.line,
+                start,
+                end,
+                link: TerminalLink::File {
+                    path,
+                    position: line.map(|line| LineCol {
+                        line: line.saturating_sub(1),
+                        column: column.unwrap_or(1).saturating_sub(1),
+                    }),
+                },
+            });
+        }
+
+        None
+    }
+
+    fn resolve_link_path(
+        &self,
+        raw: &RawTerminal,
+        path: &str,
+        has_line: bool,
+        workspace: &LapceWorkspace,
+    ) -> Option<PathBuf> {
+        if !path.contains(['/', '\\', '.']) || path.chars().all(|c| c == '.') {
+            return None;
+        }
+
+        let path = PathBuf::from(path);
+        let candidates = if path.is_absolute() {
+            vec![path]
+        } else {
+            [self.cwd_of(raw), workspace.path.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|dir| dir.join(&path))
+                .collect()
+        };
+
+        if workspace.kind.is_remote() {
+            // The files can't be checked from here, so only take what looks
+            // like a location in a compiler message.
+            return candidates.into_iter().next().filter(|_| has_line);
+        }
+        candidates.into_iter().find(|p| p.is_file())
+    }
+}
+
 #[derive(Clone)]
 pub struct EventProxy {
     pub term_id: TermId,
@@ -948,7 +1920,10 @@ fn send_event(&self, event: alacritty_terminal::event::Event) {
 mod test {
     use druid::{KbKey, KeyEvent, Modifiers};
 
-    use crate::terminal::LapceTerminalData;
+    use crate::terminal::{
+        LapceTerminalData, OscScanner, ShellEvent, ShellIntegration,
+        TerminalCommandMark,
+    };
 
     #[test]
     fn test_arrow_without_modifier() {
@@ -982,4 +1957,79 @@ fn test_arrow_with_two_modifiers() {
             ))
         );
     }
+
+    #[test]
+    fn test_osc_scanner() {
+        let mut scanner = OscScanner::default();
+        let mut found = Vec::new();
+        for byte in b"ls\x1b]7;file:///tmp\x07out\x1b[0m\x1b]133;D;1\x1b\\" {
+            if let Some(osc) = scanner.advance(*byte) {
+                found.push(osc);
+            }
+        }
+        assert_eq!(vec![b"7;file:///tmp".to_vec(), b"133;D;1".to_vec()], found);
+    }
+
+    #[test]
+    fn test_parse_shell_event() {
+        assert_eq!(
+            Some(ShellEvent::Cwd("/tmp/dir".into())),
+            ShellEvent::parse(b"7;file://host/tmp/dir")
+        );
+        assert_eq!(Some(ShellEvent::PromptStart), ShellEvent::parse(b"133;A"));
+        assert_eq!(
+            Some(ShellEvent::CommandFinished(Some(2))),
+            ShellEvent::parse(b"133;D;2")
+        );
+        assert_eq!(
+            Some(ShellEvent::CommandFinished(None)),
+            ShellEvent::parse(b"133;D")
+        );
+        assert_eq!(None, ShellEvent::parse(b"0;title"));
+    }
+
+    #[test]
+    fn test_shell_integration_commands() {
+        let mut shell = ShellIntegration::default();
+        shell.handle_event(ShellEvent::PromptStart, 0);
+        // Redrawing the prompt doesn't start a new command.
+        shell.handle_event(ShellEvent::PromptStart, 1);
+        shell.handle_event(ShellEvent::CommandExecuted, 2);
+        shell.handle_event(ShellEvent::CommandFinished(Some(1)), 5);
+        shell.handle_event(ShellEvent::PromptStart, 5);
+        assert_eq!(
+            vec![
+                TerminalCommandMark {
+                    prompt_line: 1,
+                    output_line: Some(2),
+                    end_line: Some(5),
+                    exit_code: Some(1),
+                },
+                TerminalCommandMark {
+                    prompt_line: 5,
+                    ..Default::default()
+                },
+            ],
+            shell.commands
+        );
+    }
+
+    #[test]
+    fn test_shell_integration_drop_lines() {
+        let mut shell = ShellIntegration::default();
+        shell.handle_event(ShellEvent::PromptStart, 1);
+        shell.handle_event(ShellEvent::CommandExecuted, 1);
+        shell.handle_event(ShellEvent::CommandFinished(Some(0)), 4);
+        shell.handle_event(ShellEvent::PromptStart, 4);
+        shell.handle_event(ShellEvent::CommandExecuted, 4);
+        shell.drop_lines(2);
+        assert_eq!(
+            vec![TerminalCommandMark {
+                prompt_line: 2,
+                output_line: Some(2),
+                ..Default::default()
+            }],
+            shell.commands
+        );
+    }
 }
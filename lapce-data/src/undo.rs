@@ -0,0 +1,230 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, LocalResult, TimeZone};
+use druid::WidgetId;
+use lapce_core::{
+    buffer::{Buffer, UndoHistory, UndoNode},
+    directory::Directory,
+};
+use lapce_xi_rope::Rope;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The undo history of a file as it's stored in the undo directory
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    path: PathBuf,
+    /// The hash of the text the history leads to
+    content_hash: String,
+    history: UndoHistory,
+}
+
+fn content_hash(text: &Rope) -> String {
+    let mut hasher = Sha256::new();
+    for chunk in text.iter_chunks(..) {
+        hasher.update(chunk.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn undo_file_path(dir: &Path, path: &Path) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    dir.join(format!("{:x}", hasher.finalize()))
+}
+
+/// Loads the stored undo history of `path` if it leads to `text`.
+/// A history leading to some other text means the file was changed outside
+/// of Lapce, so it's removed.
+pub fn load(path: &Path, text: &Rope) -> Option<UndoHistory> {
+    let file = undo_file_path(&Directory::undo_directory()?, path);
+    let content = fs::read(&file).ok()?;
+    match serde_json::from_slice::<UndoFile>(&content) {
+        Ok(undo_file)
+            if undo_file.path == path
+                && undo_file.content_hash == content_hash(text) =>
+        {
+            Some(undo_file.history)
+        }
+        _ => {
+            let _ = fs::remove_file(&file);
+            None
+        }
+    }
+}
+
+/// Stores the undo history of the buffer of `path`, and then removes the
+/// least recently stored histories until they fit in `max_size` MB.
+pub fn save(path: &Path, buffer: &Buffer, max_size: u64) -> Result<()> {
+    let max_size = max_size * 1024 * 1024;
+    let dir = Directory::undo_directory()
+        .ok_or_else(|| anyhow!("can't get undo directory"))?;
+    let file = undo_file_path(&dir, path);
+    let content = serde_json::to_vec(&UndoFile {
+        path: path.to_path_buf(),
+        content_hash: content_hash(buffer.text()),
+        history: buffer.undo_history(),
+    })?;
+    if content.len() as u64 > max_size {
+        let _ = fs::remove_file(&file);
+        return Ok(());
+    }
+    fs::write(&file, content)?;
+
+    let mut files = fs::read_dir(&dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.len(), metadata.modified().ok()?))
+        })
+        .collect::<Vec<_>>();
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort_by_key(|(_, _, modified)| *modified);
+    for (file, len, _) in files {
+        if size <= max_size {
+            break;
+        }
+        if fs::remove_file(file).is_ok() {
+            size -= len;
+        }
+    }
+    Ok(())
+}
+
+/// [`save`] on another thread, as the history can take a while to serialize
+pub fn save_async(path: PathBuf, buffer: Buffer, max_size: u64) {
+    std::thread::spawn(move || {
+        if let Err(err) = save(&path, &buffer, max_size) {
+            log::error!("failed to save the undo history: {err}");
+        }
+    });
+}
+
+/// How far to go back or forward in the undo tree, as typed in the palette:
+/// a number of changes like `3`, or a time like `30s`, `10m`, `2h` or `1d`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UndoJump {
+    Steps(usize),
+    /// In milliseconds
+    Time(u64),
+}
+
+impl UndoJump {
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let unit_start = input
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(input.len());
+        let n: u64 = input[..unit_start].parse().ok()?;
+        let unit = match input[unit_start..].trim() {
+            "" => return Some(UndoJump::Steps(n as usize)),
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            "d" => 24 * 60 * 60 * 1000,
+            _ => return None,
+        };
+        n.checked_mul(unit).map(UndoJump::Time)
+    }
+
+    /// The undo node this jump leads to from the current one
+    pub fn node(&self, buffer: &Buffer, earlier: bool) -> usize {
+        match *self {
+            UndoJump::Steps(n) => {
+                let n = n as isize;
+                buffer.undo_node_by_steps(if earlier { -n } else { n })
+            }
+            UndoJump::Time(millis) => {
+                let millis = millis as i64;
+                buffer.undo_node_by_time(if earlier { -millis } else { millis })
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for UndoJump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (n, unit) = match *self {
+            UndoJump::Steps(n) => (n as u64, "change"),
+            UndoJump::Time(millis) => match millis / 1000 {
+                s if s % (24 * 60 * 60) == 0 && s > 0 => (s / (24 * 60 * 60), "day"),
+                s if s % (60 * 60) == 0 && s > 0 => (s / (60 * 60), "hour"),
+                s if s % 60 == 0 && s > 0 => (s / 60, "minute"),
+                s => (s, "second"),
+            },
+        };
+        write!(f, "{n} {unit}{}", if n == 1 { "" } else { "s" })
+    }
+}
+
+/// A short description of a node of the undo tree, like `#3 +5 -2`
+pub fn node_label(node: usize, undo_node: &UndoNode) -> String {
+    if node == 0 {
+        return "Original".to_string();
+    }
+    format!("#{node} +{} -{}", undo_node.inserted, undo_node.deleted)
+}
+
+/// The time of a node of the undo tree, with the date when it isn't today
+pub fn node_time(undo_node: &UndoNode) -> String {
+    let time = match Local.timestamp_millis_opt(undo_node.time as i64) {
+        LocalResult::Single(time) => time,
+        _ => return String::new(),
+    };
+    if time.naive_local().date() == Local::now().naive_local().date() {
+        time.format("%H:%M:%S").to_string()
+    } else {
+        time.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
+
+#[derive(Clone)]
+pub struct UndoTreeData {
+    pub widget_id: WidgetId,
+    pub split_id: WidgetId,
+    pub tree_widget_id: WidgetId,
+    pub changes_widget_id: WidgetId,
+}
+
+impl UndoTreeData {
+    pub fn new() -> Self {
+        Self {
+            widget_id: WidgetId::next(),
+            split_id: WidgetId::next(),
+            tree_widget_id: WidgetId::next(),
+            changes_widget_id: WidgetId::next(),
+        }
+    }
+}
+
+impl Default for UndoTreeData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UndoJump;
+
+    #[test]
+    fn test_parse_undo_jump() {
+        assert_eq!(UndoJump::parse("3"), Some(UndoJump::Steps(3)));
+        assert_eq!(UndoJump::parse("10m"), Some(UndoJump::Time(600_000)));
+        assert_eq!(UndoJump::parse(" 2 h"), Some(UndoJump::Time(7_200_000)));
+        assert_eq!(UndoJump::parse("m"), None);
+        assert_eq!(UndoJump::parse("10x"), None);
+    }
+
+    #[test]
+    fn test_display_undo_jump() {
+        assert_eq!(UndoJump::Steps(1).to_string(), "1 change");
+        assert_eq!(UndoJump::Time(600_000).to_string(), "10 minutes");
+        assert_eq!(UndoJump::Time(90_000).to_string(), "90 seconds");
+        assert_eq!(UndoJump::Time(86_400_000).to_string(), "1 day");
+    }
+}
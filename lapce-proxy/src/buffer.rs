@@ -42,6 +42,21 @@ pub fn new(id: BufferId, path: PathBuf) -> Buffer {
         }
     }
 
+    /// A buffer with content and revision that may differ from the file,
+    /// as it had in the editor before the proxy was reconnected.
+    pub fn restore(id: BufferId, path: PathBuf, content: &str, rev: u64) -> Buffer {
+        let language_id = language_id_from_path(&path).unwrap_or("");
+        let mod_time = get_mod_time(&path);
+        Buffer {
+            id,
+            rope: Rope::from(content),
+            path,
+            language_id,
+            rev,
+            mod_time,
+        }
+    }
+
     pub fn save(&mut self, rev: u64) -> Result<()> {
         if self.rev != rev {
             return Err(anyhow!("not the right rev"));
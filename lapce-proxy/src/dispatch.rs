@@ -34,6 +34,7 @@
 
 use crate::{
     buffer::{get_mod_time, load_file, Buffer},
+    local_history,
     plugin::{catalog::PluginCatalog, remove_volt, PluginCatalogRpcHandler},
     terminal::Terminal,
     watcher::{FileWatcher, Notify, WatchToken},
@@ -135,7 +136,12 @@ fn handle_notification(&mut self, rpc: ProxyNotification) {
                 self.proxy_rpc.shutdown();
             }
             Update { path, delta, rev } => {
-                let buffer = self.buffers.get_mut(&path).unwrap();
+                // The buffer may not be open yet after a reconnect, when it's
+                // restored with the edit already applied.
+                let buffer = match self.buffers.get_mut(&path) {
+                    Some(buffer) => buffer,
+                    None => return,
+                };
                 let old_text = buffer.rope.clone();
                 buffer.update(&delta, rev);
                 self.catalog_rpc.did_change_text_document(
@@ -146,15 +152,16 @@ fn handle_notification(&mut self, rpc: ProxyNotification) {
                     buffer.rope.clone(),
                 );
             }
+            UpdateRev { path, rev } => {
+                if let Some(buffer) = self.buffers.get_mut(&path) {
+                    buffer.rev = rev;
+                }
+            }
             UpdatePluginConfigs { configs } => {
                 let _ = self.catalog_rpc.update_plugin_configs(configs);
             }
-            NewTerminal {
-                term_id,
-                cwd,
-                shell,
-            } => {
-                let mut terminal = Terminal::new(term_id, cwd, shell, 50, 10);
+            NewTerminal { term_id, profile } => {
+                let mut terminal = Terminal::new(term_id, profile, 50, 10);
                 let tx = terminal.tx.clone();
                 self.terminals.insert(term_id, tx);
                 let rpc = self.core_rpc.clone();
@@ -260,8 +267,17 @@ fn handle_notification(&mut self, rpc: ProxyNotification) {
     fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
         use ProxyRequest::*;
         match rpc {
-            NewBuffer { buffer_id, path } => {
-                let buffer = Buffer::new(buffer_id, path.clone());
+            NewBuffer {
+                buffer_id,
+                path,
+                restore,
+            } => {
+                let buffer = match restore {
+                    Some((content, rev)) => {
+                        Buffer::restore(buffer_id, path.clone(), &content, rev)
+                    }
+                    None => Buffer::new(buffer_id, path.clone()),
+                };
                 let content = buffer.rope.to_string();
                 self.catalog_rpc.did_open_document(
                     &path,
@@ -298,6 +314,34 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                 };
                 self.respond_rpc(id, result);
             }
+            LocalHistory { path } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                thread::spawn(move || {
+                    let result = local_history::versions(&path)
+                        .map(|versions| ProxyResponse::LocalHistoryResponse {
+                            versions,
+                        })
+                        .map_err(|e| RpcError {
+                            code: 0,
+                            message: e.to_string(),
+                        });
+                    proxy_rpc.handle_response(id, result);
+                });
+            }
+            LocalHistoryContent { path, time } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                thread::spawn(move || {
+                    let result = local_history::content(&path, time)
+                        .map(|content| ProxyResponse::LocalHistoryContentResponse {
+                            content,
+                        })
+                        .map_err(|e| RpcError {
+                            code: 0,
+                            message: e.to_string(),
+                        });
+                    proxy_rpc.handle_response(id, result);
+                });
+            }
             GlobalSearch {
                 pattern,
                 case_sensitive,
@@ -408,6 +452,21 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                     },
                 );
             }
+            GetDocumentHighlights { path, position } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_document_highlights(
+                    &path,
+                    position,
+                    move |_, result| {
+                        let result = result.map(|highlights| {
+                            ProxyResponse::GetDocumentHighlights {
+                                highlights: highlights.unwrap_or_default(),
+                            }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
             GitGetRemoteFileUrl { file } => {
                 if let Some(workspace) = self.workspace.as_ref() {
                     match git_get_remote_file_url(workspace, &file) {
@@ -459,9 +518,83 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                     },
                 );
             }
+            GetImplementation {
+                request_id,
+                path,
+                position,
+            } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_implementation(
+                    &path,
+                    position,
+                    move |_, result| {
+                        let result = result.map(|resp| {
+                            ProxyResponse::GetImplementation { request_id, resp }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            GetDeclaration {
+                request_id,
+                path,
+                position,
+            } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_declaration(
+                    &path,
+                    position,
+                    move |_, result| {
+                        let result = result.map(|resp| {
+                            ProxyResponse::GetDeclaration { request_id, resp }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            PrepareTypeHierarchy { path, position } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.prepare_type_hierarchy(
+                    &path,
+                    position,
+                    move |_, result| {
+                        let result = result.map(|items| {
+                            ProxyResponse::PrepareTypeHierarchy { items }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            GetTypeHierarchySupertypes { item } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_type_hierarchy_supertypes(
+                    item,
+                    move |_, result| {
+                        let result = result.map(|items| {
+                            ProxyResponse::GetTypeHierarchySupertypes { items }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            GetTypeHierarchySubtypes { item } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_type_hierarchy_subtypes(
+                    item,
+                    move |_, result| {
+                        let result = result.map(|items| {
+                            ProxyResponse::GetTypeHierarchySubtypes { items }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
             GetInlayHints { path } => {
                 let proxy_rpc = self.proxy_rpc.clone();
-                let buffer = self.buffers.get(&path).unwrap();
+                let buffer = match self.buffers.get(&path) {
+                    Some(buffer) => buffer,
+                    None => return self.respond_buffer_not_open(id),
+                };
                 let range = Range {
                     start: Position::new(0, 0),
                     end: buffer.offset_to_position(buffer.len()),
@@ -474,7 +607,10 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                     });
             }
             GetSemanticTokens { path } => {
-                let buffer = self.buffers.get(&path).unwrap();
+                let buffer = match self.buffers.get(&path) {
+                    Some(buffer) => buffer,
+                    None => return self.respond_buffer_not_open(id),
+                };
                 let text = buffer.rope.clone();
                 let rev = buffer.rev;
                 let len = buffer.len();
@@ -567,6 +703,37 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                         proxy_rpc.handle_response(id, result);
                     });
             }
+            GetDocumentRangeFormatting { path, range } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_document_range_formatting(
+                    &path,
+                    range,
+                    move |_, result| {
+                        let result = result.map(|edits| {
+                            ProxyResponse::GetDocumentFormatting {
+                                edits: edits.unwrap_or_default(),
+                            }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            GetOnTypeFormatting { path, position, ch } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_on_type_formatting(
+                    &path,
+                    position,
+                    ch,
+                    move |_, result| {
+                        let result = result.map(|edits| {
+                            ProxyResponse::GetDocumentFormatting {
+                                edits: edits.unwrap_or_default(),
+                            }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
             PrepareRename { path, position } => {
                 let proxy_rpc = self.proxy_rpc.clone();
                 self.catalog_rpc.prepare_rename(
@@ -689,12 +856,22 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                 });
             }
             Save { rev, path } => {
-                let buffer = self.buffers.get_mut(&path).unwrap();
+                let buffer = match self.buffers.get_mut(&path) {
+                    Some(buffer) => buffer,
+                    None => return self.respond_buffer_not_open(id),
+                };
                 let result = buffer
                     .save(rev)
                     .map(|_r| {
                         self.catalog_rpc
                             .did_save_text_document(&path, buffer.rope.clone());
+                        let path = path.clone();
+                        let rope = buffer.rope.clone();
+                        thread::spawn(move || {
+                            if let Err(err) = local_history::save(&path, &rope) {
+                                eprintln!("failed to save local history: {err}");
+                            }
+                        });
                         ProxyResponse::SaveResponse {}
                     })
                     .map_err(|e| RpcError {
@@ -787,6 +964,69 @@ fn handle_request(&mut self, id: RequestId, rpc: ProxyRequest) {
                     },
                 );
             }
+            GetCodeLens { path } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc
+                    .get_code_lens(&path, move |plugin_id, result| {
+                        let result =
+                            result.map(|resp| ProxyResponse::GetCodeLensResponse {
+                                plugin_id,
+                                resp: resp.unwrap_or_default(),
+                            });
+                        proxy_rpc.handle_response(id, result);
+                    });
+            }
+            GetDocumentDiagnostics { path } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.get_document_diagnostics(
+                    &path,
+                    move |plugin_id, result| {
+                        let result = result.map(|diagnostics| {
+                            ProxyResponse::GetDocumentDiagnosticsResponse {
+                                plugin_id,
+                                diagnostics,
+                            }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            CodeLensResolve {
+                code_lens,
+                plugin_id,
+            } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.code_lens_resolve(
+                    *code_lens,
+                    plugin_id,
+                    move |result| {
+                        let result = result.map(|item| {
+                            ProxyResponse::CodeLensResolveResponse {
+                                item: Box::new(item),
+                            }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
+            ExecuteCommand {
+                plugin_id,
+                command,
+                arguments,
+            } => {
+                let proxy_rpc = self.proxy_rpc.clone();
+                self.catalog_rpc.execute_command(
+                    plugin_id,
+                    command,
+                    arguments,
+                    move |result| {
+                        let result = result.map(|result| {
+                            ProxyResponse::ExecuteCommandResponse { result }
+                        });
+                        proxy_rpc.handle_response(id, result);
+                    },
+                );
+            }
             CodeActionResolve {
                 action_item,
                 plugin_id,
@@ -832,6 +1072,18 @@ pub fn new(core_rpc: CoreRpcHandler, proxy_rpc: ProxyRpcHandler) -> Self {
     fn respond_rpc(&self, id: RequestId, result: Result<ProxyResponse, RpcError>) {
         self.proxy_rpc.handle_response(id, result);
     }
+
+    /// Fails a request for a buffer that isn't open, which can happen for
+    /// requests made right after a reconnect before buffers are restored.
+    fn respond_buffer_not_open(&self, id: RequestId) {
+        self.respond_rpc(
+            id,
+            Err(RpcError {
+                code: 0,
+                message: "buffer not open".to_string(),
+            }),
+        );
+    }
 }
 
 struct FileWatchNotifier {
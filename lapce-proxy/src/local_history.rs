@@ -0,0 +1,131 @@
+//! Snapshots of every saved file, kept independently of version control so
+//! that work lost between commits, or never committed at all, can be found.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use lapce_core::directory::Directory;
+use lapce_xi_rope::Rope;
+use sha2::{Digest, Sha256};
+
+/// The most versions kept of a single file
+const MAX_VERSIONS: usize = 50;
+/// Files larger than this aren't kept
+const MAX_FILE_SIZE: usize = 5 * 1024 * 1024;
+/// The most space taken by the versions of all the files together
+const MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+fn local_history_directory() -> Result<PathBuf> {
+    Directory::local_history_directory()
+        .ok_or_else(|| anyhow!("can't get local history directory"))
+}
+
+/// The directory the versions of `path` are stored in, each in a file named
+/// after the time it was saved
+fn file_directory(dir: &Path, path: &Path) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    dir.join(format!("{:x}", hasher.finalize()))
+}
+
+/// The times of the versions stored in `file_dir`, the oldest first
+fn file_versions(file_dir: &Path) -> Vec<u64> {
+    let mut versions = fs::read_dir(file_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .collect::<Vec<u64>>()
+        })
+        .unwrap_or_default();
+    versions.sort_unstable();
+    versions
+}
+
+/// Stores `content` as a new version of `path`, unless it's the same as the
+/// latest version, and then removes the oldest versions beyond the limits.
+pub fn save(path: &Path, content: &Rope) -> Result<()> {
+    if content.len() > MAX_FILE_SIZE {
+        return Ok(());
+    }
+
+    let dir = local_history_directory()?;
+    let file_dir = file_directory(&dir, path);
+    fs::create_dir_all(&file_dir)?;
+
+    let content = content.slice_to_cow(..);
+    let mut versions = file_versions(&file_dir);
+    if let Some(latest) = versions.last() {
+        let latest_content = fs::read_to_string(file_dir.join(latest.to_string()));
+        if latest_content.ok().as_deref() == Some(content.as_ref()) {
+            return Ok(());
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    // Two saves within the same millisecond still get their own versions
+    let time = versions
+        .last()
+        .map(|latest| now.max(latest + 1))
+        .unwrap_or(now);
+    fs::write(file_dir.join(time.to_string()), content.as_bytes())?;
+    versions.push(time);
+
+    let excess = versions.len().saturating_sub(MAX_VERSIONS);
+    for time in &versions[..excess] {
+        let _ = fs::remove_file(file_dir.join(time.to_string()));
+    }
+
+    prune(&dir)
+}
+
+/// Removes the oldest versions of any file until all of them fit in
+/// [`MAX_SIZE`].
+fn prune(dir: &Path) -> Result<()> {
+    let mut versions = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let file_dir = entry?.path();
+        for time in file_versions(&file_dir) {
+            let path = file_dir.join(time.to_string());
+            if let Ok(metadata) = fs::metadata(&path) {
+                versions.push((time, metadata.len(), path));
+            }
+        }
+    }
+
+    let mut size: u64 = versions.iter().map(|(_, len, _)| len).sum();
+    versions.sort_by_key(|(time, _, _)| *time);
+    for (_, len, path) in versions {
+        if size <= MAX_SIZE {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            size -= len;
+            if let Some(file_dir) = path.parent() {
+                // Only succeeds once the last version of the file is gone
+                let _ = fs::remove_dir(file_dir);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The times of the versions kept of `path`, the latest first
+pub fn versions(path: &Path) -> Result<Vec<u64>> {
+    let file_dir = file_directory(&local_history_directory()?, path);
+    let mut versions = file_versions(&file_dir);
+    versions.reverse();
+    Ok(versions)
+}
+
+/// The content of the version of `path` saved at `time`
+pub fn content(path: &Path, time: u64) -> Result<String> {
+    let file_dir = file_directory(&local_history_directory()?, path);
+    Ok(fs::read_to_string(file_dir.join(time.to_string()))?)
+}
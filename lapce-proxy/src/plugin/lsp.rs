@@ -65,6 +65,14 @@ fn method_registered(&mut self, method: &'static str) -> bool {
         self.host.method_registered(method)
     }
 
+    fn request_params_supported(
+        &mut self,
+        method: &'static str,
+        params: &Params,
+    ) -> bool {
+        self.host.request_params_supported(method, params)
+    }
+
     fn document_supported(
         &mut self,
         lanaguage_id: Option<&str>,
@@ -382,6 +390,31 @@ fn initialize(&mut self) {
                 definition: Some(GotoCapability {
                     ..Default::default()
                 }),
+                code_lens: Some(CodeLensClientCapabilities {
+                    ..Default::default()
+                }),
+                document_highlight: Some(DocumentHighlightClientCapabilities {
+                    ..Default::default()
+                }),
+                implementation: Some(GotoCapability {
+                    link_support: Some(false),
+                    ..Default::default()
+                }),
+                declaration: Some(GotoCapability {
+                    link_support: Some(false),
+                    ..Default::default()
+                }),
+                range_formatting: Some(DocumentRangeFormattingClientCapabilities {
+                    ..Default::default()
+                }),
+                diagnostic: Some(DiagnosticClientCapabilities {
+                    ..Default::default()
+                }),
+                on_type_formatting: Some(
+                    DocumentOnTypeFormattingClientCapabilities {
+                        ..Default::default()
+                    },
+                ),
                 ..Default::default()
             }),
             window: Some(WindowClientCapabilities {
@@ -398,6 +431,12 @@ fn initialize(&mut self) {
                     ..Default::default()
                 }),
                 configuration: Some(false),
+                execute_command: Some(DynamicRegistrationClientCapabilities {
+                    ..Default::default()
+                }),
+                diagnostic: Some(DiagnosticWorkspaceClientCapabilities {
+                    ..Default::default()
+                }),
                 ..Default::default()
             }),
 
@@ -434,6 +473,10 @@ fn initialize(&mut self) {
             None,
             false,
         ) {
+            self.host.type_hierarchy_provider = value
+                .pointer("/capabilities/typeHierarchyProvider")
+                .map(|v| !v.is_null() && v.as_bool() != Some(false))
+                .unwrap_or(false);
             let result: InitializeResult = serde_json::from_value(value).unwrap();
             self.host.server_capabilities = result.capabilities;
             self.server_rpc.server_notification(
@@ -450,6 +493,9 @@ fn initialize(&mut self) {
             {
                 self.server_rpc.shutdown();
                 self.shutdown();
+            } else {
+                self.host.send_on_type_formatting_triggers();
+                self.host.pull_workspace_diagnostics();
             }
         }
         //     move |result| {
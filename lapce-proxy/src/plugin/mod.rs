@@ -29,24 +29,35 @@
 use lapce_xi_rope::{Rope, RopeDelta};
 use lsp_types::{
     request::{
-        CodeActionRequest, CodeActionResolveRequest, Completion,
-        DocumentSymbolRequest, Formatting, GotoDefinition, GotoTypeDefinition,
-        GotoTypeDefinitionParams, GotoTypeDefinitionResponse, HoverRequest,
-        InlayHintRequest, PrepareRenameRequest, References, Rename, Request,
-        ResolveCompletionItem, SelectionRangeRequest, SemanticTokensFullRequest,
-        SignatureHelpRequest, WorkspaceSymbol,
+        CodeActionRequest, CodeActionResolveRequest, CodeLensRequest,
+        CodeLensResolve, Completion, DocumentDiagnosticRequest,
+        DocumentHighlightRequest, DocumentSymbolRequest, ExecuteCommand, Formatting,
+        GotoDeclaration, GotoDeclarationParams, GotoDeclarationResponse,
+        GotoDefinition, GotoImplementation, GotoImplementationParams,
+        GotoImplementationResponse, GotoTypeDefinition, GotoTypeDefinitionParams,
+        GotoTypeDefinitionResponse, HoverRequest, InlayHintRequest,
+        OnTypeFormatting, PrepareRenameRequest, RangeFormatting, References, Rename,
+        Request, ResolveCompletionItem, SelectionRangeRequest,
+        SemanticTokensFullRequest, SignatureHelpRequest, TypeHierarchyPrepare,
+        TypeHierarchySubtypes, TypeHierarchySupertypes, WorkspaceSymbol,
     },
-    CodeAction, CodeActionContext, CodeActionParams, CodeActionResponse,
-    CompletionItem, CompletionParams, CompletionResponse, Diagnostic,
-    DocumentFormattingParams, DocumentSymbolParams, DocumentSymbolResponse,
+    CodeAction, CodeActionContext, CodeActionParams, CodeActionResponse, CodeLens,
+    CodeLensParams, CompletionItem, CompletionParams, CompletionResponse,
+    Diagnostic, DocumentDiagnosticParams, DocumentDiagnosticReport,
+    DocumentDiagnosticReportKind, DocumentDiagnosticReportResult,
+    DocumentFormattingParams, DocumentHighlight, DocumentHighlightParams,
+    DocumentOnTypeFormattingParams, DocumentRangeFormattingParams,
+    DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandParams,
     FormattingOptions, GotoDefinitionParams, GotoDefinitionResponse, Hover,
     HoverParams, InlayHint, InlayHintParams, Location, PartialResultParams,
-    Position, PrepareRenameResponse, Range, ReferenceContext, ReferenceParams,
-    RenameParams, SelectionRange, SelectionRangeParams, SemanticTokens,
-    SemanticTokensParams, SignatureHelp, SignatureHelpParams, SymbolInformation,
-    TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, TextEdit,
-    Url, VersionedTextDocumentIdentifier, WorkDoneProgressParams, WorkspaceEdit,
-    WorkspaceSymbolParams,
+    Position, PrepareRenameResponse, PublishDiagnosticsParams, Range,
+    ReferenceContext, ReferenceParams, RenameParams, SelectionRange,
+    SelectionRangeParams, SemanticTokens, SemanticTokensParams, SignatureHelp,
+    SignatureHelpParams, SymbolInformation, TextDocumentIdentifier,
+    TextDocumentItem, TextDocumentPositionParams, TextEdit, TypeHierarchyItem,
+    TypeHierarchyPrepareParams, TypeHierarchySubtypesParams,
+    TypeHierarchySupertypesParams, Url, VersionedTextDocumentIdentifier,
+    WorkDoneProgressParams, WorkspaceEdit, WorkspaceSymbolParams,
 };
 use parking_lot::Mutex;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -437,6 +448,140 @@ pub fn get_type_definition(
         );
     }
 
+    pub fn get_implementation(
+        &self,
+        path: &Path,
+        position: Position,
+        cb: impl FnOnce(PluginId, Result<GotoImplementationResponse, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = GotoImplementation::METHOD;
+        let params = GotoImplementationParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
+    pub fn get_declaration(
+        &self,
+        path: &Path,
+        position: Position,
+        cb: impl FnOnce(PluginId, Result<GotoDeclarationResponse, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = GotoDeclaration::METHOD;
+        let params = GotoDeclarationParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
+    pub fn prepare_type_hierarchy(
+        &self,
+        path: &Path,
+        position: Position,
+        cb: impl FnOnce(PluginId, Result<Vec<TypeHierarchyItem>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = TypeHierarchyPrepare::METHOD;
+        let params = TypeHierarchyPrepareParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
+    pub fn get_type_hierarchy_supertypes(
+        &self,
+        item: TypeHierarchyItem,
+        cb: impl FnOnce(PluginId, Result<Vec<TypeHierarchyItem>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let path = item.uri.to_file_path().ok();
+        let language_id = path
+            .as_ref()
+            .map(|path| language_id_from_path(path).unwrap_or("").to_string());
+        let method = TypeHierarchySupertypes::METHOD;
+        let params = TypeHierarchySupertypesParams {
+            item,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+        self.send_request_to_all_plugins(method, params, language_id, path, cb);
+    }
+
+    pub fn get_type_hierarchy_subtypes(
+        &self,
+        item: TypeHierarchyItem,
+        cb: impl FnOnce(PluginId, Result<Vec<TypeHierarchyItem>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let path = item.uri.to_file_path().ok();
+        let language_id = path
+            .as_ref()
+            .map(|path| language_id_from_path(path).unwrap_or("").to_string());
+        let method = TypeHierarchySubtypes::METHOD;
+        let params = TypeHierarchySubtypesParams {
+            item,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+        self.send_request_to_all_plugins(method, params, language_id, path, cb);
+    }
+
     pub fn get_references(
         &self,
         path: &Path,
@@ -471,6 +616,37 @@ pub fn get_references(
         );
     }
 
+    pub fn get_document_highlights(
+        &self,
+        path: &Path,
+        position: Position,
+        cb: impl FnOnce(PluginId, Result<Option<Vec<DocumentHighlight>>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = DocumentHighlightRequest::METHOD;
+        let params = DocumentHighlightParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
     pub fn get_code_actions(
         &self,
         path: &Path,
@@ -607,6 +783,73 @@ pub fn get_document_formatting(
         );
     }
 
+    pub fn get_document_range_formatting(
+        &self,
+        path: &Path,
+        range: Range,
+        cb: impl FnOnce(PluginId, Result<Option<Vec<TextEdit>>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = RangeFormatting::METHOD;
+        let params = DocumentRangeFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            range,
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
+    pub fn get_on_type_formatting(
+        &self,
+        path: &Path,
+        position: Position,
+        ch: String,
+        cb: impl FnOnce(PluginId, Result<Option<Vec<TextEdit>>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = OnTypeFormatting::METHOD;
+        let params = DocumentOnTypeFormattingParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            ch,
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces: true,
+                ..Default::default()
+            },
+        };
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
     pub fn prepare_rename(
         &self,
         path: &Path,
@@ -897,6 +1140,127 @@ pub fn action_resolve(
         );
     }
 
+    pub fn get_code_lens(
+        &self,
+        path: &Path,
+        cb: impl FnOnce(PluginId, Result<Option<Vec<CodeLens>>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = CodeLensRequest::METHOD;
+        let params = CodeLensParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            cb,
+        );
+    }
+
+    /// Pull the diagnostics of the document. Only full reports are returned,
+    /// since no previous result ids are sent.
+    pub fn get_document_diagnostics(
+        &self,
+        path: &Path,
+        cb: impl FnOnce(PluginId, Result<Vec<PublishDiagnosticsParams>, RpcError>)
+            + Clone
+            + Send
+            + 'static,
+    ) {
+        let uri = Url::from_file_path(path).unwrap();
+        let method = DocumentDiagnosticRequest::METHOD;
+        let params = DocumentDiagnosticParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            identifier: None,
+            previous_result_id: None,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let language_id =
+            Some(language_id_from_path(path).unwrap_or("").to_string());
+        self.send_request_to_all_plugins(
+            method,
+            params,
+            language_id,
+            Some(path.to_path_buf()),
+            move |plugin_id, result: Result<DocumentDiagnosticReportResult, _>| {
+                cb(
+                    plugin_id,
+                    result.map(|report| document_diagnostic_params(uri, report)),
+                )
+            },
+        );
+    }
+
+    pub fn code_lens_resolve(
+        &self,
+        item: CodeLens,
+        plugin_id: PluginId,
+        cb: impl FnOnce(Result<CodeLens, RpcError>) + Send + Clone + 'static,
+    ) {
+        let method = CodeLensResolve::METHOD;
+        self.send_request(
+            Some(plugin_id),
+            None,
+            method,
+            item,
+            None,
+            None,
+            move |_, result| {
+                let result = match result {
+                    Ok(value) => {
+                        if let Ok(item) = serde_json::from_value::<CodeLens>(value) {
+                            Ok(item)
+                        } else {
+                            Err(RpcError {
+                                code: 0,
+                                message: "code_lens item deserialize error"
+                                    .to_string(),
+                            })
+                        }
+                    }
+                    Err(e) => Err(e),
+                };
+                cb(result)
+            },
+        );
+    }
+
+    pub fn execute_command(
+        &self,
+        plugin_id: PluginId,
+        command: String,
+        arguments: Vec<Value>,
+        cb: impl FnOnce(Result<Value, RpcError>) + Send + Clone + 'static,
+    ) {
+        let method = ExecuteCommand::METHOD;
+        let params = ExecuteCommandParams {
+            command,
+            arguments,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+        self.send_request(
+            Some(plugin_id),
+            None,
+            method,
+            params,
+            None,
+            None,
+            move |_, result| cb(result),
+        );
+    }
+
     pub fn did_open_document(
         &self,
         path: &Path,
@@ -975,6 +1339,41 @@ pub enum PluginNotification {
     },
 }
 
+/// Convert the full reports of a pulled document diagnostic report, for the
+/// document and its related documents, into the shape of pushed diagnostics
+fn document_diagnostic_params(
+    uri: Url,
+    report: DocumentDiagnosticReportResult,
+) -> Vec<PublishDiagnosticsParams> {
+    let mut params = Vec::new();
+    let related_documents = match report {
+        DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(
+            report,
+        )) => {
+            params.push(PublishDiagnosticsParams {
+                uri,
+                diagnostics: report.full_document_diagnostic_report.items,
+                version: None,
+            });
+            report.related_documents
+        }
+        DocumentDiagnosticReportResult::Report(
+            DocumentDiagnosticReport::Unchanged(report),
+        ) => report.related_documents,
+        DocumentDiagnosticReportResult::Partial(report) => report.related_documents,
+    };
+    for (uri, report) in related_documents.into_iter().flatten() {
+        if let DocumentDiagnosticReportKind::Full(report) = report {
+            params.push(PublishDiagnosticsParams {
+                uri,
+                diagnostics: report.items,
+                version: None,
+            });
+        }
+    }
+    params
+}
+
 pub fn volt_icon(volt: &VoltMetadata) -> Option<String> {
     let dir = volt.dir.as_ref()?;
     let icon = dir.join(volt.icon.as_ref()?);
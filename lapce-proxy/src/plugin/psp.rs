@@ -1,8 +1,8 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     thread,
@@ -14,6 +14,7 @@
 use jsonrpc_lite::{Id, JsonRpc, Params};
 use lapce_core::{buffer::rope_text::RopeText, encoding::offset_utf16_to_utf8};
 use lapce_rpc::{
+    core::DiagnosticSource,
     plugin::PluginId,
     style::{LineStyle, Style},
     RpcError,
@@ -26,22 +27,29 @@
         ShowMessage,
     },
     request::{
-        CodeActionRequest, CodeActionResolveRequest, Completion,
-        DocumentSymbolRequest, Formatting, GotoDefinition, GotoTypeDefinition,
-        HoverRequest, Initialize, InlayHintRequest, PrepareRenameRequest,
-        References, RegisterCapability, Rename, ResolveCompletionItem,
-        SelectionRangeRequest, SemanticTokensFullRequest, SignatureHelpRequest,
-        WorkDoneProgressCreate, WorkspaceSymbol,
+        CodeActionRequest, CodeActionResolveRequest, CodeLensRequest,
+        CodeLensResolve, Completion, DocumentDiagnosticRequest,
+        DocumentHighlightRequest, DocumentSymbolRequest, ExecuteCommand, Formatting,
+        GotoDeclaration, GotoDefinition, GotoImplementation, GotoTypeDefinition,
+        HoverRequest, Initialize, InlayHintRequest, OnTypeFormatting,
+        PrepareRenameRequest, RangeFormatting, References, RegisterCapability,
+        Rename, ResolveCompletionItem, SelectionRangeRequest,
+        SemanticTokensFullRequest, SignatureHelpRequest, TypeHierarchyPrepare,
+        TypeHierarchySubtypes, TypeHierarchySupertypes, WorkDoneProgressCreate,
+        WorkspaceDiagnosticRequest, WorkspaceSymbol,
     },
-    CodeActionProviderCapability, DidChangeTextDocumentParams,
-    DidSaveTextDocumentParams, DocumentSelector, HoverProviderCapability,
-    LogMessageParams, OneOf, ProgressParams, PublishDiagnosticsParams, Range,
-    Registration, RegistrationParams, SemanticTokens, SemanticTokensLegend,
+    CodeActionProviderCapability, DiagnosticServerCapabilities,
+    DidChangeTextDocumentParams, DidSaveTextDocumentParams, DocumentSelector,
+    HoverProviderCapability, LogMessageParams, OneOf, PartialResultParams,
+    ProgressParams, PublishDiagnosticsParams, Range, Registration,
+    RegistrationParams, SemanticTokens, SemanticTokensLegend,
     SemanticTokensServerCapabilities, ServerCapabilities, ShowMessageParams,
     TextDocumentContentChangeEvent, TextDocumentIdentifier,
     TextDocumentSaveRegistrationOptions, TextDocumentSyncCapability,
-    TextDocumentSyncKind, TextDocumentSyncSaveOptions,
-    VersionedTextDocumentIdentifier,
+    TextDocumentSyncKind, TextDocumentSyncSaveOptions, Url,
+    VersionedTextDocumentIdentifier, WorkDoneProgressParams,
+    WorkspaceDiagnosticParams, WorkspaceDiagnosticReportResult,
+    WorkspaceDocumentDiagnosticReport,
 };
 use parking_lot::Mutex;
 use psp_types::{
@@ -170,6 +178,11 @@ fn document_supported(
         path: Option<&Path>,
     ) -> bool;
     fn method_registered(&mut self, method: &'static str) -> bool;
+    fn request_params_supported(
+        &mut self,
+        method: &'static str,
+        params: &Params,
+    ) -> bool;
     fn handle_host_notification(&mut self, method: String, params: Params);
     fn handle_host_request(
         &mut self,
@@ -388,6 +401,7 @@ pub fn mainloop<H>(&self, handler: &mut H)
                     if handler
                         .document_supported(language_id.as_deref(), path.as_deref())
                         && handler.method_registered(method)
+                        && handler.request_params_supported(method, &params)
                     {
                         self.send_server_request(id, method, params, rh);
                     } else {
@@ -544,7 +558,16 @@ pub struct PluginHostHandler {
     catalog_rpc: PluginCatalogRpcHandler,
     pub server_rpc: PluginServerRpcHandler,
     pub server_capabilities: ServerCapabilities,
+    /// `typeHierarchyProvider` isn't part of lsp-types' `ServerCapabilities`
+    /// yet, so it's read from the raw initialize result
+    pub type_hierarchy_provider: bool,
     server_registrations: ServerRegistrations,
+    /// If a `workspace/diagnostic` request is waiting for its response,
+    /// servers can hold on to it until something changes
+    workspace_diagnostics_pending: Arc<AtomicBool>,
+    /// The files in the last workspace diagnostics report, whose diagnostics
+    /// are cleared when they're left out of the next one
+    workspace_diagnostics_uris: Arc<Mutex<HashSet<Url>>>,
 }
 
 impl PluginHostHandler {
@@ -570,7 +593,62 @@ pub fn new(
             catalog_rpc,
             server_rpc,
             server_capabilities: ServerCapabilities::default(),
+            type_hierarchy_provider: false,
             server_registrations: ServerRegistrations::default(),
+            workspace_diagnostics_pending: Arc::new(AtomicBool::new(false)),
+            workspace_diagnostics_uris: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Tells the editor the characters the server formats on, so that on
+    /// type formatting is only requested after typing one of them
+    pub fn send_on_type_formatting_triggers(&self) {
+        let provider = match self
+            .server_capabilities
+            .document_on_type_formatting_provider
+            .as_ref()
+        {
+            Some(provider) => provider,
+            None => return,
+        };
+        let mut characters = vec![provider.first_trigger_character.clone()];
+        characters.extend(provider.more_trigger_character.iter().flatten().cloned());
+        self.catalog_rpc
+            .core_rpc
+            .on_type_formatting_triggers(self.server_rpc.plugin_id, characters);
+    }
+
+    /// Some requests are only wanted by the server for certain params, like
+    /// on type formatting which is only for the declared trigger characters
+    pub fn request_params_supported(
+        &self,
+        method: &'static str,
+        params: &Params,
+    ) -> bool {
+        match method {
+            OnTypeFormatting::METHOD => {
+                let ch = match params {
+                    Params::Map(map) => map.get("ch").and_then(|ch| ch.as_str()),
+                    _ => None,
+                };
+                match (
+                    ch,
+                    self.server_capabilities
+                        .document_on_type_formatting_provider
+                        .as_ref(),
+                ) {
+                    (Some(ch), Some(provider)) => {
+                        provider.first_trigger_character == ch
+                            || provider
+                                .more_trigger_character
+                                .as_ref()
+                                .map(|chars| chars.iter().any(|c| c == ch))
+                                .unwrap_or(false)
+                    }
+                    _ => false,
+                }
+            }
+            _ => true,
         }
     }
 
@@ -666,6 +744,15 @@ pub fn method_registered(&mut self, method: &'static str) -> bool {
             GotoTypeDefinition::METHOD => {
                 self.server_capabilities.type_definition_provider.is_some()
             }
+            GotoImplementation::METHOD => {
+                self.server_capabilities.implementation_provider.is_some()
+            }
+            GotoDeclaration::METHOD => {
+                self.server_capabilities.declaration_provider.is_some()
+            }
+            TypeHierarchyPrepare::METHOD
+            | TypeHierarchySupertypes::METHOD
+            | TypeHierarchySubtypes::METHOD => self.type_hierarchy_provider,
             References::METHOD => self
                 .server_capabilities
                 .references_provider
@@ -693,12 +780,40 @@ pub fn method_registered(&mut self, method: &'static str) -> bool {
                     OneOf::Right(_) => true,
                 })
                 .unwrap_or(false),
+            RangeFormatting::METHOD => self
+                .server_capabilities
+                .document_range_formatting_provider
+                .as_ref()
+                .map(|f| match f {
+                    OneOf::Left(is_capable) => *is_capable,
+                    OneOf::Right(_) => true,
+                })
+                .unwrap_or(false),
+            OnTypeFormatting::METHOD => self
+                .server_capabilities
+                .document_on_type_formatting_provider
+                .is_some(),
+            DocumentDiagnosticRequest::METHOD => {
+                self.server_capabilities.diagnostic_provider.is_some()
+            }
+            WorkspaceDiagnosticRequest::METHOD => {
+                self.workspace_diagnostics_provider()
+            }
             SemanticTokensFullRequest::METHOD => {
                 self.server_capabilities.semantic_tokens_provider.is_some()
             }
             InlayHintRequest::METHOD => {
                 self.server_capabilities.inlay_hint_provider.is_some()
             }
+            DocumentHighlightRequest::METHOD => self
+                .server_capabilities
+                .document_highlight_provider
+                .as_ref()
+                .map(|h| match h {
+                    OneOf::Left(is_capable) => *is_capable,
+                    OneOf::Right(_) => true,
+                })
+                .unwrap_or(false),
             DocumentSymbolRequest::METHOD => {
                 self.server_capabilities.document_symbol_provider.is_some()
             }
@@ -712,6 +827,18 @@ pub fn method_registered(&mut self, method: &'static str) -> bool {
             SelectionRangeRequest::METHOD => {
                 self.server_capabilities.selection_range_provider.is_some()
             }
+            CodeLensRequest::METHOD => {
+                self.server_capabilities.code_lens_provider.is_some()
+            }
+            CodeLensResolve::METHOD => self
+                .server_capabilities
+                .code_lens_provider
+                .as_ref()
+                .and_then(|c| c.resolve_provider)
+                .unwrap_or(false),
+            ExecuteCommand::METHOD => {
+                self.server_capabilities.execute_command_provider.is_some()
+            }
             CodeActionResolveRequest::METHOD => {
                 self.server_capabilities.code_action_provider.is_some()
             }
@@ -866,7 +993,10 @@ pub fn handle_notification(
             PublishDiagnostics::METHOD => {
                 let diagnostics: PublishDiagnosticsParams =
                     serde_json::from_value(serde_json::to_value(params)?)?;
-                self.catalog_rpc.core_rpc.publish_diagnostics(diagnostics);
+                self.catalog_rpc.core_rpc.publish_diagnostics(
+                    DiagnosticSource::Push(self.server_rpc.plugin_id),
+                    diagnostics,
+                );
             }
             Progress::METHOD => {
                 let progress: ProgressParams =
@@ -891,6 +1021,93 @@ pub fn handle_notification(
         Ok(())
     }
 
+    fn workspace_diagnostics_provider(&self) -> bool {
+        match self.server_capabilities.diagnostic_provider.as_ref() {
+            Some(DiagnosticServerCapabilities::Options(options)) => {
+                options.workspace_diagnostics
+            }
+            Some(DiagnosticServerCapabilities::RegistrationOptions(options)) => {
+                options.diagnostic_options.workspace_diagnostics
+            }
+            None => false,
+        }
+    }
+
+    /// Pull the diagnostics of the whole workspace, and publish them like the
+    /// pushed ones so that files which aren't opened get their diagnostics
+    pub fn pull_workspace_diagnostics(&self) {
+        if !self.workspace_diagnostics_provider()
+            || self
+                .workspace_diagnostics_pending
+                .swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+
+        let params = WorkspaceDiagnosticParams {
+            identifier: None,
+            previous_result_ids: Vec::new(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+        let core_rpc = self.catalog_rpc.core_rpc.clone();
+        let source = DiagnosticSource::Pull(self.server_rpc.plugin_id);
+        let pending = self.workspace_diagnostics_pending.clone();
+        let last_uris = self.workspace_diagnostics_uris.clone();
+        self.server_rpc.server_request_async(
+            WorkspaceDiagnosticRequest::METHOD,
+            params,
+            None,
+            None,
+            false,
+            move |result: Result<Value, RpcError>| {
+                pending.store(false, Ordering::Release);
+                let report = result.ok().and_then(|value| {
+                    serde_json::from_value::<WorkspaceDiagnosticReportResult>(value)
+                        .ok()
+                });
+                if let Some(WorkspaceDiagnosticReportResult::Report(report)) = report
+                {
+                    let mut uris = HashSet::new();
+                    for item in report.items {
+                        match item {
+                            WorkspaceDocumentDiagnosticReport::Full(report) => {
+                                uris.insert(report.uri.clone());
+                                core_rpc.publish_diagnostics(
+                                    source,
+                                    PublishDiagnosticsParams {
+                                        uri: report.uri,
+                                        diagnostics: report
+                                            .full_document_diagnostic_report
+                                            .items,
+                                        version: None,
+                                    },
+                                );
+                            }
+                            WorkspaceDocumentDiagnosticReport::Unchanged(report) => {
+                                uris.insert(report.uri);
+                            }
+                        }
+                    }
+
+                    // The files left out of the report have no problems left
+                    let mut last_uris = last_uris.lock();
+                    for uri in last_uris.difference(&uris) {
+                        core_rpc.publish_diagnostics(
+                            source,
+                            PublishDiagnosticsParams {
+                                uri: uri.clone(),
+                                diagnostics: Vec::new(),
+                                version: None,
+                            },
+                        );
+                    }
+                    *last_uris = uris;
+                }
+            },
+        );
+    }
+
     pub fn handle_did_save_text_document(
         &self,
         language_id: String,
@@ -898,6 +1115,10 @@ pub fn handle_did_save_text_document(
         text_document: TextDocumentIdentifier,
         text: Rope,
     ) {
+        if self.document_supported(Some(language_id.as_str()), Some(&path)) {
+            self.pull_workspace_diagnostics();
+        }
+
         let (should_send, include_text) =
             self.check_save_capability(language_id.as_str(), &path);
         if !should_send {
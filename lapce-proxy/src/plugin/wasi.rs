@@ -90,6 +90,14 @@ fn method_registered(&mut self, method: &'static str) -> bool {
         self.host.method_registered(method)
     }
 
+    fn request_params_supported(
+        &mut self,
+        method: &'static str,
+        params: &Params,
+    ) -> bool {
+        self.host.request_params_supported(method, params)
+    }
+
     fn document_supported(
         &mut self,
         language_id: Option<&str>,
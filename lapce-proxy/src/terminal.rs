@@ -14,7 +14,10 @@
     tty::{self, setup_env, EventedPty, EventedReadWrite},
 };
 use directories::BaseDirs;
-use lapce_rpc::{core::CoreRpcHandler, terminal::TermId};
+use lapce_rpc::{
+    core::CoreRpcHandler,
+    terminal::{TermId, TerminalProfile},
+};
 #[cfg(not(windows))]
 use mio::unix::UnixReady;
 #[allow(deprecated)]
@@ -42,54 +45,66 @@ pub struct Terminal {
 impl Terminal {
     pub fn new(
         term_id: TermId,
-        cwd: Option<PathBuf>,
-        shell: String,
+        profile: TerminalProfile,
         width: usize,
         height: usize,
     ) -> Terminal {
         let poll = mio::Poll::new().unwrap();
         let mut config = TermConfig::default();
-        config.pty_config.working_directory =
-            if cwd.is_some() && cwd.clone().unwrap().exists() {
-                cwd
-            } else {
-                BaseDirs::new().map(|d| PathBuf::from(d.home_dir()))
-            };
-        let shell = shell.trim();
+        config.pty_config.working_directory = if profile
+            .workdir
+            .as_ref()
+            .map(|p| p.exists())
+            .unwrap_or(false)
+        {
+            profile.workdir.clone()
+        } else {
+            BaseDirs::new().map(|d| PathBuf::from(d.home_dir()))
+        };
+        let program = profile.program.trim();
         let flatpak_use_host_terminal = flatpak_should_use_host_terminal();
 
-        if !shell.is_empty() || flatpak_use_host_terminal {
-            let mut parts = shell.split(' ');
-
-            if flatpak_use_host_terminal {
-                let flatpak_spawn_path = "/usr/bin/flatpak-spawn".to_string();
-                let host_shell = flatpak_get_default_host_shell();
-
-                let args = if shell.is_empty() {
-                    vec!["--host".to_string(), host_shell]
-                } else {
-                    vec![
-                        "--host".to_string(),
-                        host_shell,
-                        "-c".to_string(),
-                        shell.to_string(),
-                    ]
-                };
-
-                config.pty_config.shell = Some(Program::WithArgs {
-                    program: flatpak_spawn_path,
-                    args,
-                })
+        if flatpak_use_host_terminal {
+            let flatpak_spawn_path = "/usr/bin/flatpak-spawn".to_string();
+
+            let mut args = vec!["--host".to_string()];
+            args.extend(
+                profile
+                    .environment
+                    .iter()
+                    .map(|(key, value)| format!("--env={key}={value}")),
+            );
+            if program.is_empty() {
+                args.push(flatpak_get_default_host_shell());
             } else {
-                let program = parts.next().unwrap();
+                args.push(program.to_string());
+                args.extend(profile.arguments.iter().cloned());
+            }
+
+            config.pty_config.shell = Some(Program::WithArgs {
+                program: flatpak_spawn_path,
+                args,
+            })
+        } else {
+            if !program.is_empty() {
                 if let Ok(p) = which::which(program) {
                     config.pty_config.shell = Some(Program::WithArgs {
                         program: p.to_str().unwrap().to_string(),
-                        args: parts.map(|p| p.to_string()).collect::<Vec<String>>(),
+                        args: profile.arguments.clone(),
                     })
                 }
             }
+            config.env = profile.environment.clone();
         }
+
+        // setup_env sets the variables on the proxy process itself so the
+        // pty inherits them, so the ones the profile overrides are put back
+        // once the pty is spawned to not leak them into other terminals.
+        let overridden_env = config
+            .env
+            .keys()
+            .map(|key| (key.clone(), std::env::var_os(key)))
+            .collect::<Vec<_>>();
         setup_env(&config);
 
         #[cfg(target_os = "macos")]
@@ -103,6 +118,13 @@ pub fn new(
         };
         let pty = alacritty_terminal::tty::new(&config.pty_config, size, 0).unwrap();
 
+        for (key, value) in overridden_env {
+            match value {
+                Some(value) => std::env::set_var(key, value),
+                None => std::env::remove_var(key),
+            }
+        }
+
         #[allow(deprecated)]
         let (tx, rx) = channel();
 
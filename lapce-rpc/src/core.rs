@@ -29,6 +29,14 @@ pub enum CoreRpc {
     Shutdown,
 }
 
+/// How diagnostics were received from a plugin. The ones it pushes and
+/// the ones pulled from it are kept apart, so neither replaces the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiagnosticSource {
+    Push(PluginId),
+    Pull(PluginId),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "method", content = "params")]
@@ -61,8 +69,15 @@ pub enum CoreNotification {
     },
     WorkspaceFileChange {},
     PublishDiagnostics {
+        source: DiagnosticSource,
         diagnostics: PublishDiagnosticsParams,
     },
+    /// The characters the language server of a plugin formats the line on
+    /// once they're typed
+    OnTypeFormattingTriggers {
+        plugin_id: PluginId,
+        characters: Vec<String>,
+    },
     WorkDoneProgress {
         progress: ProgressParams,
     },
@@ -277,8 +292,26 @@ pub fn log(&self, level: log::Level, message: String) {
         });
     }
 
-    pub fn publish_diagnostics(&self, diagnostics: PublishDiagnosticsParams) {
-        self.notification(CoreNotification::PublishDiagnostics { diagnostics });
+    pub fn publish_diagnostics(
+        &self,
+        source: DiagnosticSource,
+        diagnostics: PublishDiagnosticsParams,
+    ) {
+        self.notification(CoreNotification::PublishDiagnostics {
+            source,
+            diagnostics,
+        });
+    }
+
+    pub fn on_type_formatting_triggers(
+        &self,
+        plugin_id: PluginId,
+        characters: Vec<String>,
+    ) {
+        self.notification(CoreNotification::OnTypeFormattingTriggers {
+            plugin_id,
+            characters,
+        });
     }
 
     pub fn work_done_progress(&self, progress: ProgressParams) {
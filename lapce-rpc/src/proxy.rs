@@ -11,10 +11,15 @@
 use indexmap::IndexMap;
 use lapce_xi_rope::RopeDelta;
 use lsp_types::{
-    request::GotoTypeDefinitionResponse, CodeAction, CodeActionResponse,
-    CompletionItem, Diagnostic, DocumentSymbolResponse, GotoDefinitionResponse,
-    Hover, InlayHint, Location, Position, PrepareRenameResponse, SelectionRange,
-    SymbolInformation, TextDocumentItem, TextEdit, WorkspaceEdit,
+    request::{
+        GotoDeclarationResponse, GotoImplementationResponse,
+        GotoTypeDefinitionResponse,
+    },
+    CodeAction, CodeActionResponse, CodeLens, CompletionItem, Diagnostic,
+    DocumentHighlight, DocumentSymbolResponse, GotoDefinitionResponse, Hover,
+    InlayHint, Location, Position, PrepareRenameResponse, PublishDiagnosticsParams,
+    Range, SelectionRange, SymbolInformation, TextDocumentItem, TextEdit,
+    TypeHierarchyItem, WorkspaceEdit,
 };
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
@@ -25,7 +30,7 @@
     plugin::{PluginId, VoltInfo, VoltMetadata},
     source_control::FileDiff,
     style::SemanticStyles,
-    terminal::TermId,
+    terminal::{TermId, TerminalProfile},
     RequestId, RpcError, RpcMessage,
 };
 
@@ -43,10 +48,22 @@ pub enum ProxyRequest {
     NewBuffer {
         buffer_id: BufferId,
         path: PathBuf,
+        /// The content and revision of a buffer restored after the proxy
+        /// was reconnected, used instead of the file on disk.
+        #[serde(default)]
+        restore: Option<(String, u64)>,
     },
     BufferHead {
         path: PathBuf,
     },
+    /// The times of the versions of `path` kept in the local history
+    LocalHistory {
+        path: PathBuf,
+    },
+    LocalHistoryContent {
+        path: PathBuf,
+        time: u64,
+    },
     GlobalSearch {
         pattern: String,
         case_sensitive: bool,
@@ -59,6 +76,21 @@ pub enum ProxyRequest {
         plugin_id: PluginId,
         action_item: Box<CodeAction>,
     },
+    GetCodeLens {
+        path: PathBuf,
+    },
+    GetDocumentDiagnostics {
+        path: PathBuf,
+    },
+    CodeLensResolve {
+        plugin_id: PluginId,
+        code_lens: Box<CodeLens>,
+    },
+    ExecuteCommand {
+        plugin_id: PluginId,
+        command: String,
+        arguments: Vec<serde_json::Value>,
+    },
     GetHover {
         request_id: usize,
         path: PathBuf,
@@ -89,9 +121,33 @@ pub enum ProxyRequest {
         path: PathBuf,
         position: Position,
     },
+    GetImplementation {
+        request_id: usize,
+        path: PathBuf,
+        position: Position,
+    },
+    GetDeclaration {
+        request_id: usize,
+        path: PathBuf,
+        position: Position,
+    },
+    PrepareTypeHierarchy {
+        path: PathBuf,
+        position: Position,
+    },
+    GetTypeHierarchySupertypes {
+        item: TypeHierarchyItem,
+    },
+    GetTypeHierarchySubtypes {
+        item: TypeHierarchyItem,
+    },
     GetInlayHints {
         path: PathBuf,
     },
+    GetDocumentHighlights {
+        path: PathBuf,
+        position: Position,
+    },
     GetSemanticTokens {
         path: PathBuf,
     },
@@ -119,6 +175,17 @@ pub enum ProxyRequest {
     GetDocumentFormatting {
         path: PathBuf,
     },
+    /// Responded with `GetDocumentFormatting`
+    GetDocumentRangeFormatting {
+        path: PathBuf,
+        range: Range,
+    },
+    /// Responded with `GetDocumentFormatting`
+    GetOnTypeFormatting {
+        path: PathBuf,
+        position: Position,
+        ch: String,
+    },
     GetOpenFilesContent {},
     GetFiles {
         path: String,
@@ -185,13 +252,18 @@ pub enum ProxyNotification {
         delta: RopeDelta,
         rev: u64,
     },
+    /// The revision of a buffer moved on without its text changing, as
+    /// when its undo history is restored
+    UpdateRev {
+        path: PathBuf,
+        rev: u64,
+    },
     UpdatePluginConfigs {
         configs: HashMap<String, HashMap<String, serde_json::Value>>,
     },
     NewTerminal {
         term_id: TermId,
-        cwd: Option<PathBuf>,
-        shell: String,
+        profile: TerminalProfile,
     },
     InstallVolt {
         volt: VoltInfo,
@@ -248,12 +320,34 @@ pub enum ProxyResponse {
         version: String,
         content: String,
     },
+    LocalHistoryResponse {
+        /// In milliseconds since the Unix epoch, the latest first
+        versions: Vec<u64>,
+    },
+    LocalHistoryContentResponse {
+        content: String,
+    },
     ReadDirResponse {
         items: HashMap<PathBuf, FileNodeItem>,
     },
     CompletionResolveResponse {
         item: Box<CompletionItem>,
     },
+    GetCodeLensResponse {
+        plugin_id: PluginId,
+        resp: Vec<CodeLens>,
+    },
+    CodeLensResolveResponse {
+        item: Box<CodeLens>,
+    },
+    /// The pulled diagnostics of the document, and of its related documents
+    GetDocumentDiagnosticsResponse {
+        plugin_id: PluginId,
+        diagnostics: Vec<PublishDiagnosticsParams>,
+    },
+    ExecuteCommandResponse {
+        result: serde_json::Value,
+    },
     CodeActionResolveResponse {
         item: Box<CodeAction>,
     },
@@ -269,9 +363,29 @@ pub enum ProxyResponse {
         request_id: usize,
         definition: GotoTypeDefinitionResponse,
     },
+    GetImplementation {
+        request_id: usize,
+        resp: GotoImplementationResponse,
+    },
+    GetDeclaration {
+        request_id: usize,
+        resp: GotoDeclarationResponse,
+    },
+    PrepareTypeHierarchy {
+        items: Vec<TypeHierarchyItem>,
+    },
+    GetTypeHierarchySupertypes {
+        items: Vec<TypeHierarchyItem>,
+    },
+    GetTypeHierarchySubtypes {
+        items: Vec<TypeHierarchyItem>,
+    },
     GetReferencesResponse {
         references: Vec<Location>,
     },
+    GetDocumentHighlights {
+        highlights: Vec<DocumentHighlight>,
+    },
     GetCodeActionsResponse {
         plugin_id: PluginId,
         resp: CodeActionResponse,
@@ -431,6 +545,18 @@ pub fn notification(&self, notification: ProxyNotification) {
         let _ = self.tx.send(ProxyRpc::Notification(notification));
     }
 
+    /// Fails the requests still waiting for a response, for when the
+    /// connection to the proxy was lost.
+    pub fn cancel_pending(&self) {
+        let pending: Vec<_> = self.pending.lock().drain().collect();
+        for (_, handler) in pending {
+            handler.invoke(Err(RpcError {
+                code: 0,
+                message: "proxy disconnected".to_string(),
+            }));
+        }
+    }
+
     pub fn git_init(&self) {
         self.notification(ProxyNotification::GitInit {});
     }
@@ -513,17 +639,8 @@ pub fn signature_help(
         });
     }
 
-    pub fn new_terminal(
-        &self,
-        term_id: TermId,
-        cwd: Option<PathBuf>,
-        shell: String,
-    ) {
-        self.notification(ProxyNotification::NewTerminal {
-            term_id,
-            cwd,
-            shell,
-        })
+    pub fn new_terminal(&self, term_id: TermId, profile: TerminalProfile) {
+        self.notification(ProxyNotification::NewTerminal { term_id, profile })
     }
 
     pub fn terminal_close(&self, term_id: TermId) {
@@ -551,7 +668,33 @@ pub fn new_buffer(
         path: PathBuf,
         f: impl ProxyCallback + 'static,
     ) {
-        self.request_async(ProxyRequest::NewBuffer { buffer_id, path }, f);
+        self.request_async(
+            ProxyRequest::NewBuffer {
+                buffer_id,
+                path,
+                restore: None,
+            },
+            f,
+        );
+    }
+
+    /// Opens a buffer with the content and revision it has in the editor,
+    /// for a proxy that was reconnected.
+    pub fn restore_buffer(
+        &self,
+        buffer_id: BufferId,
+        path: PathBuf,
+        content: String,
+        rev: u64,
+    ) {
+        self.request_async(
+            ProxyRequest::NewBuffer {
+                buffer_id,
+                path,
+                restore: Some((content, rev)),
+            },
+            |_| {},
+        );
     }
 
     pub fn get_buffer_head(
@@ -563,6 +706,19 @@ pub fn get_buffer_head(
         self.request_async(ProxyRequest::BufferHead { path }, f);
     }
 
+    pub fn get_local_history(&self, path: PathBuf, f: impl ProxyCallback + 'static) {
+        self.request_async(ProxyRequest::LocalHistory { path }, f);
+    }
+
+    pub fn get_local_history_content(
+        &self,
+        path: PathBuf,
+        time: u64,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(ProxyRequest::LocalHistoryContent { path, time }, f);
+    }
+
     pub fn create_file(&self, path: PathBuf, f: impl ProxyCallback + 'static) {
         self.request_async(ProxyRequest::CreateFile { path }, f);
     }
@@ -669,6 +825,50 @@ pub fn code_action_resolve(
         );
     }
 
+    pub fn get_code_lens(&self, path: PathBuf, f: impl ProxyCallback + 'static) {
+        self.request_async(ProxyRequest::GetCodeLens { path }, f);
+    }
+
+    pub fn get_document_diagnostics(
+        &self,
+        path: PathBuf,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(ProxyRequest::GetDocumentDiagnostics { path }, f);
+    }
+
+    pub fn code_lens_resolve(
+        &self,
+        code_lens: CodeLens,
+        plugin_id: PluginId,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::CodeLensResolve {
+                code_lens: Box::new(code_lens),
+                plugin_id,
+            },
+            f,
+        );
+    }
+
+    pub fn execute_command(
+        &self,
+        plugin_id: PluginId,
+        command: String,
+        arguments: Vec<serde_json::Value>,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::ExecuteCommand {
+                plugin_id,
+                command,
+                arguments,
+            },
+            f,
+        );
+    }
+
     pub fn get_hover(
         &self,
         request_id: usize,
@@ -720,6 +920,77 @@ pub fn get_type_definition(
         );
     }
 
+    pub fn get_implementation(
+        &self,
+        request_id: usize,
+        path: PathBuf,
+        position: Position,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::GetImplementation {
+                request_id,
+                path,
+                position,
+            },
+            f,
+        );
+    }
+
+    pub fn get_declaration(
+        &self,
+        request_id: usize,
+        path: PathBuf,
+        position: Position,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::GetDeclaration {
+                request_id,
+                path,
+                position,
+            },
+            f,
+        );
+    }
+
+    pub fn prepare_type_hierarchy(
+        &self,
+        path: PathBuf,
+        position: Position,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(ProxyRequest::PrepareTypeHierarchy { path, position }, f);
+    }
+
+    pub fn get_type_hierarchy_supertypes(
+        &self,
+        item: TypeHierarchyItem,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(ProxyRequest::GetTypeHierarchySupertypes { item }, f);
+    }
+
+    pub fn get_type_hierarchy_subtypes(
+        &self,
+        item: TypeHierarchyItem,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(ProxyRequest::GetTypeHierarchySubtypes { item }, f);
+    }
+
+    pub fn get_document_highlights(
+        &self,
+        path: PathBuf,
+        position: Position,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::GetDocumentHighlights { path, position },
+            f,
+        );
+    }
+
     pub fn get_references(
         &self,
         path: PathBuf,
@@ -754,6 +1025,31 @@ pub fn get_document_formatting(
         self.request_async(ProxyRequest::GetDocumentFormatting { path }, f);
     }
 
+    pub fn get_document_range_formatting(
+        &self,
+        path: PathBuf,
+        range: Range,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::GetDocumentRangeFormatting { path, range },
+            f,
+        );
+    }
+
+    pub fn get_on_type_formatting(
+        &self,
+        path: PathBuf,
+        position: Position,
+        ch: String,
+        f: impl ProxyCallback + 'static,
+    ) {
+        self.request_async(
+            ProxyRequest::GetOnTypeFormatting { path, position, ch },
+            f,
+        );
+    }
+
     pub fn get_semantic_tokens(
         &self,
         path: PathBuf,
@@ -820,6 +1116,10 @@ pub fn update(&self, path: PathBuf, delta: RopeDelta, rev: u64) {
         self.notification(ProxyNotification::Update { path, delta, rev });
     }
 
+    pub fn update_rev(&self, path: PathBuf, rev: u64) {
+        self.notification(ProxyNotification::UpdateRev { path, rev });
+    }
+
     pub fn update_plugin_configs(
         &self,
         configs: HashMap<String, HashMap<String, serde_json::Value>>,
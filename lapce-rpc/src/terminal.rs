@@ -1,3 +1,5 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use serde::{Deserialize, Serialize};
 
 use crate::counter::Counter;
@@ -11,3 +13,31 @@ pub fn next() -> Self {
         Self(TERMINAL_ID_COUNTER.next())
     }
 }
+
+/// How a terminal is launched. Profiles are configured by name under
+/// `[terminal.profiles]`, and the plain `shell` setting is turned into an
+/// unnamed one.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TerminalProfile {
+    /// The title shown in the terminal header instead of the one set by
+    /// the shell.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Path to an svg shown next to the title.
+    #[serde(default)]
+    pub icon: Option<PathBuf>,
+    /// The program to run. The default shell is used when it's empty.
+    #[serde(default)]
+    pub program: String,
+    /// The arguments passed to the program as they are.
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    /// The working directory. A relative path is resolved against the
+    /// workspace before the profile is sent to the proxy.
+    #[serde(default)]
+    pub workdir: Option<PathBuf>,
+    /// Extra environment variables for the program.
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+}
@@ -31,7 +31,7 @@
     panel::{PanelData, PanelKind},
     selection_range::SyntaxSelectionRanges,
 };
-use lsp_types::{CodeActionOrCommand, DiagnosticSeverity};
+use lsp_types::{CodeActionOrCommand, DiagnosticSeverity, DocumentHighlightKind};
 
 pub mod bread_crumb;
 pub mod container;
@@ -65,6 +65,8 @@ pub struct LapceEditor {
     /// of hover info (if there is any)
     mouse_hover_timer: TimerToken,
     drag_timer: TimerToken,
+    /// Where an alt+drag column selection started
+    column_select_start: Option<Point>,
 }
 
 impl LapceEditor {
@@ -77,6 +79,7 @@ pub fn new(view_id: WidgetId, editor_id: WidgetId) -> Self {
             mouse_mods: Modifiers::empty(),
             mouse_hover_timer: TimerToken::INVALID,
             drag_timer: TimerToken::INVALID,
+            column_select_start: None,
         }
     }
 
@@ -173,6 +176,11 @@ fn mouse_move(
         }
 
         if ctx.is_active() {
+            if let Some(start) = self.column_select_start.filter(|_| mods.alt()) {
+                editor_data.column_select_drag(ctx.text(), start, mouse_pos, config);
+                return;
+            }
+
             let (new_offset, _) = editor_data.doc.offset_of_point(
                 ctx.text(),
                 editor_data.get_mode(),
@@ -308,6 +316,14 @@ fn right_click(
                 },
                 enabled: true,
             }),
+            MenuKind::Item(MenuItem {
+                desc: None,
+                command: LapceCommand {
+                    kind: CommandKind::Focus(FocusCommand::GotoImplementation),
+                    data: None,
+                },
+                enabled: true,
+            }),
             MenuKind::Separator,
             MenuKind::Item(MenuItem {
                 desc: None,
@@ -420,18 +436,16 @@ pub fn get_size(
                         },
                     )
                 } else {
+                    let lines = data.doc.num_visual_lines(&data.config);
                     Size::new(
                         (width * data.doc.buffer().max_len() as f64)
                             .max(data.doc.text_layouts.borrow().max_width)
                             .max(editor_size.width),
                         if data.config.editor.scroll_beyond_last_line {
-                            (line_height * data.doc.buffer().num_lines() as f64
-                                - line_height)
-                                .max(0.0)
+                            (line_height * lines as f64 - line_height).max(0.0)
                                 + editor_size.height
                         } else {
-                            (line_height * data.doc.buffer().num_lines() as f64)
-                                .max(editor_size.height)
+                            (line_height * lines as f64).max(editor_size.height)
                         },
                     )
                 }
@@ -797,6 +811,7 @@ fn paint_content(
 
         let line_padding = Self::line_padding(data, env);
         let line_height = Self::line_height(data, env);
+        let mut code_lens_lines = Vec::new();
         let screen_lines = match &data.editor.view {
             EditorView::Normal => {
                 let rect = ctx.region().bounding_box();
@@ -805,18 +820,29 @@ fn paint_content(
 
                 let mut lines = Vec::new();
                 let mut info = HashMap::new();
-                for line in start_line..end_line + 1 {
+                for visual_line in start_line..end_line + 1 {
+                    let (line, is_code_lens) =
+                        data.doc.line_of_visual_line(visual_line, &data.config);
+                    let y = visual_line as f64 * line_height + line_padding;
+                    if is_code_lens {
+                        code_lens_lines.push((line, y));
+                        continue;
+                    }
                     lines.push(line);
                     info.insert(
                         line,
                         LineInfo {
                             font_size,
                             x: 0.0,
-                            y: line as f64 * line_height + line_padding,
+                            y,
                             line_height,
                         },
                     );
                 }
+                data.doc.resolve_code_lens(
+                    data.doc.line_of_visual_line(start_line, &data.config).0,
+                    data.doc.line_of_visual_line(end_line, &data.config).0,
+                );
                 ScreenLines { lines, info }
             }
             EditorView::Diff(version) => {
@@ -830,9 +856,11 @@ fn paint_content(
         };
 
         Self::paint_current_line(ctx, data, &screen_lines);
+        Self::paint_document_highlights(ctx, data, &screen_lines);
         Self::paint_cursor_new(ctx, data, &screen_lines, is_focused, env);
         Self::paint_find(ctx, data, &screen_lines);
         Self::paint_text(ctx, data, &screen_lines);
+        Self::paint_code_lens(ctx, data, &code_lens_lines, line_height);
         Self::paint_diagnostics(ctx, data, &screen_lines);
         Self::paint_snippet(ctx, data, &screen_lines);
         Self::highlight_scope_and_brackets(ctx, data, &screen_lines);
@@ -1530,6 +1558,82 @@ fn paint_find(
         }
     }
 
+    fn paint_document_highlights(
+        ctx: &mut PaintCtx,
+        data: &LapceEditorBufferData,
+        screen_lines: &ScreenLines,
+    ) {
+        if !data.config.editor.highlight_symbol_occurrences {
+            return;
+        }
+        let highlights = match data.doc.document_highlights.as_ref() {
+            Some(highlights) => highlights,
+            None => return,
+        };
+        if screen_lines.lines.is_empty() {
+            return;
+        }
+
+        // Only show the highlights while the cursor is on one of the occurrences
+        let cursor_offset = data.editor.cursor.offset();
+        if !highlights
+            .iter()
+            .any(|h| h.start <= cursor_offset && cursor_offset <= h.end)
+        {
+            return;
+        }
+
+        let start_line = *screen_lines.lines.first().unwrap();
+        let end_line = *screen_lines.lines.last().unwrap();
+        for highlight in highlights.iter() {
+            let (line, start_col) =
+                data.doc.buffer().offset_to_line_col(highlight.start);
+            if line < start_line || line > end_line {
+                continue;
+            }
+            let info = match screen_lines.info.get(&line) {
+                Some(info) => info,
+                None => continue,
+            };
+            let (highlight_end_line, end_col) =
+                data.doc.buffer().offset_to_line_col(highlight.end);
+            let end_col = if highlight_end_line == line {
+                end_col
+            } else {
+                data.doc.buffer().line_end_col(line, true)
+            };
+
+            let phantom_text = data.doc.line_phantom_text(&data.config, line);
+            let start_col = phantom_text.col_at(start_col);
+            let end_col = phantom_text.col_at(end_col);
+
+            let text_layout = data.doc.get_text_layout(
+                ctx.text(),
+                line,
+                info.font_size,
+                &data.config,
+            );
+            let x0 = text_layout.text.hit_test_text_position(start_col).point.x;
+            let x1 = text_layout.text.hit_test_text_position(end_col).point.x;
+            let rect = Rect::new(
+                x0 + info.x,
+                info.y,
+                x1 + info.x,
+                info.y + info.line_height,
+            );
+            let color = match highlight.kind {
+                DocumentHighlightKind::READ => {
+                    LapceTheme::EDITOR_DOCUMENT_HIGHLIGHT_READ
+                }
+                DocumentHighlightKind::WRITE => {
+                    LapceTheme::EDITOR_DOCUMENT_HIGHLIGHT_WRITE
+                }
+                _ => LapceTheme::EDITOR_DOCUMENT_HIGHLIGHT_TEXT,
+            };
+            ctx.fill(rect, data.config.get_color_unchecked(color));
+        }
+    }
+
     fn paint_sticky_headers(
         ctx: &mut PaintCtx,
         data: &LapceEditorBufferData,
@@ -1557,8 +1661,9 @@ fn paint_sticky_headers(
         let rect = ctx.region().bounding_box();
         let x0 = rect.x0;
         let y0 = rect.y0;
-        let start_line = (rect.y0 / line_height).floor() as usize;
-        let y_diff = y0 - start_line as f64 * line_height;
+        let visual_line = (rect.y0 / line_height).floor() as usize;
+        let y_diff = y0 - visual_line as f64 * line_height;
+        let start_line = data.doc.line_of_visual_line(visual_line, &data.config).0;
         let mut last_sticky_should_scroll = false;
 
         let mut sticky_lines = Vec::new();
@@ -1665,6 +1770,26 @@ fn paint_sticky_headers(
         info.lines = sticky_lines;
     }
 
+    /// Paints the titles of the code lenses on the virtual lines above
+    /// their lines, which are given with the y of their virtual line
+    fn paint_code_lens(
+        ctx: &mut PaintCtx,
+        data: &LapceEditorBufferData,
+        code_lens_lines: &[(usize, f64)],
+        line_height: f64,
+    ) {
+        for (line, y) in code_lens_lines.iter() {
+            if let Some(layout) =
+                data.doc.code_lens_layout(ctx.text(), *line, &data.config)
+            {
+                ctx.draw_text(
+                    &layout.text,
+                    Point::new(layout.x, y + layout.text.y_offset(line_height)),
+                );
+            }
+        }
+    }
+
     fn paint_snippet(
         ctx: &mut PaintCtx,
         data: &LapceEditorBufferData,
@@ -2283,6 +2408,19 @@ fn event(
                     &mut editor_data,
                     &data.config,
                 );
+                if !ctx.is_active()
+                    && editor_data
+                        .doc
+                        .code_lens_of_point(
+                            ctx.text(),
+                            mouse_event.pos,
+                            &editor_data.editor.view,
+                            &data.config,
+                        )
+                        .is_some()
+                {
+                    ctx.set_cursor(&druid::Cursor::Pointer);
+                }
                 data.update_from_editor_buffer_data(editor_data, &editor, &doc);
                 if ctx.is_active() {
                     ctx.set_handled();
@@ -2294,6 +2432,10 @@ fn event(
             }
             Event::MouseDown(mouse_event) => {
                 self.mouse_mods = mouse_event.mods;
+                self.column_select_start = (mouse_event.button == MouseButton::Left
+                    && mouse_event.count == 1
+                    && mouse_event.mods.alt())
+                .then_some(mouse_event.pos);
                 let doc = data.main_split.editor_doc(self.view_id);
                 let editor =
                     data.main_split.editors.get(&self.view_id).unwrap().clone();
@@ -2386,7 +2528,11 @@ fn event(
                                         .x;
                                     let y = editor_data.config.editor.line_height()
                                         as f64
-                                        * (line + 1) as f64;
+                                        * (editor_data
+                                            .doc
+                                            .visual_line(line, &editor_data.config)
+                                            + 1)
+                                            as f64;
                                     ctx.to_window(Point::new(x, y))
                                 });
                                 ctx.show_context_menu::<LapceData>(menu, point);
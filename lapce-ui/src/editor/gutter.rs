@@ -53,6 +53,7 @@ fn event(
                             let offset = data.editor.cursor.offset();
                             let (line, _) =
                                 data.doc.buffer().offset_to_line_col(offset);
+                            let line = data.doc.visual_line(line, &data.config);
                             ctx.submit_command(Command::new(
                                 LAPCE_UI_COMMAND,
                                 LapceUICommand::ShowCodeActions(Some(
@@ -487,6 +488,7 @@ fn code_actions_rect(
         let line_height = data.config.editor.line_height() as f64;
         let offset = data.editor.cursor.offset();
         let (line, _) = data.doc.buffer().offset_to_line_col(offset);
+        let line = data.doc.visual_line(line, &data.config);
 
         let width = 16.0;
         let height = 16.0;
@@ -591,7 +593,11 @@ fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
             }
             let line_height = data.config.editor.line_height() as f64;
             let scroll_offset = data.editor.scroll_offset;
-            let start_line = (scroll_offset.y / line_height).floor() as usize;
+            let start_visual_line = (scroll_offset.y / line_height).floor() as usize;
+            let start_line = data
+                .doc
+                .line_of_visual_line(start_visual_line, &data.config)
+                .0;
             let num_lines = (ctx.size().height / line_height).floor() as usize;
             let last_line = data.doc.buffer().last_line();
             let current_line = data
@@ -602,7 +608,6 @@ fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
 
             let line_label_length =
                 (last_line + 1).to_string().len() as f64 * char_width;
-            let last_displayed_line = (start_line + num_lines + 1).min(last_line);
 
             let sequential_line_numbers = *data.main_split.active
                 != Some(data.view_id)
@@ -611,7 +616,16 @@ fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
 
             let font_family = data.config.editor.font_family();
 
-            for line in start_line..last_displayed_line + 1 {
+            for visual_line in start_visual_line..start_visual_line + num_lines + 2 {
+                let (line, is_code_lens) =
+                    data.doc.line_of_visual_line(visual_line, &data.config);
+                if line > last_line {
+                    break;
+                }
+                if is_code_lens {
+                    continue;
+                }
+
                 let line_no = if sequential_line_numbers || line == current_line {
                     line + 1
                 } else {
@@ -645,15 +659,21 @@ fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
                 let x = line_label_length - text_layout.size().width;
 
                 // Vertically centered
-                let y = line_height * line as f64 - scroll_offset.y
+                let y = line_height * visual_line as f64 - scroll_offset.y
                     + text_layout.y_offset(line_height);
 
                 ctx.draw_text(&text_layout, Point::new(x, y));
             }
 
             if let Some(history) = data.doc.get_history("head") {
-                let end_line =
-                    (scroll_offset.y + rect.height() / line_height).ceil() as usize;
+                let end_line = data
+                    .doc
+                    .line_of_visual_line(
+                        (scroll_offset.y + rect.height() / line_height).ceil()
+                            as usize,
+                        &data.config,
+                    )
+                    .0;
 
                 let mut line = 0;
                 let mut last_change = None;
@@ -697,8 +717,12 @@ fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
                     if let Some(color) = color.cloned() {
                         let removed_height = 10.0;
                         let x = self.width + char_width;
-                        let mut y =
-                            (line - len) as f64 * line_height - scroll_offset.y;
+                        let start = data.doc.visual_line(line - len, &data.config);
+                        let end = data
+                            .doc
+                            .visual_line(line.saturating_sub(1), &data.config)
+                            + 1;
+                        let mut y = start as f64 * line_height - scroll_offset.y;
                         if len == 0 {
                             y -= removed_height / 2.0;
                         }
@@ -721,7 +745,7 @@ fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
                                 if len == 0 {
                                     removed_height
                                 } else {
-                                    line_height * len as f64
+                                    line_height * (end - start) as f64
                                 },
                             ),
                         );
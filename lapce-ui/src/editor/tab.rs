@@ -117,6 +117,9 @@ pub fn remove_child(
             match removed_child {
                 EditorTabChild::Editor(view_id, _, _) => {
                     if let Some(editor) = data.main_split.editors.remove(&view_id) {
+                        if let BufferContent::File(path) = &editor.content {
+                            data.main_split.drop_unused_histories(path);
+                        }
                         if let BufferContent::Scratch(buffer_id, _) = editor.content
                         {
                             let exits_in_other_edits =
@@ -132,6 +135,12 @@ pub fn remove_child(
                 }
                 EditorTabChild::Settings { .. } => {}
                 EditorTabChild::Plugin { .. } => {}
+                EditorTabChild::Terminal {
+                    split_id, term_id, ..
+                } => {
+                    data.main_split.terminals.remove(&split_id);
+                    data.proxy.proxy_rpc.terminal_close(term_id);
+                }
             }
         }
     }
@@ -389,7 +398,7 @@ fn event(
                         {
                             if let Some(active) = tab.children.get(tab.active) {
                                 match active.child_info(data) {
-                                    EditorTabChildInfo::Editor(info) => {
+                                    Some(EditorTabChildInfo::Editor(info)) => {
                                         if info.content
                                             == BufferContent::Local(
                                                 LocalBufferKind::Empty,
@@ -416,8 +425,10 @@ fn event(
                                             ),
                                         ));
                                     }
-                                    EditorTabChildInfo::Settings => {}
-                                    EditorTabChildInfo::Plugin { .. } => {}
+                                    Some(EditorTabChildInfo::Settings) => {}
+                                    Some(EditorTabChildInfo::Plugin { .. }) => {}
+                                    Some(EditorTabChildInfo::Terminal(_)) => {}
+                                    None => {}
                                 }
                                 return;
                             }
@@ -717,6 +728,7 @@ fn paint(
                 .is_pristine(),
             EditorTabChild::Settings { .. } => true,
             EditorTabChild::Plugin { .. } => true,
+            EditorTabChild::Terminal { .. } => true,
         };
 
         if mouse_pos
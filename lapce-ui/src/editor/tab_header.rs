@@ -144,6 +144,12 @@ fn paint_header(&self, ctx: &mut PaintCtx, data: &LapceTabData) {
                     text = format!("Plugin: {volt_name}");
                     svg = data.config.ui_svg(LapceIcons::EXTENSIONS);
                 }
+                EditorTabChild::Terminal {
+                    split_id, term_id, ..
+                } => {
+                    text = data.main_split.terminal_tab_title(split_id, term_id);
+                    svg = data.config.ui_svg(LapceIcons::TERMINAL);
+                }
             }
         }
         let font_size = data.config.ui.font_size() as f64;
@@ -133,7 +133,7 @@ fn mouse_down(
                 let tab_info = editor_tab.children[tab_idx].child_info(data);
 
                 let path = match tab_info {
-                    EditorTabChildInfo::Editor(info) => match info.content {
+                    Some(EditorTabChildInfo::Editor(info)) => match info.content {
                         BufferContent::File(path) => Some(path),
                         _ => None,
                     },
@@ -492,6 +492,12 @@ fn layout(
                     text = format!("Plugin: {volt_name}");
                     svg = data.config.ui_svg(LapceIcons::EXTENSIONS);
                 }
+                EditorTabChild::Terminal {
+                    split_id, term_id, ..
+                } => {
+                    text = data.main_split.terminal_tab_title(split_id, term_id);
+                    svg = data.config.ui_svg(LapceIcons::TERMINAL);
+                }
             }
             let font_size = data.config.ui.font_size() as f64;
             let text_layout = ctx
@@ -6,10 +6,10 @@
 };
 
 use druid::{
-    piet::PietText, BoxConstraints, Command, Data, Env, Event, EventCtx, LayoutCtx,
-    LifeCycle, LifeCycleCtx, Modifiers, PaintCtx, Point, Rect, RenderContext,
-    SingleUse, Size, Target, TimerToken, Vec2, Widget, WidgetExt, WidgetId,
-    WidgetPod,
+    piet::PietText, widget::SizedBox, BoxConstraints, Command, Data, Env, Event,
+    EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, Modifiers, PaintCtx, Point, Rect,
+    RenderContext, SingleUse, Size, Target, TimerToken, Vec2, Widget, WidgetExt,
+    WidgetId, WidgetPod,
 };
 use lapce_core::command::{EditCommand, FocusCommand};
 use lapce_data::{
@@ -34,8 +34,13 @@
     ime::ImeComponent,
     plugin::PluginInfo,
     settings::LapceSettingsPanel,
+    terminal::LapceTerminalView,
 };
 
+/// How long the document has to stay unchanged before its diagnostics are
+/// pulled again from the language servers
+const DIAGNOSTICS_PULL_DELAY: Duration = Duration::from_millis(500);
+
 pub struct LapceEditorView {
     pub view_id: WidgetId,
     pub header: WidgetPod<LapceTabData, LapceEditorHeader>,
@@ -43,6 +48,8 @@ pub struct LapceEditorView {
     pub find: Option<WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>>,
     cursor_blink_timer: TimerToken,
     autosave_timer: TimerToken,
+    document_highlight_timer: TimerToken,
+    diagnostics_pull_timer: TimerToken,
     display_border: bool,
     background_color_name: &'static str,
     ime: ImeComponent,
@@ -74,6 +81,13 @@ pub fn editor_tab_child_widget(
             ..
         } => PluginInfo::new_scroll(*widget_id, *editor_tab_id, volt_id.clone())
             .boxed(),
+        EditorTabChild::Terminal {
+            split_id, term_id, ..
+        } => match data.main_split.get_terminal(split_id, term_id) {
+            Some(terminal) => LapceTerminalView::new(terminal).boxed(),
+            // The terminal was closed before its tab was built.
+            None => SizedBox::empty().boxed(),
+        },
     }
 }
 
@@ -96,6 +110,8 @@ pub fn new(
             find,
             cursor_blink_timer: TimerToken::INVALID,
             autosave_timer: TimerToken::INVALID,
+            document_highlight_timer: TimerToken::INVALID,
+            diagnostics_pull_timer: TimerToken::INVALID,
             display_border: true,
             background_color_name: LapceTheme::EDITOR_BACKGROUND,
             ime: ImeComponent::default(),
@@ -225,6 +241,17 @@ pub fn handle_lapce_ui_command(
             LapceUICommand::EnsureRectVisible(rect) => {
                 self.ensure_rect_visible(ctx, data, *rect, env);
             }
+            LapceUICommand::InsertSnippet(body) => {
+                if let Err(err) = data.insert_snippet(body) {
+                    log::error!("failed to insert snippet: {err}");
+                }
+            }
+            LapceUICommand::UndoTo(node) => {
+                data.undo_to(*node);
+            }
+            LapceUICommand::RestoreVersion(version) => {
+                data.restore_version(version);
+            }
             LapceUICommand::ResolveCompletion(buffer_id, rev, offset, item) => {
                 if data.doc.id() != *buffer_id {
                     return;
@@ -236,10 +263,16 @@ pub fn handle_lapce_ui_command(
                     return;
                 }
                 let offset = data.editor.cursor.offset();
-                let line = data.doc.buffer().line_of_offset(offset);
+                let line = data.doc.visual_line(
+                    data.doc.buffer().line_of_offset(offset),
+                    &data.config,
+                );
                 let _ = data.apply_completion_item(item);
                 let new_offset = data.editor.cursor.offset();
-                let new_line = data.doc.buffer().line_of_offset(new_offset);
+                let new_line = data.doc.visual_line(
+                    data.doc.buffer().line_of_offset(new_offset),
+                    &data.config,
+                );
                 if line != new_line {
                     self.editor
                         .widget_mut()
@@ -526,7 +559,7 @@ fn cursor_region(data: &LapceEditorBufferData, text: &mut PietText) -> Rect {
             let line = if let EditorView::Diff(version) = &data.editor.view {
                 data.doc.history_visual_line(version, line)
             } else {
-                line
+                data.doc.visual_line(line, &data.config)
             };
             line as f64 * line_height
         };
@@ -617,6 +650,19 @@ fn event(
                     }
                 }
             }
+            Event::Timer(id) if self.document_highlight_timer == *id => {
+                ctx.set_handled();
+                self.document_highlight_timer = TimerToken::INVALID;
+                if *data.focus == self.view_id {
+                    let mut editor_data = data.editor_view_content(self.view_id);
+                    editor_data.get_document_highlights(ctx);
+                }
+            }
+            Event::Timer(id) if self.diagnostics_pull_timer == *id => {
+                ctx.set_handled();
+                self.diagnostics_pull_timer = TimerToken::INVALID;
+                data.main_split.editor_doc(self.view_id).pull_diagnostics();
+            }
             Event::Timer(id) if self.autosave_timer == *id => {
                 ctx.set_handled();
                 if let Some(editor) = data
@@ -941,6 +987,26 @@ fn update(
             );
         }
 
+        if data.config.editor.highlight_symbol_occurrences
+            && *data.focus == self.view_id
+            && (offset != old_offset
+                || editor_data.doc.rev() != old_editor_data.doc.rev())
+        {
+            self.document_highlight_timer = ctx.request_timer(
+                Duration::from_millis(
+                    data.config.editor.highlight_symbol_occurrences_delay,
+                ),
+                None,
+            );
+        }
+
+        if *data.focus == self.view_id
+            && editor_data.doc.rev() != old_editor_data.doc.rev()
+        {
+            self.diagnostics_pull_timer =
+                ctx.request_timer(DIAGNOSTICS_PULL_DELAY, None);
+        }
+
         if old_data.config.core.modal != data.config.core.modal
             && !editor_data.doc.content().is_input()
         {
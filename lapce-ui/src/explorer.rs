@@ -1200,6 +1200,12 @@ fn paint_editor(
                 text = format!("Plugin: {volt_name}");
                 svg = data.config.ui_svg(LapceIcons::EXTENSIONS);
             }
+            EditorTabChild::Terminal {
+                split_id, term_id, ..
+            } => {
+                text = data.main_split.terminal_tab_title(split_id, term_id);
+                svg = data.config.ui_svg(LapceIcons::TERMINAL);
+            }
         }
 
         let font_size = data.config.ui.font_size() as f64;
@@ -30,4 +30,5 @@
 mod tab;
 pub mod terminal;
 pub mod title;
+pub mod undo_tree;
 pub mod window;
@@ -122,6 +122,24 @@ fn event(
                             Target::Widget(data.palette.input_editor),
                         ));
                     }
+                    LapceUICommand::RunPaletteTypeHierarchy {
+                        root,
+                        supertypes,
+                        subtypes,
+                    } => {
+                        let mut palette_data = data.palette_view_data();
+                        palette_data
+                            .run_type_hierarchy(ctx, root, supertypes, subtypes);
+                        data.palette = palette_data.palette.clone();
+                        data.keypress = palette_data.keypress.clone();
+                        data.workspace = palette_data.workspace.clone();
+                        data.main_split = palette_data.main_split.clone();
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::Focus,
+                            Target::Widget(data.palette.input_editor),
+                        ));
+                    }
                     LapceUICommand::CancelPalette => {
                         let mut palette_data = data.palette_view_data();
                         palette_data.cancel(ctx);
@@ -617,6 +635,21 @@ fn paint(
             PaletteItemContent::ReferenceLocation(rel_path, _location) => {
                 file_paint_items(rel_path, &self.indices, data)
             }
+            PaletteItemContent::TypeHierarchyItem {
+                kind,
+                text,
+                location,
+            } => file_paint_symbols(
+                &location.path,
+                &self.indices,
+                data.data
+                    .workspace
+                    .as_ref()
+                    .and_then(|workspace| workspace.path.as_deref()),
+                text.as_str(),
+                *kind,
+                &data.config,
+            ),
             PaletteItemContent::Workspace(w) => {
                 let text = w.path.as_ref().unwrap().to_str().unwrap();
                 let text = match &w.kind {
@@ -627,6 +660,9 @@ fn paint(
                     LapceWorkspaceType::RemoteWSL => {
                         format!("[wsl] {text}")
                     }
+                    LapceWorkspaceType::RemoteCommand(remote) => {
+                        format!("[{remote}] {text}")
+                    }
                 };
                 PaletteItemPaintInfo::new_text(text, self.indices.to_vec())
             }
@@ -667,6 +703,52 @@ fn paint(
                 name.to_string(),
                 self.indices.to_vec(),
             ),
+            PaletteItemContent::TerminalProfile(name) => {
+                PaletteItemPaintInfo::new_text(
+                    name.to_string(),
+                    self.indices.to_vec(),
+                )
+            }
+            PaletteItemContent::Task(name) => PaletteItemPaintInfo::new_text(
+                name.to_string(),
+                self.indices.to_vec(),
+            ),
+            PaletteItemContent::Snippet {
+                name,
+                prefix,
+                description,
+                ..
+            } => {
+                let text = name.to_string();
+                let hint = description.clone().unwrap_or_else(|| prefix.clone());
+                let text_indices = self
+                    .indices
+                    .iter()
+                    .filter(|i| **i < text.len())
+                    .copied()
+                    .collect();
+                PaletteItemPaintInfo {
+                    svg: None,
+                    svg_color: None,
+                    text,
+                    text_indices,
+                    hint,
+                    hint_indices: Vec::new(),
+                    keymap: None,
+                }
+            }
+            PaletteItemContent::UndoState { text, hint, .. }
+            | PaletteItemContent::LocalHistory { text, hint, .. } => {
+                PaletteItemPaintInfo {
+                    svg: None,
+                    svg_color: None,
+                    text: text.to_string(),
+                    text_indices: self.indices.to_vec(),
+                    hint: hint.to_string(),
+                    hint_indices: Vec::new(),
+                    keymap: None,
+                }
+            }
             PaletteItemContent::TerminalLine(_line, content) => {
                 PaletteItemPaintInfo::new_text(
                     content.clone(),
@@ -677,6 +759,12 @@ fn paint(
                 format!("{ssh}"),
                 self.indices.to_vec(),
             ),
+            PaletteItemContent::CommandRemote(remote) => {
+                PaletteItemPaintInfo::new_text(
+                    format!("{remote}"),
+                    self.indices.to_vec(),
+                )
+            }
         };
 
         let line_height = data.line_height() as f64;
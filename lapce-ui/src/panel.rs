@@ -943,6 +943,7 @@ fn panel_icon(kind: &PanelKind, data: &LapceTabData) -> (PanelKind, LapceIcon) {
             PanelKind::Terminal => LapceWorkbenchCommand::ToggleTerminalVisual,
             PanelKind::Search => LapceWorkbenchCommand::ToggleSearchVisual,
             PanelKind::Problem => LapceWorkbenchCommand::ToggleProblemVisual,
+            PanelKind::UndoTree => LapceWorkbenchCommand::ToggleUndoTreeVisual,
         };
         (
             *kind,
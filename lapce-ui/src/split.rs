@@ -740,19 +740,28 @@ pub fn split_terminal(
             }
         }
 
+        let (profile, cwd) = data
+            .terminal
+            .active_terminal_split()
+            .and_then(|split| {
+                split.terminals.values().find(|t| t.widget_id == widget_id)
+            })
+            .map(|t| (t.profile_name.clone(), t.cwd()))
+            .unwrap_or_default();
         let terminal_data = Arc::new(LapceTerminalData::new(
             data.workspace.clone(),
             self.split_id,
             ctx.get_external_handle(),
             data.proxy.clone(),
             &data.config,
+            profile,
+            cwd,
         ));
         let terminal = LapceTerminalView::new(&terminal_data);
         Arc::make_mut(&mut data.terminal)
             .active_terminal_split_mut()
             .unwrap()
-            .terminals
-            .insert(terminal_data.term_id, terminal_data.clone());
+            .insert_terminal(index + 1, terminal_data.clone());
 
         self.insert_flex_child(
             index + 1,
@@ -777,11 +786,12 @@ pub fn split_terminal_close(
         }
 
         if self.children.len() == 1 {
-            Arc::make_mut(&mut data.terminal)
-                .active_terminal_split_mut()
-                .unwrap()
-                .terminals
-                .remove(&term_id);
+            if let Some(split) = Arc::make_mut(&mut data.terminal)
+                .tabs
+                .get_mut(&self.split_id)
+            {
+                split.remove_terminal(&term_id);
+            }
             self.children.remove(0);
             self.children_ids.remove(0);
 
@@ -811,11 +821,12 @@ pub fn split_terminal_close(
             Target::Widget(new_terminal_id),
         ));
 
-        Arc::make_mut(&mut data.terminal)
-            .active_terminal_split_mut()
-            .unwrap()
-            .terminals
-            .remove(&term_id);
+        if let Some(split) = Arc::make_mut(&mut data.terminal)
+            .tabs
+            .get_mut(&self.split_id)
+        {
+            split.remove_terminal(&term_id);
+        }
         self.children.remove(index);
         self.children_ids.remove(index);
 
@@ -1153,6 +1164,8 @@ fn event(
                                 ctx.get_external_handle(),
                                 data.proxy.clone(),
                                 &data.config,
+                                None,
+                                None,
                             ));
                             let terminal = LapceTerminalView::new(&terminal_data);
                             self.insert_flex_child(
@@ -1174,9 +1187,7 @@ fn event(
                                 .unwrap();
                             terminal_split.active = terminal_data.widget_id;
                             terminal_split.active_term_id = terminal_data.term_id;
-                            terminal_split
-                                .terminals
-                                .insert(terminal_data.term_id, terminal_data);
+                            terminal_split.insert_terminal(0, terminal_data);
                             ctx.children_changed();
                         }
                     }
@@ -10,6 +10,7 @@
     config::{LapceConfig, LapceIcons, LapceTheme},
     data::{EditorTabChild, LapceEditorData, LapceTabData},
     panel::PanelContainerPosition,
+    proxy::ProxyStatus,
 };
 
 use crate::tab::LapceIcon;
@@ -396,7 +397,9 @@ fn update(
             return;
         }
 
-        if !old_data.progresses.same(&data.progresses) {
+        if !old_data.progresses.same(&data.progresses)
+            || !old_data.proxy_status.same(&data.proxy_status)
+        {
             ctx.request_paint();
         }
     }
@@ -545,6 +548,39 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &druid::Env)
             ),
         ));
 
+        let proxy_status = if data.workspace.kind.is_remote() {
+            match *data.proxy_status {
+                ProxyStatus::Connecting => Some("Connecting to remote..."),
+                ProxyStatus::Disconnected => {
+                    Some("Remote disconnected, reconnecting...")
+                }
+                ProxyStatus::Connected => None,
+            }
+        } else {
+            None
+        };
+        if let Some(text) = proxy_status {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(text)
+                .font(
+                    data.config.ui.font_family(),
+                    data.config.ui.font_size() as f64,
+                )
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(left + 10.0, text_layout.y_offset(size.height)),
+            );
+            left += 10.0 + text_layout.size().width;
+        }
+
         for progress in data.progresses.iter() {
             let mut text = progress.title.clone();
             if let Some(message) = progress.message.as_ref() {
@@ -605,7 +641,8 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &druid::Env)
                     if let Some(child) = editor_tab.active_child() {
                         match child {
                             EditorTabChild::Settings { .. }
-                            | EditorTabChild::Plugin { .. } => {}
+                            | EditorTabChild::Plugin { .. }
+                            | EditorTabChild::Terminal { .. } => {}
                             EditorTabChild::Editor(_, _, _) => {
                                 self.draw_document_details(ctx, data, editor)
                             }
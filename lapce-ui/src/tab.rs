@@ -1,12 +1,12 @@
-use std::{process, sync::Arc};
+use std::{process, sync::Arc, time::Duration};
 
 use druid::{
     kurbo::Line,
     piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder},
     BoxConstraints, Command, Data, Env, Event, EventCtx, InternalLifeCycle,
     LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect, RenderContext,
-    Selector, SingleUse, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetId,
-    WidgetPod,
+    Selector, SingleUse, Size, Target, TimerToken, UpdateCtx, Widget, WidgetExt,
+    WidgetId, WidgetPod,
 };
 use itertools::Itertools;
 use lapce_core::{
@@ -24,12 +24,12 @@
     completion::CompletionStatus,
     config::{LapceConfig, LapceIcons, LapceTheme},
     data::{
-        DragContent, EditorDiagnostic, EditorTabChild, FocusArea, LapceData,
-        LapceTabData, LapceWindowData, LapceWorkspace, LapceWorkspaceType,
-        WorkProgress,
+        DragContent, EditorTabChild, FocusArea, LapceData, LapceTabData,
+        LapceWindowData, LapceWorkspace, LapceWorkspaceType, WorkProgress,
     },
     document::{BufferContent, LocalBufferKind},
     editor::EditorLocation,
+    hot_exit,
     hover::HoverStatus,
     keypress::{DefaultKeyPressHandler, KeyMap, KeyPressData},
     menu::MenuKind,
@@ -41,10 +41,10 @@
     plugin::plugin_install_status::{PluginInstallStatus, PluginInstallType},
     proxy::path_from_url,
     signature::SignatureStatus,
+    undo,
 };
-use lapce_rpc::proxy::ProxyResponse;
+use lapce_rpc::{buffer::BufferId, proxy::ProxyResponse};
 use lapce_xi_rope::Rope;
-use lsp_types::DiagnosticSeverity;
 
 use crate::{
     about::AboutBox, alert::AlertBox, completion::CompletionContainer,
@@ -54,11 +54,15 @@
     search::new_search_panel, signature::SignatureContainer,
     source_control::new_source_control_panel, split::split_data_widget,
     status::LapceStatus, terminal::TerminalPanel, title::Title,
+    undo_tree::new_undo_tree_panel,
 };
 
 pub const LAPCE_TAB_META: Selector<SingleUse<LapceTabMeta>> =
     Selector::new("lapce.tab_meta");
 
+/// How often the unsaved buffers are checked for changes to snapshot
+const UNSAVED_BUFFERS_INTERVAL: Duration = Duration::from_secs(5);
+
 pub struct LapceTabMeta {
     pub data: LapceTabData,
     pub widget: WidgetPod<LapceWindowData, Box<dyn Widget<LapceWindowData>>>,
@@ -98,6 +102,9 @@ pub struct LapceTab {
     title_height: f64,
     status_height: f64,
     mouse_pos: Point,
+    unsaved_buffers_timer: TimerToken,
+    /// The revisions of the unsaved buffers in the last snapshot
+    unsaved_buffers_revs: Vec<(BufferId, u64)>,
 }
 
 impl LapceTab {
@@ -179,6 +186,14 @@ pub fn new(data: &mut LapceTabData) -> Self {
                             WidgetPod::new(new_problem_panel(&data.problem).boxed()),
                         );
                     }
+                    PanelKind::UndoTree => {
+                        panel.insert_panel(
+                            *kind,
+                            WidgetPod::new(
+                                new_undo_tree_panel(&data.undo_tree).boxed(),
+                            ),
+                        );
+                    }
                 }
             }
         }
@@ -205,6 +220,19 @@ pub fn new(data: &mut LapceTabData) -> Self {
             status_height: 0.0,
             title_height: 0.0,
             mouse_pos: Point::ZERO,
+            unsaved_buffers_timer: TimerToken::INVALID,
+            unsaved_buffers_revs: Vec::new(),
+        }
+    }
+
+    /// Takes a snapshot of the unsaved buffers if they changed since the
+    /// last one, so that they can be restored after a crash
+    fn save_unsaved_buffers(&mut self, data: &LapceTabData) {
+        if let Some(revs) = hot_exit::unsaved_revs(&data.main_split) {
+            if revs != self.unsaved_buffers_revs {
+                let _ = data.db.save_unsaved_buffers_async(data);
+                self.unsaved_buffers_revs = revs;
+            }
         }
     }
 
@@ -921,6 +949,10 @@ fn handle_command_event(
                         data.proxy_status = Arc::new(*status);
                         ctx.set_handled();
                     }
+                    LapceUICommand::ProxyReconnected => {
+                        data.resync_proxy();
+                        ctx.set_handled();
+                    }
                     LapceUICommand::HomeDir(path) => {
                         Arc::make_mut(&mut data.picker).init_home(path);
                         data.set_picker_pwd(path.clone());
@@ -944,6 +976,13 @@ fn handle_command_event(
                             *plugin_id,
                         );
                     }
+                    LapceUICommand::UpdateBuiltinCompletion {
+                        request_id,
+                        items,
+                    } => {
+                        let completion = Arc::make_mut(&mut data.completion);
+                        completion.receive_builtin(*request_id, items.clone());
+                    }
                     LapceUICommand::CancelCompletion(request_id) => {
                         if data.completion.request_id == *request_id {
                             let completion = Arc::make_mut(&mut data.completion);
@@ -959,23 +998,32 @@ fn handle_command_event(
                         signature.receive(*request_id, resp.to_owned(), *plugin_id);
                     }
                     LapceUICommand::CloseTerminal(id) => {
-                        let terminal_panel = Arc::make_mut(&mut data.terminal);
-                        if let Some(terminal) = terminal_panel
-                            .active_terminal_split_mut()
-                            .unwrap()
-                            .terminals
-                            .get_mut(id)
+                        ctx.set_handled();
+                        if data.finish_task(ctx.get_external_handle(), id) {
+                            // Keep the output of the task around.
+                        } else if let Some(terminal) = data.get_terminal(id).cloned()
                         {
-                            ctx.submit_command(Command::new(
-                                LAPCE_UI_COMMAND,
-                                LapceUICommand::SplitTerminalClose(
-                                    terminal.term_id,
-                                    terminal.widget_id,
-                                ),
-                                Target::Widget(terminal.split_id),
-                            ));
+                            match terminal.editor_tab_id {
+                                Some(editor_tab_id) => {
+                                    data.main_split.widget_close(
+                                        ctx,
+                                        terminal.widget_id,
+                                        editor_tab_id,
+                                    );
+                                }
+                                None => {
+                                    data.proxy.proxy_rpc.terminal_close(*id);
+                                    ctx.submit_command(Command::new(
+                                        LAPCE_UI_COMMAND,
+                                        LapceUICommand::SplitTerminalClose(
+                                            terminal.term_id,
+                                            terminal.widget_id,
+                                        ),
+                                        Target::Widget(terminal.split_id),
+                                    ));
+                                }
+                            }
                         }
-                        ctx.set_handled();
                     }
                     LapceUICommand::LoadPluginLatest(info) => {
                         ctx.set_handled();
@@ -1232,6 +1280,7 @@ fn handle_command_event(
                                             let editor_data =
                                                 data.editor_view_content(*view_id);
                                             editor_data.doc.get_inlay_hints();
+                                            editor_data.doc.get_code_lens();
                                         }
                                         for i in data
                                             .progresses
@@ -1248,63 +1297,25 @@ fn handle_command_event(
                             }
                         }
                     }
-                    LapceUICommand::PublishDiagnostics(diagnostics) => {
+                    LapceUICommand::PublishDiagnostics {
+                        source,
+                        diagnostics,
+                    } => {
                         let path = path_from_url(&diagnostics.uri);
-                        let diagnostics = diagnostics
-                            .diagnostics
-                            .iter()
-                            .map(|d| EditorDiagnostic {
-                                range: (0, 0),
-                                diagnostic: d.clone(),
-                                lines: d
-                                    .related_information
-                                    .as_ref()
-                                    .map(|r| {
-                                        r.iter()
-                                            .map(|r| {
-                                                r.message.matches('\n').count()
-                                                    + 1
-                                                    + 1
-                                            })
-                                            .sum()
-                                    })
-                                    .unwrap_or(0)
-                                    + d.message.matches('\n').count()
-                                    + 1,
-                            })
-                            .sorted_by_key(|d| d.diagnostic.range.start)
-                            .collect();
-                        let diagnostics: Arc<Vec<EditorDiagnostic>> =
-                            Arc::new(diagnostics);
-
-                        // inform the document about the diagnostics
-                        if let Some(document) =
-                            data.main_split.open_docs.get_mut(&path)
-                        {
-                            let document = Arc::make_mut(document);
-                            document.set_diagnostics(&diagnostics);
-                        }
-
-                        data.main_split.diagnostics.insert(path, diagnostics);
-
-                        let mut errors = 0;
-                        let mut warnings = 0;
-                        for (_, diagnostics) in data.main_split.diagnostics.iter() {
-                            for diagnostic in diagnostics.iter() {
-                                if let Some(severity) =
-                                    diagnostic.diagnostic.severity
-                                {
-                                    match severity {
-                                        DiagnosticSeverity::ERROR => errors += 1,
-                                        DiagnosticSeverity::WARNING => warnings += 1,
-                                        _ => (),
-                                    }
-                                }
-                            }
-                        }
-                        data.main_split.error_count = errors;
-                        data.main_split.warning_count = warnings;
-
+                        data.main_split.publish_diagnostics(
+                            path,
+                            *source,
+                            diagnostics.diagnostics.clone(),
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::OnTypeFormattingTriggers {
+                        plugin_id,
+                        characters,
+                    } => {
+                        data.main_split
+                            .on_type_formatting_triggers
+                            .insert(*plugin_id, characters.clone());
                         ctx.set_handled();
                     }
                     LapceUICommand::DocumentSave(path, exit) => {
@@ -1351,6 +1362,16 @@ fn handle_command_event(
                         let doc = data.main_split.open_docs.get_mut(path).unwrap();
                         if doc.rev() == *rev {
                             Arc::make_mut(doc).buffer_mut().set_pristine();
+                            if data.config.editor.persistent_undo {
+                                undo::save_async(
+                                    path.clone(),
+                                    doc.buffer().clone(),
+                                    data.config.editor.persistent_undo_size,
+                                );
+                            }
+                            // The saved changes mustn't come back as unsaved
+                            // ones after a crash
+                            self.save_unsaved_buffers(data);
                             if let Some(widget_id) = exit_widget_id {
                                 ctx.submit_command(Command::new(
                                     LAPCE_COMMAND,
@@ -1626,6 +1647,32 @@ fn handle_command_event(
                             }
                         }
                     }
+                    LapceUICommand::UpdateCodeLens {
+                        path,
+                        rev,
+                        code_lens,
+                    } => {
+                        if let Some(doc) = data.main_split.open_docs.get_mut(path) {
+                            if doc.rev() == *rev {
+                                Arc::make_mut(doc).set_code_lens(code_lens.clone());
+                            }
+                        }
+                    }
+                    LapceUICommand::UpdateResolvedCodeLens {
+                        path,
+                        rev,
+                        index,
+                        code_lens,
+                    } => {
+                        if let Some(doc) = data.main_split.open_docs.get_mut(path) {
+                            if doc.rev() == *rev {
+                                Arc::make_mut(doc).set_resolved_code_lens(
+                                    *index,
+                                    code_lens.clone(),
+                                );
+                            }
+                        }
+                    }
                     LapceUICommand::CodeActionsError { path, rev, offset } => {
                         if let Some(doc) = data.main_split.open_docs.get_mut(path) {
                             if doc.rev() == *rev {
@@ -1633,6 +1680,21 @@ fn handle_command_event(
                             }
                         }
                     }
+                    LapceUICommand::UpdateDocumentHighlights {
+                        path,
+                        rev,
+                        offset,
+                        highlights,
+                    } => {
+                        if let Some(doc) = data.main_split.open_docs.get_mut(path) {
+                            if doc.rev() == *rev {
+                                Arc::make_mut(doc).set_document_highlights(
+                                    *offset,
+                                    highlights.as_deref(),
+                                );
+                            }
+                        }
+                    }
                     LapceUICommand::UpdateCodeActions {
                         path,
                         plugin_id,
@@ -1669,6 +1731,27 @@ fn handle_command_event(
                         }
                         ctx.set_handled();
                     }
+                    LapceUICommand::PaletteTypeHierarchy {
+                        offset,
+                        root,
+                        supertypes,
+                        subtypes,
+                    } => {
+                        if let Some(editor) = data.main_split.active_editor() {
+                            if *offset == editor.cursor.offset() {
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::RunPaletteTypeHierarchy {
+                                        root: root.clone(),
+                                        supertypes: supertypes.clone(),
+                                        subtypes: subtypes.clone(),
+                                    },
+                                    Target::Widget(data.palette.widget_id),
+                                ));
+                            }
+                        }
+                        ctx.set_handled();
+                    }
                     LapceUICommand::SaveAs(content, path, view_id, exit) => {
                         data.main_split.save_as(ctx, content, path, *view_id, *exit);
                         ctx.set_handled();
@@ -1756,6 +1839,9 @@ fn handle_command_event(
                                     LapceWorkspaceType::RemoteWSL => {
                                         format!("{dir} [wsl]")
                                     }
+                                    LapceWorkspaceType::RemoteCommand(remote) => {
+                                        format!("{dir} [{remote}]")
+                                    }
                                 }
                             })
                             .unwrap_or_else(|| "Lapce".to_string());
@@ -1829,6 +1915,37 @@ fn handle_command_event(
                         }
                         doc.trigger_syntax_change(None);
                     }
+                    LapceUICommand::NewTerminalTabWithProfile(name) => {
+                        ctx.set_handled();
+                        let terminal_panel = Arc::make_mut(&mut data.terminal);
+                        terminal_panel.new_tab(
+                            data.workspace.clone(),
+                            data.proxy.clone(),
+                            &data.config,
+                            ctx.get_external_handle(),
+                            Some(name.to_string()),
+                        );
+                        if !data.panel.is_panel_visible(&PanelKind::Terminal) {
+                            Arc::make_mut(&mut data.panel)
+                                .show_panel(&PanelKind::Terminal);
+                        }
+                    }
+                    LapceUICommand::RunTask(name) => {
+                        ctx.set_handled();
+                        data.run_task(ctx.get_external_handle(), name);
+                    }
+                    LapceUICommand::RunTaskConfig { name, task } => {
+                        ctx.set_handled();
+                        data.run_task_config(ctx.get_external_handle(), name, task);
+                    }
+                    LapceUICommand::RunInTerminal { text, language } => {
+                        ctx.set_handled();
+                        data.run_in_terminal(
+                            ctx.get_external_handle(),
+                            text,
+                            *language,
+                        );
+                    }
                     LapceUICommand::UpdateHistoryChanges {
                         path,
                         rev,
@@ -2033,6 +2150,16 @@ fn event(
         data: &mut LapceTabData,
         env: &Env,
     ) {
+        if let Event::Timer(token) = event {
+            if *token == self.unsaved_buffers_timer {
+                ctx.set_handled();
+                self.save_unsaved_buffers(data);
+                self.unsaved_buffers_timer =
+                    ctx.request_timer(UNSAVED_BUFFERS_INTERVAL, None);
+                return;
+            }
+        }
+
         self.handle_command_event(ctx, event, data, env);
 
         if data.about.active || event.should_propagate_to_hidden() {
@@ -2203,6 +2330,10 @@ fn lifecycle(
         data: &LapceTabData,
         env: &Env,
     ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.unsaved_buffers_timer =
+                ctx.request_timer(UNSAVED_BUFFERS_INTERVAL, None);
+        }
         if let LifeCycle::Internal(InternalLifeCycle::ParentWindowOrigin) = event {
             let current_window_origin = ctx.window_origin();
             if current_window_origin != *data.window_origin.borrow() {
@@ -2862,6 +2993,9 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
                     LapceWorkspaceType::RemoteWSL => {
                         format!("{dir} [wsl]")
                     }
+                    LapceWorkspaceType::RemoteCommand(remote) => {
+                        format!("{dir} [{remote}]")
+                    }
                 }
             })
             .unwrap_or_else(|| "Lapce".to_string());
@@ -7,24 +7,31 @@
     term::{cell::Flags, search::RegexSearch, Term},
 };
 use druid::{
-    piet::{PietTextLayout, Text, TextAttribute, TextLayout, TextLayoutBuilder},
+    piet::{
+        PietTextLayout, Svg, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+    },
     widget::{Click, ControllerHost},
-    BoxConstraints, Command, Cursor, Data, Env, Event, EventCtx, FontWeight,
-    LayoutCtx, LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point, Rect,
-    RenderContext, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetId, WidgetPod,
+    Affine, BoxConstraints, Command, Cursor, Data, Env, Event, EventCtx, FontWeight,
+    LayoutCtx, LifeCycle, LifeCycleCtx, Modifiers, MouseEvent, PaintCtx, Point,
+    Rect, RenderContext, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetId,
+    WidgetPod,
 };
-use lapce_core::{mode::Mode, register::Clipboard};
+use lapce_core::{command::FocusCommand, mode::Mode, register::Clipboard};
 use lapce_data::{
     command::{
-        CommandKind, LapceCommand, LapceUICommand, LapceWorkbenchCommand,
-        LAPCE_COMMAND, LAPCE_UI_COMMAND,
+        CommandExecuted, CommandKind, LapceCommand, LapceUICommand,
+        LapceWorkbenchCommand, LAPCE_COMMAND, LAPCE_UI_COMMAND,
     },
     config::{LapceIcons, LapceTheme},
     data::{FocusArea, LapceTabData},
     document::SystemClipboard,
+    editor::EditorLocation,
+    keypress::KeyPressFocus,
     panel::PanelKind,
-    proxy::LapceProxy,
-    terminal::{EventProxy, LapceTerminalData, LapceTerminalViewData},
+    terminal::{
+        EventProxy, LapceTerminalData, LapceTerminalViewData, RawTerminal,
+        TerminalLink, TerminalLinkMatch,
+    },
 };
 use lapce_rpc::terminal::TermId;
 use unicode_width::UnicodeWidthChar;
@@ -39,6 +46,10 @@
 
 pub type TermConfig = alacritty_terminal::config::Config;
 
+/// The width of the gutter left of the terminal grid with the marks of
+/// the commands run in the shell.
+const COMMAND_MARK_WIDTH: f64 = 6.0;
+
 /// This struct represents the main body of the terminal, i.e. the part
 /// where the shell is presented.
 pub struct TerminalPanel {
@@ -55,7 +66,7 @@ pub fn new(data: &LapceTabData) -> Self {
             .iter()
             .map(|(term_tab_id, tab)| {
                 let mut split = LapceSplit::new(tab.split_id);
-                for (_, term_data) in tab.terminals.iter() {
+                for term_data in tab.ordered_terminals() {
                     let term = LapceTerminalView::new(term_data);
                     split = split.with_flex_child(
                         term.boxed(),
@@ -104,6 +115,7 @@ fn handle_focus(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
                 data.proxy.clone(),
                 &data.config,
                 ctx.get_external_handle(),
+                None,
             );
         }
     }
@@ -659,6 +671,7 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
 
 struct LapceTerminalPanelHeaderContentItem {
     text_layout: Option<PietTextLayout>,
+    profile_svg: Option<Svg>,
     split_id: WidgetId,
     padding: f64,
     icon_padding: f64,
@@ -692,6 +705,7 @@ fn new(split_id: WidgetId) -> Self {
             }));
         Self {
             text_layout: None,
+            profile_svg: None,
             split_id,
             mouse_pos: Point::ZERO,
             padding,
@@ -756,13 +770,13 @@ fn update(
             .tabs
             .get(&self.split_id)
             .and_then(|t| t.active_terminal())
-            .map(|t| &t.title);
+            .map(|t| t.display_title());
         let new_title = data
             .terminal
             .tabs
             .get(&self.split_id)
             .and_then(|t| t.active_terminal())
-            .map(|t| &t.title);
+            .map(|t| t.display_title());
         if old_title != new_title {
             ctx.request_layout();
         }
@@ -775,16 +789,27 @@ fn layout(
         data: &LapceTabData,
         env: &Env,
     ) -> Size {
-        let text = match data
+        let (text, profile_svg) = match data
             .terminal
             .tabs
             .get(&self.split_id)
             .and_then(|t| t.active_terminal())
-            .map(|t| t.title.clone())
-        {
+            .map(|t| {
+                (
+                    t.display_title().to_string(),
+                    data.config.terminal_profile_svg(&t.profile),
+                )
+            }) {
             Some(title) => title,
             None => return Size::new(0.0, bc.max().height),
         };
+        let icon_size = data.config.ui.icon_size() as f64;
+        let text_width = if profile_svg.is_some() {
+            self.title_width - icon_size - self.icon_padding
+        } else {
+            self.title_width
+        };
+        self.profile_svg = profile_svg;
 
         let text_color = data
             .config
@@ -803,7 +828,7 @@ fn layout(
                 .build()
                 .unwrap();
 
-            if text_layout.layout.width() > self.title_width as f32 {
+            if text_layout.layout.width() > text_width as f32 {
                 let ending = ctx
                     .text()
                     .new_text_layout("...")
@@ -815,10 +840,8 @@ fn layout(
                     .unwrap();
                 let ending_width = ending.size().width;
 
-                let hit_point = text_layout.hit_test_point(Point::new(
-                    self.title_width - ending_width,
-                    0.0,
-                ));
+                let hit_point = text_layout
+                    .hit_test_point(Point::new(text_width - ending_width, 0.0));
 
                 ctx.text()
                     .new_text_layout(format!("{}...", &text[..hit_point.idx]))
@@ -836,7 +859,6 @@ fn layout(
 
         let height = bc.max().height;
 
-        let icon_size = data.config.ui.icon_size() as f64;
         self.icon.layout(
             ctx,
             &BoxConstraints::tight(Size::new(
@@ -864,10 +886,24 @@ fn layout(
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
         let size = ctx.size();
 
+        let mut text_x = self.padding;
+        if let Some(svg) = self.profile_svg.as_ref() {
+            let icon_size = data.config.ui.icon_size() as f64;
+            let rect =
+                Size::new(icon_size, icon_size)
+                    .to_rect()
+                    .with_origin(Point::new(
+                        self.padding,
+                        (size.height - icon_size) / 2.0,
+                    ));
+            ctx.draw_svg(svg, rect, None);
+            text_x += icon_size + self.icon_padding;
+        }
+
         let text_layout = self.text_layout.as_ref().unwrap();
         ctx.draw_text(
             text_layout,
-            Point::new(self.padding, text_layout.y_offset(size.height)),
+            Point::new(text_x, text_layout.y_offset(size.height)),
         );
 
         let icon_rect = self.icon.layout_rect();
@@ -992,14 +1028,13 @@ pub fn new(data: &LapceTerminalData) -> Self {
     fn get_icons(&self, self_size: Size, data: &LapceTabData) -> Vec<LapceIcon> {
         let gap = (self.height - self.icon_size) / 2.0;
 
-        let terminal_data = data
-            .terminal
-            .tabs
-            .get(&self.split_id)
-            .unwrap()
-            .terminals
-            .get(&self.term_id)
-            .unwrap();
+        let terminal_data = match data
+            .terminal_split(&self.split_id)
+            .and_then(|split| split.terminals.get(&self.term_id))
+        {
+            Some(terminal_data) => terminal_data,
+            None => return Vec::new(),
+        };
 
         let mut icons = Vec::new();
         let x =
@@ -1024,11 +1059,22 @@ fn get_icons(&self, self_size: Size, data: &LapceTabData) -> Vec<LapceIcon> {
             rect: Size::new(self.icon_size, self.icon_size)
                 .to_rect()
                 .with_origin(Point::new(x, gap)),
-            command: Command::new(
-                LAPCE_UI_COMMAND,
-                LapceUICommand::SplitTerminal(true, terminal_data.widget_id),
-                Target::Widget(terminal_data.split_id),
-            ),
+            command: if terminal_data.editor_tab_id.is_some() {
+                Command::new(
+                    LAPCE_COMMAND,
+                    LapceCommand {
+                        kind: CommandKind::Focus(FocusCommand::SplitVertical),
+                        data: None,
+                    },
+                    Target::Widget(terminal_data.widget_id),
+                )
+            } else {
+                Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitTerminal(true, terminal_data.widget_id),
+                    Target::Widget(terminal_data.split_id),
+                )
+            },
         };
         icons.push(icon);
 
@@ -1145,13 +1191,7 @@ struct LapceTerminal {
     split_id: WidgetId,
     width: f64,
     height: f64,
-    proxy: Arc<LapceProxy>,
-}
-
-impl Drop for LapceTerminal {
-    fn drop(&mut self) {
-        self.proxy.proxy_rpc.terminal_close(self.term_id);
-    }
+    hover_link: Option<TerminalLinkMatch>,
 }
 
 impl LapceTerminal {
@@ -1160,14 +1200,37 @@ pub fn new(data: &LapceTerminalData) -> Self {
             term_id: data.term_id,
             widget_id: data.widget_id,
             split_id: data.split_id,
-            proxy: data.proxy.clone(),
             width: 0.0,
             height: 0.0,
+            hover_link: None,
         }
     }
 
     pub fn request_focus(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
         ctx.request_focus();
+        let editor_tab_id = data
+            .terminal_split(&self.split_id)
+            .and_then(|split| split.terminals.get(&self.term_id))
+            .and_then(|terminal| terminal.editor_tab_id);
+        if let Some(editor_tab_id) = editor_tab_id {
+            if let Some(editor_tab) =
+                data.main_split.editor_tabs.get_mut(&editor_tab_id)
+            {
+                let editor_tab = Arc::make_mut(editor_tab);
+                if let Some(index) = editor_tab
+                    .children
+                    .iter()
+                    .position(|child| child.widget_id() == self.widget_id)
+                {
+                    editor_tab.active = index;
+                }
+            }
+            data.main_split.active_tab = Arc::new(Some(editor_tab_id));
+            data.focus = Arc::new(self.widget_id);
+            data.focus_area = FocusArea::Editor;
+            return;
+        }
+
         let terminal_split = Arc::make_mut(&mut data.terminal)
             .active_terminal_split_mut()
             .unwrap();
@@ -1186,31 +1249,131 @@ pub fn request_focus(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
         }
     }
 
+    fn grid_point(
+        &self,
+        term: &Term<EventProxy>,
+        pos: Point,
+    ) -> alacritty_terminal::index::Point {
+        let row_size = self.height / term.screen_lines() as f64;
+        let col_size = (self.width - COMMAND_MARK_WIDTH) / term.columns() as f64;
+        let offset = term.grid().display_offset();
+        let column =
+            Column(((pos.x - COMMAND_MARK_WIDTH).max(0.0) / col_size) as usize);
+        let line = Line((pos.y / row_size) as i32 - offset as i32);
+        alacritty_terminal::index::Point { line, column }
+    }
+
     fn select(
         &self,
         term: &mut Term<EventProxy>,
         mouse_event: &MouseEvent,
         ty: SelectionType,
     ) {
-        let row_size = self.height / term.screen_lines() as f64;
-        let col_size = self.width / term.columns() as f64;
-        let offset = term.grid().display_offset();
-        let column = Column((mouse_event.pos.x / col_size) as usize);
-        let line = Line((mouse_event.pos.y / row_size) as i32 - offset as i32);
+        let point = self.grid_point(term, mouse_event.pos);
         match &mut term.selection {
-            Some(selection) => selection.update(
-                alacritty_terminal::index::Point { line, column },
-                Direction::Left,
-            ),
+            Some(selection) => selection.update(point, Direction::Left),
             None => {
-                term.selection = Some(Selection::new(
-                    ty,
-                    alacritty_terminal::index::Point { line, column },
-                    Direction::Left,
-                ));
+                term.selection = Some(Selection::new(ty, point, Direction::Left));
             }
         }
     }
+
+    /// Paints a mark in the gutter along each command reported by shell
+    /// integration, coloured by its exit code.
+    fn paint_command_marks(
+        &self,
+        ctx: &mut PaintCtx,
+        data: &LapceTabData,
+        raw: &RawTerminal,
+        line_height: f64,
+    ) {
+        let term = &raw.term;
+        let display_offset = term.grid().display_offset() as i32;
+        let screen_lines = term.screen_lines() as i32;
+        let cursor_line = term.grid().cursor.point.line.0;
+        for command in raw.shell_integration.commands.iter() {
+            if command.output_line.is_none() {
+                continue;
+            }
+            let start =
+                RawTerminal::grid_line(term, command.prompt_line).0 + display_offset;
+            let end = match command.end_line {
+                Some(end_line) => {
+                    RawTerminal::grid_line(term, end_line).0 + display_offset - 1
+                }
+                None => cursor_line + display_offset,
+            };
+            if end < 0 || start >= screen_lines {
+                continue;
+            }
+
+            let color = match (command.end_line, command.exit_code) {
+                (None, _) => LapceTheme::TERMINAL_BLUE,
+                (Some(_), Some(0)) => LapceTheme::TERMINAL_GREEN,
+                (Some(_), Some(_)) => LapceTheme::TERMINAL_RED,
+                (Some(_), None) => LapceTheme::TERMINAL_BRIGHT_BLACK,
+            };
+            let y0 = start.max(0) as f64 * line_height;
+            let y1 = (end.max(start).min(screen_lines - 1) + 1) as f64 * line_height;
+            ctx.fill(
+                Rect::new(1.0, y0 + 1.0, COMMAND_MARK_WIDTH - 2.0, y1 - 1.0),
+                data.config.get_color_unchecked(color),
+            );
+        }
+    }
+
+    /// Updates the link under the mouse, which is only looked up while the
+    /// link modifier is held.
+    fn update_hover_link(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &LapceTabData,
+        terminal: &LapceTerminalData,
+        mouse_event: &MouseEvent,
+    ) {
+        let link = if is_link_modifier(&mouse_event.mods) {
+            let raw = terminal.raw.lock();
+            let point = self.grid_point(&raw.term, mouse_event.pos);
+            terminal.link_at(&raw, point, &data.workspace)
+        } else {
+            None
+        };
+        if link.is_some() {
+            ctx.set_cursor(&Cursor::Pointer);
+        }
+        if link != self.hover_link {
+            self.hover_link = link;
+            ctx.request_paint();
+        }
+    }
+
+    fn open_link(
+        &self,
+        ctx: &mut EventCtx,
+        data: &LapceTabData,
+        link: &TerminalLink,
+    ) {
+        let command = match link {
+            TerminalLink::File { path, position } => {
+                LapceUICommand::JumpToLineColLocation(
+                    None,
+                    EditorLocation {
+                        path: path.clone(),
+                        position: *position,
+                        scroll_offset: None,
+                        history: None,
+                    },
+                    false,
+                )
+            }
+            TerminalLink::Uri(uri) => LapceUICommand::OpenURI(uri.clone()),
+        };
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            command,
+            Target::Widget(data.id),
+        ));
+    }
 }
 
 impl Widget<LapceTabData> for LapceTerminal {
@@ -1226,9 +1389,7 @@ fn event(
         env: &Env,
     ) {
         let old_terminal_data = data
-            .terminal
-            .tabs
-            .get(&self.split_id)
+            .terminal_split(&self.split_id)
             .and_then(|split| split.terminals.get(&self.term_id).cloned());
         let old_terminal_data = match old_terminal_data {
             Some(t) => t,
@@ -1237,6 +1398,7 @@ fn event(
 
         let mut term_data = LapceTerminalViewData {
             terminal: old_terminal_data.clone(),
+            main_split: data.main_split.clone(),
             config: data.config.clone(),
             find: data.find.clone(),
         };
@@ -1244,6 +1406,19 @@ fn event(
         match event {
             Event::MouseDown(mouse_event) => {
                 self.request_focus(ctx, data);
+                if mouse_event.button.is_left()
+                    && is_link_modifier(&mouse_event.mods)
+                {
+                    let link = {
+                        let raw = old_terminal_data.raw.lock();
+                        let point = self.grid_point(&raw.term, mouse_event.pos);
+                        old_terminal_data.link_at(&raw, point, &data.workspace)
+                    };
+                    if let Some(link) = link {
+                        self.open_link(ctx, data, &link.link);
+                        return;
+                    }
+                }
                 let terminal = old_terminal_data.clone();
                 let term = &mut terminal.raw.lock().term;
                 if mouse_event.button.is_right() {
@@ -1281,6 +1456,13 @@ fn event(
                     let term = &mut terminal.raw.lock().term;
                     self.select(term, mouse_event, SelectionType::Simple);
                     ctx.request_paint();
+                } else {
+                    self.update_hover_link(
+                        ctx,
+                        data,
+                        &old_terminal_data,
+                        mouse_event,
+                    );
                 }
             }
             Event::Wheel(wheel_event) => {
@@ -1300,6 +1482,16 @@ fn event(
                 }
                 ctx.set_handled();
                 data.keypress = keypress.clone();
+                data.main_split = term_data.main_split.clone();
+            }
+            Event::Command(cmd) if cmd.is(LAPCE_COMMAND) => {
+                let command = cmd.get_unchecked(LAPCE_COMMAND);
+                if term_data.run_command(ctx, command, None, Modifiers::empty(), env)
+                    == CommandExecuted::Yes
+                {
+                    ctx.set_handled();
+                }
+                data.main_split = term_data.main_split.clone();
             }
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
@@ -1310,12 +1502,11 @@ fn event(
             _ => (),
         }
         if !term_data.terminal.same(&old_terminal_data) {
-            Arc::make_mut(&mut data.terminal)
-                .tabs
-                .get_mut(&self.split_id)
-                .unwrap()
-                .terminals
-                .insert(term_data.terminal.term_id, term_data.terminal.clone());
+            if let Some(split) = data.terminal_split_mut(&self.split_id) {
+                split
+                    .terminals
+                    .insert(term_data.terminal.term_id, term_data.terminal.clone());
+            }
         }
     }
 
@@ -1354,19 +1545,17 @@ fn layout(
             let width = data.config.terminal_char_width(ctx.text());
             let line_height = data.config.terminal_line_height() as f64;
             let width = if width > 0.0 {
-                (self.width / width).floor() as usize
+                ((self.width - COMMAND_MARK_WIDTH) / width).floor() as usize
             } else {
                 0
             };
             let height = (self.height / line_height).floor() as usize;
-            data.terminal
-                .tabs
-                .get(&self.split_id)
-                .unwrap()
-                .terminals
-                .get(&self.term_id)
-                .unwrap()
-                .resize(width, height);
+            if let Some(terminal) = data
+                .terminal_split(&self.split_id)
+                .and_then(|split| split.terminals.get(&self.term_id))
+            {
+                terminal.resize(width, height);
+            }
         }
         size
     }
@@ -1376,18 +1565,21 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
         let char_width = char_size.width;
         let line_height = data.config.terminal_line_height() as f64;
 
-        let terminal = data
-            .terminal
-            .tabs
-            .get(&self.split_id)
-            .unwrap()
-            .terminals
-            .get(&self.term_id)
-            .unwrap();
+        let terminal_split = match data.terminal_split(&self.split_id) {
+            Some(terminal_split) => terminal_split,
+            None => return,
+        };
+        let terminal = match terminal_split.terminals.get(&self.term_id) {
+            Some(terminal) => terminal,
+            None => return,
+        };
         let raw = terminal.raw.lock();
         let term = &raw.term;
         let content = term.renderable_content();
 
+        self.paint_command_marks(ctx, data, &raw, line_height);
+        ctx.transform(Affine::translate((COMMAND_MARK_WIDTH, 0.0)));
+
         if let Some(selection) = content.selection.as_ref() {
             let start_line = selection.start.line.0 + content.display_offset as i32;
             let start_line = if start_line < 0 {
@@ -1453,16 +1645,10 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
             let y =
                 (point.line.0 as f64 + content.display_offset as f64) * line_height;
 
-            let mut bg = data.terminal.tabs.get(&self.split_id).unwrap().get_color(
-                &cell.bg,
-                content.colors,
-                &data.config,
-            );
-            let mut fg = data.terminal.tabs.get(&self.split_id).unwrap().get_color(
-                &cell.fg,
-                content.colors,
-                &data.config,
-            );
+            let mut bg =
+                terminal_split.get_color(&cell.bg, content.colors, &data.config);
+            let mut fg =
+                terminal_split.get_color(&cell.fg, content.colors, &data.config);
             if cell.flags.contains(Flags::DIM)
                 || cell.flags.contains(Flags::DIM_BOLD)
             {
@@ -1532,6 +1718,21 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
                 );
             }
         }
+        if let Some(link) = self.hover_link.as_ref() {
+            let y = (link.line.0 as f64 + content.display_offset as f64 + 1.0)
+                * line_height
+                - 1.0;
+            ctx.stroke(
+                druid::kurbo::Line::new(
+                    Point::new(link.start.0 as f64 * char_width, y),
+                    Point::new((link.end.0 + 1) as f64 * char_width, y),
+                ),
+                data.config
+                    .get_color_unchecked(LapceTheme::TERMINAL_FOREGROUND),
+                1.0,
+            );
+        }
+
         if data.find.visual {
             if let Some(search_string) = data.find.search_string.as_ref() {
                 if let Ok(dfas) = RegexSearch::new(&regex::escape(search_string)) {
@@ -1593,3 +1794,13 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
         }
     }
 }
+
+/// Whether the modifier for following links in the terminal is held, which
+/// is the same one as for go to definition in the editor.
+fn is_link_modifier(mods: &Modifiers) -> bool {
+    #[cfg(target_os = "macos")]
+    let pressed = mods.meta();
+    #[cfg(not(target_os = "macos"))]
+    let pressed = mods.ctrl();
+    pressed
+}
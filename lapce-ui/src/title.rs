@@ -163,19 +163,19 @@ fn update_remote(
             LapceWorkspaceType::Local => data
                 .config
                 .get_color_unchecked(LapceTheme::LAPCE_REMOTE_LOCAL),
-            LapceWorkspaceType::RemoteSSH(_) | LapceWorkspaceType::RemoteWSL => {
-                match *data.proxy_status {
-                    ProxyStatus::Connecting => data
-                        .config
-                        .get_color_unchecked(LapceTheme::LAPCE_REMOTE_CONNECTING),
-                    ProxyStatus::Connected => data
-                        .config
-                        .get_color_unchecked(LapceTheme::LAPCE_REMOTE_CONNECTED),
-                    ProxyStatus::Disconnected => data
-                        .config
-                        .get_color_unchecked(LapceTheme::LAPCE_REMOTE_DISCONNECTED),
-                }
-            }
+            LapceWorkspaceType::RemoteSSH(_)
+            | LapceWorkspaceType::RemoteWSL
+            | LapceWorkspaceType::RemoteCommand(_) => match *data.proxy_status {
+                ProxyStatus::Connecting => data
+                    .config
+                    .get_color_unchecked(LapceTheme::LAPCE_REMOTE_CONNECTING),
+                ProxyStatus::Connected => data
+                    .config
+                    .get_color_unchecked(LapceTheme::LAPCE_REMOTE_CONNECTED),
+                ProxyStatus::Disconnected => data
+                    .config
+                    .get_color_unchecked(LapceTheme::LAPCE_REMOTE_DISCONNECTED),
+            },
         };
         self.rects.push((remote_rect, color.clone()));
         let remote_svg = data.config.ui_svg(LapceIcons::REMOTE);
@@ -217,6 +217,19 @@ fn update_remote(
             }));
         }
 
+        if !data.config.core.remotes.is_empty() {
+            menu_items.push(MenuKind::Item(MenuItem {
+                desc: None,
+                command: LapceCommand {
+                    kind: CommandKind::Workbench(
+                        LapceWorkbenchCommand::ConnectCommandRemote,
+                    ),
+                    data: None,
+                },
+                enabled: true,
+            }));
+        }
+
         if data.workspace.kind.is_remote() {
             menu_items.push(MenuKind::Item(MenuItem {
                 desc: None,
@@ -526,6 +539,7 @@ fn update_folder(
                 format!(" [SSH: {}]", ssh.host)
             }
             LapceWorkspaceType::RemoteWSL => " [WSL]".to_string(),
+            LapceWorkspaceType::RemoteCommand(remote) => format!(" [{remote}]"),
         };
         let text = format!("{path}{remote}");
         let text_layout = piet_text
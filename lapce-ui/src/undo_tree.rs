@@ -0,0 +1,491 @@
+use std::{ops::Range, sync::Arc};
+
+use druid::{
+    kurbo::{Circle, Line},
+    piet::{Text, TextLayout as PietTextLayout, TextLayoutBuilder},
+    BoxConstraints, Command, Cursor, Env, Event, EventCtx, LayoutCtx, LifeCycle,
+    LifeCycleCtx, PaintCtx, Point, Rect, RenderContext, Selector, SingleUse, Size,
+    Target, UpdateCtx, Widget, WidgetExt, WidgetId,
+};
+use lapce_core::buffer::{rope_diff, Buffer, DiffLines, UndoNode};
+use lapce_data::{
+    command::{LapceUICommand, LAPCE_UI_COMMAND},
+    config::LapceTheme,
+    data::LapceTabData,
+    document::Document,
+    panel::PanelKind,
+    undo::{self, UndoTreeData},
+};
+use lapce_rpc::buffer::BufferId;
+use lapce_xi_rope::Rope;
+
+use crate::panel::{LapcePanel, PanelHeaderKind, PanelSizing};
+
+/// The width of a column of the graph of the undo tree
+const COLUMN_WIDTH: f64 = 12.0;
+
+/// The changes of the current node of the undo tree, diffed in the
+/// background for the state of the panel they were diffed for
+const UNDO_CHANGES: Selector<SingleUse<(UndoState, Vec<ChangeLine>)>> =
+    Selector::new("lapce.undo_changes");
+
+/// The active editor, its document and the revision of the document
+type UndoState = Option<(WidgetId, BufferId, u64)>;
+
+pub fn new_undo_tree_panel(data: &UndoTreeData) -> LapcePanel {
+    LapcePanel::new(
+        PanelKind::UndoTree,
+        data.widget_id,
+        data.split_id,
+        vec![
+            (
+                data.tree_widget_id,
+                PanelHeaderKind::Simple("Undo Tree".into()),
+                UndoTreeContent::new().boxed(),
+                PanelSizing::Flex(true),
+            ),
+            (
+                data.changes_widget_id,
+                PanelHeaderKind::Simple("Changes".into()),
+                UndoChangesContent::new().boxed(),
+                PanelSizing::Flex(true),
+            ),
+        ],
+    )
+}
+
+/// The view id of the active editor and its document, whose undo tree the
+/// panel shows
+fn active_doc(data: &LapceTabData) -> Option<(WidgetId, Arc<Document>)> {
+    let editor = data.main_split.active_editor()?;
+    Some((editor.view_id, data.main_split.content_doc(&editor.content)))
+}
+
+/// Changes whenever the undo tree shown by the panel might have changed
+fn undo_state(data: &LapceTabData) -> UndoState {
+    active_doc(data).map(|(view_id, doc)| (view_id, doc.id(), doc.rev()))
+}
+
+/// The column of the graph each node of the undo tree is drawn in. The first
+/// child of a node continues its column, and every other child, which is a
+/// new branch, starts a column of its own.
+fn node_columns(tree: &[UndoNode]) -> Vec<usize> {
+    let mut columns = vec![0; tree.len()];
+    let mut has_child = vec![false; tree.len()];
+    let mut next_column = 1;
+    for (node, undo_node) in tree.iter().enumerate().skip(1) {
+        let parent = undo_node.parent;
+        if has_child[parent] {
+            columns[node] = next_column;
+            next_column += 1;
+        } else {
+            has_child[parent] = true;
+            columns[node] = columns[parent];
+        }
+    }
+    columns
+}
+
+/// Lists the nodes of the undo tree, the latest at the top, with a graph of
+/// their branches. Clicking a node moves the editor to it.
+struct UndoTreeContent {
+    mouse_pos: Point,
+    columns: Vec<usize>,
+    content_height: f64,
+}
+
+impl UndoTreeContent {
+    fn new() -> Self {
+        Self {
+            mouse_pos: Point::ZERO,
+            columns: Vec::new(),
+            content_height: 0.0,
+        }
+    }
+
+    fn mouse_down(&self, ctx: &mut EventCtx, pos: Point, data: &LapceTabData) {
+        let (view_id, doc) = match active_doc(data) {
+            Some(active) => active,
+            None => return,
+        };
+        let line_height = data.config.editor.line_height() as f64;
+        let row = (pos.y / line_height).floor() as usize;
+        let len = doc.buffer().undo_tree().len();
+        if row < len {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::UndoTo(len - 1 - row),
+                Target::Widget(view_id),
+            ));
+        }
+    }
+}
+
+impl Widget<LapceTabData> for UndoTreeContent {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        _env: &Env,
+    ) {
+        match event {
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = mouse_event.pos;
+
+                if mouse_event.pos.y < self.content_height {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse_event) => {
+                self.mouse_down(ctx, mouse_event.pos, data);
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        _data: &LapceTabData,
+        _env: &Env,
+    ) {
+        if let LifeCycle::HotChanged(_) = event {
+            ctx.request_paint();
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        _env: &Env,
+    ) {
+        if undo_state(data) != undo_state(old_data) {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        _env: &Env,
+    ) -> Size {
+        self.columns = active_doc(data)
+            .map(|(_, doc)| node_columns(doc.buffer().undo_tree()))
+            .unwrap_or_default();
+        let line_height = data.config.editor.line_height() as f64;
+        self.content_height = line_height * self.columns.len() as f64;
+
+        Size::new(bc.max().width, self.content_height.max(bc.max().height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
+        let doc = match active_doc(data) {
+            Some((_, doc)) => doc,
+            None => return,
+        };
+        let buffer = doc.buffer();
+        let tree = buffer.undo_tree();
+        if tree.len() != self.columns.len() {
+            return;
+        }
+        let current = buffer.current_undo_node();
+
+        let line_height = data.config.editor.line_height() as f64;
+        let size = ctx.size();
+        let rect = ctx.region().bounding_box();
+        let min = (rect.y0 / line_height).floor() as usize;
+        let max = ((rect.y1 / line_height) as usize + 2).min(tree.len());
+
+        let row_of = |node: usize| tree.len() - 1 - node;
+        let center = |node: usize| {
+            Point::new(
+                COLUMN_WIDTH * (self.columns[node] as f64 + 0.5) + 5.0,
+                line_height * (row_of(node) as f64 + 0.5),
+            )
+        };
+        let row_rect = |row: usize| {
+            Rect::ZERO
+                .with_origin(Point::new(0.0, line_height * row as f64))
+                .with_size(Size::new(size.width, line_height))
+        };
+
+        let mouse_row = (self.mouse_pos.y / line_height).floor() as usize;
+        if ctx.is_hot() && mouse_row < tree.len() {
+            ctx.fill(
+                row_rect(mouse_row),
+                data.config
+                    .get_color_unchecked(LapceTheme::PANEL_HOVERED_BACKGROUND),
+            );
+        }
+        ctx.fill(
+            row_rect(row_of(current)),
+            data.config
+                .get_color_unchecked(LapceTheme::PANEL_CURRENT_BACKGROUND),
+        );
+
+        let graph_color = data.config.get_color_unchecked(LapceTheme::EDITOR_DIM);
+        for (node, undo_node) in tree.iter().enumerate().skip(1) {
+            if row_of(node) > max || row_of(undo_node.parent) < min {
+                continue;
+            }
+            let from = center(node);
+            let to = center(undo_node.parent);
+            let bend = Point::new(from.x, to.y - line_height / 2.0);
+            ctx.stroke(Line::new(from, bend), graph_color, 1.0);
+            ctx.stroke(Line::new(bend, to), graph_color, 1.0);
+        }
+
+        let text_x = COLUMN_WIDTH
+            * (self.columns.iter().max().copied().unwrap_or(0) + 1) as f64
+            + 10.0;
+        let ui_font_family = data.config.ui.font_family();
+        let ui_font_size = data.config.ui.font_size() as f64;
+        for row in min..max {
+            let node = tree.len() - 1 - row;
+            let dot_color = if node == current {
+                data.config.get_color_unchecked(LapceTheme::EDITOR_CARET)
+            } else {
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+            };
+            ctx.fill(Circle::new(center(node), 3.5), dot_color);
+
+            let y = line_height * row as f64;
+            let text_layout = ctx
+                .text()
+                .new_text_layout(undo::node_label(node, &tree[node]))
+                .font(ui_font_family.clone(), ui_font_size)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(text_x, y + text_layout.y_offset(line_height)),
+            );
+
+            let x = text_x + text_layout.size().width + 10.0;
+            let text_layout = ctx
+                .text()
+                .new_text_layout(undo::node_time(&tree[node]))
+                .font(ui_font_family.clone(), ui_font_size)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(x, y + text_layout.y_offset(line_height)),
+            );
+        }
+    }
+}
+
+enum ChangeLine {
+    Added(String),
+    Removed(String),
+    Unchanged(String),
+    Note(String),
+}
+
+/// The lines of `rope` in `range`
+fn rope_lines(
+    rope: &Rope,
+    range: Range<usize>,
+) -> impl Iterator<Item = String> + '_ {
+    let start = rope.offset_of_line(range.start);
+    let end = rope.offset_of_line(range.end);
+    rope.lines(start..end).map(|line| line.to_string())
+}
+
+/// The diff of the current node of the undo tree of `buffer` against its
+/// parent, which is `None` when the buffer changed while diffing
+fn current_changes(buffer: &Buffer) -> Option<Vec<ChangeLine>> {
+    let node = buffer.current_undo_node();
+    if node == 0 {
+        return Some(Vec::new());
+    }
+    let undo_node = &buffer.undo_tree()[node];
+
+    let old_text = buffer.undo_node_text(undo_node.parent);
+    let new_text = buffer.text();
+    let diff = rope_diff(
+        old_text.clone(),
+        new_text.clone(),
+        buffer.rev(),
+        buffer.atomic_rev(),
+    )?;
+
+    let mut lines = Vec::new();
+    for change in diff {
+        match change {
+            DiffLines::Left(range) => {
+                lines.extend(rope_lines(&old_text, range).map(ChangeLine::Removed));
+            }
+            DiffLines::Right(range) => {
+                lines.extend(rope_lines(new_text, range).map(ChangeLine::Added));
+            }
+            DiffLines::Both(_, range) => {
+                lines.extend(rope_lines(new_text, range).map(ChangeLine::Unchanged));
+            }
+            DiffLines::Skip(_, range) => {
+                lines.push(ChangeLine::Note(format!(
+                    "{} unchanged lines",
+                    range.len()
+                )));
+            }
+        }
+    }
+    Some(lines)
+}
+
+/// Shows what the current node of the undo tree changed
+struct UndoChangesContent {
+    state: UndoState,
+    lines: Vec<ChangeLine>,
+}
+
+impl UndoChangesContent {
+    fn new() -> Self {
+        Self {
+            state: None,
+            lines: Vec::new(),
+        }
+    }
+}
+
+impl Widget<LapceTabData> for UndoChangesContent {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        _data: &mut LapceTabData,
+        _env: &Env,
+    ) {
+        if let Event::Command(cmd) = event {
+            if let Some(changes) = cmd.get(UNDO_CHANGES) {
+                let (state, lines) = changes.take().unwrap();
+                if state == self.state {
+                    self.lines = lines;
+                    ctx.request_layout();
+                    ctx.request_paint();
+                }
+                ctx.set_handled();
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &LapceTabData,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        _env: &Env,
+    ) {
+        if undo_state(data) != undo_state(old_data) {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        _env: &Env,
+    ) -> Size {
+        let state = undo_state(data);
+        if state != self.state {
+            self.state = state;
+            match active_doc(data) {
+                Some((_, doc)) => {
+                    // Rebuilding the text of the parent node and diffing it
+                    // walk the whole document, so it's done in the
+                    // background, and given up on if the document changes.
+                    let buffer = doc.buffer().clone();
+                    let event_sink = doc.event_sink.clone();
+                    let widget_id = ctx.widget_id();
+                    rayon::spawn(move || {
+                        if let Some(lines) = current_changes(&buffer) {
+                            let _ = event_sink.submit_command(
+                                UNDO_CHANGES,
+                                SingleUse::new((state, lines)),
+                                Target::Widget(widget_id),
+                            );
+                        }
+                    });
+                }
+                None => self.lines.clear(),
+            }
+        }
+        let line_height = data.config.editor.line_height() as f64;
+        let content_height = line_height * self.lines.len() as f64;
+
+        Size::new(bc.max().width, content_height.max(bc.max().height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
+        let line_height = data.config.editor.line_height() as f64;
+        let rect = ctx.region().bounding_box();
+        let min = (rect.y0 / line_height).floor() as usize;
+        let max = ((rect.y1 / line_height) as usize + 2).min(self.lines.len());
+
+        let font_family = data.config.editor.font_family();
+        let font_size = data.config.editor.font_size as f64;
+        for (row, line) in self.lines.iter().enumerate().take(max).skip(min) {
+            let (text, color) = match line {
+                ChangeLine::Added(line) => {
+                    (format!("+ {line}"), LapceTheme::SOURCE_CONTROL_ADDED)
+                }
+                ChangeLine::Removed(line) => {
+                    (format!("- {line}"), LapceTheme::SOURCE_CONTROL_REMOVED)
+                }
+                ChangeLine::Unchanged(line) => {
+                    (format!("  {line}"), LapceTheme::EDITOR_FOREGROUND)
+                }
+                ChangeLine::Note(note) => {
+                    (format!("  {note}"), LapceTheme::EDITOR_DIM)
+                }
+            };
+            let text_layout = ctx
+                .text()
+                .new_text_layout(text)
+                .font(font_family.clone(), font_size)
+                .text_color(data.config.get_color_unchecked(color).clone())
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    10.0,
+                    line_height * row as f64 + text_layout.y_offset(line_height),
+                ),
+            );
+        }
+    }
+}